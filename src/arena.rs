@@ -117,6 +117,11 @@ impl<T: Hash + Eq + Clone> Interner<T> {
     pub fn get_mut(&mut self, idx: Index<T>) -> &mut T {
         self.arena.get_mut(idx)
     }
+
+    /// Get an iterator over all items of this interner and their indices
+    pub fn iter_with_indices(&self) -> impl Iterator<Item = (Index<T>, &T)> {
+        self.arena.iter_with_indices()
+    }
 }
 
 impl<T> Arena<T> {
@@ -171,6 +176,14 @@ impl<T> Arena<T> {
         self.data.iter()
     }
 
+    /// Get an iterator over all items of this arena and their indices
+    pub fn iter_with_indices(&self) -> impl Iterator<Item = (Index<T>, &T)> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| (Index::new(idx), item))
+    }
+
     /// Get an iterator over all indices in this arena
     pub fn indices(&self) -> impl Iterator<Item = Index<T>> {
         (0..self.data.len() - 1)
@@ -235,3 +248,32 @@ impl<T> fmt::Display for Index<T> {
         std::fmt::UpperHex::fmt(&self.0, f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arena_items_are_iterated_with_their_indices() {
+        let mut arena = Arena::new();
+        let a = arena.insert("a");
+        let b = arena.insert("b");
+        let items = arena.iter_with_indices().collect::<Vec<_>>();
+        assert_eq!(items, vec![(a, &"a"), (b, &"b")]);
+        for (idx, item) in items {
+            assert_eq!(arena.get(idx), item);
+        }
+    }
+
+    #[test]
+    fn interned_items_are_iterated_once() {
+        let mut interner = Interner::new();
+        let a = interner.insert("a");
+        let b = interner.insert("b");
+        assert_eq!(interner.insert("a"), a);
+        assert_eq!(
+            interner.iter_with_indices().collect::<Vec<_>>(),
+            vec![(a, &"a"), (b, &"b")]
+        );
+    }
+}
@@ -130,6 +130,15 @@ impl SymbolPath {
         }
     }
 
+    /// Return the path made of every part of this path but the last, or `None` if this path
+    /// has only one part
+    pub fn parent(&self) -> Option<Self> {
+        match &self.internal {
+            SymbolPathInternal::Single(_) => None,
+            SymbolPathInternal::Multiple(parts) => Some(Self::new_parts(&parts[..parts.len() - 1])),
+        }
+    }
+
     /// Return an iterator over all parts of this path from first to last
     pub fn iter(&self) -> PathIter<'_> {
         match &self.internal {
@@ -162,7 +171,7 @@ impl fmt::Display for SymbolPath {
 }
 
 /// Data structure storing a function prototype
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FunProto<T: Clone + Hash + Eq> {
     /// User-defined name of the function
     pub name: Symbol,
@@ -172,6 +181,9 @@ pub struct FunProto<T: Clone + Hash + Eq> {
     pub args: Vec<(Symbol, T)>,
     /// Return type of the function
     pub return_ty: T,
+    /// Names of the type parameters of a generic function, empty if the function is not
+    /// generic
+    pub type_params: Vec<Symbol>,
 }
 
 /// A node in an Abstract Syntax Tree
@@ -202,6 +214,14 @@ where
         /// If the variable is mutable
         mutable: bool,
     },
+    /// A destructuring variable declaration using the `let` or `mut` keywords, must be the
+    /// left hand side of an assignment
+    Destructure {
+        /// The names to bind the destructured value's elements to
+        pattern: DestructurePattern,
+        /// If the declared variables are mutable
+        mutable: bool,
+    },
     /// A value is being assigned to another value
     Assignment {
         /// The left hand side of the assignment expression
@@ -209,8 +229,8 @@ where
         /// The value being assigned to the left hand side
         rhs: Box<Ast<T>>,
     },
-    /// A binary expression with LHS, operator, and RHS
-    BinExpr(Box<Ast<T>>, Op, Box<Ast<T>>),
+    /// A binary expression with LHS, operator, the span of the operator token, and RHS
+    BinExpr(Box<Ast<T>>, Op, Span, Box<Ast<T>>),
     /// A unary expression with only operator and RHS
     UnaryExpr(Op, Box<Ast<T>>),
     /// Phi returning a value from the current block
@@ -221,19 +241,66 @@ where
     CastExpr(T, Box<Ast<T>>),
     /// A single constant literal
     Literal(Literal<T>),
-    /// Breaking out of a loop
-    Break,
-    /// Continuing in a loop
-    Continue,
-    /// A block of statements
-    Block(Vec<Ast<T>>),
+    /// Breaking out of the innermost loop or the loop with the given label, optionally
+    /// with a value that the loop evaluates to
+    Break(Option<Symbol>, Option<Box<Ast<T>>>),
+    /// Continuing in the innermost loop or the loop with the given label
+    Continue(Option<Symbol>),
+    /// A block of statements with an optional label that `break` and `continue` can name
+    Block(Option<Symbol>, Vec<Ast<T>>),
+    /// A loop that runs its body for as long as the condition is true, with an optional
+    /// label that `break` and `continue` can name
+    While {
+        label: Option<Symbol>,
+        /// The condition, evaluated in the enclosing scope before every iteration
+        cond: Box<Ast<T>>,
+        /// The loop body, with variables declared in it only visible for one iteration
+        body: Vec<Ast<T>>,
+    },
     /// A match statement
     Match {
         //The expression being matched
         matched: Box<Ast<T>>,
-        //The possible cases being tested for
-        cases: Vec<(T, Ast<T>)>,
+        //The possible cases being tested for, with an optional guard expression that must
+        //be true for the arm to be taken
+        cases: Vec<(MatchPattern<T>, Option<Ast<T>>, Ast<T>)>,
     },
+    /// A function defined inside of another function's body, only visible
+    /// in the enclosing scope
+    ///
+    /// The body is moved into the lowered [Function](crate::codegen::ir::Function) when
+    /// lowering, so lowered nodes always have an empty body
+    FunDef(FunProto<T>, Vec<Ast<T>>),
+    /// A type alias defined inside of a function's body, only visible in the rest of the
+    /// enclosing block
+    ///
+    /// Uses of the alias are replaced by the aliased type when lowering, so lowered nodes
+    /// have no effect
+    TypeDef(Symbol, T),
+}
+
+/// A pattern that the value of a match expression is tested against in a match arm
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MatchPattern<T: Clone + Hash + Eq> {
+    /// Matching a variant of an enum by its type, optionally destructuring the variant's
+    /// structure or tuple value into new variables scoped to the arm
+    Type(T, Option<DestructurePattern>),
+    /// Matching an integer value against a constant
+    Integer(BigInt),
+    /// Matching an integer value against an inclusive range of constants
+    Range(BigInt, BigInt),
+    /// The `_` pattern, matching any value not matched by other arms
+    Default,
+}
+
+/// A pattern used to bind the elements of an aggregate value to new variables
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DestructurePattern {
+    /// Binding each element of a tuple by position, `None` if the element is ignored
+    /// with a `_` wildcard
+    Tuple(Vec<Option<Symbol>>),
+    /// Binding structure fields to variables of the same name
+    Struct(Vec<Symbol>),
 }
 
 /// An enumeration of all parseable literals
@@ -243,6 +310,7 @@ pub enum Literal<T: Clone + Hash + Eq> {
     String(String),
     Bool(bool),
     Array(Vec<Ast<T>>),
+    Tuple(Vec<Ast<T>>),
     Struct {
         ty: Option<T>,
         fields: Vec<(Symbol, Ast<T>)>,
@@ -291,11 +359,47 @@ pub enum DefData {
     AliasDef {
         /// The alias that `aliased` can be accessed by
         name: Symbol,
+        /// Names of the type parameters of a generic type, empty if the type is not generic
+        params: Vec<Symbol>,
         /// The aliased type
         aliased: UnresolvedType,
+        /// Default values of fields that can be left out of structure literals, empty if the
+        /// aliased type is not a structure
+        defaults: Vec<(Symbol, Ast)>,
     },
     /// An imported module definition
     ImportDef { name: SymbolPath },
+    /// A global variable with a constant initializer
+    GlobalDef {
+        /// Name of the global variable
+        name: Symbol,
+        /// Explicitly declared type of the global, inferred from the initializer if not given
+        ty: Option<UnresolvedType>,
+        /// If the global can be assigned to after initialization
+        mutable: bool,
+        /// If the global was declared with `const` and can be used in constant expressions
+        constant: bool,
+        /// The initial value of the global, or `None` for an external global declared with
+        /// `ext` that is defined in another object
+        init: Option<Ast>,
+    },
+    /// A set of function declarations that types can implement, where `Self` is the type
+    /// implementing the interface
+    InterfaceDef {
+        /// Name of the interface
+        name: Symbol,
+        /// Declarations of the functions that implementations must define
+        funs: Vec<Def>,
+    },
+    /// Definitions of an interface's functions for one type
+    ImplDef {
+        /// Path to the implemented interface
+        interface: SymbolPath,
+        /// The type that the interface is implemented for
+        ty: UnresolvedType,
+        /// Definitions of every function declared in the interface
+        funs: Vec<Def>,
+    },
 }
 impl DefData {
     /// Get the name of this definition
@@ -304,6 +408,11 @@ impl DefData {
             Self::FunDef(proto, _) | Self::FunDec(proto) => proto.name,
             Self::AliasDef { name, .. } => *name,
             Self::ImportDef { name } => name.last(),
+            Self::GlobalDef { name, .. } => *name,
+            Self::InterfaceDef { name, .. } => *name,
+            Self::ImplDef { interface, ty, .. } => {
+                Symbol::from(&format!("impl {} for {}", interface, ty))
+            }
         }
     }
 }
@@ -315,6 +424,45 @@ pub struct Def {
     pub data: DefData,
     pub span: Span,
     pub file: FileId,
+    /// All attributes applied to this definition
+    pub attrs: Vec<Attribute>,
+    /// If this definition was marked with `pub` and can be accessed from other modules
+    pub public: bool,
+}
+
+/// An attribute applied to a definition with `#[name]` or `#[name(args)]` syntax before the
+/// definition
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribute {
+    pub name: Symbol,
+    /// Identifiers or string literals passed in parentheses after the attribute name
+    pub args: Vec<Symbol>,
+    /// Arguments passed in parentheses with `key = "value"` syntax
+    pub named: Vec<(Symbol, Symbol)>,
+    pub span: Span,
+}
+
+impl Def {
+    /// Check if all `#[cfg(target = "...")]` attributes applied to this definition match the
+    /// given target triple, where a target matches if it is the full triple or the
+    /// architecture component of the triple.
+    ///
+    /// Malformed `cfg` attributes are treated as matching so that they can be reported when the
+    /// definition is lowered
+    pub fn cfg_matches(&self, triple: &str) -> bool {
+        let cfgs = self.attrs.iter().filter(|attr| attr.name.as_str() == "cfg");
+        //A definition with an unknown predicate is kept even if its other predicates don't
+        //match, so that the unknown predicate is always reported
+        let malformed = cfgs.clone().any(|attr| {
+            !attr.args.is_empty()
+                || attr.named.is_empty()
+                || attr.named.iter().any(|(key, _)| key.as_str() != "target")
+        });
+        malformed
+            || cfgs.flat_map(|attr| attr.named.iter()).all(|(_, value)| {
+                triple == value.as_str() || triple.split('-').next() == Some(value.as_str())
+            })
+    }
 }
 
 /// Structure representing a fully parsed module with easy access
@@ -341,6 +489,13 @@ impl ParsedModule {
             imports: vec![],
         }
     }
+
+    /// Get all definitions of this module in the order they appear in its source files
+    pub fn defs_in_order(&self) -> Vec<&Def> {
+        let mut defs = self.defs.values().collect::<Vec<_>>();
+        defs.sort_by_key(|def| (def.file.val(), def.span.from));
+        defs
+    }
 }
 
 /// A number literal holding either a big integer or
@@ -384,6 +539,43 @@ pub enum NumberLiteralAnnotation {
     I64,
 }
 
+impl NumberLiteralAnnotation {
+    /// Get the name of the annotated type as it is written in source
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+            Self::U8 => "u8",
+            Self::U16 => "u16",
+            Self::U32 => "u32",
+            Self::U64 => "u64",
+            Self::I8 => "i8",
+            Self::I16 => "i16",
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+        }
+    }
+
+    /// Get the largest integer literal that can be written with this annotation, or `None` if
+    /// the annotation is a floating point type.
+    ///
+    /// Literals are never negative when parsed, so a `negated` literal of a signed type may be
+    /// one larger than the type's maximum to support literals like `-128i8`
+    pub const fn max_literal(&self, negated: bool) -> Option<u64> {
+        Some(match self {
+            Self::F32 | Self::F64 => return None,
+            Self::U8 => u8::MAX as u64,
+            Self::U16 => u16::MAX as u64,
+            Self::U32 => u32::MAX as u64,
+            Self::U64 => u64::MAX,
+            Self::I8 => i8::MAX as u64 + negated as u64,
+            Self::I16 => i16::MAX as u64 + negated as u64,
+            Self::I32 => i32::MAX as u64 + negated as u64,
+            Self::I64 => i64::MAX as u64 + negated as u64,
+        })
+    }
+}
+
 /// Type representing a function's type in spark
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct UnresolvedFunType {
@@ -411,9 +603,15 @@ pub enum UnresolvedType {
         /// If this is an f32 or an f64
         doublewide: bool,
     },
+    /// A pointer to a value of another type, `*()` is an opaque pointer like C's void*
     Pointer(Box<UnresolvedType>),
     Array {
         elements: Box<UnresolvedType>,
+        len: ArrayLen,
+    },
+    /// A fixed-width SIMD vector of integers or floats, written `<len x element>`
+    Vector {
+        element: Box<UnresolvedType>,
         len: u64,
     },
     /// Unit type with only one value, like void in C or () in rust
@@ -422,6 +620,14 @@ pub enum UnresolvedType {
     Struct {
         fields: Vec<(UnresolvedType, Symbol)>,
     },
+    /// An untagged union of named members that all share the same memory
+    Union {
+        fields: Vec<(UnresolvedType, Symbol)>,
+    },
+    /// An anonymous structure with elements accessed by position
+    Tuple {
+        elements: Vec<UnresolvedType>,
+    },
     /// A tagged union with variant types
     Enum {
         variants: Vec<UnresolvedType>,
@@ -430,9 +636,69 @@ pub enum UnresolvedType {
     UserDefined {
         /// The name of the user-defined type
         name: SymbolPath,
+        /// Type arguments given to a generic type, empty if none are given
+        args: Vec<UnresolvedType>,
     },
 }
 
+impl fmt::Display for UnresolvedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn join<T: fmt::Display>(items: impl Iterator<Item = T>, sep: &str) -> String {
+            items.map(|item| item.to_string()).collect::<Vec<_>>().join(sep)
+        }
+
+        match self {
+            Self::Integer { width, signed } => {
+                write!(f, "{}{}", if *signed { "i" } else { "u" }, *width as u8)
+            }
+            Self::Bool => write!(f, "bool"),
+            Self::Fun(fun) => write!(
+                f,
+                "fun({})->{}",
+                join(fun.arg_tys.iter(), ", "),
+                fun.return_ty
+            ),
+            Self::Float { doublewide } => write!(f, "{}", if *doublewide { "f64" } else { "f32" }),
+            Self::Pointer(ty) => write!(f, "*{}", ty),
+            Self::Array { elements, len } => write!(f, "[{}]{}", len, elements),
+            Self::Vector { element, len } => write!(f, "<{} x {}>", len, element),
+            Self::Unit => write!(f, "()"),
+            Self::Struct { fields } => write!(
+                f,
+                "{{ {} }}",
+                join(fields.iter().map(|(ty, name)| format!("{} {}", ty, name)), " ")
+            ),
+            Self::Union { fields } => write!(
+                f,
+                "union {{ {} }}",
+                join(fields.iter().map(|(ty, name)| format!("{} {}", ty, name)), " ")
+            ),
+            Self::Tuple { elements } => write!(f, "({})", join(elements.iter(), ", ")),
+            Self::Enum { variants } => write!(f, "( {} )", join(variants.iter(), " | ")),
+            Self::UserDefined { name, args } if args.is_empty() => name.fmt(f),
+            Self::UserDefined { name, args } => write!(f, "{}<{}>", name, join(args.iter(), ", ")),
+        }
+    }
+}
+
+/// The length of an array type as written in source
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ArrayLen {
+    /// A length given as an integer literal
+    Literal(u64),
+    /// A length given by the name of a `const` global
+    Const(SymbolPath),
+}
+
+impl fmt::Display for ArrayLen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Literal(len) => len.fmt(f),
+            Self::Const(name) => name.fmt(f),
+        }
+    }
+}
+
 /// Enumeration for all possible integer bit widths in the [UnresolvedType] enum
 #[repr(u8)]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -452,8 +718,11 @@ impl<T: std::fmt::Debug + Clone + Hash + Eq> std::fmt::Debug for AstNode<T> {
                 //for (literal, case_expr) in cases.iter() {}
                 write!(w, "}}")
             }
-            Self::Block(stmts) => {
-                writeln!(w, "BLOCK {{")?;
+            Self::Block(label, stmts) => {
+                match label {
+                    Some(label) => writeln!(w, "BLOCK '{} {{", label)?,
+                    None => writeln!(w, "BLOCK {{")?,
+                }
                 for stmt in stmts {
                     writeln!(w, "{:?}", stmt.node)?;
                 }
@@ -466,8 +735,38 @@ impl<T: std::fmt::Debug + Clone + Hash + Eq> std::fmt::Debug for AstNode<T> {
                 }
                 write!(w, " ]")
             }
-            Self::Break => write!(w, "BREAK"),
-            Self::Continue => write!(w, "CONTINUE"),
+            Self::FunDef(proto, body) => {
+                writeln!(w, "FUNDEF {} {{", proto.name)?;
+                for stmt in body {
+                    writeln!(w, "{:?}", stmt.node)?;
+                }
+                write!(w, "}}")
+            }
+            Self::Literal(Literal::Tuple(parts)) => {
+                write!(w, "TUPLE ( ")?;
+                for part in parts.iter() {
+                    write!(w, "{:?}, ", part.node)?;
+                }
+                write!(w, " )")
+            }
+            Self::Destructure { pattern, mutable } => write!(
+                w,
+                "DESTRUCTURE {} {:?}",
+                if *mutable { "mut" } else { "let" },
+                pattern
+            ),
+            Self::Break(label, value) => {
+                write!(w, "BREAK")?;
+                if let Some(label) = label {
+                    write!(w, " '{}", label)?;
+                }
+                match value {
+                    Some(value) => write!(w, " {:?}", value.node),
+                    None => Ok(()),
+                }
+            }
+            Self::Continue(None) => write!(w, "CONTINUE"),
+            Self::Continue(Some(label)) => write!(w, "CONTINUE '{}", label),
             Self::Literal(Literal::Number(num)) => write!(w, "NUMBER LITERAL {:?}", num),
             Self::Literal(Literal::String(string)) => write!(w, "STRING LITERAL {:?}", string),
             Self::Literal(Literal::Struct{..}) => write!(w, "STRUCT LITERAL"),
@@ -517,7 +816,7 @@ impl<T: std::fmt::Debug + Clone + Hash + Eq> std::fmt::Debug for AstNode<T> {
                 write!(w, ".")?;
                 write!(w, "{}", index)
             }
-            Self::BinExpr(lhs, op, rhs) => {
+            Self::BinExpr(lhs, op, _, rhs) => {
                 write!(w, "BIN {:?}", lhs.node)?;
                 write!(w, " {} ", op)?;
                 write!(w, "{:?}", rhs.node)
@@ -550,6 +849,17 @@ impl<T: std::fmt::Debug + Clone + Hash + Eq> std::fmt::Debug for AstNode<T> {
 
                 display_if(ifexpr, w)
             }
+            Self::While { label, cond, body } => {
+                write!(w, "WHILE")?;
+                if let Some(label) = label {
+                    write!(w, " '{}", label)?;
+                }
+                writeln!(w, " {:?} {{", cond.node)?;
+                for stmt in body {
+                    writeln!(w, "{:?}", stmt.node)?;
+                }
+                write!(w, "}}")
+            }
             Self::Index { object, index } => {
                 write!(w, "INDEX {:?}", object.node)?;
                 write!(w, " [ ")?;
@@ -559,3 +869,590 @@ impl<T: std::fmt::Debug + Clone + Hash + Eq> std::fmt::Debug for AstNode<T> {
         }
     }
 }
+
+/// Wrapper over an [Ast] that displays it as an indented tree with one node per line, followed
+/// by the span of source code that the node occupies
+pub struct AstTree<'a, T, F>
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> String,
+{
+    ast: &'a Ast<T>,
+    /// Function used to display the types contained in the tree
+    type_name: F,
+}
+
+impl<T: Clone + Hash + Eq> Ast<T> {
+    /// Get a value that displays this AST as an indented tree, using `type_name` to display
+    /// types
+    pub fn tree<F: Fn(&T) -> String>(&self, type_name: F) -> AstTree<'_, T, F> {
+        AstTree {
+            ast: self,
+            type_name,
+        }
+    }
+
+    /// Call a function on this node and every node contained in it, parents before children
+    pub fn walk<F: FnMut(&Ast<T>)>(&self, f: &mut F) {
+        f(self);
+        match &self.node {
+            AstNode::MemberAccess(object, _)
+            | AstNode::UnaryExpr(_, object)
+            | AstNode::PhiExpr(object)
+            | AstNode::Return(object)
+            | AstNode::Break(_, Some(object))
+            | AstNode::CastExpr(_, object) => object.walk(f),
+            AstNode::Index { object, index } => {
+                object.walk(f);
+                index.walk(f);
+            }
+            AstNode::FunCall(called, args) => {
+                called.walk(f);
+                args.iter().for_each(|arg| arg.walk(f));
+            }
+            AstNode::IfExpr(if_expr) => if_expr.walk(f),
+            AstNode::While { cond, body, .. } => {
+                cond.walk(f);
+                body.iter().for_each(|stmt| stmt.walk(f));
+            }
+            AstNode::Assignment { lhs, rhs } | AstNode::BinExpr(lhs, _, _, rhs) => {
+                lhs.walk(f);
+                rhs.walk(f);
+            }
+            AstNode::Literal(Literal::Array(elems) | Literal::Tuple(elems)) => {
+                elems.iter().for_each(|elem| elem.walk(f))
+            }
+            AstNode::Literal(Literal::Struct { fields, .. }) => {
+                fields.iter().for_each(|(_, field)| field.walk(f))
+            }
+            AstNode::Block(_, body) | AstNode::FunDef(_, body) => {
+                body.iter().for_each(|stmt| stmt.walk(f))
+            }
+            AstNode::Match { matched, cases } => {
+                matched.walk(f);
+                cases.iter().for_each(|(_, guard, case)| {
+                    if let Some(guard) = guard {
+                        guard.walk(f);
+                    }
+                    case.walk(f)
+                });
+            }
+            AstNode::Access(_)
+            | AstNode::VarDeclaration { .. }
+            | AstNode::Destructure { .. }
+            | AstNode::Literal(_)
+            | AstNode::Break(_, None)
+            | AstNode::Continue(_)
+            | AstNode::TypeDef(..) => (),
+        }
+    }
+
+    /// Create a copy of this AST with every type it contains replaced by the result of `f`
+    pub fn map_types<U: Clone + Hash + Eq, F: FnMut(&T) -> U>(&self, f: &mut F) -> Ast<U> {
+        let map_body = |body: &[Ast<T>], f: &mut F| {
+            body.iter().map(|stmt| stmt.map_types(f)).collect::<Vec<_>>()
+        };
+        let node = match &self.node {
+            AstNode::Access(path) => AstNode::Access(path.clone()),
+            AstNode::MemberAccess(object, field) => {
+                AstNode::MemberAccess(Box::new(object.map_types(f)), *field)
+            }
+            AstNode::Index { object, index } => AstNode::Index {
+                object: Box::new(object.map_types(f)),
+                index: Box::new(index.map_types(f)),
+            },
+            AstNode::FunCall(called, args) => {
+                AstNode::FunCall(Box::new(called.map_types(f)), map_body(args, f))
+            }
+            AstNode::IfExpr(if_expr) => AstNode::IfExpr(if_expr.map_types(f)),
+            AstNode::VarDeclaration { name, ty, mutable } => AstNode::VarDeclaration {
+                name: *name,
+                ty: ty.as_ref().map(|ty| f(ty)),
+                mutable: *mutable,
+            },
+            AstNode::Destructure { pattern, mutable } => AstNode::Destructure {
+                pattern: pattern.clone(),
+                mutable: *mutable,
+            },
+            AstNode::Assignment { lhs, rhs } => AstNode::Assignment {
+                lhs: Box::new(lhs.map_types(f)),
+                rhs: Box::new(rhs.map_types(f)),
+            },
+            AstNode::BinExpr(lhs, op, op_span, rhs) => AstNode::BinExpr(
+                Box::new(lhs.map_types(f)),
+                *op,
+                *op_span,
+                Box::new(rhs.map_types(f)),
+            ),
+            AstNode::UnaryExpr(op, rhs) => AstNode::UnaryExpr(*op, Box::new(rhs.map_types(f))),
+            AstNode::PhiExpr(val) => AstNode::PhiExpr(Box::new(val.map_types(f))),
+            AstNode::Return(val) => AstNode::Return(Box::new(val.map_types(f))),
+            AstNode::CastExpr(ty, casted) => {
+                AstNode::CastExpr(f(ty), Box::new(casted.map_types(f)))
+            }
+            AstNode::Literal(literal) => AstNode::Literal(match literal {
+                Literal::Number(num) => Literal::Number(num.clone()),
+                Literal::String(string) => Literal::String(string.clone()),
+                Literal::Bool(b) => Literal::Bool(*b),
+                Literal::Array(elems) => Literal::Array(map_body(elems, f)),
+                Literal::Tuple(elems) => Literal::Tuple(map_body(elems, f)),
+                Literal::Struct { ty, fields } => Literal::Struct {
+                    ty: ty.as_ref().map(|ty| f(ty)),
+                    fields: fields
+                        .iter()
+                        .map(|(name, field)| (*name, field.map_types(f)))
+                        .collect(),
+                },
+                Literal::Unit => Literal::Unit,
+            }),
+            AstNode::Break(label, val) => {
+                AstNode::Break(*label, val.as_ref().map(|val| Box::new(val.map_types(f))))
+            }
+            AstNode::Continue(label) => AstNode::Continue(*label),
+            AstNode::Block(label, body) => AstNode::Block(*label, map_body(body, f)),
+            AstNode::While { label, cond, body } => AstNode::While {
+                label: *label,
+                cond: Box::new(cond.map_types(f)),
+                body: map_body(body, f),
+            },
+            AstNode::Match { matched, cases } => AstNode::Match {
+                matched: Box::new(matched.map_types(f)),
+                cases: cases
+                    .iter()
+                    .map(|(pattern, guard, case)| {
+                        let pattern = match pattern {
+                            MatchPattern::Type(ty, destructure) => {
+                                MatchPattern::Type(f(ty), destructure.clone())
+                            }
+                            MatchPattern::Integer(int) => MatchPattern::Integer(int.clone()),
+                            MatchPattern::Range(from, to) => {
+                                MatchPattern::Range(from.clone(), to.clone())
+                            }
+                            MatchPattern::Default => MatchPattern::Default,
+                        };
+                        (
+                            pattern,
+                            guard.as_ref().map(|guard| guard.map_types(f)),
+                            case.map_types(f),
+                        )
+                    })
+                    .collect(),
+            },
+            AstNode::FunDef(proto, body) => AstNode::FunDef(
+                FunProto {
+                    name: proto.name,
+                    flags: proto.flags,
+                    args: proto.args.iter().map(|(name, ty)| (*name, f(ty))).collect(),
+                    return_ty: f(&proto.return_ty),
+                    type_params: proto.type_params.clone(),
+                },
+                map_body(body, f),
+            ),
+            AstNode::TypeDef(name, ty) => AstNode::TypeDef(*name, f(ty)),
+        };
+
+        Ast {
+            node,
+            span: self.span,
+        }
+    }
+}
+
+impl<T: Clone + Hash + Eq> IfExpr<T> {
+    /// Create a copy of this if expression with every type it contains replaced by the result
+    /// of `f`
+    fn map_types<U: Clone + Hash + Eq, F: FnMut(&T) -> U>(&self, f: &mut F) -> IfExpr<U> {
+        IfExpr {
+            cond: Box::new(self.cond.map_types(f)),
+            body: self.body.iter().map(|stmt| stmt.map_types(f)).collect(),
+            else_expr: match &self.else_expr {
+                Some(ElseExpr::ElseIf(else_if)) => {
+                    Some(ElseExpr::ElseIf(Box::new(else_if.map_types(f))))
+                }
+                Some(ElseExpr::Else(body)) => {
+                    Some(ElseExpr::Else(body.iter().map(|stmt| stmt.map_types(f)).collect()))
+                }
+                None => None,
+            },
+        }
+    }
+
+    /// Walk the condition and bodies of this if expression and all else branches
+    fn walk<F: FnMut(&Ast<T>)>(&self, f: &mut F) {
+        self.cond.walk(f);
+        self.body.iter().for_each(|stmt| stmt.walk(f));
+        match &self.else_expr {
+            Some(ElseExpr::ElseIf(else_if)) => else_if.walk(f),
+            Some(ElseExpr::Else(body)) => body.iter().for_each(|stmt| stmt.walk(f)),
+            None => (),
+        }
+    }
+}
+
+impl<T, F> AstTree<'_, T, F>
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> String,
+{
+    /// Write a single line of the tree at the given depth
+    fn line(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+        label: fmt::Arguments<'_>,
+        span: Option<Span>,
+    ) -> fmt::Result {
+        write!(f, "{:width$}{}", "", label, width = depth * 2)?;
+        match span {
+            Some(span) => writeln!(f, " [{}]", span),
+            None => writeln!(f),
+        }
+    }
+
+    /// Write all nodes in a list of statements at the given depth
+    fn block(&self, f: &mut fmt::Formatter<'_>, depth: usize, stmts: &[Ast<T>]) -> fmt::Result {
+        for stmt in stmts {
+            self.node(f, depth, stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Write an if expression and all else branches
+    fn if_expr(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        depth: usize,
+        if_expr: &IfExpr<T>,
+        span: Option<Span>,
+    ) -> fmt::Result {
+        self.line(f, depth, format_args!("IF"), span)?;
+        self.node(f, depth + 1, &if_expr.cond)?;
+        self.line(f, depth, format_args!("THEN"), None)?;
+        self.block(f, depth + 1, &if_expr.body)?;
+        match &if_expr.else_expr {
+            Some(ElseExpr::ElseIf(else_if)) => {
+                self.line(f, depth, format_args!("ELSE"), None)?;
+                self.if_expr(f, depth + 1, else_if, None)
+            }
+            Some(ElseExpr::Else(body)) => {
+                self.line(f, depth, format_args!("ELSE"), None)?;
+                self.block(f, depth + 1, body)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Write a node and all of its children
+    fn node(&self, f: &mut fmt::Formatter<'_>, depth: usize, ast: &Ast<T>) -> fmt::Result {
+        let span = Some(ast.span);
+        match &ast.node {
+            AstNode::Access(path) => self.line(f, depth, format_args!("ACCESS {}", path), span),
+            AstNode::MemberAccess(object, field) => {
+                self.line(f, depth, format_args!("MEMBER .{}", field), span)?;
+                self.node(f, depth + 1, object)
+            }
+            AstNode::Index { object, index } => {
+                self.line(f, depth, format_args!("INDEX"), span)?;
+                self.node(f, depth + 1, object)?;
+                self.node(f, depth + 1, index)
+            }
+            AstNode::FunCall(called, args) => {
+                self.line(f, depth, format_args!("CALL"), span)?;
+                self.node(f, depth + 1, called)?;
+                self.block(f, depth + 1, args)
+            }
+            AstNode::IfExpr(if_expr) => self.if_expr(f, depth, if_expr, span),
+            AstNode::VarDeclaration { name, ty, mutable } => {
+                let keyword = if *mutable { "mut" } else { "let" };
+                match ty {
+                    Some(ty) => self.line(
+                        f,
+                        depth,
+                        format_args!("DECLARE {} ({}) {}", keyword, (self.type_name)(ty), name),
+                        span,
+                    ),
+                    None => self.line(f, depth, format_args!("DECLARE {} {}", keyword, name), span),
+                }
+            }
+            AstNode::Destructure { pattern, mutable } => self.line(
+                f,
+                depth,
+                format_args!(
+                    "DESTRUCTURE {} {:?}",
+                    if *mutable { "mut" } else { "let" },
+                    pattern
+                ),
+                span,
+            ),
+            AstNode::Assignment { lhs, rhs } => {
+                self.line(f, depth, format_args!("ASSIGN"), span)?;
+                self.node(f, depth + 1, lhs)?;
+                self.node(f, depth + 1, rhs)
+            }
+            AstNode::BinExpr(lhs, op, _, rhs) => {
+                self.line(f, depth, format_args!("BINARY {}", op), span)?;
+                self.node(f, depth + 1, lhs)?;
+                self.node(f, depth + 1, rhs)
+            }
+            AstNode::UnaryExpr(op, expr) => {
+                self.line(f, depth, format_args!("UNARY {}", op), span)?;
+                self.node(f, depth + 1, expr)
+            }
+            AstNode::PhiExpr(expr) => {
+                self.line(f, depth, format_args!("PHI"), span)?;
+                self.node(f, depth + 1, expr)
+            }
+            AstNode::Return(expr) => {
+                self.line(f, depth, format_args!("RETURN"), span)?;
+                self.node(f, depth + 1, expr)
+            }
+            AstNode::CastExpr(ty, expr) => {
+                self.line(f, depth, format_args!("CAST {}", (self.type_name)(ty)), span)?;
+                self.node(f, depth + 1, expr)
+            }
+            AstNode::Literal(literal) => match literal {
+                Literal::Number(NumberLiteral::Integer(num, annotation)) => self.line(
+                    f,
+                    depth,
+                    format_args!(
+                        "INTEGER {}{}{}",
+                        if num.sign { "-" } else { "" },
+                        num.val,
+                        annotation.map(|a| a.name()).unwrap_or("")
+                    ),
+                    span,
+                ),
+                Literal::Number(NumberLiteral::Float(num, annotation)) => self.line(
+                    f,
+                    depth,
+                    format_args!("FLOAT {}{}", num, annotation.map(|a| a.name()).unwrap_or("")),
+                    span,
+                ),
+                Literal::String(string) => {
+                    self.line(f, depth, format_args!("STRING {:?}", string), span)
+                }
+                Literal::Bool(boolean) => self.line(f, depth, format_args!("BOOL {}", boolean), span),
+                Literal::Array(elements) => {
+                    self.line(f, depth, format_args!("ARRAY"), span)?;
+                    self.block(f, depth + 1, elements)
+                }
+                Literal::Tuple(elements) => {
+                    self.line(f, depth, format_args!("TUPLE"), span)?;
+                    self.block(f, depth + 1, elements)
+                }
+                Literal::Struct { ty, fields } => {
+                    match ty {
+                        Some(ty) => self.line(
+                            f,
+                            depth,
+                            format_args!("STRUCT {}", (self.type_name)(ty)),
+                            span,
+                        )?,
+                        None => self.line(f, depth, format_args!("STRUCT"), span)?,
+                    }
+                    for (name, value) in fields {
+                        self.line(f, depth + 1, format_args!("FIELD {}", name), None)?;
+                        self.node(f, depth + 2, value)?;
+                    }
+                    Ok(())
+                }
+                Literal::Unit => self.line(f, depth, format_args!("UNIT"), span),
+            },
+            AstNode::Break(label, value) => {
+                match label {
+                    Some(label) => self.line(f, depth, format_args!("BREAK '{}", label), span)?,
+                    None => self.line(f, depth, format_args!("BREAK"), span)?,
+                }
+                match value {
+                    Some(value) => self.node(f, depth + 1, value),
+                    None => Ok(()),
+                }
+            }
+            AstNode::Continue(None) => self.line(f, depth, format_args!("CONTINUE"), span),
+            AstNode::Continue(Some(label)) => {
+                self.line(f, depth, format_args!("CONTINUE '{}", label), span)
+            }
+            AstNode::Block(None, stmts) => {
+                self.line(f, depth, format_args!("BLOCK"), span)?;
+                self.block(f, depth + 1, stmts)
+            }
+            AstNode::Block(Some(label), stmts) => {
+                self.line(f, depth, format_args!("BLOCK '{}", label), span)?;
+                self.block(f, depth + 1, stmts)
+            }
+            AstNode::While { label, cond, body } => {
+                match label {
+                    Some(label) => self.line(f, depth, format_args!("WHILE '{}", label), span)?,
+                    None => self.line(f, depth, format_args!("WHILE"), span)?,
+                }
+                self.node(f, depth + 1, cond)?;
+                self.line(f, depth, format_args!("DO"), None)?;
+                self.block(f, depth + 1, body)
+            }
+            AstNode::Match { matched, cases } => {
+                self.line(f, depth, format_args!("MATCH"), span)?;
+                self.node(f, depth + 1, matched)?;
+                for (pattern, guard, expr) in cases {
+                    match pattern {
+                        MatchPattern::Type(ty, None) => self.line(
+                            f,
+                            depth + 1,
+                            format_args!("CASE {}", (self.type_name)(ty)),
+                            None,
+                        )?,
+                        MatchPattern::Type(ty, Some(pattern)) => self.line(
+                            f,
+                            depth + 1,
+                            format_args!("CASE {} {:?}", (self.type_name)(ty), pattern),
+                            None,
+                        )?,
+                        MatchPattern::Integer(num) => self.line(
+                            f,
+                            depth + 1,
+                            format_args!("CASE {}{}", if num.sign { "-" } else { "" }, num.val),
+                            None,
+                        )?,
+                        MatchPattern::Range(start, end) => self.line(
+                            f,
+                            depth + 1,
+                            format_args!(
+                                "CASE {}{}..{}{}",
+                                if start.sign { "-" } else { "" },
+                                start.val,
+                                if end.sign { "-" } else { "" },
+                                end.val
+                            ),
+                            None,
+                        )?,
+                        MatchPattern::Default => {
+                            self.line(f, depth + 1, format_args!("CASE _"), None)?
+                        }
+                    }
+                    if let Some(guard) = guard {
+                        self.line(f, depth + 2, format_args!("GUARD"), None)?;
+                        self.node(f, depth + 3, guard)?;
+                    }
+                    self.node(f, depth + 2, expr)?;
+                }
+                Ok(())
+            }
+            AstNode::FunDef(proto, body) => {
+                self.line(
+                    f,
+                    depth,
+                    format_args!(
+                        "FUN {}({}) -> {}",
+                        proto.name,
+                        proto
+                            .args
+                            .iter()
+                            .map(|(name, ty)| format!("{} {}", (self.type_name)(ty), name))
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                        (self.type_name)(&proto.return_ty)
+                    ),
+                    span,
+                )?;
+                self.block(f, depth + 1, body)
+            }
+            AstNode::TypeDef(name, ty) => self.line(
+                f,
+                depth,
+                format_args!("TYPE {} = {}", name, (self.type_name)(ty)),
+                span,
+            ),
+        }
+    }
+}
+
+impl<T, F> fmt::Display for AstTree<'_, T, F>
+where
+    T: Clone + Hash + Eq,
+    F: Fn(&T) -> String,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.node(f, 0, self.ast)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn positive_signed_literals_stop_at_maximum() {
+        assert_eq!(NumberLiteralAnnotation::I8.max_literal(false), Some(127));
+        assert_eq!(
+            NumberLiteralAnnotation::I64.max_literal(false),
+            Some(i64::MAX as u64)
+        );
+    }
+
+    #[test]
+    fn negated_signed_literals_reach_minimum() {
+        assert_eq!(NumberLiteralAnnotation::I8.max_literal(true), Some(128));
+        assert_eq!(
+            NumberLiteralAnnotation::I64.max_literal(true),
+            Some(i64::MAX as u64 + 1)
+        );
+    }
+
+    #[test]
+    fn unsigned_and_float_literal_bounds() {
+        assert_eq!(NumberLiteralAnnotation::U8.max_literal(false), Some(255));
+        assert_eq!(
+            NumberLiteralAnnotation::U64.max_literal(true),
+            Some(u64::MAX)
+        );
+        assert_eq!(NumberLiteralAnnotation::F32.max_literal(false), None);
+    }
+
+    #[test]
+    fn syntax_trees_print_as_indented_nodes_with_spans() {
+        // if a < b {
+        //     return a + 1
+        // }
+        let access = |name: &str, from: usize| Ast {
+            node: AstNode::Access(SymbolPath::new(Symbol::from(name))),
+            span: Span::new(from, from + 1),
+        };
+        let ast: Ast = Ast {
+            node: AstNode::IfExpr(IfExpr {
+                cond: Box::new(Ast {
+                    node: AstNode::BinExpr(
+                        Box::new(access("a", 3)),
+                        Op::Less,
+                        Span::new(5, 6),
+                        Box::new(access("b", 7)),
+                    ),
+                    span: Span::new(3, 8),
+                }),
+                body: vec![Ast {
+                    node: AstNode::Return(Box::new(Ast {
+                        node: AstNode::BinExpr(
+                            Box::new(access("a", 22)),
+                            Op::Add,
+                            Span::new(24, 25),
+                            Box::new(Ast {
+                                node: AstNode::Literal(Literal::Number(NumberLiteral::Integer(
+                                    BigInt { val: 1, sign: false },
+                                    None,
+                                ))),
+                                span: Span::new(26, 27),
+                            }),
+                        ),
+                        span: Span::new(22, 27),
+                    })),
+                    span: Span::new(15, 27),
+                }],
+                else_expr: None,
+            }),
+            span: Span::new(0, 29),
+        };
+
+        assert_eq!(
+            ast.tree(|ty| ty.to_string()).to_string(),
+            "IF [0-29]\n  BINARY < [3-8]\n    ACCESS a [3-4]\n    ACCESS b [7-8]\nTHEN\n  RETURN [15-27]\n    BINARY + [22-27]\n      ACCESS a [22-23]\n      INTEGER 1 [26-27]\n"
+        );
+    }
+}
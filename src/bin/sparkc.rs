@@ -1,15 +1,14 @@
 use std::path::{Path, PathBuf};
 
 use clap::{App, Arg, ValueHint};
-use codespan_reporting::diagnostic::{Diagnostic, Label};
-use inkwell::context::Context;
+use inkwell::{context::Context, targets::TargetMachine};
 use spark::{
-    ast::ParsedModule,
-    codegen::{ir::SparkCtx, llvm::LlvmCodeGenerator, lower::Lowerer},
+    ast::{DefData, ParsedModule},
+    codegen::{c::CCodeGenerator, ir::SparkCtx, llvm::LlvmCodeGenerator, lower::Lowerer},
     error::DiagnosticManager,
     parse::{ParseError, Parser},
     util::files::{CompiledFile, FileId, Files},
-    CompileOpts, OutputFileType, OutputOptimizationLevel, Symbol,
+    CompileOpts, OutputFileType, OutputOptimizationLevel, Symbol, TrapBehavior,
 };
 
 enum InputItem {
@@ -65,7 +64,8 @@ fn main() {
             .possible_values([
                 "asm",
                 "obj",
-                "ll"
+                "ll",
+                "c"
             ])
             .help("Set the output type to be written to the output file")
             .help_heading("output")
@@ -82,6 +82,50 @@ fn main() {
             .takes_value(false)
             .help("Strip symbols from the produced output (redundant if -Osize is passed)")
             .help_heading("output")
+        )
+        .arg(Arg::new("trap")
+            .long("trap")
+            .takes_value(true)
+            .default_value("abort")
+            .possible_values([
+                "abort",
+                "trap",
+                "unreachable"
+            ])
+            .help("Set what the program does when a runtime check fails")
+            .long_help("Set what the program does when a runtime check fails.\n'abort' calls libc's abort function, 'trap' executes a trap instruction without depending on libc, and 'unreachable' assumes that checks never fail")
+            .help_heading("output")
+        )
+        .arg(Arg::new("print-ast")
+            .long("print-ast")
+            .takes_value(false)
+            .help("Print the parsed syntax tree of every function body to stdout")
+            .help_heading("debug")
+        )
+        .arg(Arg::new("promote-ints")
+            .long("promote-ints")
+            .takes_value(false)
+            .help("Implicitly widen integers of different widths in binary expressions")
+            .help_heading("input")
+        )
+        .arg(Arg::new("fast-math")
+            .long("fast-math")
+            .takes_value(false)
+            .help("Allow unsafe floating point optimizations that may change results")
+            .help_heading("output")
+        )
+        .arg(Arg::new("warn-self-assign")
+            .long("warn-self-assign")
+            .takes_value(false)
+            .help("Warn when a variable or field is assigned to itself")
+            .help_heading("input")
+        )
+        .arg(Arg::new("target")
+            .long("target")
+            .takes_value(true)
+            .value_name("triple")
+            .help("Set the target triple to compile for, defaulting to the host")
+            .help_heading("output")
         );
 
     let args = app.get_matches();
@@ -90,6 +134,10 @@ fn main() {
         out_file: PathBuf::from(args.value_of("output-file").unwrap()),
         out_type: match args.value_of("output-type") {
             Some(ty) => match ty {
+                "asm" => OutputFileType::Assembly,
+                "obj" => OutputFileType::Object,
+                "ll" => OutputFileType::LLVMIR,
+                "c" => OutputFileType::C,
                 _ => unreachable!(),
             },
             None => match Path::new(args.value_of("output-file").unwrap()).extension() {
@@ -97,6 +145,7 @@ fn main() {
                     Some("obj") | Some("o") => OutputFileType::Object,
                     Some("ll") => OutputFileType::LLVMIR,
                     Some("asm") | Some("s") => OutputFileType::Assembly,
+                    Some("c") => OutputFileType::C,
                     _ => {
                         eprintln!(
                             "Output file '{}' has an unknown extension\nUse -T[type] option to explicitly set output type",
@@ -123,7 +172,23 @@ fn main() {
         },
         pic: args.is_present("pic"),
         stripped: args.is_present("strip"),
+        trap: match args.value_of("trap").unwrap() {
+            "abort" => TrapBehavior::Abort,
+            "trap" => TrapBehavior::Trap,
+            "unreachable" => TrapBehavior::Unreachable,
+            _ => unreachable!(),
+        },
+        target: args.value_of("target").map(str::to_owned),
+        int_promotion: args.is_present("promote-ints"),
+        fast_math: args.is_present("fast-math"),
+        warn_self_assign: args.is_present("warn-self-assign"),
     };
+    let triple = opts.target.clone().unwrap_or_else(|| {
+        TargetMachine::get_default_triple()
+            .as_str()
+            .to_string_lossy()
+            .into_owned()
+    });
 
     let input = Path::new(args.value_of("input-path").unwrap());
     let mut files = Files::new();
@@ -133,6 +198,7 @@ fn main() {
         InputItem::File(f) => {
             let src = files.get(f).text.as_str();
             let mut parser = Parser::new(src);
+            parser.set_target(triple.as_str());
             let module = handle_parse_error(parser.parse(Symbol::from("root"), f), &files, f);
             drop(parser);
             drop(src);
@@ -162,6 +228,7 @@ fn main() {
                 .expect("main.sprk does not exist in root directory");
             let mut root = ParsedModule::new(Symbol::from("root"));
             let mut parser = Parser::new(files.get(main).text.as_str());
+            parser.set_target(triple.as_str());
             handle_parse_error(parser.parse_to(&mut root, main), &files, main);
 
             for item in items {
@@ -182,12 +249,29 @@ fn main() {
         }
     };
 
+    if args.is_present("print-ast") {
+        print_ast(&root_module);
+    }
+
     let mut ctx = SparkCtx::new();
     let mut lowerer = Lowerer::new(&mut ctx, &files);
 
     let root_id = lowerer.lower_module(&root_module).unwrap_or_else(|_| std::process::exit(-1));
+    if opts.out_type == OutputFileType::C {
+        let mut generator = CCodeGenerator::new(ctx, &files);
+        let source = generator.codegen_module(root_id).unwrap_or_else(|_| std::process::exit(-1));
+        if let Err(e) = std::fs::write(&opts.out_file, source) {
+            eprintln!("Failed to write output file '{}': {}", opts.out_file.display(), e);
+            std::process::exit(-1);
+        }
+        return;
+    }
     let mut llvm_ctx = Context::create();
-    let mut generator = LlvmCodeGenerator::new(ctx, &mut llvm_ctx, &files, opts.clone());
+    let mut generator = LlvmCodeGenerator::new(ctx, &mut llvm_ctx, &files, opts.clone())
+        .unwrap_or_else(|e| {
+            DiagnosticManager::new(&files).emit(e);
+            std::process::exit(-1);
+        });
     let llvm_root = generator.codegen_module(root_id).unwrap_or_else(|_| std::process::exit(-1));
     if let Err(e) = llvm_root.verify() {
         eprintln!("Failure to verify generated LLVM module: {}", e);
@@ -200,28 +284,32 @@ fn main() {
     //llvm_root.print_to_stderr();
 }
 
-fn handle_parse_error<T>(res: Result<T, ParseError>, files: &Files, file: FileId) -> T {
-    res.unwrap_or_else(|e| {
+fn handle_parse_error<T>(res: Result<T, Vec<ParseError>>, files: &Files, file: FileId) -> T {
+    res.unwrap_or_else(|errors| {
         let mut diags = DiagnosticManager::new(files);
-        let diag = Diagnostic::error()
-            .with_message(e.error.to_string())
-            .with_notes(
-                e.backtrace
-                    .iter()
-                    .map(|trace| format!("in {}", trace))
-                    .collect(),
-            );
-
-        diags.emit(if let Some(span) = e.highlighted_span {
-            diag.with_labels(vec![Label::primary(file, span)])
-        } else {
-            diag
-        });
+        for e in errors {
+            diags.emit(e.to_diagnostic(file));
+        }
 
         std::process::exit(-1);
     })
 }
 
+/// Print the syntax tree of all function bodies in a module and its children
+fn print_ast(module: &ParsedModule) {
+    for def in module.defs.values() {
+        if let DefData::FunDef(proto, body) = &def.data {
+            println!("{}:{}", module.name, proto.name);
+            for stmt in body {
+                print!("{}", stmt.tree(|ty| ty.to_string()));
+            }
+        }
+    }
+    for child in module.children.values() {
+        print_ast(child);
+    }
+}
+
 fn parse_dir<'src>(
     name: String,
     items: Vec<InputItem>,
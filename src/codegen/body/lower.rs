@@ -0,0 +1,838 @@
+//! Lowering of typed function bodies into the basic block IR
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hashbrown::HashSet;
+use quickscope::ScopeMap;
+
+use crate::{
+    arena::Arena,
+    ast::{
+        Ast, AstNode, ElseExpr, IfExpr, Literal, NumberLiteral, NumberLiteralAnnotation,
+    },
+    codegen::{
+        ir::{FunId, GlobalId, ModId, SparkCtx, SparkDef, TypeData, TypeId},
+        CompilerRes,
+    },
+    parse::token::Op,
+    util::{files::FileId, loc::Span},
+    Symbol,
+};
+
+use super::{
+    value::{IrConst, IrPlace, IrRvalue},
+    BBId, IrBB, IrBody, IrLocal, IrStmt, IrTerminator, LocalId,
+};
+
+/// A name that is only visible inside of a function body
+#[derive(Clone, Copy, Debug)]
+enum BodyDef {
+    Local(LocalId),
+    Fun(FunId),
+}
+
+/// Structure that builds the basic blocks of a single function's body
+pub struct BodyLowerer<'ctx> {
+    ctx: &'ctx mut SparkCtx,
+    module: ModId,
+    file: FileId,
+    return_ty: TypeId,
+    body: IrBody,
+    /// The block that statements are currently being appended to
+    bb: BBId,
+    /// If the current block has already been given a terminator
+    terminated: bool,
+    scope: ScopeMap<Symbol, BodyDef>,
+    /// Labels, continue targets, and break targets of all enclosing loops, innermost last
+    loops: Vec<(Option<Symbol>, BBId, BBId)>,
+}
+
+/// Lower the bodies of all functions defined in a module and its children, including nested
+/// functions
+pub fn lower_module_bodies(ctx: &mut SparkCtx, module: ModId) -> CompilerRes<Vec<IrBody>> {
+    let mut bodies = vec![];
+    let mut visited = HashSet::new();
+    lower_module_bodies_impl(ctx, module, &mut visited, &mut bodies)?;
+    Ok(bodies)
+}
+
+fn lower_module_bodies_impl(
+    ctx: &mut SparkCtx,
+    module: ModId,
+    visited: &mut HashSet<FunId>,
+    bodies: &mut Vec<IrBody>,
+) -> CompilerRes<()> {
+    let defs = ctx[module]
+        .ordered_defs()
+        .into_iter()
+        .map(|(_, def)| def)
+        .collect::<Vec<_>>();
+
+    for def in defs {
+        match def {
+            SparkDef::FunDef(file, fun) => {
+                lower_fun_bodies(ctx, module, file, fun, visited, bodies)?
+            }
+            SparkDef::ModDef(submod) if submod != module => {
+                lower_module_bodies_impl(ctx, submod, visited, bodies)?
+            }
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+fn lower_fun_bodies(
+    ctx: &mut SparkCtx,
+    module: ModId,
+    file: FileId,
+    fun: FunId,
+    visited: &mut HashSet<FunId>,
+    bodies: &mut Vec<IrBody>,
+) -> CompilerRes<()> {
+    if !visited.insert(fun) || ctx[fun].body.is_none() {
+        return Ok(());
+    }
+
+    bodies.push(lower_body(ctx, module, file, fun)?);
+    for nested in ctx[fun].nested.clone() {
+        lower_fun_bodies(ctx, module, file, nested, visited, bodies)?;
+    }
+    Ok(())
+}
+
+/// Lower the body of a single function defined in the given module and file
+pub fn lower_body(
+    ctx: &mut SparkCtx,
+    module: ModId,
+    file: FileId,
+    fun: FunId,
+) -> CompilerRes<IrBody> {
+    let mut lowerer = BodyLowerer::new(ctx, module, file, fun);
+    let stmts = lowerer.ctx[fun].body.clone().unwrap_or_default();
+    lowerer.lower_stmts(&stmts)?;
+    Ok(lowerer.finish())
+}
+
+impl<'ctx> BodyLowerer<'ctx> {
+    fn new(ctx: &'ctx mut SparkCtx, module: ModId, file: FileId, fun: FunId) -> Self {
+        let mut bbs = Arena::new();
+        let entry = bbs.insert_with(|id| IrBB {
+            id,
+            stmts: vec![],
+            terminator: IrTerminator::Unreachable,
+        });
+
+        let mut locals = Arena::new();
+        let mut scope = ScopeMap::new();
+        let f = &ctx[fun];
+        //Nested functions can't see the enclosing function's scope, but can call themselves
+        scope.define(f.name, BodyDef::Fun(fun));
+        let args = f
+            .arg_names
+            .iter()
+            .zip(f.ty.args.iter())
+            .map(|(name, ty)| {
+                name.map(|name| {
+                    let local = locals.insert_with(|id| IrLocal {
+                        id,
+                        name: Some(name),
+                        ty: *ty,
+                    });
+                    scope.define(name, BodyDef::Local(local));
+                    local
+                })
+            })
+            .collect();
+        let return_ty = f.ty.return_ty;
+
+        Self {
+            ctx,
+            module,
+            file,
+            return_ty,
+            body: IrBody {
+                fun,
+                args,
+                locals,
+                bbs,
+                entry,
+            },
+            bb: entry,
+            terminated: false,
+            scope,
+            loops: vec![],
+        }
+    }
+
+    /// Terminate the final block and return the finished body
+    fn finish(mut self) -> IrBody {
+        if self.return_ty == SparkCtx::UNIT {
+            self.terminate(IrTerminator::Return(IrRvalue::Const(IrConst::Unit)));
+        } else {
+            self.terminate(IrTerminator::Unreachable);
+        }
+        self.body
+    }
+
+    /// Create a new empty basic block
+    fn new_bb(&mut self) -> BBId {
+        self.body.bbs.insert_with(|id| IrBB {
+            id,
+            stmts: vec![],
+            terminator: IrTerminator::Unreachable,
+        })
+    }
+
+    /// Continue appending statements to the given block
+    fn switch_to(&mut self, bb: BBId) {
+        self.bb = bb;
+        self.terminated = false;
+    }
+
+    /// Set the terminator of the current block if it has not already been terminated
+    fn terminate(&mut self, terminator: IrTerminator) {
+        if !self.terminated {
+            self.body.bbs[self.bb].terminator = terminator;
+            self.terminated = true;
+        }
+    }
+
+    /// Terminate the current block and continue in a new block that has no predecessors, used
+    /// after statements that never fall through like `return` and `break`
+    fn terminate_and_continue(&mut self, terminator: IrTerminator) {
+        self.terminate(terminator);
+        let dead = self.new_bb();
+        self.switch_to(dead);
+    }
+
+    fn push_stmt(&mut self, stmt: IrStmt) {
+        self.body.bbs[self.bb].stmts.push(stmt);
+    }
+
+    fn new_local(&mut self, name: Option<Symbol>, ty: TypeId) -> LocalId {
+        self.body
+            .locals
+            .insert_with(|id| IrLocal { id, name, ty })
+    }
+
+    fn unsupported(&self, span: Span, what: &str) -> Diagnostic<FileId> {
+        Diagnostic::error()
+            .with_message(format!("{} is not supported by the basic block IR", what))
+            .with_labels(vec![Label::primary(self.file, span)])
+    }
+
+    fn type_mismatch(&self, span: Span, expected: TypeId, found: TypeId) -> Diagnostic<FileId> {
+        Diagnostic::error()
+            .with_message(format!(
+                "Value of type '{}' cannot be used as a value of type '{}'",
+                self.ctx.get_type_name(found),
+                self.ctx.get_type_name(expected),
+            ))
+            .with_labels(vec![Label::primary(self.file, span)])
+    }
+
+    fn lower_stmts(&mut self, stmts: &[Ast<TypeId>]) -> CompilerRes<()> {
+        for stmt in stmts {
+            self.lower_stmt(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Lower a list of statements in a new scope
+    fn lower_scoped(&mut self, stmts: &[Ast<TypeId>]) -> CompilerRes<()> {
+        self.scope.push_layer();
+        let res = self.lower_stmts(stmts);
+        self.scope.pop_layer();
+        res
+    }
+
+    fn lower_stmt(&mut self, ast: &Ast<TypeId>) -> CompilerRes<()> {
+        match &ast.node {
+            AstNode::Assignment { lhs, rhs } => match &lhs.node {
+                AstNode::VarDeclaration { name, ty, .. } => {
+                    let value = self.lower_expected(rhs, *ty)?;
+                    let ty = ty.unwrap_or(value.ty());
+                    if ty != value.ty() {
+                        return Err(self.type_mismatch(rhs.span, ty, value.ty()));
+                    }
+                    let local = self.new_local(Some(*name), ty);
+                    self.push_stmt(IrStmt::Store {
+                        place: IrPlace::Local(local),
+                        value,
+                    });
+                    self.scope.define(*name, BodyDef::Local(local));
+                }
+                AstNode::Destructure { .. } => {
+                    return Err(self.unsupported(lhs.span, "Destructuring assignment"))
+                }
+                _ => {
+                    let (place, ty) = self.lower_place(lhs)?;
+                    let value = self.lower_expected(rhs, Some(ty))?;
+                    if ty != value.ty() {
+                        return Err(self.type_mismatch(rhs.span, ty, value.ty()));
+                    }
+                    self.push_stmt(IrStmt::Store { place, value });
+                }
+            },
+            AstNode::VarDeclaration { name, ty, .. } => match ty {
+                Some(ty) => {
+                    let local = self.new_local(Some(*name), *ty);
+                    self.scope.define(*name, BodyDef::Local(local));
+                }
+                None => {
+                    return Err(Diagnostic::error()
+                        .with_message("Must provide type of variable or assign a value")
+                        .with_labels(vec![Label::primary(self.file, ast.span)
+                            .with_message("In this variable declaration")]))
+                }
+            },
+            AstNode::Return(returned) => {
+                let value = self.lower_expected(returned, Some(self.return_ty))?;
+                if value.ty() != self.return_ty {
+                    return Err(self.type_mismatch(returned.span, self.return_ty, value.ty()));
+                }
+                self.terminate_and_continue(IrTerminator::Return(value));
+            }
+            AstNode::IfExpr(if_expr) => self.lower_if(if_expr)?,
+            AstNode::Block(label, stmts) => {
+                let start = self.new_bb();
+                let after = self.new_bb();
+                self.terminate(IrTerminator::Jump(start));
+                self.switch_to(start);
+
+                self.loops.push((*label, start, after));
+                let res = self.lower_scoped(stmts);
+                self.loops.pop();
+                res?;
+
+                self.terminate(IrTerminator::Jump(after));
+                self.switch_to(after);
+            }
+            AstNode::While { label, cond, body } => {
+                let cond_bb = self.new_bb();
+                let body_bb = self.new_bb();
+                let after = self.new_bb();
+                self.terminate(IrTerminator::Jump(cond_bb));
+
+                //The condition is lowered in the enclosing scope, before the body's layer
+                self.switch_to(cond_bb);
+                let cond_val = self.lower_rvalue(cond)?;
+                if cond_val.ty() != SparkCtx::BOOL {
+                    return Err(self.type_mismatch(cond.span, SparkCtx::BOOL, cond_val.ty())
+                        .with_notes(self.ctx.non_bool_cond_notes(cond_val.ty())));
+                }
+                self.terminate(IrTerminator::Branch {
+                    cond: cond_val,
+                    if_true: body_bb,
+                    if_false: after,
+                });
+
+                self.switch_to(body_bb);
+                self.loops.push((*label, cond_bb, after));
+                let res = self.lower_scoped(body);
+                self.loops.pop();
+                res?;
+
+                self.terminate(IrTerminator::Jump(cond_bb));
+                self.switch_to(after);
+            }
+            AstNode::Break(_, Some(_)) => {
+                return Err(self.unsupported(ast.span, "Break with a value"))
+            }
+            AstNode::Break(label, None) | AstNode::Continue(label) => {
+                let (_, continue_bb, break_bb) = *self
+                    .loops
+                    .iter()
+                    .rev()
+                    .find(|(loop_label, ..)| label.is_none() || loop_label == label)
+                    .ok_or_else(|| match label {
+                        Some(label) => Diagnostic::error()
+                            .with_message(format!("Unknown loop label '{}", label))
+                            .with_labels(vec![Label::primary(self.file, ast.span)]),
+                        None => Diagnostic::error()
+                            .with_message(
+                                "Loop control statement encountered while not in a block",
+                            )
+                            .with_labels(vec![Label::primary(self.file, ast.span)]),
+                    })?;
+                let target = match ast.node {
+                    AstNode::Break(..) => break_bb,
+                    _ => continue_bb,
+                };
+                self.terminate_and_continue(IrTerminator::Jump(target));
+            }
+            AstNode::FunDef(proto, _) => {
+                let enclosing = self.body.fun;
+                let nested = self.ctx[enclosing]
+                    .nested
+                    .iter()
+                    .copied()
+                    .find(|nested| {
+                        self.ctx[*nested].name == proto.name && self.ctx[*nested].span == ast.span
+                    })
+                    .ok_or_else(|| {
+                        Diagnostic::error()
+                            .with_message(format!(
+                                "Nested function '{}' was not lowered",
+                                proto.name
+                            ))
+                            .with_labels(vec![Label::primary(self.file, ast.span)
+                                .with_message("Nested function defined here")])
+                    })?;
+                self.scope.define(proto.name, BodyDef::Fun(nested));
+            }
+            AstNode::TypeDef(..) => (),
+            AstNode::PhiExpr(_) => return Err(self.unsupported(ast.span, "Phi statement")),
+            AstNode::Match { .. } => return Err(self.unsupported(ast.span, "Match statement")),
+            AstNode::Destructure { .. } => {
+                return Err(self.unsupported(ast.span, "Destructuring declaration"))
+            }
+            _ => {
+                let value = self.lower_rvalue(ast)?;
+                self.push_stmt(IrStmt::Eval(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn lower_if(&mut self, if_expr: &IfExpr<TypeId>) -> CompilerRes<()> {
+        let cond = self.lower_rvalue(&if_expr.cond)?;
+        if cond.ty() != SparkCtx::BOOL {
+            return Err(self.type_mismatch(if_expr.cond.span, SparkCtx::BOOL, cond.ty())
+                .with_notes(self.ctx.non_bool_cond_notes(cond.ty())));
+        }
+
+        let if_true = self.new_bb();
+        let if_false = self.new_bb();
+        let after = match if_expr.else_expr {
+            Some(_) => self.new_bb(),
+            None => if_false,
+        };
+        self.terminate(IrTerminator::Branch {
+            cond,
+            if_true,
+            if_false,
+        });
+
+        self.switch_to(if_true);
+        self.lower_scoped(&if_expr.body)?;
+        self.terminate(IrTerminator::Jump(after));
+
+        if let Some(else_expr) = &if_expr.else_expr {
+            self.switch_to(if_false);
+            match else_expr {
+                ElseExpr::ElseIf(else_if) => self.lower_if(else_if)?,
+                ElseExpr::Else(body) => self.lower_scoped(body)?,
+            }
+            self.terminate(IrTerminator::Jump(after));
+        }
+
+        self.switch_to(after);
+        Ok(())
+    }
+
+    /// Lower an expression that refers to a location in memory, returning the place and the
+    /// type of the value stored there
+    fn lower_place(&mut self, ast: &Ast<TypeId>) -> CompilerRes<(IrPlace, TypeId)> {
+        match &ast.node {
+            AstNode::Access(path) => {
+                if path.len() == 1 {
+                    if let Some(BodyDef::Local(local)) = self.scope.get(&path.last()) {
+                        return Ok((IrPlace::Local(*local), self.body.locals[*local].ty));
+                    }
+                }
+
+                match self.ctx.get_def(self.module, path) {
+                    Ok(SparkDef::GlobalDef(file, global)) => {
+                        Ok((IrPlace::Global(global), self.global_ty(file, global)?))
+                    }
+                    _ => {
+                        let value = self.lower_rvalue(ast)?;
+                        Ok(self.spill(value))
+                    }
+                }
+            }
+            AstNode::MemberAccess(object, name) => {
+                let (object, object_ty) = self.lower_place(object)?;
+                let struct_ty = self.ctx.unwrap_alias(object_ty);
+                let field = match &self.ctx[struct_ty] {
+                    TypeData::Struct { fields, .. } | TypeData::Union { fields } => fields
+                        .iter()
+                        .enumerate()
+                        .find(|(_, (_, field_name))| field_name == name)
+                        .map(|(idx, (ty, _))| (idx, *ty)),
+                    _ => None,
+                };
+
+                match field {
+                    Some((idx, ty)) => Ok((IrPlace::Field(Box::new(object), idx), ty)),
+                    None => Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Attempting to access field '{}' of type '{}' but no such field exists",
+                            name,
+                            self.ctx.get_type_name(object_ty),
+                        ))
+                        .with_labels(vec![Label::primary(self.file, ast.span)])),
+                }
+            }
+            AstNode::Index { object, index } => {
+                let (object, object_ty) = self.lower_place(object)?;
+                let element = match self.ctx[self.ctx.unwrap_alias(object_ty)] {
+                    TypeData::Array { element, .. } => element,
+                    _ => {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Attempting to index into a value of type '{}'",
+                                self.ctx.get_type_name(object_ty)
+                            ))
+                            .with_labels(vec![Label::primary(self.file, ast.span)]))
+                    }
+                };
+                let index = self.lower_rvalue(index)?;
+                Ok((
+                    IrPlace::Index(Box::new(object), Box::new(index)),
+                    element,
+                ))
+            }
+            AstNode::UnaryExpr(Op::Star, pointer) => {
+                let pointer = self.lower_rvalue(pointer)?;
+                match self.ctx[self.ctx.unwrap_alias(pointer.ty())] {
+                    TypeData::Pointer(pointee) => {
+                        Ok((IrPlace::Deref(Box::new(pointer)), pointee))
+                    }
+                    _ => Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Attempting to dereference expression of non-pointer type '{}'",
+                            self.ctx.get_type_name(pointer.ty())
+                        ))
+                        .with_labels(vec![Label::primary(self.file, ast.span)])),
+                }
+            }
+            _ => {
+                let value = self.lower_rvalue(ast)?;
+                Ok(self.spill(value))
+            }
+        }
+    }
+
+    /// Store a value that is not located in memory to a temporary local
+    fn spill(&mut self, value: IrRvalue) -> (IrPlace, TypeId) {
+        let ty = value.ty();
+        let local = self.new_local(None, ty);
+        self.push_stmt(IrStmt::Store {
+            place: IrPlace::Local(local),
+            value,
+        });
+        (IrPlace::Local(local), ty)
+    }
+
+    /// Get the type of a global, lowering its initializer if the type was not given
+    fn global_ty(&mut self, file: FileId, global: GlobalId) -> CompilerRes<TypeId> {
+        if let Some(ty) = self.ctx[global].ty {
+            return Ok(ty);
+        }
+
+        //External globals always declare their type, so only initialized globals get here
+        let init = self.ctx[global].init.clone().unwrap();
+        let enclosing = std::mem::replace(&mut self.file, file);
+        let value = self.lower_rvalue(&init);
+        self.file = enclosing;
+        Ok(value?.ty())
+    }
+
+    fn lower_rvalue(&mut self, ast: &Ast<TypeId>) -> CompilerRes<IrRvalue> {
+        Ok(match &ast.node {
+            AstNode::Literal(Literal::Number(num)) => {
+                let ty = match num.annotation() {
+                    Some(ann) => annotation_ty(ann),
+                    None => match num {
+                        NumberLiteral::Float(..) => SparkCtx::F64,
+                        NumberLiteral::Integer(..) => SparkCtx::I32,
+                    },
+                };
+                let is_float = matches!(self.ctx[ty], TypeData::Float { .. });
+                IrRvalue::Const(match (num, is_float) {
+                    (NumberLiteral::Integer(int, _), false) => IrConst::Int {
+                        val: match int.sign {
+                            true => (int.val as i64).wrapping_neg() as u64,
+                            false => int.val,
+                        },
+                        ty,
+                    },
+                    (NumberLiteral::Integer(int, _), true) => IrConst::Float {
+                        val: match int.sign {
+                            true => -(int.val as f64),
+                            false => int.val as f64,
+                        },
+                        ty,
+                    },
+                    (NumberLiteral::Float(val, _), true) => IrConst::Float { val: *val, ty },
+                    (NumberLiteral::Float(val, _), false) => IrConst::Int {
+                        val: *val as i64 as u64,
+                        ty,
+                    },
+                })
+            }
+            AstNode::Literal(Literal::Bool(val)) => IrRvalue::Const(IrConst::Bool(*val)),
+            AstNode::Literal(Literal::Unit) => IrRvalue::Const(IrConst::Unit),
+            AstNode::Literal(Literal::String(val)) => IrRvalue::Const(IrConst::String {
+                val: val.clone(),
+                ty: self.ctx.new_type(TypeData::Pointer(SparkCtx::U8)),
+            }),
+            AstNode::Literal(_) => return Err(self.unsupported(ast.span, "Aggregate literal")),
+            AstNode::Access(path) => {
+                if path.len() == 1 {
+                    match self.scope.get(&path.last()).copied() {
+                        Some(BodyDef::Local(local)) => {
+                            return Ok(IrRvalue::Load(
+                                IrPlace::Local(local),
+                                self.body.locals[local].ty,
+                            ))
+                        }
+                        Some(BodyDef::Fun(fun)) => return Ok(self.fun_value(fun)),
+                        None => (),
+                    }
+                }
+
+                match self.ctx.get_def(self.module, path) {
+                    Ok(SparkDef::FunDef(_, fun)) => self.fun_value(fun),
+                    Ok(SparkDef::GlobalDef(file, global)) => {
+                        let ty = self.global_ty(file, global)?;
+                        IrRvalue::Load(IrPlace::Global(global), ty)
+                    }
+                    Ok(def) => {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Definition '{}' cannot be used as a value",
+                                self.ctx.get_def_name(def)
+                            ))
+                            .with_labels(vec![Label::primary(self.file, ast.span)]))
+                    }
+                    Err(e) => {
+                        return Err(Diagnostic::error()
+                            .with_message(format!("Symbol {}", e))
+                            .with_labels(vec![Label::primary(self.file, ast.span)]))
+                    }
+                }
+            }
+            AstNode::MemberAccess(..) | AstNode::Index { .. } => {
+                let (place, ty) = self.lower_place(ast)?;
+                IrRvalue::Load(place, ty)
+            }
+            AstNode::UnaryExpr(Op::Star, _) => {
+                let (place, ty) = self.lower_place(ast)?;
+                IrRvalue::Load(place, ty)
+            }
+            AstNode::UnaryExpr(Op::AND, addressed) => {
+                if let AstNode::FunCall(..) = addressed.node {
+                    return Err(Diagnostic::error()
+                        .with_message("Cannot take the address of a function's return value")
+                        .with_labels(vec![Label::primary(self.file, addressed.span)
+                            .with_message("The value returned by this call is a temporary that is dropped after the expression")])
+                        .with_notes(vec![
+                            "Assign the returned value to a variable and take the address of the variable instead".to_owned(),
+                        ]));
+                }
+                let (place, ty) = self.lower_place(addressed)?;
+                IrRvalue::AddressOf(place, self.ctx.new_type(TypeData::Pointer(ty)))
+            }
+            AstNode::UnaryExpr(op, _) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!("Unsupported unary operator '{}' used", op))
+                    .with_labels(vec![Label::primary(self.file, ast.span)]))
+            }
+            AstNode::BinExpr(lhs, op, _, rhs) => {
+                //An unannotated literal operand takes the type of the other operand
+                let (lhs, rhs) = if Self::is_unannotated_literal(lhs) {
+                    let rhs = self.lower_rvalue(rhs)?;
+                    (self.lower_expected(lhs, Some(rhs.ty()))?, rhs)
+                } else {
+                    let lhs = self.lower_rvalue(lhs)?;
+                    let rhs = self.lower_expected(rhs, Some(lhs.ty()))?;
+                    (lhs, rhs)
+                };
+                let ty = match op {
+                    Op::Greater
+                    | Op::GreaterEq
+                    | Op::Less
+                    | Op::LessEq
+                    | Op::Eq
+                    | Op::NotEq
+                    | Op::LogicalAnd
+                    | Op::LogicalOr => SparkCtx::BOOL,
+                    _ => lhs.ty(),
+                };
+                IrRvalue::Binary {
+                    op: *op,
+                    lhs: Box::new(lhs),
+                    rhs: Box::new(rhs),
+                    ty,
+                }
+            }
+            AstNode::CastExpr(to, val) => IrRvalue::Cast {
+                val: Box::new(self.lower_rvalue(val)?),
+                to: *to,
+            },
+            AstNode::FunCall(called, args) => {
+                let called_val = self.lower_rvalue(called)?;
+                let called_ty = self.ctx.unwrap_alias(called_val.ty());
+                let fun_ty = match &self.ctx[called_ty] {
+                    TypeData::Function(fun_ty) => fun_ty.clone(),
+                    _ => {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Attempting to call a value of type '{}' as a function",
+                                self.ctx.get_type_name(called_ty)
+                            ))
+                            .with_labels(vec![Label::primary(self.file, called.span)]))
+                    }
+                };
+
+                if fun_ty.args.len() != args.len() {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Function takes {} arguments but {} were passed",
+                            fun_ty.args.len(),
+                            args.len(),
+                        ))
+                        .with_labels(vec![Label::primary(self.file, ast.span)]));
+                }
+
+                let args = args
+                    .iter()
+                    .zip(fun_ty.args.iter())
+                    .map(|(arg, expected)| {
+                        let val = self.lower_expected(arg, Some(*expected))?;
+                        if val.ty() != *expected {
+                            return Err(self.type_mismatch(arg.span, *expected, val.ty()));
+                        }
+                        Ok(val)
+                    })
+                    .collect::<CompilerRes<Vec<_>>>()?;
+
+                IrRvalue::Call {
+                    called: Box::new(called_val),
+                    args,
+                    ty: fun_ty.return_ty,
+                }
+            }
+            AstNode::IfExpr(_) | AstNode::Block(..) | AstNode::Match { .. } => {
+                return Err(self.unsupported(ast.span, "Value-producing block"))
+            }
+            _ => {
+                return Err(Diagnostic::error()
+                    .with_message("Statement used where a value was expected")
+                    .with_labels(vec![Label::primary(self.file, ast.span)]))
+            }
+        })
+    }
+
+    /// Lower an expression used where a value of the given type is expected, giving
+    /// unannotated number literals that type if it can hold their value
+    fn lower_expected(&mut self, ast: &Ast<TypeId>, expected: Option<TypeId>) -> CompilerRes<IrRvalue> {
+        match expected.and_then(|expected| self.ctx.coerce_literal(ast, expected)) {
+            Some(coerced) => self.lower_rvalue(&coerced),
+            None => self.lower_rvalue(ast),
+        }
+    }
+
+    /// Check if an expression is a number literal without a type annotation, or the negation
+    /// of one
+    fn is_unannotated_literal(ast: &Ast<TypeId>) -> bool {
+        match &ast.node {
+            AstNode::Literal(Literal::Number(
+                NumberLiteral::Integer(_, None) | NumberLiteral::Float(_, None),
+            )) => true,
+            AstNode::UnaryExpr(Op::Sub, negated) => Self::is_unannotated_literal(negated),
+            _ => false,
+        }
+    }
+
+    fn fun_value(&mut self, fun: FunId) -> IrRvalue {
+        let ty = self.ctx.new_type(TypeData::Function(self.ctx[fun].ty.clone()));
+        IrRvalue::Fun(fun, ty)
+    }
+}
+
+/// Get the type of a number literal with a type annotation
+pub(crate) const fn annotation_ty(ann: NumberLiteralAnnotation) -> TypeId {
+    match ann {
+        NumberLiteralAnnotation::I8 => SparkCtx::I8,
+        NumberLiteralAnnotation::I16 => SparkCtx::I16,
+        NumberLiteralAnnotation::I32 => SparkCtx::I32,
+        NumberLiteralAnnotation::I64 => SparkCtx::I64,
+        NumberLiteralAnnotation::U8 => SparkCtx::U8,
+        NumberLiteralAnnotation::U16 => SparkCtx::U16,
+        NumberLiteralAnnotation::U32 => SparkCtx::U32,
+        NumberLiteralAnnotation::U64 => SparkCtx::U64,
+        NumberLiteralAnnotation::F32 => SparkCtx::F32,
+        NumberLiteralAnnotation::F64 => SparkCtx::F64,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        codegen::lower::Lowerer,
+        parse::Parser,
+        util::files::{CompiledFile, Files},
+    };
+
+    use super::*;
+
+    /// Lower the bodies of all functions in a program contained in a single file
+    fn lower_bodies(src: &str) -> CompilerRes<Vec<IrBody>> {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files).lower_module(&module)?;
+        lower_module_bodies(&mut ctx, root)
+    }
+
+    #[test]
+    fn simple_function_returns_from_entry() {
+        let bodies = lower_bodies("fun ext add(i32 a, i32 b) -> i32 {\n    return a + b\n}\n")
+            .unwrap_or_else(|_| panic!("Failed to lower function body"));
+        assert_eq!(bodies.len(), 1);
+        let entry = &bodies[0].bbs[bodies[0].entry];
+        assert!(entry.stmts.is_empty());
+        assert!(matches!(
+            entry.terminator,
+            IrTerminator::Return(IrRvalue::Binary { op: Op::Add, .. })
+        ));
+    }
+
+    #[test]
+    fn unannotated_literals_take_the_expected_type() {
+        let src = "fun ext widen(i64 a) -> i64 {\n    let (i64) b = 1\n    mut c = a\n    c = 2\n    return 3 + c * b\n}\n";
+        assert!(lower_bodies(src).is_ok());
+        assert!(lower_bodies("fun ext narrow() -> u8 {\n    let (u8) b = 300\n    return b\n}\n").is_err());
+    }
+
+    #[test]
+    fn while_body_variables_are_scoped_to_the_loop() {
+        let body = "    mut i = 0\n    mut sum = 0\n    while i < n {\n        let square = i * i\n        sum = sum + square\n        i = i + 1\n    }\n";
+        let scoped = format!("fun ext sum_below(i32 n) -> i32 {{\n{}    return sum\n}}\n", body);
+        assert!(lower_bodies(&scoped).is_ok());
+        let leaked = format!("fun ext sum_below(i32 n) -> i32 {{\n{}    return square\n}}\n", body);
+        assert!(lower_bodies(&leaked).is_err());
+    }
+
+    #[test]
+    fn boolean_literals_are_conditions() {
+        let src = "fun ext pick() -> i32 {\n    if true {\n        return 1\n    }\n    return 0\n}\n";
+        assert!(lower_bodies(src).is_ok());
+        let src = "fun ext pick() -> i32 {\n    if 1 {\n        return 1\n    }\n    return 0\n}\n";
+        assert!(lower_bodies(src).is_err());
+    }
+
+    #[test]
+    fn nested_functions_are_lowered_after_their_enclosing_function() {
+        let src = "fun ext sum_squares(i32 a, i32 b) -> i32 {\n    fun square(i32 n) -> i32 {\n        return n * n\n    }\n    return square.(a) + square.(b)\n}\n";
+        let bodies = lower_bodies(src).unwrap_or_else(|_| panic!("Failed to lower function bodies"));
+        assert_eq!(bodies.len(), 2);
+    }
+}
@@ -0,0 +1,80 @@
+//! A basic block representation of function bodies that does not depend on LLVM, lowered from
+//! a typed [Ast](crate::ast::Ast) so that backends other than LLVM can generate code for
+//! spark programs
+
+use crate::{
+    arena::{Arena, Index},
+    Symbol,
+};
+
+use self::value::IrRvalue;
+
+use super::ir::{FunId, TypeId};
+
+pub mod lower;
+pub mod value;
+
+pub type LocalId = Index<IrLocal>;
+pub type BBId = Index<IrBB>;
+
+/// A stack slot in a function body that values can be stored to and loaded from
+#[derive(Clone, Debug)]
+pub struct IrLocal {
+    pub id: LocalId,
+    /// Name of the variable this local was declared for, or `None` for temporaries
+    pub name: Option<Symbol>,
+    pub ty: TypeId,
+}
+
+/// A single basic block, a list of statements that are always executed in order and end with
+/// one terminator
+#[derive(Clone, Debug)]
+pub struct IrBB {
+    pub id: BBId,
+    pub stmts: Vec<IrStmt>,
+    pub terminator: IrTerminator,
+}
+
+/// A statement in a basic block that does not change control flow
+#[derive(Clone, Debug)]
+pub enum IrStmt {
+    /// Store a value to a place in memory
+    Store {
+        place: value::IrPlace,
+        value: IrRvalue,
+    },
+    /// Evaluate a value only for its side effects, like a call to a function returning unit
+    Eval(IrRvalue),
+}
+
+/// The final instruction of a basic block, transferring control to another block or out of
+/// the function
+#[derive(Clone, Debug)]
+pub enum IrTerminator {
+    /// Return a value from the function
+    Return(IrRvalue),
+    /// Unconditionally continue execution in another block
+    Jump(BBId),
+    /// Continue execution in one of two blocks depending on a boolean condition
+    Branch {
+        cond: IrRvalue,
+        if_true: BBId,
+        if_false: BBId,
+    },
+    /// Control never reaches the end of this block
+    Unreachable,
+}
+
+/// The lowered body of a single function
+#[derive(Clone, Debug)]
+pub struct IrBody {
+    /// The function that this is the body of
+    pub fun: FunId,
+    /// Locals holding each named argument of the function, in the same order as the arguments
+    /// of the function's type
+    pub args: Vec<Option<LocalId>>,
+    pub locals: Arena<IrLocal>,
+    pub bbs: Arena<IrBB>,
+    /// The block that execution starts in when the function is called
+    pub entry: BBId,
+}
@@ -0,0 +1,80 @@
+//! Values and memory locations that are used by statements and terminators in the basic block IR
+
+use crate::{
+    codegen::ir::{FunId, GlobalId, SparkCtx, TypeId},
+    parse::token::Op,
+};
+
+use super::LocalId;
+
+/// A location in memory that can be loaded from, stored to, or have its address taken
+#[derive(Clone, Debug)]
+pub enum IrPlace {
+    /// A local variable of the current function
+    Local(LocalId),
+    /// A global variable
+    Global(GlobalId),
+    /// The memory that a pointer value points to
+    Deref(Box<IrRvalue>),
+    /// A field of a structure, by the field's position in the structure's type
+    Field(Box<IrPlace>, usize),
+    /// An element of an array
+    Index(Box<IrPlace>, Box<IrRvalue>),
+}
+
+/// A constant value that is known when the IR is created
+#[derive(Clone, Debug)]
+pub enum IrConst {
+    Int { val: u64, ty: TypeId },
+    Float { val: f64, ty: TypeId },
+    Bool(bool),
+    /// A pointer to a null-terminated string, `ty` is the `*u8` type
+    String { val: String, ty: TypeId },
+    Unit,
+}
+
+/// A value produced by evaluating an expression, with no side effects other than calls
+#[derive(Clone, Debug)]
+pub enum IrRvalue {
+    Const(IrConst),
+    /// The value stored at a place
+    Load(IrPlace, TypeId),
+    /// A pointer to a place
+    AddressOf(IrPlace, TypeId),
+    /// A function used as a value, either to call it or as a function pointer
+    Fun(FunId, TypeId),
+    /// A binary operation, `ty` is the type of the result
+    Binary {
+        op: Op,
+        lhs: Box<IrRvalue>,
+        rhs: Box<IrRvalue>,
+        ty: TypeId,
+    },
+    /// A call to a function value, `ty` is the return type of the called function
+    Call {
+        called: Box<IrRvalue>,
+        args: Vec<IrRvalue>,
+        ty: TypeId,
+    },
+    /// Converting a value to another type
+    Cast { val: Box<IrRvalue>, to: TypeId },
+}
+
+impl IrRvalue {
+    /// Get the type of this value
+    pub fn ty(&self) -> TypeId {
+        match self {
+            Self::Const(
+                IrConst::Int { ty, .. } | IrConst::Float { ty, .. } | IrConst::String { ty, .. },
+            ) => *ty,
+            Self::Const(IrConst::Bool(_)) => SparkCtx::BOOL,
+            Self::Const(IrConst::Unit) => SparkCtx::UNIT,
+            Self::Load(_, ty)
+            | Self::AddressOf(_, ty)
+            | Self::Fun(_, ty)
+            | Self::Binary { ty, .. }
+            | Self::Call { ty, .. } => *ty,
+            Self::Cast { to, .. } => *to,
+        }
+    }
+}
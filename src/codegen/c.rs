@@ -0,0 +1,786 @@
+//! A code generator that emits portable C source from the basic block IR, for platforms that
+//! LLVM does not support
+
+use std::fmt::Write;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hashbrown::HashMap;
+
+use crate::{
+    ast::{Ast, AstNode, FunFlags, IntegerWidth, Literal, NumberLiteral},
+    error::DiagnosticManager,
+    parse::token::Op,
+    util::files::{FileId, Files},
+};
+
+use super::{
+    body::{
+        lower::{annotation_ty, lower_module_bodies},
+        value::{IrConst, IrPlace, IrRvalue},
+        IrBody, IrStmt, IrTerminator,
+    },
+    ir::{CallConv, DefOptimization, FunId, GlobalFlags, GlobalId, ModId, SparkCtx, SparkDef, TypeData, TypeId},
+    CompilerRes,
+};
+
+/// Structure that generates a single C source file from all modules of a program
+pub struct CCodeGenerator<'files> {
+    spark: SparkCtx,
+    pub diags: DiagnosticManager<'files>,
+    /// Forward declarations and definitions of all types used in the program
+    typedefs: String,
+    type_names: HashMap<TypeId, String>,
+    /// Names and defining files of all functions, in the order they are declared
+    funs: Vec<(FunId, FileId)>,
+    fun_names: HashMap<FunId, String>,
+    global_names: HashMap<GlobalId, String>,
+    /// Globals whose initializers are currently being evaluated, used to detect constants
+    /// that depend on their own value
+    evaluating_consts: Vec<GlobalId>,
+}
+
+impl<'files> CCodeGenerator<'files> {
+    /// Create a new code generator from a type-lowered context
+    pub fn new(spark: SparkCtx, files: &'files Files) -> Self {
+        Self {
+            spark,
+            diags: DiagnosticManager::new(files),
+            typedefs: String::new(),
+            type_names: HashMap::new(),
+            funs: vec![],
+            fun_names: HashMap::new(),
+            global_names: HashMap::new(),
+            evaluating_consts: vec![],
+        }
+    }
+
+    /// Generate C source for a module and all of its children
+    pub fn codegen_module(&mut self, module: ModId) -> CompilerRes<String> {
+        self.codegen_module_impl(module).map_err(|e| {
+            self.diags.emit(e.clone());
+            e
+        })
+    }
+
+    fn codegen_module_impl(&mut self, module: ModId) -> CompilerRes<String> {
+        let bodies = lower_module_bodies(&mut self.spark, module)?;
+
+        let mut funs = vec![];
+        let mut globals = vec![];
+        self.collect_defs(module, &mut funs, &mut globals);
+
+        for (file, fun) in funs {
+            self.name_fun(file, fun, None);
+        }
+        let mut decls = String::new();
+        for fun in self.funs.iter().map(|(fun, _)| *fun).collect::<Vec<_>>() {
+            let proto = self.fun_proto(fun)?;
+            writeln!(decls, "{};", proto).unwrap();
+        }
+
+        let mut global_defs = String::new();
+        for (module, file, global) in globals {
+            self.gen_global(module, file, global, &mut global_defs)?;
+        }
+
+        let mut defs = String::new();
+        for body in bodies.iter() {
+            self.gen_body(body, &mut defs)?;
+        }
+
+        Ok(format!(
+            "#include <stdint.h>\n#include <stdbool.h>\n\nvoid abort(void);\n\n{}\n{}\n{}\n{}",
+            self.typedefs, decls, global_defs, defs
+        ))
+    }
+
+    /// Collect all functions and globals defined in a module and its children
+    fn collect_defs(
+        &self,
+        module: ModId,
+        funs: &mut Vec<(FileId, FunId)>,
+        globals: &mut Vec<(ModId, FileId, GlobalId)>,
+    ) {
+        for (_, def) in self.spark[module].ordered_defs() {
+            match def {
+                SparkDef::FunDef(file, fun) if !funs.iter().any(|(_, f)| *f == fun) => {
+                    funs.push((file, fun))
+                }
+                SparkDef::GlobalDef(file, global)
+                    if !globals.iter().any(|(_, _, g)| *g == global) =>
+                {
+                    globals.push((module, file, global))
+                }
+                SparkDef::ModDef(submod) if submod != module => {
+                    self.collect_defs(submod, funs, globals)
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Give a function and all functions nested inside of it a name in the generated source,
+    /// mangling the names of functions that are not visible outside of the program
+    fn name_fun(&mut self, file: FileId, fun_id: FunId, enclosing: Option<&str>) {
+        if self.fun_names.contains_key(&fun_id) {
+            return;
+        }
+        let fun = &self.spark[fun_id];
+        let name = match enclosing {
+            None if fun.linkage.is_some() || fun.flags.contains(FunFlags::EXTERN) => {
+                fun.name.to_string()
+            }
+            None => format!("{}_{}", fun.name, fun_id.val()),
+            Some(enclosing) => format!("{}_{}_{}", enclosing, fun.name, fun_id.val()),
+        };
+        let nested = fun.nested.clone();
+        self.funs.push((fun_id, file));
+        for nested in nested {
+            self.name_fun(file, nested, Some(name.as_str()));
+        }
+        self.fun_names.insert(fun_id, name);
+    }
+
+    /// Get the signature of a function, without a trailing semicolon
+    fn fun_proto(&mut self, fun_id: FunId) -> CompilerRes<String> {
+        let fun = self.spark[fun_id].clone();
+        let file = self
+            .funs
+            .iter()
+            .find_map(|(id, file)| (*id == fun_id).then(|| *file))
+            .unwrap();
+        let with_span = |e: Diagnostic<FileId>| e.with_labels(vec![Label::primary(file, fun.span)]);
+
+        let mut args = vec![];
+        for (idx, arg) in fun.ty.args.iter().enumerate() {
+            if !self.is_unit(*arg) {
+                args.push(format!("{} a{}", self.c_ty(*arg).map_err(with_span)?, idx));
+            }
+        }
+        let args = match args.is_empty() {
+            true => "void".to_owned(),
+            false => args.join(", "),
+        };
+        let call_conv = match fun.call_conv {
+            None | Some(CallConv::Fast) => "",
+            Some(CallConv::Cold) => "__attribute__((cold)) ",
+            Some(CallConv::Stdcall) => "__attribute__((stdcall)) ",
+            Some(CallConv::Fastcall) => "__attribute__((fastcall)) ",
+            Some(CallConv::Thiscall) => "__attribute__((thiscall)) ",
+            Some(CallConv::Vectorcall) => "__attribute__((vectorcall)) ",
+            Some(CallConv::Win64) => "__attribute__((ms_abi)) ",
+            Some(CallConv::Sysv64) => "__attribute__((sysv_abi)) ",
+            Some(CallConv::PtxKernel | CallConv::AmdgpuKernel) => {
+                return Err(with_span(Diagnostic::error().with_message(format!(
+                    "GPU kernel function '{}' is not supported by the C backend",
+                    fun.name
+                ))))
+            }
+        };
+
+        let optimization = match fun.optimization {
+            None => "",
+            Some(DefOptimization::None) => "__attribute__((noinline, optimize(\"O0\"))) ",
+            Some(DefOptimization::Size) => "__attribute__((optimize(\"Os\"))) ",
+            Some(DefOptimization::Hot) => "__attribute__((hot)) ",
+            Some(DefOptimization::Cold) => "__attribute__((cold)) ",
+        };
+
+        Ok(format!(
+            "{}{}{}{} {}({})",
+            call_conv,
+            optimization,
+            match fun.linkage.is_some() || fun.flags.contains(FunFlags::EXTERN) {
+                true => "",
+                false => "static ",
+            },
+            self.c_ty(fun.ty.return_ty).map_err(with_span)?,
+            self.fun_names[&fun_id],
+            args,
+        ))
+    }
+
+    /// Get the name of a type in the generated source, declaring it if needed
+    fn c_ty(&mut self, id: TypeId) -> CompilerRes<String> {
+        if let Some(name) = self.type_names.get(&id) {
+            return Ok(name.clone());
+        }
+
+        //Explicitly aligned types are rejected inside of other types like in the LLVM backend
+        let contained = match &self.spark[id] {
+            TypeData::Struct { fields, .. } | TypeData::Union { fields } => {
+                fields.iter().map(|(field, _)| *field).collect()
+            }
+            TypeData::Tuple { elements } => elements.clone(),
+            TypeData::Array { element, .. } => vec![*element],
+            _ => vec![],
+        };
+        let aligned = contained.into_iter().find(|ty| {
+            matches!(
+                self.spark[self.spark.unwrap_alias(*ty)],
+                TypeData::Struct { align: Some(_), .. }
+            )
+        });
+        if let Some(aligned) = aligned {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Type {} with an explicit alignment cannot be stored inside of type {}",
+                    self.spark.get_type_name(aligned),
+                    self.spark.get_type_name(id),
+                ))
+                .with_notes(vec![
+                    "#[repr(align = \"N\")] only applies to variables and globals of the type, store a pointer to the value instead".to_owned(),
+                ]));
+        }
+
+        let name = match self.spark[id].clone() {
+            TypeData::Integer { signed, width } => format!(
+                "{}int{}_t",
+                if signed { "" } else { "u" },
+                match width {
+                    IntegerWidth::Eight => 8,
+                    IntegerWidth::Sixteen => 16,
+                    IntegerWidth::ThirtyTwo => 32,
+                    IntegerWidth::SixtyFour => 64,
+                }
+            ),
+            TypeData::Float { doublewide } => match doublewide {
+                true => "double".to_owned(),
+                false => "float".to_owned(),
+            },
+            TypeData::Bool => "bool".to_owned(),
+            TypeData::Unit => "void".to_owned(),
+            TypeData::Alias(_, _, aliased) => self.c_ty(aliased)?,
+            TypeData::Pointer(pointee) => format!("{}*", self.c_ty(pointee)?),
+            //Structures and unions are declared before their fields are so that they can
+            //contain pointers to themselves
+            TypeData::Struct { fields, .. } | TypeData::Union { fields } => {
+                let keyword = match self.spark[id] {
+                    TypeData::Union { .. } => "union",
+                    _ => "struct",
+                };
+                let name = format!("{}{}", keyword, id.val());
+                writeln!(self.typedefs, "typedef {} {} {};", keyword, name, name).unwrap();
+                self.type_names.insert(id, name.clone());
+
+                let mut def = format!("{} {} {{\n", keyword, name);
+                for (idx, (field, _)) in fields.iter().enumerate() {
+                    //Unit fields are skipped like in the LLVM backend, but the remaining
+                    //fields keep their index
+                    if self.spark[self.spark.unwrap_alias(*field)] != TypeData::Unit {
+                        writeln!(def, "    {} f{};", self.c_ty(*field)?, idx).unwrap();
+                    }
+                }
+                let align = match self.spark[id] {
+                    TypeData::Struct { align: Some(align), .. } => {
+                        format!(" __attribute__((aligned({})))", align)
+                    }
+                    _ => String::new(),
+                };
+                writeln!(self.typedefs, "{}}}{};", def, align).unwrap();
+                return Ok(name);
+            }
+            TypeData::Tuple { elements } => {
+                let mut def = String::from("typedef struct {\n");
+                for (idx, element) in elements.iter().enumerate() {
+                    writeln!(def, "    {} f{};", self.c_ty(*element)?, idx).unwrap();
+                }
+                let name = format!("tuple{}", id.val());
+                writeln!(self.typedefs, "{}}} {};", def, name).unwrap();
+                name
+            }
+            //Arrays are wrapped in a structure so that they can be passed and assigned by value
+            TypeData::Array { element, len } => {
+                let element = self.c_ty(element)?;
+                let name = format!("array{}", id.val());
+                writeln!(
+                    self.typedefs,
+                    "typedef struct {{ {} e[{}]; }} {};",
+                    element, len, name
+                )
+                .unwrap();
+                name
+            }
+            TypeData::Function(ty) => {
+                let return_ty = self.c_ty(ty.return_ty)?;
+                let mut args = vec![];
+                for arg in ty.args.iter().filter(|arg| !self.is_unit(**arg)) {
+                    args.push(self.c_ty(*arg)?);
+                }
+                let args = match args.is_empty() {
+                    true => "void".to_owned(),
+                    false => args.join(", "),
+                };
+                let name = format!("fun{}", id.val());
+                writeln!(self.typedefs, "typedef {} (*{})({});", return_ty, name, args).unwrap();
+                name
+            }
+            TypeData::Enum { .. }
+            | TypeData::Vector { .. }
+            | TypeData::Generic(_)
+            | TypeData::Invalid => {
+                return Err(Diagnostic::error().with_message(format!(
+                    "Type '{}' is not supported by the C backend",
+                    self.spark.get_type_name(id)
+                )))
+            }
+        };
+
+        self.type_names.insert(id, name.clone());
+        Ok(name)
+    }
+
+    /// Generate the definition of a global variable
+    fn gen_global(
+        &mut self,
+        module: ModId,
+        file: FileId,
+        id: GlobalId,
+        out: &mut String,
+    ) -> CompilerRes<()> {
+        let global = self.spark[id].clone();
+        let init = match &global.init {
+            Some(init) => init,
+            None => return self.gen_extern_global(file, id, out),
+        };
+        let (init, init_ty) = self.gen_const_expr(module, file, init)?;
+        let ty = global.ty.unwrap_or(init_ty);
+
+        let name = match global.linkage {
+            Some(_) => global.name.to_string(),
+            None => format!("{}_{}", global.name, id.val()),
+        };
+        let c_ty = self
+            .c_ty(ty)
+            .map_err(|e| e.with_labels(vec![Label::primary(file, global.span)]))?;
+        writeln!(
+            out,
+            "{}{}{}{} {} = {};",
+            if global.linkage.is_some() { "" } else { "static " },
+            if global.flags.contains(GlobalFlags::THREAD_LOCAL) { "_Thread_local " } else { "" },
+            if global.mutable { "" } else { "const " },
+            c_ty,
+            name,
+            init,
+        )
+        .unwrap();
+        self.global_names.insert(id, name);
+        Ok(())
+    }
+
+    /// Generate the declaration of an external global defined in another object
+    fn gen_extern_global(&mut self, file: FileId, id: GlobalId, out: &mut String) -> CompilerRes<()> {
+        let global = self.spark[id].clone();
+        let c_ty = self
+            .c_ty(global.ty.unwrap())
+            .map_err(|e| e.with_labels(vec![Label::primary(file, global.span)]))?;
+        writeln!(
+            out,
+            "extern {}{}{} {};",
+            if global.flags.contains(GlobalFlags::THREAD_LOCAL) { "_Thread_local " } else { "" },
+            if global.mutable { "" } else { "const " },
+            c_ty,
+            global.name,
+        )
+        .unwrap();
+        self.global_names.insert(id, global.name.to_string());
+        Ok(())
+    }
+
+    /// Generate a C constant expression from a global's initializer, inlining the values of
+    /// other globals because C does not allow them in constant expressions
+    fn gen_const_expr(
+        &mut self,
+        module: ModId,
+        file: FileId,
+        ast: &Ast<TypeId>,
+    ) -> CompilerRes<(String, TypeId)> {
+        Ok(match &ast.node {
+            AstNode::Literal(Literal::Number(num)) => {
+                let ty = match num.annotation() {
+                    Some(ann) => annotation_ty(ann),
+                    None => match num {
+                        NumberLiteral::Float(..) => SparkCtx::F64,
+                        NumberLiteral::Integer(..) => SparkCtx::I32,
+                    },
+                };
+                let c_ty = self.c_ty(ty)?;
+                match num {
+                    NumberLiteral::Integer(int, _) => (
+                        format!("(({}){}{}ULL)", c_ty, if int.sign { "-" } else { "" }, int.val),
+                        ty,
+                    ),
+                    NumberLiteral::Float(val, _) => (format!("(({}){:?})", c_ty, val), ty),
+                }
+            }
+            AstNode::Literal(Literal::Bool(val)) => (val.to_string(), SparkCtx::BOOL),
+            AstNode::Literal(Literal::String(val)) => (
+                format!("(uint8_t*){}", Self::c_string(val)),
+                self.spark.new_type(TypeData::Pointer(SparkCtx::U8)),
+            ),
+            AstNode::BinExpr(lhs, op, _, rhs) => {
+                let (lhs, ty) = self.gen_const_expr(module, file, lhs)?;
+                let (rhs, _) = self.gen_const_expr(module, file, rhs)?;
+                let ty = match op {
+                    Op::Greater
+                    | Op::GreaterEq
+                    | Op::Less
+                    | Op::LessEq
+                    | Op::Eq
+                    | Op::NotEq
+                    | Op::LogicalAnd
+                    | Op::LogicalOr => SparkCtx::BOOL,
+                    _ => ty,
+                };
+                (format!("({} {} {})", lhs, Self::c_op(*op), rhs), ty)
+            }
+            AstNode::CastExpr(to, val) => {
+                let (val, _) = self.gen_const_expr(module, file, val)?;
+                (format!("(({})({}))", self.c_ty(*to)?, val), *to)
+            }
+            AstNode::Access(path) => match self.spark.get_def(module, path) {
+                Ok(SparkDef::GlobalDef(global_file, global)) => {
+                    let source = self.spark.path_module(module, path).unwrap_or(module);
+                    let init = match self.spark[global].init.clone() {
+                        Some(init) => init,
+                        None => {
+                            return Err(Diagnostic::error()
+                                .with_message(format!(
+                                    "External global '{}' is not a constant value",
+                                    path
+                                ))
+                                .with_labels(vec![Label::primary(file, ast.span)]))
+                        }
+                    };
+                    if self.evaluating_consts.contains(&global) {
+                        return Err(Diagnostic::error()
+                            .with_message(format!("Cyclic constant definition of '{}'", path))
+                            .with_labels(vec![
+                                Label::primary(file, ast.span)
+                                    .with_message("Constant used in its own initializer here"),
+                                Label::secondary(global_file, self.spark[global].span)
+                                    .with_message("Constant defined here"),
+                            ]));
+                    }
+                    self.evaluating_consts.push(global);
+                    let val = self.gen_const_expr(source, global_file, &init);
+                    self.evaluating_consts.pop();
+                    let (val, ty) = val?;
+                    (val, self.spark[global].ty.unwrap_or(ty))
+                }
+                _ => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!("'{}' is not a constant value", path))
+                        .with_labels(vec![Label::primary(file, ast.span)]))
+                }
+            },
+            _ => {
+                return Err(Diagnostic::error()
+                    .with_message("Global initializer is not supported by the C backend")
+                    .with_labels(vec![Label::primary(file, ast.span)]))
+            }
+        })
+    }
+
+    /// Generate the definition of a function from its lowered body
+    fn gen_body(&mut self, body: &IrBody, out: &mut String) -> CompilerRes<()> {
+        let proto = self.fun_proto(body.fun)?;
+        writeln!(out, "{} {{", proto).unwrap();
+
+        for local in body.locals.iter() {
+            if !self.is_unit(local.ty) {
+                writeln!(out, "    {} l{};", self.c_ty(local.ty)?, local.id.val()).unwrap();
+            }
+        }
+        for (idx, arg) in body.args.iter().enumerate() {
+            if let Some(local) = arg {
+                if !self.is_unit(body.locals[*local].ty) {
+                    writeln!(out, "    l{} = a{};", local.val(), idx).unwrap();
+                }
+            }
+        }
+        writeln!(out, "    goto bb{};", body.entry.val()).unwrap();
+
+        for bb in body.bbs.iter() {
+            writeln!(out, "bb{}:;", bb.id.val()).unwrap();
+            for stmt in bb.stmts.iter() {
+                match stmt {
+                    IrStmt::Store { value, .. } if value.ty() == SparkCtx::UNIT => {
+                        writeln!(out, "    (void)({});", self.gen_rvalue(value)?).unwrap();
+                    }
+                    IrStmt::Store { place, value } => {
+                        writeln!(
+                            out,
+                            "    {} = {};",
+                            self.gen_place(place)?,
+                            self.gen_rvalue(value)?
+                        )
+                        .unwrap();
+                    }
+                    IrStmt::Eval(value) => {
+                        writeln!(out, "    (void)({});", self.gen_rvalue(value)?).unwrap();
+                    }
+                }
+            }
+
+            match &bb.terminator {
+                IrTerminator::Return(IrRvalue::Const(IrConst::Unit)) => {
+                    writeln!(out, "    return;").unwrap()
+                }
+                IrTerminator::Return(value) if value.ty() == SparkCtx::UNIT => {
+                    writeln!(out, "    (void)({});\n    return;", self.gen_rvalue(value)?).unwrap()
+                }
+                IrTerminator::Return(value) => {
+                    writeln!(out, "    return {};", self.gen_rvalue(value)?).unwrap()
+                }
+                IrTerminator::Jump(bb) => writeln!(out, "    goto bb{};", bb.val()).unwrap(),
+                IrTerminator::Branch {
+                    cond,
+                    if_true,
+                    if_false,
+                } => writeln!(
+                    out,
+                    "    if ({}) goto bb{}; else goto bb{};",
+                    self.gen_rvalue(cond)?,
+                    if_true.val(),
+                    if_false.val()
+                )
+                .unwrap(),
+                IrTerminator::Unreachable => writeln!(out, "    abort();").unwrap(),
+            }
+        }
+
+        writeln!(out, "}}\n").unwrap();
+        Ok(())
+    }
+
+    fn gen_place(&mut self, place: &IrPlace) -> CompilerRes<String> {
+        Ok(match place {
+            IrPlace::Local(local) => format!("l{}", local.val()),
+            IrPlace::Global(global) => self.global_names[global].clone(),
+            IrPlace::Deref(pointer) => format!("(*{})", self.gen_rvalue(pointer)?),
+            IrPlace::Field(object, idx) => format!("{}.f{}", self.gen_place(object)?, idx),
+            IrPlace::Index(object, idx) => format!(
+                "{}.e[{}]",
+                self.gen_place(object)?,
+                self.gen_rvalue(idx)?
+            ),
+        })
+    }
+
+    fn gen_rvalue(&mut self, value: &IrRvalue) -> CompilerRes<String> {
+        Ok(match value {
+            IrRvalue::Const(IrConst::Int { val, ty }) => format!("(({}){}ULL)", self.c_ty(*ty)?, val),
+            IrRvalue::Const(IrConst::Float { val, ty }) => format!("(({}){:?})", self.c_ty(*ty)?, val),
+            IrRvalue::Const(IrConst::Bool(val)) => val.to_string(),
+            IrRvalue::Const(IrConst::String { val, .. }) => {
+                format!("((uint8_t*){})", Self::c_string(val))
+            }
+            IrRvalue::Const(IrConst::Unit) => "((void)0)".to_owned(),
+            IrRvalue::Load(_, ty) if *ty == SparkCtx::UNIT => "((void)0)".to_owned(),
+            IrRvalue::Load(place, _) => self.gen_place(place)?,
+            IrRvalue::AddressOf(place, _) => format!("(&{})", self.gen_place(place)?),
+            IrRvalue::Fun(fun, _) => self.fun_names[fun].clone(),
+            IrRvalue::Binary { op: Op::Mod, lhs, .. }
+                if matches!(self.spark[self.spark.unwrap_alias(lhs.ty())], TypeData::Float { .. }) =>
+            {
+                return Err(Diagnostic::error()
+                    .with_message("Floating point remainder is not supported by the C backend"))
+            }
+            IrRvalue::Binary { op, lhs, rhs, ty } => {
+                let lhs = self.gen_rvalue(lhs)?;
+                let rhs = self.gen_rvalue(rhs)?;
+                match self.spark[self.spark.unwrap_alias(*ty)].clone() {
+                    //C promotes narrow operands to int and leaves signed overflow undefined,
+                    //so wrapping operations are done on unsigned operands of at least the
+                    //width of int and every integer result is converted back to its type
+                    TypeData::Integer { width, .. } => {
+                        let c_ty = self.c_ty(*ty)?;
+                        match op {
+                            Op::Add | Op::Sub | Op::Star | Op::ShLeft => {
+                                let unsigned = match width {
+                                    IntegerWidth::SixtyFour => "uint64_t",
+                                    _ => "uint32_t",
+                                };
+                                format!(
+                                    "(({})(({}){} {} ({}){}))",
+                                    c_ty,
+                                    unsigned,
+                                    lhs,
+                                    Self::c_op(*op),
+                                    unsigned,
+                                    rhs
+                                )
+                            }
+                            _ => format!("(({})({} {} {}))", c_ty, lhs, Self::c_op(*op), rhs),
+                        }
+                    }
+                    _ => format!("({} {} {})", lhs, Self::c_op(*op), rhs),
+                }
+            }
+            IrRvalue::Call { called, args, .. } => {
+                let called = self.gen_rvalue(called)?;
+                //Unit arguments are left out of function signatures, but are still evaluated
+                //before the call
+                let mut evaluated = String::new();
+                let mut passed = vec![];
+                for arg in args.iter() {
+                    let val = self.gen_rvalue(arg)?;
+                    match self.is_unit(arg.ty()) {
+                        true => write!(evaluated, "(void)({}), ", val).unwrap(),
+                        false => passed.push(val),
+                    }
+                }
+                match evaluated.is_empty() {
+                    true => format!("{}({})", called, passed.join(", ")),
+                    false => format!("({}{}({}))", evaluated, called, passed.join(", ")),
+                }
+            }
+            IrRvalue::Cast { val, to } => {
+                format!("(({})({}))", self.c_ty(*to)?, self.gen_rvalue(val)?)
+            }
+        })
+    }
+
+    /// Check if a type is the unit type, which has no values in C
+    fn is_unit(&self, ty: TypeId) -> bool {
+        self.spark[self.spark.unwrap_alias(ty)] == TypeData::Unit
+    }
+
+    /// Get the C operator for a binary operator
+    const fn c_op(op: Op) -> &'static str {
+        match op {
+            Op::Star => "*",
+            Op::Div => "/",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mod => "%",
+            Op::AND => "&",
+            Op::OR => "|",
+            Op::XOR => "^",
+            Op::NOT => "~",
+            Op::LogicalAnd => "&&",
+            Op::LogicalOr => "||",
+            Op::LogicalNot => "!",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Eq => "==",
+            Op::NotEq => "!=",
+            Op::ShLeft => "<<",
+            Op::ShRight => ">>",
+        }
+    }
+
+    /// Quote a string as a C string literal, escaping any characters that are not printable
+    fn c_string(val: &str) -> String {
+        let mut quoted = String::from("\"");
+        for byte in val.bytes() {
+            match byte {
+                b'"' | b'\\' => write!(quoted, "\\{}", byte as char).unwrap(),
+                b' '..=b'~' => quoted.push(byte as char),
+                _ => write!(quoted, "\\{:03o}", byte).unwrap(),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use crate::{
+        codegen::{ir::SparkCtx, lower::Lowerer, CompilerRes},
+        parse::Parser,
+        util::files::{CompiledFile, Files},
+        Symbol,
+    };
+
+    use super::CCodeGenerator;
+
+    /// Generate C source for a program contained in a single file
+    fn gen_c(src: &str) -> String {
+        try_gen_c(src).unwrap_or_else(|_| panic!("Failed to generate C source"))
+    }
+
+    /// Generate C source for a program contained in a single file that may contain errors
+    fn try_gen_c(src: &str) -> CompilerRes<String> {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files).lower_module(&module)?;
+        CCodeGenerator::new(ctx, &files).codegen_module(root)
+    }
+
+    /// Compile C source to an object file with the system C compiler, returning `None` if
+    /// there is no C compiler installed
+    fn compile_c(name: &str, source: &str) -> Option<bool> {
+        let dir = std::env::temp_dir();
+        let src_path = dir.join(format!("spark-{}-{}.c", name, std::process::id()));
+        let obj_path = src_path.with_extension("o");
+        std::fs::write(&src_path, source).unwrap();
+        let status = Command::new("cc")
+            .args(["-std=c99", "-c", "-o"])
+            .arg(&obj_path)
+            .arg(&src_path)
+            .status();
+        std::fs::remove_file(&src_path).ok();
+        std::fs::remove_file(&obj_path).ok();
+        status.ok().map(|status| status.success())
+    }
+
+    #[test]
+    fn integer_addition_compiles() {
+        let source = gen_c("fun ext add(i32 a, i32 b) -> i32 {\n    return a + b\n}\n");
+        assert!(source.contains("int32_t add(int32_t a0, int32_t a1)"));
+        if let Some(compiled) = compile_c("add", &source) {
+            assert!(compiled, "C compiler rejected generated source:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn narrow_arithmetic_is_converted_back() {
+        let source = gen_c("fun ext wrap(u8 a, u8 b) -> u8 {\n    return a + b\n}\n");
+        assert!(source.contains("((uint8_t)((uint32_t)"));
+        if let Some(compiled) = compile_c("wrap", &source) {
+            assert!(compiled, "C compiler rejected generated source:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn unit_arguments_are_left_out() {
+        let source = gen_c("fun ext ignore(() unit, i32 n) -> i32 {\n    return n\n}\n");
+        assert!(source.contains("int32_t ignore(int32_t a1)"));
+        if let Some(compiled) = compile_c("ignore", &source) {
+            assert!(compiled, "C compiler rejected generated source:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn literals_are_only_coerced_when_in_range() {
+        let source = gen_c("fun ext small() -> u8 {\n    return 255\n}\n");
+        assert!(source.contains("255"));
+        assert!(try_gen_c("fun ext small() -> u8 {\n    return 300\n}\n").is_err());
+        assert!(try_gen_c("fun ext neg() -> i8 {\n    return -128\n}\n").is_ok());
+        assert!(try_gen_c("fun ext neg() -> i8 {\n    return 128\n}\n").is_err());
+    }
+
+    #[test]
+    fn aligned_types_cannot_be_nested() {
+        let aligned = "#[repr(align = \"16\")]\ntype wide = { i32 x }\n";
+        let source = gen_c(&format!(
+            "{}fun ext wide_x(wide w) -> i32 {{\n    return w.x\n}}\n",
+            aligned
+        ));
+        assert!(source.contains("__attribute__((aligned(16)))"));
+        assert!(try_gen_c(&format!(
+            "{}type pair = {{ wide a, wide b }}\nfun ext pair_x(pair p) -> i32 {{\n    return p.a.x\n}}\n",
+            aligned
+        ))
+        .is_err());
+    }
+}
@@ -1,10 +1,16 @@
-use std::ops;
+use std::{fmt, ops};
 
+use bitflags::bitflags;
+use hashbrown::{HashMap, HashSet};
 use quickscope::ScopeMap;
 
 use crate::{
     arena::{Arena, Index, Interner},
-    ast::{Ast, FunFlags, IntegerWidth, PathIter, SymbolPath},
+    ast::{
+        Ast, AstNode, FunFlags, IntegerWidth, Literal, NumberLiteral, NumberLiteralAnnotation,
+        PathIter, SymbolPath,
+    },
+    parse::token::Op,
     util::{files::FileId, loc::Span},
     Symbol,
 };
@@ -13,6 +19,8 @@ pub type TypeId = Index<TypeData>;
 pub type FunId = Index<Function>;
 pub type ModId = Index<SparkModule>;
 pub type DefId = Index<SparkDef>;
+pub type GlobalId = Index<Global>;
+pub type InterfaceId = Index<Interface>;
 
 /// Structure containing arenas holding all function definitions,
 /// types, etc.
@@ -21,26 +29,45 @@ pub struct SparkCtx {
     types: Interner<TypeData>,
     modules: Arena<SparkModule>,
     funs: Arena<Function>,
+    globals: Arena<Global>,
+    interfaces: Arena<Interface>,
+    /// Instances of generic functions created for each list of type arguments
+    instances: HashMap<(FunId, Vec<TypeId>), FunId>,
+    /// The [Generic](TypeData::Generic) type of each type parameter of generic type definitions
+    type_templates: HashMap<TypeId, Vec<TypeId>>,
+    /// Instances of generic types created for each list of type arguments
+    type_instances: HashMap<(TypeId, Vec<TypeId>), TypeId>,
+    /// The generic type and type arguments that each generic type instance was created from
+    instance_of: HashMap<TypeId, (TypeId, Vec<TypeId>)>,
+    /// Default values of the fields of structure type definitions and the file they are
+    /// defined in
+    field_defaults: HashMap<TypeId, (FileId, Vec<(Symbol, Ast<TypeId>)>)>,
 }
 
 impl SparkCtx {
     /// Create a new module with the given name and return an ID for the created
     /// module
-    pub fn new_module(&mut self, name: Symbol) -> ModId {
+    pub fn new_module(&mut self, name: Symbol, parent: Option<ModId>) -> ModId {
         self.modules.insert_with(|id| SparkModule {
             id,
             name,
+            parent,
             defs: ScopeMap::new(),
+            public: HashSet::new(),
+            order: HashMap::new(),
         })
     }
 
     /// Create a type using the given type data and return the ID of the created
-    /// type
+    /// type. Types are interned, so structurally identical anonymous structs and tuples
+    /// share one ID and can be compared by ID
     pub fn new_type(&mut self, data: TypeData) -> TypeId {
         self.types.insert(data)
     }
 
-    /// Create a new invalid type with a unique type ID for forward references
+    /// Create a new invalid type with a unique type ID for forward references. This is the only
+    /// kind of type that may be overwritten through [IndexMut](ops::IndexMut), as overwriting
+    /// an interned type would break interning
     pub fn new_empty_type(&mut self) -> TypeId {
         self.types.insert_nointern(TypeData::Invalid)
     }
@@ -62,24 +89,571 @@ impl SparkCtx {
             span,
             arg_names,
             body: None,
+            nested: vec![],
+            linkage: None,
+            visibility: None,
+            call_conv: None,
+            optimization: None,
+            generic: None,
+        })
+    }
+
+    /// Get the instance of a generic function with the given type arguments, creating it by
+    /// substituting the type arguments for the function's type parameters in its type and body
+    /// if it has not been instantiated with the same type arguments before
+    pub fn instantiate(&mut self, generic: FunId, type_args: Vec<TypeId>) -> FunId {
+        if let Some(instance) = self.instances.get(&(generic, type_args.clone())) {
+            return *instance;
+        }
+
+        let fun = self[generic].clone();
+        let params = fun.generic.as_ref().unwrap().params.clone();
+        let substitutions = params
+            .iter()
+            .copied()
+            .zip(type_args.iter().copied())
+            .collect::<HashMap<_, _>>();
+
+        let ty = FunctionType {
+            return_ty: self.substitute(fun.ty.return_ty, &substitutions),
+            args: fun
+                .ty
+                .args
+                .iter()
+                .map(|arg| self.substitute(*arg, &substitutions))
+                .collect(),
+        };
+        let body = fun.body.as_ref().map(|body| {
+            body.iter()
+                .map(|stmt| stmt.map_types(&mut |ty| self.substitute(*ty, &substitutions)))
+                .collect()
+        });
+        let name = Symbol::from(&format!(
+            "{}<{}>",
+            fun.name,
+            type_args
+                .iter()
+                .map(|ty| self.get_type_name(*ty).to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+
+        let instance = self.new_fun(name, ty, fun.flags, fun.arg_names, fun.span);
+        self[instance].body = body;
+        self[instance].call_conv = fun.call_conv;
+        self[instance].optimization = fun.optimization;
+        self.instances.insert((generic, type_args), instance);
+        instance
+    }
+
+    /// Mark a forward-declared type definition as generic over the given type parameters
+    pub fn set_type_params(&mut self, template: TypeId, params: Vec<TypeId>) {
+        self.type_templates.insert(template, params);
+    }
+
+    /// Get the type parameters of a generic type definition, or `None` if the type is not
+    /// generic
+    pub fn type_params(&self, ty: TypeId) -> Option<&[TypeId]> {
+        self.type_templates.get(&ty).map(Vec::as_slice)
+    }
+
+    /// Get the instance of a generic type definition with the given type arguments, creating it
+    /// if it has not been instantiated with the same type arguments before
+    ///
+    /// Instances may be created before the generic type's definition is lowered, in which case
+    /// the instance is filled by [fill_type_instances](Self::fill_type_instances) once it is
+    pub fn instantiate_type(&mut self, template: TypeId, type_args: Vec<TypeId>) -> TypeId {
+        if let Some(instance) = self.type_instances.get(&(template, type_args.clone())) {
+            return *instance;
+        }
+
+        let instance = self.new_empty_type();
+        self.type_instances
+            .insert((template, type_args.clone()), instance);
+        self.instance_of.insert(instance, (template, type_args));
+        if let TypeData::Alias(..) = self[template] {
+            self.fill_type_instance(instance);
+        }
+        instance
+    }
+
+    /// Set the default values of fields of a structure type definition
+    pub fn set_field_defaults(
+        &mut self,
+        ty: TypeId,
+        file: FileId,
+        defaults: Vec<(Symbol, Ast<TypeId>)>,
+    ) {
+        self.field_defaults.insert(ty, (file, defaults));
+    }
+
+    /// Get the default value of a field of a structure type definition and the file that it is
+    /// defined in, or `None` if the field has no default value
+    pub fn field_default(&self, ty: TypeId, field: Symbol) -> Option<(FileId, &Ast<TypeId>)> {
+        let (file, defaults) = self.field_defaults.get(&ty)?;
+        defaults
+            .iter()
+            .find(|(name, _)| *name == field)
+            .map(|(_, default)| (*file, default))
+    }
+
+    /// Get all instances of a generic type created so far
+    pub fn type_instances_of(&self, template: TypeId) -> Vec<TypeId> {
+        self.instance_of
+            .iter()
+            .filter(|(_, (of, _))| *of == template)
+            .map(|(instance, _)| *instance)
+            .collect()
+    }
+
+    /// Fill all instances of a generic type that were created before its definition was lowered
+    pub fn fill_type_instances(&mut self, template: TypeId) {
+        for instance in self.type_instances_of(template) {
+            if let TypeData::Invalid = self[instance] {
+                self.fill_type_instance(instance);
+            }
+        }
+    }
+
+    /// Substitute the type arguments of a generic type instance into its generic type's
+    /// definition
+    fn fill_type_instance(&mut self, instance: TypeId) {
+        let (template, type_args) = self.instance_of[&instance].clone();
+        let (name, module, aliased) = match self[template] {
+            TypeData::Alias(name, module, aliased) => (name, module, aliased),
+            _ => unreachable!(),
+        };
+        let substitutions = self.type_templates[&template]
+            .iter()
+            .copied()
+            .zip(type_args)
+            .collect::<HashMap<_, _>>();
+        let aliased = self.substitute(aliased, &substitutions);
+        self[instance] = TypeData::Alias(name, module, aliased);
+
+        if let Some((file, defaults)) = self.field_defaults.get(&template).cloned() {
+            let defaults = defaults
+                .iter()
+                .map(|(name, default)| {
+                    (
+                        *name,
+                        default.map_types(&mut |ty| self.substitute(*ty, &substitutions)),
+                    )
+                })
+                .collect();
+            self.field_defaults.insert(instance, (file, defaults));
+        }
+    }
+
+    /// Replace every type parameter contained in a type with the type it is mapped to
+    pub fn substitute(&mut self, ty: TypeId, substitutions: &HashMap<TypeId, TypeId>) -> TypeId {
+        if let Some(substituted) = substitutions.get(&ty) {
+            return *substituted;
+        }
+        if let Some((template, type_args)) = self.instance_of.get(&ty).cloned() {
+            let type_args = type_args
+                .into_iter()
+                .map(|arg| self.substitute(arg, substitutions))
+                .collect();
+            return self.instantiate_type(template, type_args);
+        }
+
+        let data = match self[ty].clone() {
+            TypeData::Pointer(pointee) => TypeData::Pointer(self.substitute(pointee, substitutions)),
+            TypeData::Array { element, len } => TypeData::Array {
+                element: self.substitute(element, substitutions),
+                len,
+            },
+            TypeData::Vector { element, len } => TypeData::Vector {
+                element: self.substitute(element, substitutions),
+                len,
+            },
+            TypeData::Struct { fields, repr, align } => TypeData::Struct {
+                fields: fields
+                    .into_iter()
+                    .map(|(field, name)| (self.substitute(field, substitutions), name))
+                    .collect(),
+                repr,
+                align,
+            },
+            TypeData::Union { fields } => TypeData::Union {
+                fields: fields
+                    .into_iter()
+                    .map(|(field, name)| (self.substitute(field, substitutions), name))
+                    .collect(),
+            },
+            TypeData::Tuple { elements } => TypeData::Tuple {
+                elements: elements
+                    .into_iter()
+                    .map(|element| self.substitute(element, substitutions))
+                    .collect(),
+            },
+            TypeData::Enum { parts } => TypeData::Enum {
+                parts: parts
+                    .into_iter()
+                    .map(|part| self.substitute(part, substitutions))
+                    .collect(),
+            },
+            TypeData::Function(FunctionType { return_ty, args }) => {
+                TypeData::Function(FunctionType {
+                    return_ty: self.substitute(return_ty, substitutions),
+                    args: args
+                        .into_iter()
+                        .map(|arg| self.substitute(arg, substitutions))
+                        .collect(),
+                })
+            }
+            _ => return ty,
+        };
+        self.new_type(data)
+    }
+
+    /// Match a type that may contain type parameters against a type passed in its place,
+    /// binding each type parameter to the type in its position and returning `false` if the
+    /// types can't be matched
+    pub fn unify(
+        &self,
+        generic: TypeId,
+        passed: TypeId,
+        bindings: &mut HashMap<TypeId, TypeId>,
+    ) -> bool {
+        if let TypeData::Generic(_) = self[generic] {
+            return match bindings.get(&generic) {
+                Some(bound) => self.unwrap_alias(*bound) == self.unwrap_alias(passed),
+                None => {
+                    bindings.insert(generic, passed);
+                    true
+                }
+            };
+        }
+
+        if let (Some((template, type_args)), Some((passed_template, passed_args))) =
+            (self.instance_of.get(&generic), self.instance_of.get(&passed))
+        {
+            return template == passed_template
+                && type_args
+                    .iter()
+                    .zip(passed_args.iter())
+                    .all(|(generic, passed)| self.unify(*generic, *passed, bindings));
+        }
+
+        match (&self[generic], &self[self.unwrap_alias(passed)]) {
+            (TypeData::Pointer(generic), TypeData::Pointer(passed)) => {
+                self.unify(*generic, *passed, bindings)
+            }
+            (
+                TypeData::Array { element, len },
+                TypeData::Array {
+                    element: passed_element,
+                    len: passed_len,
+                },
+            ) => len == passed_len && self.unify(*element, *passed_element, bindings),
+            (TypeData::Tuple { elements }, TypeData::Tuple { elements: passed }) => {
+                elements.len() == passed.len()
+                    && elements
+                        .iter()
+                        .zip(passed.iter())
+                        .all(|(generic, passed)| self.unify(*generic, *passed, bindings))
+            }
+            (TypeData::Function(generic), TypeData::Function(passed)) => {
+                generic.args.len() == passed.args.len()
+                    && generic
+                        .args
+                        .iter()
+                        .zip(passed.args.iter())
+                        .all(|(generic, passed)| self.unify(*generic, *passed, bindings))
+                    && self.unify(generic.return_ty, passed.return_ty, bindings)
+            }
+            _ => self.unwrap_alias(generic) == self.unwrap_alias(passed),
+        }
+    }
+
+    /// Create a new interface with no functions or implementations and return the ID of the
+    /// created interface
+    pub fn new_interface(&mut self, name: Symbol) -> InterfaceId {
+        self.interfaces.insert_with(|id| Interface {
+            id,
+            name,
+            funs: vec![],
+            impls: HashMap::new(),
         })
     }
 
+    /// Get the function implementing a function declared in an interface for the given type,
+    /// along with the module and file that the implementation is defined in
+    pub fn implementation(&self, fun: FunId, ty: TypeId) -> Option<(ModId, FileId, FunId)> {
+        let interface = &self[self[fun].generic.as_ref()?.interface?];
+        let idx = interface.funs.iter().position(|declared| *declared == fun)?;
+        let implementation = interface.impls.get(&ty)?;
+        Some((
+            implementation.module,
+            implementation.file,
+            implementation.funs[idx],
+        ))
+    }
+
+    /// Get every function implementing a function declared in an interface, along with the
+    /// module each implementation is defined in
+    pub fn implementations_of(&self, fun: FunId) -> Vec<(ModId, FunId)> {
+        let interface = match self[fun].generic.as_ref().and_then(|generic| generic.interface) {
+            Some(interface) => &self[interface],
+            None => return vec![],
+        };
+        let idx = match interface.funs.iter().position(|declared| *declared == fun) {
+            Some(idx) => idx,
+            None => return vec![],
+        };
+        interface
+            .impls
+            .values()
+            .map(|implementation| (implementation.module, implementation.funs[idx]))
+            .collect()
+    }
+
+    /// Create a new global variable and return the ID of the created global
+    pub fn new_global(
+        &mut self,
+        name: Symbol,
+        ty: Option<TypeId>,
+        mutable: bool,
+        flags: GlobalFlags,
+        linkage: Option<DefLinkage>,
+        visibility: Option<DefVisibility>,
+        init: Option<Ast<TypeId>>,
+        span: Span,
+    ) -> GlobalId {
+        self.globals.insert_with(|id| Global {
+            id,
+            name,
+            ty,
+            mutable,
+            flags,
+            linkage,
+            visibility,
+            init,
+            span,
+        })
+    }
+
+    /// Get an iterator over every type in this context and its ID
+    pub fn types(&self) -> impl Iterator<Item = (TypeId, &TypeData)> {
+        self.types.iter_with_indices()
+    }
+
+    /// Get an iterator over every function in this context and its ID, including instances of
+    /// generic functions
+    pub fn funs(&self) -> impl Iterator<Item = (FunId, &Function)> {
+        self.funs.iter_with_indices()
+    }
+
+    /// Get an iterator over every module in this context and its ID
+    pub fn modules(&self) -> impl Iterator<Item = (ModId, &SparkModule)> {
+        self.modules.iter_with_indices()
+    }
+
     /// Recursively unwrap any aliased types, returning a type id that is guranteeed to
     /// not be an alias type
     pub fn unwrap_alias(&self, ty: TypeId) -> TypeId {
         match &self[ty] {
-            TypeData::Alias(_, ty) => self.unwrap_alias(*ty),
+            TypeData::Alias(_, _, ty) => self.unwrap_alias(*ty),
             _ => ty,
         }
     }
 
+    /// Get the annotation that number literals of an integer or floating point type are
+    /// written with, or `None` if the type is not numeric
+    pub fn literal_annotation(&self, ty: TypeId) -> Option<NumberLiteralAnnotation> {
+        Some(match &self[self.unwrap_alias(ty)] {
+            TypeData::Integer { signed, width } => match (signed, width) {
+                (true, IntegerWidth::Eight) => NumberLiteralAnnotation::I8,
+                (true, IntegerWidth::Sixteen) => NumberLiteralAnnotation::I16,
+                (true, IntegerWidth::ThirtyTwo) => NumberLiteralAnnotation::I32,
+                (true, IntegerWidth::SixtyFour) => NumberLiteralAnnotation::I64,
+                (false, IntegerWidth::Eight) => NumberLiteralAnnotation::U8,
+                (false, IntegerWidth::Sixteen) => NumberLiteralAnnotation::U16,
+                (false, IntegerWidth::ThirtyTwo) => NumberLiteralAnnotation::U32,
+                (false, IntegerWidth::SixtyFour) => NumberLiteralAnnotation::U64,
+            },
+            TypeData::Float { doublewide: true } => NumberLiteralAnnotation::F64,
+            TypeData::Float { doublewide: false } => NumberLiteralAnnotation::F32,
+            _ => return None,
+        })
+    }
+
+    /// Give an unannotated number literal, or the negation of one, the annotation of the numeric
+    /// type it is used as, and give an anonymous structure literal with the same field names as a
+    /// structure type that type, returning `None` if the expression can't be coerced to the type
+    /// or an integer literal is out of the type's range
+    pub fn coerce_literal(&self, ast: &Ast<TypeId>, ty: TypeId) -> Option<Ast<TypeId>> {
+        if let AstNode::Literal(Literal::Struct { ty: None, fields }) = &ast.node {
+            let struct_fields = match &self[self.unwrap_alias(ty)] {
+                TypeData::Struct { fields, .. } => fields,
+                _ => return None,
+            };
+            if fields.len() != struct_fields.len() {
+                return None;
+            }
+
+            let fields = fields
+                .iter()
+                .map(|(name, field)| {
+                    let (field_ty, _) = struct_fields.iter().find(|(_, fname)| fname == name)?;
+                    Some((
+                        name.clone(),
+                        self.coerce_literal(field, *field_ty)
+                            .unwrap_or_else(|| field.clone()),
+                    ))
+                })
+                .collect::<Option<Vec<_>>>()?;
+
+            return Some(Ast {
+                span: ast.span,
+                node: AstNode::Literal(Literal::Struct {
+                    ty: Some(ty),
+                    fields,
+                }),
+            });
+        }
+
+        let annotation = self.literal_annotation(ty)?;
+        let signed = !matches!(
+            annotation,
+            NumberLiteralAnnotation::U8
+                | NumberLiteralAnnotation::U16
+                | NumberLiteralAnnotation::U32
+                | NumberLiteralAnnotation::U64
+        );
+        let is_float = matches!(
+            annotation,
+            NumberLiteralAnnotation::F32 | NumberLiteralAnnotation::F64
+        );
+        //Integer literals are only coerced to types that can hold their value
+        let fits = |val: u64, negated: bool| {
+            annotation
+                .max_literal(negated)
+                .map_or(true, |max| val <= max)
+        };
+
+        let node = match &ast.node {
+            AstNode::Literal(Literal::Number(NumberLiteral::Integer(num, None)))
+                if fits(num.val, false) =>
+            {
+                AstNode::Literal(Literal::Number(NumberLiteral::Integer(*num, Some(annotation))))
+            }
+            AstNode::Literal(Literal::Number(NumberLiteral::Float(num, None))) if is_float => {
+                AstNode::Literal(Literal::Number(NumberLiteral::Float(*num, Some(annotation))))
+            }
+            AstNode::UnaryExpr(Op::Sub, negated) if signed => match &negated.node {
+                AstNode::Literal(Literal::Number(NumberLiteral::Integer(num, None))) => {
+                    if !fits(num.val, true) {
+                        return None;
+                    }
+                    AstNode::UnaryExpr(
+                        Op::Sub,
+                        Box::new(Ast {
+                            span: negated.span,
+                            node: AstNode::Literal(Literal::Number(NumberLiteral::Integer(
+                                *num,
+                                Some(annotation),
+                            ))),
+                        }),
+                    )
+                }
+                _ => AstNode::UnaryExpr(Op::Sub, Box::new(self.coerce_literal(negated, ty)?)),
+            },
+            _ => return None,
+        };
+
+        Some(Ast {
+            span: ast.span,
+            node,
+        })
+    }
+
+    /// Check if a value of type `ty` contains a value of type `contained` directly, without a
+    /// pointer in between, skipping any types in `visited`
+    pub fn contains_by_value(
+        &self,
+        ty: TypeId,
+        contained: TypeId,
+        visited: &mut HashSet<TypeId>,
+    ) -> bool {
+        if !visited.insert(ty) {
+            return false;
+        }
+
+        let inner = match &self[ty] {
+            TypeData::Struct { fields, .. } | TypeData::Union { fields } => {
+                fields.iter().map(|(field, _)| *field).collect()
+            }
+            TypeData::Tuple { elements } => elements.clone(),
+            TypeData::Enum { parts } => parts.clone(),
+            TypeData::Array { element, .. } | TypeData::Vector { element, .. } => vec![*element],
+            TypeData::Alias(_, _, aliased) => vec![*aliased],
+            _ => vec![],
+        };
+        inner
+            .into_iter()
+            .any(|inner| inner == contained || self.contains_by_value(inner, contained, visited))
+    }
+
+    /// Get the name that a variant of an enum type is referred to by, which is the name of the
+    /// variant's type
+    pub fn variant_name(&self, variant: TypeId) -> Symbol {
+        match &self[variant] {
+            TypeData::Alias(name, ..) => *name,
+            _ => self.get_type_name(variant),
+        }
+    }
+
+    /// Get notes suggesting how to check a value of the given type that was used as a boolean
+    /// condition
+    pub fn non_bool_cond_notes(&self, ty: TypeId) -> Vec<String> {
+        match &self[self.unwrap_alias(ty)] {
+            TypeData::Pointer(_) => vec![
+                "Pointers are not implicitly converted to booleans".to_owned(),
+                "Compare the pointer's address to zero to check if it is null, like `($u64 p) != 0u64`"
+                    .to_owned(),
+            ],
+            _ => vec![],
+        }
+    }
+
+    /// Check if more than one module defines a distinct type with the given name
+    fn type_name_ambiguous(&self, name: Symbol) -> bool {
+        let mut defined = HashSet::new();
+        for module in self.modules.iter() {
+            if let Some(SparkDef::TypeDef(_, ty)) = module.defs.get(&name) {
+                if let TypeData::Alias(alias_name, ..) = &self[*ty] {
+                    if *alias_name == name {
+                        defined.insert(*ty);
+                    }
+                }
+            }
+        }
+        defined.len() > 1
+    }
+
+    /// Get the path to a name defined in a module, relative to the root module
+    pub fn qualified_name(&self, module: ModId, name: Symbol) -> String {
+        let mut parts = vec![name.to_string()];
+        let mut current = &self[module];
+        while let Some(parent) = current.parent {
+            parts.push(current.name.to_string());
+            current = &self[parent];
+        }
+        parts.reverse();
+        parts.join(":")
+    }
+
     /// Get the name of a definition
     pub fn get_def_name(&self, def: SparkDef) -> Symbol {
         match def {
             SparkDef::TypeDef(_, ty) => self.get_type_name(ty),
             SparkDef::FunDef(_, fun) => self.funs[fun].name,
             SparkDef::ModDef(module) => self.modules[module].name,
+            SparkDef::GlobalDef(_, global) => self.globals[global].name,
+            SparkDef::InterfaceDef(_, interface) => self.interfaces[interface].name,
         }
     }
 
@@ -104,7 +678,24 @@ impl SparkCtx {
                 true => "f64",
                 false => "f32",
             }),
-            TypeData::Alias(name, _) => name.clone(),
+            TypeData::Alias(name, module, _) => {
+                let name = match self.type_name_ambiguous(*name) {
+                    true => Symbol::from(&self.qualified_name(*module, *name)),
+                    false => *name,
+                };
+                match self.instance_of.get(&type_id) {
+                    Some((_, type_args)) => Symbol::from(&format!(
+                        "{}<{}>",
+                        name,
+                        type_args
+                            .iter()
+                            .map(|ty| self.get_type_name(*ty).to_string())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )),
+                    None => name,
+                }
+            }
             TypeData::Pointer(ty) => Symbol::from(&format!("*{}", self.get_type_name(*ty))),
             TypeData::Unit => Symbol::from("()"),
             TypeData::Bool => Symbol::from("bool"),
@@ -116,17 +707,42 @@ impl SparkCtx {
                     .collect::<Vec<_>>()
                     .join(" | ")
             )),
-            TypeData::Struct { fields } => Symbol::from(&format!(
-                "{{ {} }}",
+            TypeData::Struct { fields, repr, align } => Symbol::from(&format!(
+                "{}{{ {} }}",
+                match (repr, align) {
+                    (StructRepr::Default, None) => "".to_owned(),
+                    (StructRepr::C, None) => "#[repr(C)] ".to_owned(),
+                    (StructRepr::Default, Some(align)) => format!("#[repr(align = \"{}\")] ", align),
+                    (StructRepr::C, Some(align)) => format!("#[repr(C, align = \"{}\")] ", align),
+                },
+                fields
+                    .iter()
+                    .map(|(field, name)| format!("{} {}", self.get_type_name(*field), name))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            )),
+            TypeData::Union { fields } => Symbol::from(&format!(
+                "union {{ {} }}",
                 fields
                     .iter()
                     .map(|(field, name)| format!("{} {}", self.get_type_name(*field), name))
                     .collect::<Vec<_>>()
                     .join(" ")
             )),
+            TypeData::Tuple { elements } => Symbol::from(&format!(
+                "({})",
+                elements
+                    .iter()
+                    .map(|ty| self.get_type_name(*ty).to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
             TypeData::Array { element, len } => {
                 Symbol::from(&format!("[{}]{}", len, self.get_type_name(*element)))
             }
+            TypeData::Vector { element, len } => {
+                Symbol::from(&format!("<{} x {}>", len, self.get_type_name(*element)))
+            }
             TypeData::Function(f_ty) => Symbol::from(&format!(
                 "fun({})->{}",
                 f_ty.args
@@ -136,38 +752,60 @@ impl SparkCtx {
                     .join(", "),
                 self.get_type_name(f_ty.return_ty),
             )),
+            TypeData::Generic(name) => *name,
             TypeData::Invalid => Symbol::from("INVALID TYPE"),
         }
     }
 
-    /// Get a definition by path from the given module, returns the symbol that is unresolved if
-    /// error occurs
-    pub fn get_def(&self, module: ModId, path: &SymbolPath) -> Result<SparkDef, Symbol> {
+    /// Get the module that the last part of a path is looked up in when the path is accessed
+    /// from inside the given module
+    pub fn path_module(&self, mut module: ModId, path: &SymbolPath) -> Option<ModId> {
+        let mut parts = path.iter();
+        while parts.len() > 1 {
+            match self[module].defs.get(&parts.next().unwrap()) {
+                Some(SparkDef::ModDef(child)) => module = *child,
+                _ => return None,
+            }
+        }
+        Some(module)
+    }
+
+    /// Get a definition by path from inside the given module, definitions in other modules
+    /// must be public to be accessed
+    pub fn get_def(&self, module: ModId, path: &SymbolPath) -> Result<SparkDef, DefLookupError> {
         let parts = path.iter();
-        self.get_def_impl(module, parts)
+        self.get_def_impl(module, parts, false)
     }
 
-    pub fn get_def_impl(&self, module: ModId, mut parts: PathIter<'_>) -> Result<SparkDef, Symbol> {
-        if parts.len() == 1 {
-            let name = parts.next().unwrap();
-            let def = self.modules[module].defs.get(&name);
-            def.copied().ok_or(name)
-        } else {
-            let name = parts.next().expect("invariant in get_def_impl");
-            let def = self[module].defs.get(&name);
-            if let Some(def) = def {
-                if let SparkDef::ModDef(mod_id) = def {
-                    return self.get_def_impl(*mod_id, parts);
-                } else if parts.is_final() {
-                    if let SparkDef::TypeDef(_, _) = def {
-                        unimplemented!("Functions associated with types not implemented");
-                    }
-                }
+    /// Look up the path in parts relative to the given module, if `exported` is true then the
+    /// module is being accessed from outside and the definition must be public
+    pub fn get_def_impl(
+        &self,
+        module: ModId,
+        mut parts: PathIter<'_>,
+        exported: bool,
+    ) -> Result<SparkDef, DefLookupError> {
+        let name = parts.next().expect("invariant in get_def_impl");
+        let def = self.modules[module]
+            .defs
+            .get(&name)
+            .copied()
+            .ok_or(DefLookupError::NotFound(name))?;
+        if exported
+            && !matches!(def, SparkDef::ModDef(_))
+            && !self[module].public.contains(&name)
+        {
+            return Err(DefLookupError::Private(name, self[module].name));
+        }
 
-                Err(name)
-            } else {
-                Err(name)
-            }
+        if parts.len() == 0 {
+            Ok(def)
+        } else if let SparkDef::ModDef(mod_id) = def {
+            self.get_def_impl(mod_id, parts, true)
+        } else {
+            //Paths through a type like `temperature:celsius` name enum variants, which are
+            //resolved as types instead of definitions
+            Err(DefLookupError::NotFound(name))
         }
     }
 
@@ -233,6 +871,13 @@ impl SparkCtx {
             types,
             modules,
             funs: Arena::new(),
+            globals: Arena::new(),
+            interfaces: Arena::new(),
+            instances: HashMap::new(),
+            type_templates: HashMap::new(),
+            type_instances: HashMap::new(),
+            instance_of: HashMap::new(),
+            field_defaults: HashMap::new(),
         }
     }
 }
@@ -255,6 +900,157 @@ pub struct Function {
     pub span: Span,
     pub arg_names: Vec<Option<Symbol>>,
     pub body: Option<Vec<Ast<TypeId>>>,
+    /// All functions defined inside of this function's body
+    pub nested: Vec<FunId>,
+    /// Linkage set by an attribute, or `None` for the default linkage
+    pub linkage: Option<DefLinkage>,
+    /// Visibility set by an attribute, or `None` for default visibility
+    pub visibility: Option<DefVisibility>,
+    /// Calling convention set by an attribute, or `None` for the C calling convention
+    pub call_conv: Option<CallConv>,
+    /// Optimization set by an attribute, or `None` to optimize at the module's level
+    pub optimization: Option<DefOptimization>,
+    /// Type parameters of a generic function, or `None` if the function is not generic
+    pub generic: Option<GenericParams>,
+}
+
+/// The type parameters of a generic function and the module it is defined in, which its
+/// instances look up names in
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenericParams {
+    /// The [Generic](TypeData::Generic) type of each type parameter
+    pub params: Vec<TypeId>,
+    pub module: ModId,
+    /// The interface that declares this function, with implementations in place of a body
+    pub interface: Option<InterfaceId>,
+}
+
+/// A set of functions declared generic over the type implementing them, with definitions
+/// provided by implementations for each type
+#[derive(Clone, Debug)]
+pub struct Interface {
+    pub id: InterfaceId,
+    pub name: Symbol,
+    /// Functions declared by the interface, with `Self` as their only type parameter
+    pub funs: Vec<FunId>,
+    /// Implementations of the interface for each type
+    pub impls: HashMap<TypeId, Implementation>,
+}
+
+/// Definitions of all functions in an interface for one type
+#[derive(Clone, Debug)]
+pub struct Implementation {
+    pub module: ModId,
+    pub file: FileId,
+    /// The function defining each function of the interface, in the order they are declared
+    pub funs: Vec<FunId>,
+}
+
+bitflags! {
+    /// Structure holding flags of a global variable, set by attributes on its definition
+    pub struct GlobalFlags: u8 {
+        /// Each thread has its own copy of the global
+        const THREAD_LOCAL = 0b00000001;
+        /// The global was declared with `const` and its initializer can be used in constant
+        /// expressions
+        const CONSTANT = 0b00000010;
+    }
+}
+
+/// Linkage of a function or global variable that can be set with an attribute, all
+/// symbols with a set linkage keep their name in the output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefLinkage {
+    /// Visible to other objects, set with `#[external]`
+    External,
+    /// Visible to other objects and may be overridden by a non-weak definition, set with `#[weak]`
+    Weak,
+    /// Merged with other definitions of the same name, set with `#[linkonce_odr]`
+    LinkOnceOdr,
+}
+
+/// Non-default calling convention of a function, set with `#[callconv("name")]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CallConv {
+    /// Calls may be optimized for speed without following any ABI, `fast`
+    Fast,
+    /// Calls are expected to be rare and preserve most registers, `cold`
+    Cold,
+    /// The x86 Win32 API convention where the callee pops arguments, `stdcall`
+    Stdcall,
+    /// The x86 convention passing the first two arguments in registers, `fastcall`
+    Fastcall,
+    /// The x86 convention used for C++ member functions on Windows, `thiscall`
+    Thiscall,
+    /// The x86 convention passing vector arguments in registers, `vectorcall`
+    Vectorcall,
+    /// The Windows x86-64 convention, `win64`
+    Win64,
+    /// The System V x86-64 convention, `sysv64`
+    Sysv64,
+    /// An entry point of an NVPTX GPU kernel, `ptx_kernel`
+    PtxKernel,
+    /// An entry point of an AMDGPU kernel, `amdgpu_kernel`
+    AmdgpuKernel,
+}
+
+impl CallConv {
+    /// All calling conventions with the name they are selected by in an attribute
+    pub const NAMES: &'static [(&'static str, Self)] = &[
+        ("fast", Self::Fast),
+        ("cold", Self::Cold),
+        ("stdcall", Self::Stdcall),
+        ("fastcall", Self::Fastcall),
+        ("thiscall", Self::Thiscall),
+        ("vectorcall", Self::Vectorcall),
+        ("win64", Self::Win64),
+        ("sysv64", Self::Sysv64),
+        ("ptx_kernel", Self::PtxKernel),
+        ("amdgpu_kernel", Self::AmdgpuKernel),
+    ];
+}
+
+/// Non-default visibility of an externally linked function or global variable, set with an
+/// attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefVisibility {
+    /// Not exported from a shared library, set with `#[hidden]`
+    Hidden,
+    /// Exported from a shared library but can't be preempted, set with `#[protected]`
+    Protected,
+}
+
+/// How a function is optimized when it differs from the rest of the module, set with an
+/// attribute
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefOptimization {
+    /// Not optimized or inlined at all, set with `#[optnone]`
+    None,
+    /// Optimized for code size, set with `#[optsize]`
+    Size,
+    /// Frequently executed and optimized aggressively, set with `#[hot]`
+    Hot,
+    /// Rarely executed and kept out of hot paths, set with `#[cold]`
+    Cold,
+}
+
+/// A global variable with a constant initializer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Global {
+    pub id: GlobalId,
+    pub name: Symbol,
+    /// Declared type of the global, or `None` if the type is inferred from the initializer
+    pub ty: Option<TypeId>,
+    pub mutable: bool,
+    pub flags: GlobalFlags,
+    /// Linkage set by an attribute, or `None` for the default linkage
+    pub linkage: Option<DefLinkage>,
+    /// Visibility set by an attribute, or `None` for default visibility
+    pub visibility: Option<DefVisibility>,
+    /// The initializer of the global, or `None` if the global is external and defined in
+    /// another object
+    pub init: Option<Ast<TypeId>>,
+    pub span: Span,
 }
 
 /// A single type, either user-defined or predefined
@@ -274,18 +1070,46 @@ pub enum TypeData {
         element: TypeId,
         len: u64,
     },
+    /// Fixed-width SIMD vector of integer or float elements
+    Vector {
+        element: TypeId,
+        len: u32,
+    },
     Struct {
         fields: Vec<(TypeId, Symbol)>,
+        repr: StructRepr,
+        /// Minimum alignment in bytes of values of this type, set with `#[repr(align = "N")]`
+        align: Option<u32>,
+    },
+    /// Untagged union with all fields stored at the same address
+    Union {
+        fields: Vec<(TypeId, Symbol)>,
+    },
+    Tuple {
+        elements: Vec<TypeId>,
     },
     Enum {
         parts: Vec<TypeId>,
     },
-    Alias(Symbol, TypeId),
+    /// A named type with the module it was defined in and the type it names
+    Alias(Symbol, ModId, TypeId),
     Function(FunctionType),
+    /// A type parameter of a generic function, replaced by a concrete type when the function
+    /// is instantiated
+    Generic(Symbol),
     /// For internal compiler use only
     Invalid,
 }
 
+/// Layout of a structure type's fields in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StructRepr {
+    /// The compiler may reorder fields to reduce the size of the structure
+    Default,
+    /// Fields are laid out in declaration order like a C struct, set with `#[repr(C)]`
+    C,
+}
+
 /// A function's type including argument types, return type, and flags
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct FunctionType {
@@ -293,12 +1117,53 @@ pub struct FunctionType {
     pub args: Vec<TypeId>,
 }
 
+/// Reasons that looking up a definition by path can fail
+#[derive(Clone, Copy, Debug)]
+pub enum DefLookupError {
+    /// No definition with the given name exists
+    NotFound(Symbol),
+    /// The definition exists but is private to the module it is defined in
+    Private(Symbol, Symbol),
+}
+
+impl fmt::Display for DefLookupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotFound(name) => write!(f, "'{}' not found", name),
+            Self::Private(name, module) => {
+                write!(f, "'{}' is private to module '{}'", name, module)
+            }
+        }
+    }
+}
+
 /// Structure holding all definitions contained in a single module
 #[derive(Clone)]
 pub struct SparkModule {
     pub id: ModId,
     pub name: Symbol,
+    /// The module that this module is a child of, or `None` for the root module
+    pub parent: Option<ModId>,
     pub defs: ScopeMap<Symbol, SparkDef>,
+    /// Names of all definitions that were marked `pub` and can be accessed from other modules
+    pub public: HashSet<Symbol>,
+    /// Position of each definition in the module's source files, used to generate
+    /// definitions in the order they were written
+    pub order: HashMap<Symbol, usize>,
+}
+
+impl SparkModule {
+    /// Get all definitions in this module in source order, followed by definitions that have
+    /// no source position like imports and child modules
+    pub fn ordered_defs(&self) -> Vec<(Symbol, SparkDef)> {
+        let mut defs = self
+            .defs
+            .iter()
+            .map(|(name, def)| (*name, *def))
+            .collect::<Vec<_>>();
+        defs.sort_by_key(|(name, _)| self.order.get(name).copied().unwrap_or(usize::MAX));
+        defs
+    }
 }
 
 impl std::fmt::Debug for SparkModule {
@@ -318,7 +1183,9 @@ impl std::fmt::Debug for SparkModule {
 pub enum SparkDef {
     TypeDef(FileId, TypeId),
     FunDef(FileId, FunId),
+    GlobalDef(FileId, GlobalId),
     ModDef(ModId),
+    InterfaceDef(FileId, InterfaceId),
 }
 
 impl ops::Index<TypeId> for SparkCtx {
@@ -354,3 +1221,239 @@ impl ops::IndexMut<FunId> for SparkCtx {
         self.funs.get_mut(index)
     }
 }
+impl ops::Index<InterfaceId> for SparkCtx {
+    type Output = Interface;
+    fn index(&self, index: InterfaceId) -> &Self::Output {
+        self.interfaces.get(index)
+    }
+}
+impl ops::IndexMut<InterfaceId> for SparkCtx {
+    fn index_mut(&mut self, index: InterfaceId) -> &mut Self::Output {
+        self.interfaces.get_mut(index)
+    }
+}
+impl ops::Index<GlobalId> for SparkCtx {
+    type Output = Global;
+    fn index(&self, index: GlobalId) -> &Self::Output {
+        self.globals.get(index)
+    }
+}
+impl ops::IndexMut<GlobalId> for SparkCtx {
+    fn index_mut(&mut self, index: GlobalId) -> &mut Self::Output {
+        self.globals.get_mut(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        codegen::lower::Lowerer,
+        parse::Parser,
+        util::files::{CompiledFile, Files},
+    };
+
+    #[test]
+    fn functions_of_a_module_are_iterated() {
+        let src = "fun first() {}\nfun second() -> i32 { return 1 }\n";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files)
+            .lower_module(&module)
+            .unwrap_or_else(|_| panic!("Failed to lower module"));
+
+        let mut names = ctx
+            .funs()
+            .map(|(id, fun)| {
+                assert_eq!(ctx[id].name, fun.name);
+                fun.name.as_str()
+            })
+            .collect::<Vec<_>>();
+        names.sort_unstable();
+        assert_eq!(names, vec!["first", "second"]);
+
+        assert!(ctx
+            .modules()
+            .any(|(id, module)| id == root && module.name.as_str() == "root"));
+        assert!(ctx.types().count() > 0);
+    }
+
+    /// Lower a root module and a child module that are each parsed from their own file
+    fn lower_with_child(
+        root_src: &str,
+        child_name: &str,
+        child_src: &str,
+    ) -> (SparkCtx, Result<ModId, String>) {
+        let mut files = Files::new();
+        let root_file = files.add(CompiledFile::in_memory(root_src.to_owned()));
+        let child_file = files.add(CompiledFile::in_memory(child_src.to_owned()));
+        let mut root = Parser::new(root_src)
+            .parse(Symbol::from("root"), root_file)
+            .unwrap_or_else(|_| panic!("Failed to parse root module"));
+        let child = Parser::new(child_src)
+            .parse(Symbol::from(child_name), child_file)
+            .unwrap_or_else(|_| panic!("Failed to parse child module"));
+        root.children.insert(Symbol::from(child_name), child);
+
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files)
+            .lower_module(&root)
+            .map_err(|e| e.message);
+        (ctx, root)
+    }
+
+    #[test]
+    fn imports_resolve_definitions_in_other_files() {
+        let (ctx, root) = lower_with_child(
+            "imp lib:double\nfun ext quadruple(i32 a) -> i32 {\n    return double.(double.(a))\n}\n",
+            "lib",
+            "pub fun double(i32 a) -> i32 {\n    return a * 2\n}\n",
+        );
+        let root = root.unwrap();
+        let double = Symbol::from("double");
+        let imported = ctx.get_def(root, &SymbolPath::new(double));
+        let defined = ctx.get_def(root, &SymbolPath::new_parts(&[Symbol::from("lib"), double]));
+        match (imported, defined) {
+            (Ok(SparkDef::FunDef(_, imported)), Ok(SparkDef::FunDef(_, defined))) => {
+                assert_eq!(imported, defined)
+            }
+            _ => panic!("Imported function was not resolved"),
+        }
+    }
+
+    #[test]
+    fn private_definitions_cannot_be_imported() {
+        let (_, root) = lower_with_child(
+            "imp lib:helper\n",
+            "lib",
+            "fun helper(i32 a) -> i32 {\n    return a\n}\n",
+        );
+        assert_eq!(
+            root.unwrap_err(),
+            "Imported item 'helper' is private to module 'lib'"
+        );
+    }
+
+    #[test]
+    fn paths_through_types_are_not_definitions() {
+        let src = "type celsius = f32\ntype kelvin = f32\ntype temperature = celsius | kelvin\n";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files)
+            .lower_module(&module)
+            .unwrap_or_else(|_| panic!("Failed to lower module"));
+
+        let variant = SymbolPath::new_parts(&[Symbol::from("temperature"), Symbol::from("celsius")]);
+        assert!(matches!(
+            ctx.get_def(root, &variant),
+            Err(DefLookupError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn function_bodies_resolve_declared_structure_types() {
+        let src = "type point = { i32 x, i32 y }\nfun ext origin() -> point {\n    let (point) p = #point { x = 0, y = 0 }\n    return p\n}\n";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        Lowerer::new(&mut ctx, &files)
+            .lower_module(&module)
+            .unwrap_or_else(|e| panic!("Failed to lower module: {}", e.message));
+
+        let (_, origin) = ctx
+            .funs()
+            .find(|(_, fun)| fun.name.as_str() == "origin")
+            .unwrap();
+        let point = origin.ty.return_ty;
+        match &ctx[ctx.unwrap_alias(point)] {
+            TypeData::Struct { fields, .. } => assert_eq!(fields.len(), 2),
+            _ => panic!("point is not a structure type"),
+        }
+
+        let body = origin.body.as_ref().unwrap();
+        match &body[0].node {
+            AstNode::Assignment { lhs, rhs } => {
+                assert!(matches!(lhs.node, AstNode::VarDeclaration { ty: Some(ty), .. } if ty == point));
+                assert!(matches!(rhs.node, AstNode::Literal(Literal::Struct { ty: Some(ty), .. }) if ty == point));
+            }
+            _ => panic!("First statement is not an assignment"),
+        }
+    }
+
+    /// Lower a root module parsed from a single file
+    fn lower_src(src: &str) -> (SparkCtx, Result<ModId, String>) {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files)
+            .lower_module(&module)
+            .map_err(|e| e.message);
+        (ctx, root)
+    }
+
+    #[test]
+    fn array_lengths_fold_constant_globals() {
+        let (ctx, root) = lower_src("const N = 8\nfun ext first([N]i32 values) -> i32 {\n    return values[0]\n}\n");
+        root.unwrap_or_else(|e| panic!("Failed to lower module: {}", e));
+        let (_, first) = ctx.funs().find(|(_, fun)| fun.name.as_str() == "first").unwrap();
+        assert!(matches!(
+            ctx[ctx.unwrap_alias(first.ty.args[0])],
+            TypeData::Array { len: 8, .. }
+        ));
+
+        let (_, root) = lower_src("let N = 8\nfun ext first([N]i32 values) -> i32 {\n    return values[0]\n}\n");
+        assert_eq!(root.unwrap_err(), "Global variable 'N' is not a constant");
+    }
+
+    #[test]
+    fn ambiguous_type_names_are_qualified_with_their_module() {
+        let (ctx, root) = lower_with_child(
+            "type point = { i32 x, i32 y }\ntype size = { i32 w, i32 h }\n",
+            "geo",
+            "pub type point = { f64 x, f64 y }\n",
+        );
+        let root = root.unwrap();
+        let type_in = |module: ModId, name: &str| match ctx[module].defs.get(&Symbol::from(name)) {
+            Some(SparkDef::TypeDef(_, ty)) => *ty,
+            _ => panic!("{} is not a type", name),
+        };
+        let geo = ctx
+            .modules()
+            .find(|(_, module)| module.name.as_str() == "geo")
+            .map(|(id, _)| id)
+            .unwrap();
+
+        assert_eq!(ctx.get_type_name(type_in(root, "point")).as_str(), "point");
+        assert_eq!(ctx.get_type_name(type_in(geo, "point")).as_str(), "geo:point");
+        assert_eq!(ctx.get_type_name(type_in(root, "size")).as_str(), "size");
+    }
+
+    #[test]
+    fn structurally_identical_anonymous_types_are_interned() {
+        let (ctx, root) = lower_src("fun ext span_len({i32 first, i32 last} span) -> i32 {\n    return span.last - span.first\n}\nfun ext span_start({i32 first, i32 last} span, (i32, i32) pair) -> (i32, i32) {\n    return pair\n}\n");
+        root.unwrap_or_else(|e| panic!("Failed to lower module: {}", e));
+        let fun = |name: &str| &ctx.funs().find(|(_, fun)| fun.name.as_str() == name).unwrap().1.ty;
+        assert_eq!(fun("span_len").args[0], fun("span_start").args[0]);
+        assert_eq!(fun("span_start").args[1], fun("span_start").return_ty);
+
+        let mut ctx = SparkCtx::new();
+        let pair = |ctx: &mut SparkCtx| {
+            ctx.new_type(TypeData::Tuple {
+                elements: vec![SparkCtx::I32, SparkCtx::BOOL],
+            })
+        };
+        assert_eq!(pair(&mut ctx), pair(&mut ctx));
+    }
+
+    #[test]
+    fn unknown_cfg_predicates_are_rejected() {
+        let (_, root) = lower_src("#[cfg(os = \"linux\")]\nfun ext page_size() -> u32 {\n    return 4096u32\n}\n");
+        assert_eq!(root.unwrap_err(), "Invalid arguments to cfg attribute");
+    }
+}
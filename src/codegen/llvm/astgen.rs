@@ -1,8 +1,15 @@
 use codespan_reporting::diagnostic::{Diagnostic, Label};
-use inkwell::{types::IntType, values::CallableValue, FloatPredicate, IntPredicate};
+use inkwell::{
+    types::{IntType, StructType},
+    values::{ArrayValue, CallableValue, IntValue, StructValue},
+    FloatPredicate, IntPredicate,
+};
 
 use crate::{
-    ast::{Ast, AstNode, ElseExpr, IfExpr, Literal, NumberLiteral, NumberLiteralAnnotation},
+    ast::{
+        Ast, AstNode, BigInt, DestructurePattern, ElseExpr, IfExpr, Literal, MatchPattern, NumberLiteral,
+        NumberLiteralAnnotation,
+    },
     parse::token::Op,
     util::files::FileId, codegen::CompilerRes,
 };
@@ -17,18 +24,26 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         ast: &Ast<TypeId>,
     ) -> CompilerRes<()> {
         match &ast.node {
-            AstNode::Block(block) => {
-                self.gen_block_ast(module, block)?;
+            AstNode::Block(label, block) => {
+                self.gen_block_ast(module, *label, block)?;
             }
             AstNode::IfExpr(if_expr) => {
                 self.gen_if_expr(module, if_expr)?;
             }
+            AstNode::While { label, cond, body } => {
+                self.gen_while(module, *label, cond, body)?;
+            }
             AstNode::FunCall(called, args) => {
                 self.gen_call( module, called, args)?;
             }
             AstNode::Match { matched, cases } => {
                 self.gen_match_expr(module, matched, cases, ast.span)?;
             }
+            AstNode::Assignment { lhs, rhs } if matches!(lhs.node, AstNode::Destructure { .. }) => {
+                if let AstNode::Destructure { pattern, .. } = &lhs.node {
+                    self.gen_destructure(module, pattern, lhs.span, rhs)?;
+                }
+            }
             AstNode::Assignment { lhs, rhs } => {
                 let rhs_ty = self.ast_type(module, rhs)?;
 
@@ -52,7 +67,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         ]));
                 }
 
-                let lhs = if let AstNode::VarDeclaration {
+                let lhs_ptr = if let AstNode::VarDeclaration {
                     name,
                     ty: _,
                     mutable: _,
@@ -60,7 +75,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 {
                     let llvm_ty = Self::require_basictype(self.file, ast.span, self.llvm_ty(ast.span, lhs_ty)?)?;
                     if let Ok(llvm_ty) = BasicTypeEnum::try_from(llvm_ty) {
-                        let pv = self.builder.build_alloca(llvm_ty, "var_dec_aloca");
+                        let pv = self.build_var_alloca(*name, lhs_ty, llvm_ty);
                         self.current_scope
                             .define(*name, ScopeDef::Value(lhs_ty, pv));
                         pv
@@ -76,15 +91,36 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                     self.gen_lval(module, lhs)?
                 };
 
+                if self.opts.warn_self_assign && Self::same_lvalue(lhs, rhs) {
+                    self.diags.emit(Diagnostic::warning()
+                        .with_message("Assigning a value to itself has no effect")
+                        .with_labels(vec![
+                            Label::primary(self.file, ast.span)
+                                .with_message("Both sides of this assignment refer to the same location"),
+                        ]));
+                }
+
+                let assigned = match &lhs.node {
+                    AstNode::VarDeclaration { name, .. } => Some(*name),
+                    AstNode::Access(path) if path.len() == 1 => Some(path.last()),
+                    _ => None,
+                };
+                if let Some(assigned) = assigned {
+                    match self.local_address_of(rhs) {
+                        Some(local) => self.local_refs.insert(assigned, local),
+                        None => self.local_refs.remove(&assigned),
+                    };
+                }
+
                 let rhs = self.gen_expr(module, rhs)?;
 
-                self.builder.build_store(lhs, rhs);
+                self.builder.build_store(lhs_ptr, rhs);
             }
             AstNode::VarDeclaration { name, ty, mutable } => {
                 if let Some(ty) = ty {
                     let llvm_ty = self.llvm_ty(ast.span, *ty)?;
                     if let Ok(llvm_ty) = BasicTypeEnum::try_from(llvm_ty) {
-                        let pv = self.builder.build_alloca(llvm_ty, name.as_str());
+                        let pv = self.build_var_alloca(*name, *ty, llvm_ty);
                         self.current_scope
                             .define(*name, ScopeDef::Value(*ty, pv.into()));
                     } else {
@@ -104,6 +140,11 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 }
             }
             AstNode::Return(returned) => {
+                //Unannotated number literals take on the declared return type
+                let return_ty = self.spark[self.current_fun.unwrap().1].ty.return_ty;
+                let coerced = self.spark.coerce_literal(returned, return_ty);
+                let returned = coerced.as_ref().unwrap_or(&**returned);
+
                 let returned_ty = self.ast_type(module, returned).map_err(|e| {
                     e.with_labels(vec![
                         Label::secondary(self.file, ast.span).with_message("In this return statement")
@@ -125,6 +166,18 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
 
                 self.placed_terminator = true;
 
+                if let Some(local) = self.local_address_of(returned) {
+                    self.diags.emit(Diagnostic::warning()
+                        .with_message("Returning the address of a stack-allocated local variable")
+                        .with_labels(vec![
+                            Label::primary(self.file, returned.span)
+                                .with_message("Returned pointer here"),
+                            Label::secondary(self.file, local)
+                                .with_message("Address of local taken here, which is invalid after the function returns"),
+                        ]));
+                }
+
+                let current_fun = &self.spark[self.current_fun.unwrap().1];
                 if current_fun.ty.return_ty != SparkCtx::UNIT {
                     let returned = self.gen_expr(module, returned)?;
                     self.builder.build_return(Some(&returned));
@@ -141,7 +194,9 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                             .with_message("Phi statement returns a value with type different to expected type")
                             .with_labels(vec![
                                 Label::primary(self.file, phi.span)
-                                    .with_message(format!("Phi statement of type '{}' encountered here", self.spark.get_type_name(phid_ty)))
+                                    .with_message(format!("Phi statement of type '{}' encountered here", self.spark.get_type_name(phid_ty))),
+                                Label::secondary(self.file, phi_data.phi_span)
+                                    .with_message(format!("Expected type '{}' inferred from this phi statement", self.spark.get_type_name(phi_data.phi_ty))),
                             ])
                         );
                     }
@@ -152,25 +207,101 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
 
                     self.builder.build_unconditional_branch(phi_data.break_bb);
                 } else {
+                    let enclosing = &self.spark[self.current_fun.unwrap().1];
                     return Err(Diagnostic::error()
-                        .with_message("Phi statement not in a block")
-                        .with_labels(vec![Label::primary(self.file, ast.span)]));
+                        .with_message("Phi statement not in a value-producing block")
+                        .with_labels(vec![
+                            Label::primary(self.file, ast.span)
+                                .with_message("Phi statement used here"),
+                            Label::secondary(self.file, enclosing.span)
+                                .with_message(format!("In function '{}', which must return its value instead", enclosing.name)),
+                        ])
+                        .with_notes(vec![
+                            "phi can only be used to produce the value of an if, match, or block expression".to_owned(),
+                            "Use return to return a value from a function".to_owned(),
+                        ]));
                 }
             }
-            AstNode::Break => {
-                if let Some(break_bb) = self.break_bb {
-                    self.placed_terminator = true;
-                    self.builder.build_unconditional_branch(break_bb);
+            AstNode::Destructure { .. } => {
+                return Err(Diagnostic::error()
+                    .with_message("Must assign a value to destructure")
+                    .with_labels(vec![Label::primary(self.file, ast.span)
+                        .with_message("In this destructuring declaration")]));
+            }
+            AstNode::FunDef(proto, _) => {
+                let enclosing = self.current_fun.unwrap().1;
+                let nested = self.spark[enclosing]
+                    .nested
+                    .iter()
+                    .copied()
+                    .find(|nested| {
+                        self.spark[*nested].name == proto.name && self.spark[*nested].span == ast.span
+                    })
+                    .ok_or_else(|| Diagnostic::error()
+                        .with_message(format!("Nested function '{}' was not lowered", proto.name))
+                        .with_labels(vec![Label::primary(self.file, ast.span)
+                            .with_message("Nested function defined here")]))?;
+                self.current_scope
+                    .define(proto.name, ScopeDef::Def(SparkDef::FunDef(self.file, nested)));
+            }
+            //Local type aliases were already replaced by their aliased types when lowering
+            AstNode::TypeDef(..) => (),
+            AstNode::Break(label, value) => {
+                let loop_data = if let Some(loop_data) = self.find_loop(*label) {
+                    loop_data
+                } else if let Some(label) = label {
+                    return Err(self.unknown_label(*label, ast.span));
                 } else {
                     return Err(Diagnostic::error()
                         .with_message("Break statement encountered while not in a block")
                         .with_labels(vec![Label::primary(self.file, ast.span)]));
+                };
+
+                match (value, loop_data.phi) {
+                    (Some(value), Some(phi_data)) => {
+                        let value_ty = self.ast_type(module, value)?;
+                        if value_ty != phi_data.phi_ty {
+                            return Err(Diagnostic::error()
+                                .with_message("Break statement returns a value with type different to expected type")
+                                .with_labels(vec![
+                                    Label::primary(self.file, value.span)
+                                        .with_message(format!("Break value of type '{}' encountered here", self.spark.get_type_name(value_ty))),
+                                    Label::secondary(self.file, phi_data.phi_span)
+                                        .with_message(format!("Expected type '{}' inferred from this value", self.spark.get_type_name(phi_data.phi_ty))),
+                                ])
+                            );
+                        }
+
+                        let value = self.gen_expr(module, value)?;
+                        self.builder.build_store(phi_data.alloca, value);
+                    }
+                    (Some(value), None) => {
+                        return Err(Diagnostic::error()
+                            .with_message("Break statement with a value out of a block that does not produce a value")
+                            .with_labels(vec![Label::primary(self.file, value.span)
+                                .with_message("Break value encountered here")]));
+                    }
+                    (None, Some(phi_data)) => {
+                        return Err(Diagnostic::error()
+                            .with_message("Break statement without a value out of a value-producing block")
+                            .with_labels(vec![
+                                Label::primary(self.file, ast.span),
+                                Label::secondary(self.file, phi_data.phi_span)
+                                    .with_message("Block value produced here"),
+                            ]));
+                    }
+                    (None, None) => (),
                 }
+
+                self.placed_terminator = true;
+                self.builder.build_unconditional_branch(loop_data.break_bb);
             }
-            AstNode::Continue => {
-                if let Some(continue_bb) = self.continue_bb {
+            AstNode::Continue(label) => {
+                if let Some(loop_data) = self.find_loop(*label) {
                     self.placed_terminator = true;
-                    self.builder.build_unconditional_branch(continue_bb);
+                    self.builder.build_unconditional_branch(loop_data.continue_bb);
+                } else if let Some(label) = label {
+                    return Err(self.unknown_label(*label, ast.span));
                 } else {
                     return Err(Diagnostic::error()
                         .with_message("Continue statement while not in a block")
@@ -178,10 +309,9 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                             .with_message("Continue statement encountered here")]));
                 }
             }
-            other => {
-                return Err(Diagnostic::error()
-                    .with_message(format!("Invalid statement: {:#?}", other))
-                    .with_labels(vec![Label::primary(self.file, ast.span)]))
+            //Any other expression is evaluated for its side effects and the value discarded
+            _ => {
+                self.gen_expr(module, ast)?;
             }
         }
 
@@ -189,7 +319,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     }
 
     /// Generate code for a single AST expression
-    fn gen_expr(
+    pub(super) fn gen_expr(
         &mut self,
         module: ModId,
         ast: &Ast<TypeId>,
@@ -205,6 +335,9 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             }
             AstNode::CastExpr(to, rhs) => self.gen_cast(module, *to, rhs)?,
             AstNode::Access(path) => {
+                if let Some((_, idx)) = self.enum_variant(ast.span, path)? {
+                    return Ok(self.ctx.i8_type().const_int(idx as u64, false).into());
+                }
                 let access = self.gen_access(ast.span, path)?;
                 if access.get_type().get_element_type().is_function_type() {
                     access.into()
@@ -216,11 +349,38 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 let rhs_ty = self.ast_type(module, rhs)?;
                 match op {
                     Op::AND => {
+                        if let AstNode::FunCall(..) = rhs.node {
+                            return Err(Diagnostic::error()
+                                .with_message("Cannot take the address of a function's return value")
+                                .with_labels(vec![Label::primary(self.file, rhs.span)
+                                    .with_message("The value returned by this call is a temporary that is dropped after the expression")])
+                                .with_notes(vec![
+                                    "Assign the returned value to a variable and take the address of the variable instead".to_owned(),
+                                ]));
+                        }
+                        if !Self::is_lvalue(rhs) {
+                            return Err(Diagnostic::error()
+                                .with_message("Cannot take the address of a temporary value")
+                                .with_labels(vec![Label::primary(self.file, rhs.span)
+                                    .with_message("This expression does not refer to a variable, field, or array element")])
+                                .with_notes(vec![
+                                    "Assign the value to a variable and take the address of the variable instead".to_owned(),
+                                ]));
+                        }
                         let lval = self.gen_lval(module, rhs)?;
                         lval.into()
                     }
                     Op::Star => {
-                        if let TypeData::Pointer(_) = &self.spark[rhs_ty] {
+                        if let TypeData::Pointer(pointee) = &self.spark[rhs_ty] {
+                            if self.spark[self.spark.unwrap_alias(*pointee)] == TypeData::Unit {
+                                return Err(Diagnostic::error()
+                                    .with_message("Cannot dereference opaque pointer type *()")
+                                    .with_labels(vec![Label::primary(self.file, ast.span)])
+                                    .with_notes(vec![
+                                        "Cast the pointer to a pointer of a specific type before dereferencing".to_owned(),
+                                    ]));
+                            }
+
                             let pv = self.gen_expr(module, rhs)?.into_pointer_value();
                             let deref = self.builder.build_load(pv, "deref_load");
                             deref
@@ -249,10 +409,21 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                             .with_message("This is found to be of function type returning '()'")]))
                 }
             },
-            AstNode::BinExpr(lhs, op, rhs) => {
-                return self.gen_bin_expr(module, lhs, *op, rhs)
+            AstNode::BinExpr(lhs, op, op_span, rhs) => {
+                return self.gen_bin_expr(module, lhs, *op, *op_span, rhs)
             }
             AstNode::Literal(literal) => self.gen_literal(module, literal, ast.span)?,
+            AstNode::Index { object, index } if self.is_vector(module, object)? => {
+                let vector_ty = self.ast_type(module, object)?;
+                let vector = self.gen_expr(module, object)?.into_vector_value();
+                let lane = self.gen_int_index(module, index, "vector")?;
+                self.check_const_index(lane, vector.get_type().get_size() as u64, index, vector_ty, "a vector")?;
+                self.builder.build_extract_element(vector, lane, "vector_lane")
+            }
+            AstNode::Index { object, .. } if self.is_array(module, object)? => {
+                let element = self.gen_lval(module, ast)?;
+                self.builder.build_load(element, "array_element")
+            }
             _ => {
                 return Err(Diagnostic::error()
                     .with_message("Expression not yet implemented")
@@ -261,17 +432,154 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         })
     }
 
+    /// Generate code binding each element of an aggregate value to new variables in the current
+    /// scope
+    fn gen_destructure(
+        &mut self,
+        module: ModId,
+        pattern: &DestructurePattern,
+        span: Span,
+        rhs: &Ast<TypeId>,
+    ) -> CompilerRes<()> {
+        let rhs_ty = self.ast_type(module, rhs)?;
+        let bindings = self.destructure_bindings(pattern, span, rhs.span, rhs_ty)?;
+        let aggregate = self.gen_expr(module, rhs)?.into_struct_value();
+        self.bind_destructured(aggregate, span, bindings)
+    }
+
+    /// Get the index in the generated LLVM aggregate, name, and type of every variable bound by
+    /// destructuring a value of type `rhs_ty` with a pattern
+    fn destructure_bindings(
+        &mut self,
+        pattern: &DestructurePattern,
+        span: Span,
+        rhs_span: Span,
+        rhs_ty: TypeId,
+    ) -> CompilerRes<Vec<(usize, Symbol, TypeId)>> {
+        let rhs_ty = self.spark.unwrap_alias(rhs_ty);
+
+        let bindings = match (pattern, &self.spark[rhs_ty]) {
+            (DestructurePattern::Tuple(names), TypeData::Tuple { elements })
+                if names.len() == elements.len() =>
+            {
+                names
+                    .iter()
+                    .zip(elements.iter())
+                    .enumerate()
+                    .filter_map(|(idx, (name, ty))| name.map(|name| (idx, name, *ty)))
+                    .collect::<Vec<_>>()
+            }
+            (DestructurePattern::Tuple(names), TypeData::Tuple { elements }) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Cannot destructure tuple of {} elements into {} names",
+                        elements.len(),
+                        names.len()
+                    ))
+                    .with_labels(vec![
+                        Label::primary(self.file, span).with_message("In this destructuring"),
+                        Label::secondary(self.file, rhs_span).with_message(format!(
+                            "This expression is found to be of type '{}'",
+                            self.spark.get_type_name(rhs_ty)
+                        )),
+                    ]))
+            }
+            (DestructurePattern::Struct(names), TypeData::Struct { fields, .. }) => names
+                .iter()
+                .map(|name| {
+                    fields
+                        .iter()
+                        .position(|(_, field)| field == name)
+                        .map(|idx| (idx, *name, fields[idx].0))
+                        .ok_or_else(|| {
+                            Diagnostic::error()
+                                .with_message(format!(
+                                    "Structure type '{}' has no field named '{}' to destructure",
+                                    self.spark.get_type_name(rhs_ty),
+                                    name
+                                ))
+                                .with_labels(vec![
+                                    Label::primary(self.file, span)
+                                        .with_message("In this destructuring"),
+                                    Label::secondary(self.file, rhs_span).with_message(format!(
+                                        "This expression is found to be of type '{}'",
+                                        self.spark.get_type_name(rhs_ty)
+                                    )),
+                                ])
+                        })
+                })
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                //Zero-sized fields are rejected when their binding is declared below
+                .map(|(idx, name, ty)| {
+                    (self.struct_layout(rhs_ty)[idx].unwrap_or_default() as usize, name, ty)
+                })
+                .collect(),
+            (DestructurePattern::Struct(_), _) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Cannot destructure value of non-struct type '{}' as a structure",
+                        self.spark.get_type_name(rhs_ty)
+                    ))
+                    .with_labels(vec![
+                        Label::primary(self.file, span).with_message("In this destructuring"),
+                        Label::secondary(self.file, rhs_span).with_message(format!(
+                            "This expression is found to be of type '{}'",
+                            self.spark.get_type_name(rhs_ty)
+                        )),
+                    ]))
+            }
+            (DestructurePattern::Tuple(_), _) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Cannot destructure value of non-tuple type '{}' as a tuple",
+                        self.spark.get_type_name(rhs_ty)
+                    ))
+                    .with_labels(vec![
+                        Label::primary(self.file, span).with_message("In this destructuring"),
+                        Label::secondary(self.file, rhs_span).with_message(format!(
+                            "This expression is found to be of type '{}'",
+                            self.spark.get_type_name(rhs_ty)
+                        )),
+                    ]))
+            }
+        };
+
+        Ok(bindings)
+    }
+
+    /// Bind elements of an aggregate value to new variables in the current scope
+    fn bind_destructured(
+        &mut self,
+        aggregate: StructValue<'ctx>,
+        span: Span,
+        bindings: Vec<(usize, Symbol, TypeId)>,
+    ) -> CompilerRes<()> {
+        for (idx, name, ty) in bindings {
+            let llvm_ty = Self::require_basictype(self.file, span, self.llvm_ty(span, ty)?)?;
+            let element = self
+                .builder
+                .build_extract_value(aggregate, idx as u32, "destructure_extract")
+                .unwrap();
+            let pv = self.build_var_alloca(name, ty, llvm_ty);
+            self.builder.build_store(pv, element);
+            self.current_scope.define(name, ScopeDef::Value(ty, pv));
+        }
+
+        Ok(())
+    }
+
     /// Generate code for a match expression, returning a pointer to the phi value if any
     fn gen_match_expr(
         &mut self,
         module: ModId,
         matched: &Ast<TypeId>,
-        arms: &[(TypeId, Ast<TypeId>)],
+        arms: &[(MatchPattern<TypeId>, Option<Ast<TypeId>>, Ast<TypeId>)],
         span: Span,
     ) -> CompilerRes<Option<PointerValue<'ctx>>> {
         let mut has_phi = false;
         let mut all_arms_have_phi = true;
-        for (_, expr) in arms {
+        for (_, _, expr) in arms {
             if let AstNode::PhiExpr(_) = expr.node {
                 has_phi = true;
             } else {
@@ -293,12 +601,15 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             .append_basic_block(self.current_fun.unwrap().0, "after_match");
 
         let phi_data = if has_phi {
-            let ty = self.ast_type(module, &arms[0].1)?;
+            let ty = self.ast_type(module, &arms[0].2)?;
             let llvm_ty = Self::require_basictype(self.file, span, self.llvm_ty(span, ty)?)?;
+            let alloca = self.build_entry_alloca(llvm_ty, "match_phi");
+            self.set_explicit_align(ty, alloca);
             Some(PhiData {
-                alloca: self.builder.build_alloca(llvm_ty, "match_phi"),
+                alloca,
                 break_bb: after_bb,
                 phi_ty: ty,
+                phi_span: arms[0].2.span,
             })
         } else {
             None
@@ -309,60 +620,298 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
 
         let matched_ty = self.ast_type(module, matched)?;
         let matched_ty = self.spark.unwrap_alias(matched_ty);
-        let matched_parts = if let TypeData::Enum { ref parts } = self.spark[matched_ty] {
-            parts.clone()
-        } else {
-            return Err(Diagnostic::error()
-                .with_message(format!(
-                    "Cannot match against non-enum type {}",
-                    self.spark.get_type_name(matched_ty)
-                ))
-                .with_labels(vec![Label::primary(self.file, matched.span)]));
+        //Pointer to the matched enum value, used to read the variant value in arms that
+        //destructure it
+        let mut matched_ptr = None;
+        let (switched, matched_parts) = match self.spark[matched_ty].clone() {
+            TypeData::Enum { parts } if self.is_payloadless_enum(matched_ty) => {
+                (self.gen_expr(module, matched)?.into_int_value(), Some(parts))
+            }
+            TypeData::Enum { parts } => {
+                let matched = self.gen_lval(module, matched)?;
+                matched_ptr = Some(matched);
+                let discr = self
+                    .builder
+                    .build_struct_gep(matched, 0, "enum_match_discr")
+                    .unwrap();
+                let discr = self
+                    .builder
+                    .build_load(discr, "enum_match_discr_load")
+                    .into_int_value();
+                (discr, Some(parts))
+            }
+            TypeData::Integer { .. } => (self.gen_expr(module, matched)?.into_int_value(), None),
+            _ => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Cannot match against type {} that is not an enum or integer",
+                        self.spark.get_type_name(matched_ty)
+                    ))
+                    .with_labels(vec![Label::primary(self.file, matched.span)]))
+            }
         };
 
-        let matched = self.gen_lval(module, matched)?;
-        let discr = self
-            .builder
-            .build_struct_gep(matched, 0, "enum_match_discr")
-            .unwrap();
-        let discr = self
-            .builder
-            .build_load(discr, "enum_match_discr_load")
-            .into_int_value();
-
         let start_bb = self.builder.get_insert_block().unwrap();
 
-        let cases = arms
-            .into_iter()
-            .map(|(ty, expr)| {
-                if let Some(idx) = matched_parts.iter().position(|part| *part == *ty) {
-                    let arm_bb = self
-                        .ctx
-                        .append_basic_block(self.current_fun.unwrap().0, "matcharm_bb");
-                    self.builder.position_at_end(arm_bb);
-                    match self.gen_stmt(module, expr) {
-                        Ok(_) => {
-                            if !self.placed_terminator {
-                                self.builder.build_unconditional_branch(after_bb);
+        let mut default_bb = None;
+        //Arms matching each constant value in order, with the block that a failed guard
+        //branches from to reach the next candidate arm
+        let mut switch_arms: Vec<(u64, Vec<(BasicBlock<'ctx>, Option<BasicBlock<'ctx>>)>)> = vec![];
+        let mut add_switch_arm = |value: u64, arm: (BasicBlock<'ctx>, Option<BasicBlock<'ctx>>)| {
+            match switch_arms.iter_mut().find(|(other, _)| *other == value) {
+                Some((_, candidates)) => candidates.push(arm),
+                None => switch_arms.push((value, vec![arm])),
+            }
+        };
+        let mut ranges = vec![];
+        //All integer constants and ranges that have been matched, used to detect overlapping arms
+        let mut matched_ints: Vec<(i128, i128, Span)> = vec![];
+        //Which variants of a matched enum have an arm, used to check exhaustiveness
+        let mut covered_parts = vec![false; matched_parts.as_ref().map_or(0, |parts| parts.len())];
+        for (pattern, guard, expr) in arms {
+            if let (Some(guard), MatchPattern::Range(..) | MatchPattern::Default) = (guard, pattern) {
+                return Err(Diagnostic::error()
+                    .with_message("Guards can only be applied to enum variant and integer constant patterns")
+                    .with_labels(vec![Label::primary(self.file, guard.span)
+                        .with_message("Guard used here")]));
+            }
+
+            match (pattern, &matched_parts) {
+                (MatchPattern::Type(ty, destructured), Some(parts)) => {
+                    match parts.iter().position(|part| *part == *ty) {
+                        Some(idx) => {
+                            if covered_parts[idx] {
+                                return Err(Diagnostic::error()
+                                    .with_message(format!(
+                                        "Match arm for type {} is unreachable",
+                                        self.spark.get_type_name(*ty)
+                                    ))
+                                    .with_labels(vec![Label::primary(self.file, expr.span)])
+                                    .with_notes(vec![
+                                        "A previous arm without a guard matches the same type".to_owned(),
+                                    ]));
                             }
-                            Ok((self.ctx.i8_type().const_int(idx as u64, false), arm_bb))
+                            //Guarded arms may not be taken, so they don't cover their variant
+                            covered_parts[idx] = guard.is_none();
+                            let destructure = match destructured {
+                                Some(pattern) => Some((
+                                    matched_ptr,
+                                    *ty,
+                                    self.destructure_bindings(pattern, expr.span, matched.span, *ty)?,
+                                )),
+                                None => None,
+                            };
+                            let arm = self.gen_match_arm(module, expr, guard.as_ref(), after_bb, destructure)?;
+                            add_switch_arm(idx as u64, arm);
+                        }
+                        None => {
+                            return Err(Diagnostic::error()
+                                .with_message(format!(
+                                    "Cannot match type {} that is not contained in matched enum type {}",
+                                    self.spark.get_type_name(*ty),
+                                    self.spark.get_type_name(matched_ty)
+                                ))
+                                .with_labels(vec![Label::primary(self.file, expr.span)]))
                         }
-                        Err(e) => Err(e),
                     }
-                } else {
-                    Err(Diagnostic::error()
+                }
+                (MatchPattern::Integer(start), None) | (MatchPattern::Range(start, _), None) => {
+                    let start = Self::int_pattern_value(*start);
+                    let end = match pattern {
+                        MatchPattern::Range(_, end) => Self::int_pattern_value(*end),
+                        _ => start,
+                    };
+
+                    if start > end {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Range pattern {}..={} is empty",
+                                start, end
+                            ))
+                            .with_labels(vec![Label::primary(self.file, expr.span)
+                                .with_message("Match arm here")]));
+                    }
+
+                    //Patterns that can't be represented by the matched type would be truncated
+                    //to match a different value
+                    let (min, max) = self.int_bounds(matched_ty).unwrap();
+                    if let Some(outside) = [start, end].iter().copied().find(|val| *val < min || *val > max) {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Pattern {} can never match a value of type {}",
+                                outside,
+                                self.spark.get_type_name(matched_ty),
+                            ))
+                            .with_labels(vec![Label::primary(self.file, expr.span)
+                                .with_message("Match arm here")])
+                            .with_notes(vec![format!(
+                                "Values of type {} are in the range {}..={}",
+                                self.spark.get_type_name(matched_ty),
+                                min,
+                                max
+                            )]));
+                    }
+
+                    if let Some((_, _, other)) = matched_ints
+                        .iter()
+                        .find(|(other_start, other_end, _)| start <= *other_end && *other_start <= end)
+                    {
+                        return Err(Diagnostic::error()
+                            .with_message("Match arm pattern overlaps with a previous arm")
+                            .with_labels(vec![
+                                Label::primary(self.file, expr.span)
+                                    .with_message("Overlapping match arm here"),
+                                Label::secondary(self.file, *other)
+                                    .with_message("Previous match arm here"),
+                            ]));
+                    }
+                    if guard.is_none() {
+                        matched_ints.push((start, end, expr.span));
+                    }
+
+                    let arm = self.gen_match_arm(module, expr, guard.as_ref(), after_bb, None)?;
+                    //Negative values are truncated to their two's complement representation
+                    //when converted to constants of the matched type
+                    if let MatchPattern::Range(..) = pattern {
+                        ranges.push((start as u64, end as u64, arm.0));
+                    } else {
+                        add_switch_arm(start as u64, arm);
+                    }
+                }
+                (MatchPattern::Default, _) => {
+                    if default_bb.is_some() {
+                        return Err(Diagnostic::error()
+                            .with_message("Match expression has more than one default arm")
+                            .with_labels(vec![Label::primary(self.file, expr.span)
+                                .with_message("Second default arm here")]));
+                    }
+                    default_bb = Some(self.gen_match_arm(module, expr, None, after_bb, None)?.0);
+                }
+                (MatchPattern::Type(ty, _), None) => {
+                    return Err(Diagnostic::error()
                         .with_message(format!(
-                            "Cannot match type {} that is not contained in matched enum type {}",
+                            "Cannot match integer type {} against type {}",
+                            self.spark.get_type_name(matched_ty),
                             self.spark.get_type_name(*ty),
+                        ))
+                        .with_labels(vec![Label::primary(self.file, expr.span)
+                            .with_message("Arm of integer match here")])
+                        .with_notes(vec![
+                            "Arms of an integer match must be constant integers, ranges, or '_'"
+                                .to_owned(),
+                        ]))
+                }
+                (MatchPattern::Integer(_) | MatchPattern::Range(..), Some(_)) => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Cannot match enum type {} against an integer",
                             self.spark.get_type_name(matched_ty)
                         ))
-                        .with_labels(vec![Label::primary(self.file, expr.span)]))
+                        .with_labels(vec![Label::primary(self.file, expr.span)
+                            .with_message("Arm of enum match here")]))
                 }
-            })
-            .collect::<Result<Vec<_>, _>>()?;
+            }
+        }
+
+        if default_bb.is_none() {
+            let uncovered = match &matched_parts {
+                Some(parts) => parts
+                    .iter()
+                    .zip(covered_parts.iter())
+                    .filter(|(_, covered)| !**covered)
+                    .map(|(part, _)| self.spark.get_type_name(*part).to_string())
+                    .collect::<Vec<_>>(),
+                None => self
+                    .uncovered_int_pattern(matched_ty, &mut matched_ints)
+                    .into_iter()
+                    .collect(),
+            };
+
+            if !uncovered.is_empty() {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Match expression on type {} is not exhaustive",
+                        self.spark.get_type_name(matched_ty)
+                    ))
+                    .with_labels(vec![Label::primary(self.file, span)
+                        .with_message(format!("Patterns {} not covered", uncovered.join(", ")))])
+                    .with_notes(vec![
+                        "Add arms for the uncovered patterns or a default '_' arm".to_owned(),
+                    ]));
+            }
+        }
+
+        //Without a default arm the match is exhaustive, so any other value is an invalid
+        //enum discriminant
+        let fallback_bb = match default_bb {
+            Some(default_bb) => default_bb,
+            None => {
+                let invalid_bb = self
+                    .ctx
+                    .append_basic_block(self.current_fun.unwrap().0, "match_invalid");
+                self.builder.position_at_end(invalid_bb);
+                self.gen_trap();
+                invalid_bb
+            }
+        };
+
+        //Values not matched by a constant arm are tested against each range in order
+        let switch_default_bb = if ranges.is_empty() {
+            fallback_bb
+        } else {
+            let signed = matches!(self.spark[matched_ty], TypeData::Integer { signed: true, .. });
+            let int_ty = switched.get_type();
+            let first_check_bb = self
+                .ctx
+                .append_basic_block(self.current_fun.unwrap().0, "match_range_check");
+            self.builder.position_at_end(first_check_bb);
+
+            for (idx, (start, end, arm_bb)) in ranges.iter().enumerate() {
+                let above_start = self.builder.build_int_compare(
+                    if signed { IntPredicate::SGE } else { IntPredicate::UGE },
+                    switched,
+                    int_ty.const_int(*start, false),
+                    "match_range_start",
+                );
+                let below_end = self.builder.build_int_compare(
+                    if signed { IntPredicate::SLE } else { IntPredicate::ULE },
+                    switched,
+                    int_ty.const_int(*end, false),
+                    "match_range_end",
+                );
+                let in_range = self.builder.build_and(above_start, below_end, "match_in_range");
+
+                let next_bb = if idx + 1 == ranges.len() {
+                    fallback_bb
+                } else {
+                    self.ctx
+                        .append_basic_block(self.current_fun.unwrap().0, "match_range_check")
+                };
+                self.builder.build_conditional_branch(in_range, *arm_bb, next_bb);
+                self.builder.position_at_end(next_bb);
+            }
+
+            first_check_bb
+        };
+
+        //Each value's arms are tried in order, with a failed guard falling through to the next
+        //arm for the same value and the last arm falling through to the default
+        let mut cases = Vec::with_capacity(switch_arms.len());
+        for (value, candidates) in switch_arms {
+            let mut target = switch_default_bb;
+            for (arm_bb, guard_fail_bb) in candidates.into_iter().rev() {
+                if let Some(guard_fail_bb) = guard_fail_bb {
+                    self.builder.position_at_end(guard_fail_bb);
+                    self.builder.build_unconditional_branch(target);
+                }
+                target = arm_bb;
+            }
+            cases.push((switched.get_type().const_int(value, false), target));
+        }
 
         self.builder.position_at_end(start_bb);
-        self.builder.build_switch(discr, after_bb, &cases);
+        self.builder
+            .build_switch(switched, switch_default_bb, &cases);
         self.builder.position_at_end(after_bb);
 
         let phi_alloca = self.phi_data.map(|data| data.alloca);
@@ -370,6 +919,160 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         Ok(phi_alloca)
     }
 
+    /// Get the value matched by an integer pattern
+    fn int_pattern_value(int: BigInt) -> i128 {
+        match int.sign {
+            true => -(int.val as i128),
+            false => int.val as i128,
+        }
+    }
+
+    /// Get the smallest and largest values of an integer type
+    fn int_bounds(&self, ty: TypeId) -> Option<(i128, i128)> {
+        match self.spark[ty] {
+            TypeData::Integer { signed: true, width } => {
+                let half = 1i128 << (width as u32 - 1);
+                Some((-half, half - 1))
+            }
+            TypeData::Integer { signed: false, width } => Some((0, (1i128 << width as u32) - 1)),
+            _ => None,
+        }
+    }
+
+    /// Find the first range of values of integer type `ty` that are not matched by any of
+    /// the given constant or range patterns, formatted as a pattern
+    fn uncovered_int_pattern(
+        &self,
+        ty: TypeId,
+        matched: &mut [(i128, i128, Span)],
+    ) -> Option<String> {
+        let (min, max) = self.int_bounds(ty)?;
+
+        let pattern = |start: i128, end: i128| match start == end {
+            true => start.to_string(),
+            false => format!("{}..={}", start, end),
+        };
+
+        matched.sort_unstable_by_key(|(start, _, _)| *start);
+        let mut next = min;
+        for (start, end, _) in matched.iter() {
+            if *start > next {
+                return Some(pattern(next, *start - 1));
+            }
+            if *end >= max {
+                return None;
+            }
+            next = next.max(*end + 1);
+        }
+
+        Some(pattern(next, max))
+    }
+
+    /// Terminate the current basic block with a failed runtime check, using the
+    /// configured [TrapBehavior](crate::TrapBehavior). The called function is only declared
+    /// once a check needs it, so programs can define their own `abort` otherwise
+    pub(super) fn gen_trap(&mut self) {
+        let trap_fun_ty = self.ctx.void_type().fn_type(&[], false);
+        let trap_fun = match self.opts.trap {
+            TrapBehavior::Abort => Some(self.libc_fun("abort", trap_fun_ty)),
+            TrapBehavior::Trap => {
+                let module = self.llvm_module.as_ref().unwrap();
+                Some(CallableValue::from(
+                    module
+                        .get_function("llvm.trap")
+                        .unwrap_or_else(|| module.add_function("llvm.trap", trap_fun_ty, None)),
+                ))
+            }
+            TrapBehavior::Unreachable => None,
+        };
+        if let Some(trap_fun) = trap_fun {
+            self.builder.build_call(trap_fun, &[], "trap");
+        }
+        self.builder.build_unreachable();
+    }
+
+    /// Generate the body of a single match arm in a new basic block that branches to `after_bb`,
+    /// binding the destructured variant value and checking the guard if given. Returns the
+    /// created basic block and the block that is reached if the guard is false
+    fn gen_match_arm(
+        &mut self,
+        module: ModId,
+        expr: &Ast<TypeId>,
+        guard: Option<&Ast<TypeId>>,
+        after_bb: BasicBlock<'ctx>,
+        destructure: Option<(Option<PointerValue<'ctx>>, TypeId, Vec<(usize, Symbol, TypeId)>)>,
+    ) -> CompilerRes<(BasicBlock<'ctx>, Option<BasicBlock<'ctx>>)> {
+        let arm_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "matcharm_bb");
+        self.builder.position_at_end(arm_bb);
+        self.current_scope.push_layer();
+
+        //Enums without variant data have no value to bind, and any pattern that binds names
+        //has already been rejected for their zero-sized variants
+        if let Some((Some(matched), variant_ty, bindings)) = destructure {
+            if !bindings.is_empty() {
+                let llvm_variant_ty = Self::require_basictype(
+                    self.file,
+                    expr.span,
+                    self.llvm_ty(expr.span, variant_ty)?,
+                )?;
+                let variant = self
+                    .builder
+                    .build_struct_gep(matched, 1, "enum_match_variant")
+                    .unwrap();
+                let variant = self
+                    .builder
+                    .build_bitcast(
+                        variant,
+                        llvm_variant_ty.ptr_type(AddressSpace::Generic),
+                        "enum_match_variant_cast",
+                    )
+                    .into_pointer_value();
+                let aggregate = self
+                    .builder
+                    .build_load(variant, "enum_match_variant_load")
+                    .into_struct_value();
+                self.bind_destructured(aggregate, expr.span, bindings)?;
+            }
+        }
+
+        //The guard is checked after the variant value is bound so that it can use the bound
+        //names, and the failure block is branched to the next candidate arm by the caller
+        let guard_fail_bb = match guard {
+            Some(guard) => {
+                let guard_ty = self.ast_type(module, guard)?;
+                if self.spark.unwrap_alias(guard_ty) != SparkCtx::BOOL {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Match arm guard must be of type bool, found {}",
+                            self.spark.get_type_name(guard_ty)
+                        ))
+                        .with_labels(vec![Label::primary(self.file, guard.span)]));
+                }
+                let cond = self.gen_expr(module, guard)?.into_int_value();
+                let body_bb = self
+                    .ctx
+                    .append_basic_block(self.current_fun.unwrap().0, "matcharm_body");
+                let guard_fail_bb = self
+                    .ctx
+                    .append_basic_block(self.current_fun.unwrap().0, "match_guard_fail");
+                self.builder.build_conditional_branch(cond, body_bb, guard_fail_bb);
+                self.builder.position_at_end(body_bb);
+                Some(guard_fail_bb)
+            }
+            None => None,
+        };
+
+        self.gen_stmt(module, expr)?;
+        self.current_scope.pop_layer();
+        if !self.placed_terminator {
+            self.builder.build_unconditional_branch(after_bb);
+        }
+        self.placed_terminator = false;
+        Ok((arm_bb, guard_fail_bb))
+    }
+
     /// Generate code for a literal
     fn gen_literal(
         &mut self,
@@ -384,129 +1087,92 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             }
             .into(),
             Literal::String(s) => {
-                let glob = self
-                    .builder
-                    .build_global_string_ptr(s.as_str(), "const_str");
-                glob.as_pointer_value().into()
-            },
-            Literal::Struct {
+                let glob = match self.string_consts.get(s) {
+                    Some(glob) => *glob,
+                    None => {
+                        let glob = self
+                            .builder
+                            .build_global_string_ptr(s.as_str(), "const_str");
+                        self.string_consts.insert(s.clone(), glob);
+                        glob
+                    }
+                };
+                glob.as_pointer_value().into()
+            },
+            Literal::Struct {
                 ty,
                 fields
             } => {
-                    let typedata = ty.map(|ty| {
-                        let ty = self.spark.unwrap_alias(ty);
-                        self.spark[ty].clone()
-                    });
-                    let field_types = match typedata {
-                        Some(TypeData::Struct{fields}) => fields,
-                        None => fields.iter()
-                            .map(|(name, expr)| match self.ast_type(module, expr) {
-                                Ok(ty) => Ok((ty, name.clone())),
-                                Err(e) => Err(e),
-                            })
-                            .collect::<Result<Vec<_>, _>>()?,
-                        Some(_) => return Err(Diagnostic::error()
-                            .with_message(format!(
-                                    "Cannot create structure literal with non-struct type {}",
-                                    self.spark.get_type_name(ty.unwrap())
-                                )
-                            )
-                            .with_labels(vec![
-                                Label::primary(self.file, span)
-                                    .with_message("Structure literal encountered here")
-                            ])
-                        )
-                    };
-                    let ty = self.spark.new_type(TypeData::Struct{fields: field_types.clone()});
-
-                    let llvm_ty = self.llvm_ty(span, ty)?.into_struct_type();
-                    let struct_alloca = self.builder.build_alloca(llvm_ty, "struct_literal_alloca");
-                    
-                    for (name, fieldexpr) in fields {
-                        if let Some(idx) = field_types.iter().position(|(_ty, fname)| fname == name) {
-                            let field_ty = self.ast_type(module, fieldexpr)?;
-                            if field_ty != field_types[idx].0 {
-                                return Err(Diagnostic::error()
-                                    .with_message(format!(
-                                            "Assigning value of type {} to non-compatible field type {}",
-                                            self.spark.get_type_name(field_ty),
-                                            self.spark.get_type_name(field_types[idx].0)
-                                        )
-                                    )
-                                    .with_labels(vec![
-                                        Label::primary(self.file, fieldexpr.span)
-                                            .with_message("Assignment to field here")
-                                    ])
-                                )
-                            }
+                    let (llvm_ty, field_values) =
+                        self.gen_struct_fields(module, *ty, fields, literal, span, false)?;
+
+                    //Structures with only constant fields are created as a single constant
+                    //instead of storing each field separately
+                    let const_fields = field_values
+                        .iter()
+                        .map(|value| value.filter(|value| Self::is_const_value(*value)))
+                        .collect::<Option<Vec<_>>>();
+                    if let Some(const_fields) = const_fields {
+                        return Ok(llvm_ty.const_named_struct(&const_fields).into())
+                    }
 
-                            let fieldexpr_llvm = self.gen_expr(module, fieldexpr)?;
+                    let struct_alloca = self.build_entry_alloca(llvm_ty, "struct_literal_alloca");
+                    if let Some(ty) = ty {
+                        self.set_explicit_align(*ty, struct_alloca);
+                    }
+                    for (idx, value) in field_values.into_iter().enumerate() {
+                        if let Some(value) = value {
                             let structfield_ptr = self.builder.build_struct_gep(
                                 struct_alloca,
                                 idx as u32,
                                 "struct_literal_field"
                             ).unwrap();
 
-                            self.builder.build_store(structfield_ptr, fieldexpr_llvm);
-                        } else {
-                            return Err(Diagnostic::error()
-                                .with_message(format!(
-                                        "Assigning to field {} not contained in structure type {}",
-                                        name,
-                                        self.spark.get_type_name(ty)
-                                    )
-                                )
-                                .with_labels(vec![
-                                    Label::primary(self.file, fieldexpr.span)
-                                        .with_message("Field assigned here")
-                                ])
-                            )
+                            self.builder.build_store(structfield_ptr, value);
                         }
                     }
-                    
+
                     self.builder.build_load(struct_alloca, "struct_literal_load")
                 }
-            Literal::Array(elems) => {
-                if elems.len() == 0 {
-                    return Err(Diagnostic::error()
-                        .with_message("Cannot create array literal with zero elements")
-                        .with_labels(vec![
-                            Label::primary(self.file, span)
-                        ])
-                    )
-                }
+            Literal::Tuple(elems) => {
+                let ty = self.ast_type(module, &Ast {
+                    span,
+                    node: AstNode::Literal(literal.clone()),
+                })?;
+                let llvm_ty = Self::require_basictype(self.file, span, self.llvm_ty(span, ty)?)?;
+                let tuple_alloca = self.build_entry_alloca(llvm_ty, "tuple_literal_alloca");
 
-                let elem_ty = self.ast_type(module, &elems[0])?;
-                for elem in elems.iter() {
-                    let ty = self.ast_type(module, elem)?;
-                    if ty != elem_ty {
-                        return Err(Diagnostic::error()
-                            .with_message("Creating array literal with mismatched element types")
-                            .with_labels(vec![
-                                Label::primary(self.file, elem.span)
-                                    .with_message(format!(
-                                        "This element has type {}",
-                                        self.spark.get_type_name(ty)
-                                    )),
-                                Label::primary(self.file, elems[0].span)
-                                    .with_message(format!(
-                                        "First element has type {}",
-                                        self.spark.get_type_name(elem_ty)
-                                    ))
-                            ])
-                        )
-                    } 
+                for (idx, elem) in elems.iter().enumerate() {
+                    let elem = self.gen_expr(module, elem)?;
+                    let elem_ptr = self
+                        .builder
+                        .build_struct_gep(tuple_alloca, idx as u32, "tuple_literal_element")
+                        .unwrap();
+                    self.builder.build_store(elem_ptr, elem);
                 }
 
+                self.builder.build_load(tuple_alloca, "tuple_literal_load")
+            }
+            Literal::Array(elems) => {
+                let elem_ty = self.array_literal_elem_ty(module, elems, span)?;
                 let llvm_elem_type = Self::require_basictype(self.file, elems[0].span, self.llvm_ty(elems[0].span, elem_ty)?)?;
+                let elems = elems
+                    .iter()
+                    .map(|elem| self.gen_expr(module, elem))
+                    .collect::<CompilerRes<Vec<_>>>()?;
+
+                //Arrays of constants are created as a single constant instead of storing each
+                //element separately
+                if elems.iter().all(|elem| Self::is_const_value(*elem)) {
+                    return Ok(Self::const_array(llvm_elem_type, &elems).into())
+                }
 
-                let array_alloca = self.builder.build_alloca(
+                let array_alloca = self.build_entry_alloca(
                     llvm_elem_type.array_type(elems.len() as u32),
                     "array_literal_alloca"
                 );
 
-                for (i, elem) in elems.iter().enumerate() {
-                    let elem = self.gen_expr(module, elem)?;
+                for (i, elem) in elems.into_iter().enumerate() {
                     let elem_ptr = unsafe {
                         self.builder.build_in_bounds_gep(
                             array_alloca,
@@ -678,15 +1344,32 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         module: ModId,
         lhs: &Ast<TypeId>,
         op: Op,
+        op_span: Span,
         rhs: &Ast<TypeId>,
     ) -> CompilerRes<BasicValueEnum<'ctx>> {
-        let lhs_ty = self.ast_type(module, lhs)?;
-        let rhs_ty = self.ast_type(module, rhs)?;
+        if let Op::LogicalAnd | Op::LogicalOr = op {
+            return self.gen_logical_expr(module, lhs, op, op_span, rhs);
+        }
 
-        let llvm_lhs = self.gen_expr(module, lhs)?;
-        let llvm_rhs = self.gen_expr(module, rhs)?;
+        let mut lhs_ty = self.ast_type(module, lhs)?;
+        let mut rhs_ty = self.ast_type(module, rhs)?;
+
+        let mut llvm_lhs = self.gen_expr(module, lhs)?;
+        let mut llvm_rhs = self.gen_expr(module, rhs)?;
+
+        if let Some(promoted) = self.promoted_int_ty(lhs_ty, rhs_ty) {
+            llvm_lhs = self.promote_int(llvm_lhs, lhs_ty, promoted);
+            llvm_rhs = self.promote_int(llvm_rhs, rhs_ty, promoted);
+            lhs_ty = promoted;
+            rhs_ty = promoted;
+        }
 
         if lhs_ty == rhs_ty {
+            self.check_const_overflow(llvm_lhs, op, llvm_rhs, lhs_ty, (lhs.span.from, rhs.span.to).into())?;
+            if let Some(folded) = Self::fold_const_bin_expr(llvm_lhs, op, llvm_rhs, &self.spark[lhs_ty]) {
+                return Ok(folded)
+            }
+
             match (op, &self.spark[lhs_ty]) {
                 (Op::Star, TypeData::Integer { .. }) => {
                     return Ok(self
@@ -748,7 +1431,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 }
 
                 (
-                    Op::Eq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq,
+                    Op::Eq | Op::NotEq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq,
                     TypeData::Integer { signed, .. },
                 ) => {
                     return Ok(self
@@ -756,6 +1439,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .build_int_compare(
                             match (op, signed) {
                                 (Op::Eq, _) => IntPredicate::EQ,
+                                (Op::NotEq, _) => IntPredicate::NE,
                                 (Op::Greater, true) => IntPredicate::SGT,
                                 (Op::Greater, false) => IntPredicate::UGT,
                                 (Op::GreaterEq, true) => IntPredicate::SGE,
@@ -773,8 +1457,37 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .into())
                 }
 
+                //Function pointers and pointers are equal if they point to the same address
+                (Op::Eq | Op::NotEq, TypeData::Function(_) | TypeData::Pointer(_)) => {
+                    let addr_ty = self
+                        .ctx
+                        .ptr_sized_int_type(&self.target.get_target_data(), None);
+                    let lhs_addr = self.builder.build_ptr_to_int(
+                        llvm_lhs.into_pointer_value(),
+                        addr_ty,
+                        "lhs_addr",
+                    );
+                    let rhs_addr = self.builder.build_ptr_to_int(
+                        llvm_rhs.into_pointer_value(),
+                        addr_ty,
+                        "rhs_addr",
+                    );
+                    return Ok(self
+                        .builder
+                        .build_int_compare(
+                            match op {
+                                Op::Eq => IntPredicate::EQ,
+                                _ => IntPredicate::NE,
+                            },
+                            lhs_addr,
+                            rhs_addr,
+                            "pcmp",
+                        )
+                        .into())
+                }
+
                 (
-                    Op::Eq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq,
+                    Op::Eq | Op::NotEq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq,
                     TypeData::Float { .. },
                 ) => {
                     return Ok(self
@@ -782,6 +1495,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .build_float_compare(
                             match op {
                                 Op::Eq => FloatPredicate::OEQ,
+                                Op::NotEq => FloatPredicate::UNE,
                                 Op::Greater => FloatPredicate::OGT,
                                 Op::GreaterEq => FloatPredicate::OGE,
                                 Op::Less => FloatPredicate::OLT,
@@ -845,6 +1559,35 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         )
                         .into())
                 }
+                (
+                    Op::Add | Op::Sub | Op::Star | Op::Div | Op::Mod,
+                    TypeData::Vector { element, .. },
+                ) => {
+                    let (lhs, rhs) = (llvm_lhs.into_vector_value(), llvm_rhs.into_vector_value());
+                    return Ok(match (op, self.spark[self.spark.unwrap_alias(*element)].clone()) {
+                        (Op::Add, TypeData::Integer { .. }) => self.builder.build_int_add(lhs, rhs, "viadd"),
+                        (Op::Sub, TypeData::Integer { .. }) => self.builder.build_int_sub(lhs, rhs, "visub"),
+                        (Op::Star, TypeData::Integer { .. }) => self.builder.build_int_mul(lhs, rhs, "vimul"),
+                        (Op::Div, TypeData::Integer { signed: true, .. }) => {
+                            self.builder.build_int_signed_div(lhs, rhs, "vsidiv")
+                        }
+                        (Op::Div, TypeData::Integer { signed: false, .. }) => {
+                            self.builder.build_int_unsigned_div(lhs, rhs, "vuidiv")
+                        }
+                        (Op::Mod, TypeData::Integer { signed: true, .. }) => {
+                            self.builder.build_int_signed_rem(lhs, rhs, "vsimod")
+                        }
+                        (Op::Mod, TypeData::Integer { signed: false, .. }) => {
+                            self.builder.build_int_unsigned_rem(lhs, rhs, "vuimod")
+                        }
+                        (Op::Add, _) => self.builder.build_float_add(lhs, rhs, "vfadd"),
+                        (Op::Sub, _) => self.builder.build_float_sub(lhs, rhs, "vfsub"),
+                        (Op::Star, _) => self.builder.build_float_mul(lhs, rhs, "vfmul"),
+                        (Op::Div, _) => self.builder.build_float_div(lhs, rhs, "vfdiv"),
+                        (_, _) => self.builder.build_float_rem(lhs, rhs, "vfmod"),
+                    }
+                    .into())
+                }
                 _ => (),
             }
         }
@@ -871,11 +1614,13 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                             op
                         ))
                         .with_labels(vec![
-                            Label::primary(self.file, lhs.span).with_message(format!(
+                            Label::primary(self.file, op_span)
+                                .with_message("Operator used here"),
+                            Label::secondary(self.file, lhs.span).with_message(format!(
                                 "Left hand side is found to be of type {}",
                                 self.spark.get_type_name(lhs_ty)
                             )),
-                            Label::primary(self.file, rhs.span).with_message(format!(
+                            Label::secondary(self.file, rhs.span).with_message(format!(
                                 "Right hand side is found to be of type {}",
                                 self.spark.get_type_name(rhs_ty)
                             )),
@@ -885,16 +1630,413 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         )
     }
 
+    /// Generate code for a logical and or or expression, only evaluating the right hand side
+    /// if the left hand side doesn't decide the result
+    fn gen_logical_expr(
+        &mut self,
+        module: ModId,
+        lhs: &Ast<TypeId>,
+        op: Op,
+        op_span: Span,
+        rhs: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        for operand in [lhs, rhs] {
+            let ty = self.ast_type(module, operand)?;
+            if self.spark.unwrap_alias(ty) != SparkCtx::BOOL {
+                return Err(Diagnostic::error()
+                    .with_message(format!("Operands of {} must be of type bool", op))
+                    .with_labels(vec![
+                        Label::primary(self.file, operand.span).with_message(format!(
+                            "This is found to be of type {}",
+                            self.spark.get_type_name(ty)
+                        )),
+                        Label::secondary(self.file, op_span).with_message("Operator used here"),
+                    ]));
+            }
+        }
+
+        let fun = self.current_fun.unwrap().0;
+        let llvm_lhs = self.gen_expr(module, lhs)?.into_int_value();
+        let lhs_bb = self.builder.get_insert_block().unwrap();
+        let rhs_bb = self.ctx.append_basic_block(fun, "logical_rhs");
+        let after_bb = self.ctx.append_basic_block(fun, "logical_after");
+        let decided = match op {
+            Op::LogicalAnd => {
+                self.builder.build_conditional_branch(llvm_lhs, rhs_bb, after_bb);
+                self.ctx.bool_type().const_zero()
+            }
+            _ => {
+                self.builder.build_conditional_branch(llvm_lhs, after_bb, rhs_bb);
+                self.ctx.bool_type().const_all_ones()
+            }
+        };
+
+        self.builder.position_at_end(rhs_bb);
+        let llvm_rhs = self.gen_expr(module, rhs)?.into_int_value();
+        //The right hand side may end in a different block than it started in
+        let rhs_end_bb = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(after_bb);
+
+        self.builder.position_at_end(after_bb);
+        let phi = self.builder.build_phi(self.ctx.bool_type(), "logical_phi");
+        phi.add_incoming(&[(&decided, lhs_bb), (&llvm_rhs, rhs_end_bb)]);
+        Ok(phi.as_basic_value())
+    }
+
+    /// Get the type that both operands of a binary expression are widened to if integer
+    /// promotion is enabled and the operands are integers of different widths.
+    ///
+    /// The expression takes the type of the wider operand, including its signedness, and each
+    /// operand is extended according to its own signedness like in C, so `-1i8 + 1u32` is `0u32`.
+    /// Operands of the same width but different signedness are not promoted and are rejected as
+    /// mismatched types
+    fn promoted_int_ty(&self, lhs_ty: TypeId, rhs_ty: TypeId) -> Option<TypeId> {
+        if !self.opts.int_promotion {
+            return None;
+        }
+
+        let lhs_ty = self.spark.unwrap_alias(lhs_ty);
+        let rhs_ty = self.spark.unwrap_alias(rhs_ty);
+        match (&self.spark[lhs_ty], &self.spark[rhs_ty]) {
+            (TypeData::Integer { width: lhs, .. }, TypeData::Integer { width: rhs, .. })
+                if lhs != rhs =>
+            {
+                Some(if (*lhs as u8) > (*rhs as u8) { lhs_ty } else { rhs_ty })
+            }
+            _ => None,
+        }
+    }
+
+    /// Extend an integer to a wider integer type, sign extending if the integer is signed
+    fn promote_int(
+        &mut self,
+        val: BasicValueEnum<'ctx>,
+        ty: TypeId,
+        promoted: TypeId,
+    ) -> BasicValueEnum<'ctx> {
+        let ty = self.spark.unwrap_alias(ty);
+        if ty == promoted {
+            return val;
+        }
+
+        let (signed, width) = match (&self.spark[ty], &self.spark[promoted]) {
+            (TypeData::Integer { signed, .. }, TypeData::Integer { width, .. }) => (*signed, *width),
+            _ => unreachable!(),
+        };
+        let llvm_ty = self.llvm_int_ty(width);
+        match signed {
+            true => self
+                .builder
+                .build_int_s_extend(val.into_int_value(), llvm_ty, "promote_sext")
+                .into(),
+            false => self
+                .builder
+                .build_int_z_extend(val.into_int_value(), llvm_ty, "promote_zext")
+                .into(),
+        }
+    }
+
+    /// Generate the default value of a structure field given in the structure's type definition,
+    /// which must be a constant of the field's type
+    fn gen_field_default(
+        &mut self,
+        module: ModId,
+        default: &Ast<TypeId>,
+        field_ty: TypeId,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let default_ty = self.ast_type(module, default)?;
+        if default_ty != field_ty {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Default value of type {} cannot be assigned to field of type {}",
+                    self.spark.get_type_name(default_ty),
+                    self.spark.get_type_name(field_ty)
+                ))
+                .with_labels(vec![Label::primary(self.file, default.span)
+                    .with_message("Default value given here")]));
+        }
+        self.gen_const_expr(module, default)
+    }
+
+    /// Generate a constant value from an expression that can be evaluated at compile time,
+    /// like the initializer of a global variable
+    fn gen_const_expr(
+        &mut self,
+        module: ModId,
+        ast: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let file = self.file;
+        let not_const = || {
+            Diagnostic::error()
+                .with_message("Expression cannot be evaluated at compile time")
+                .with_labels(vec![Label::primary(file, ast.span)
+                    .with_message("Constant expression expected here")])
+        };
+
+        match &ast.node {
+            AstNode::Literal(literal) => match literal {
+                Literal::Number(_) | Literal::Bool(_) => self.gen_expr(module, ast),
+                Literal::Array(elems) => {
+                    let elem_ty = self.array_literal_elem_ty(module, elems, ast.span)?;
+                    let llvm_elem_ty = Self::require_basictype(
+                        self.file,
+                        elems[0].span,
+                        self.llvm_ty(elems[0].span, elem_ty)?,
+                    )?;
+                    let elems = elems
+                        .iter()
+                        .map(|elem| self.gen_const_expr(module, elem))
+                        .collect::<CompilerRes<Vec<_>>>()?;
+                    Ok(Self::const_array(llvm_elem_ty, &elems).into())
+                }
+                Literal::Struct { ty, fields } => {
+                    let (llvm_ty, field_values) =
+                        self.gen_struct_fields(module, *ty, fields, literal, ast.span, true)?;
+                    let field_values = field_values.into_iter().flatten().collect::<Vec<_>>();
+                    Ok(llvm_ty.const_named_struct(&field_values).into())
+                }
+                _ => Err(not_const()),
+            },
+            AstNode::BinExpr(lhs, op, _, rhs) => {
+                let lhs_ty = self.ast_type(module, lhs)?;
+                let rhs_ty = self.ast_type(module, rhs)?;
+                let lhs_val = self.gen_const_expr(module, lhs)?;
+                let rhs_val = self.gen_const_expr(module, rhs)?;
+                if lhs_ty != rhs_ty {
+                    return Err(not_const());
+                }
+                self.check_const_overflow(lhs_val, *op, rhs_val, lhs_ty, ast.span)?;
+                Self::fold_const_bin_expr(lhs_val, *op, rhs_val, &self.spark[lhs_ty])
+                    .ok_or_else(not_const)
+            }
+            //Constants are folded to their initializer's value instead of being loaded
+            AstNode::Access(path) => match self.spark.get_def(module, path) {
+                Ok(SparkDef::GlobalDef(global_file, global)) => {
+                    let global = &self.spark[global];
+                    if !global.flags.contains(GlobalFlags::CONSTANT) {
+                        return Err(not_const().with_notes(vec![
+                            format!("Global variable '{}' is not a constant", global.name),
+                            "Declare it with `const` to use it in constant expressions".to_owned(),
+                        ]));
+                    }
+                    if self.evaluating_consts.contains(&global.id) {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Cyclic constant definition of '{}'",
+                                global.name
+                            ))
+                            .with_labels(vec![
+                                Label::primary(file, ast.span)
+                                    .with_message("Constant used in its own initializer here"),
+                                Label::secondary(global_file, global.span)
+                                    .with_message("Constant defined here"),
+                            ]));
+                    }
+                    //Constants always have an initializer because external globals can't be const
+                    let init = global.init.clone().unwrap();
+                    let source = self.spark.path_module(module, path).unwrap_or(module);
+                    let old_file = self.file;
+                    self.file = global_file;
+                    self.evaluating_consts.push(global.id);
+                    let val = self.gen_const_expr(source, &init);
+                    self.evaluating_consts.pop();
+                    self.file = old_file;
+                    val
+                }
+                _ => Err(not_const()),
+            },
+            _ => Err(not_const()),
+        }
+    }
+
+    /// Check if a value is an LLVM constant that can be used in other constants
+    fn is_const_value(value: BasicValueEnum<'ctx>) -> bool {
+        match value {
+            BasicValueEnum::IntValue(value) => value.is_const(),
+            BasicValueEnum::FloatValue(value) => value.is_const(),
+            BasicValueEnum::PointerValue(value) => value.is_const(),
+            BasicValueEnum::VectorValue(value) => value.is_const(),
+            BasicValueEnum::ArrayValue(value) => value.is_const(),
+            BasicValueEnum::StructValue(value) => value.is_const(),
+        }
+    }
+
+    /// Create a constant array from constant elements of the given type
+    fn const_array(
+        elem_ty: BasicTypeEnum<'ctx>,
+        elems: &[BasicValueEnum<'ctx>],
+    ) -> ArrayValue<'ctx> {
+        match elem_ty {
+            BasicTypeEnum::IntType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_int_value()).collect::<Vec<_>>(),
+            ),
+            BasicTypeEnum::FloatType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_float_value()).collect::<Vec<_>>(),
+            ),
+            BasicTypeEnum::PointerType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_pointer_value()).collect::<Vec<_>>(),
+            ),
+            BasicTypeEnum::VectorType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_vector_value()).collect::<Vec<_>>(),
+            ),
+            BasicTypeEnum::ArrayType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_array_value()).collect::<Vec<_>>(),
+            ),
+            BasicTypeEnum::StructType(ty) => ty.const_array(
+                &elems.iter().map(|elem| elem.into_struct_value()).collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    /// Check that an integer operation on two constant operands of the same type produces a
+    /// value that fits in the type, as folding the operation would silently wrap the result
+    fn check_const_overflow(
+        &self,
+        lhs: BasicValueEnum<'ctx>,
+        op: Op,
+        rhs: BasicValueEnum<'ctx>,
+        ty: TypeId,
+        span: Span,
+    ) -> CompilerRes<()> {
+        let (lhs, rhs, signed, bits) = match (lhs, rhs, &self.spark[ty]) {
+            (
+                BasicValueEnum::IntValue(lhs),
+                BasicValueEnum::IntValue(rhs),
+                TypeData::Integer { signed, width },
+            ) if lhs.is_const() && rhs.is_const() => (lhs, rhs, *signed, *width as u32),
+            _ => return Ok(()),
+        };
+        let value = |val: IntValue<'ctx>| match signed {
+            true => val.get_sign_extended_constant().map(i128::from),
+            false => val.get_zero_extended_constant().map(i128::from),
+        };
+        let (lhs, rhs) = match (value(lhs), value(rhs)) {
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return Ok(()),
+        };
+
+        if let (Op::Div | Op::Mod, 0) = (op, rhs) {
+            return Err(Diagnostic::error()
+                .with_message("Division by zero in constant expression")
+                .with_labels(vec![Label::primary(self.file, span)]));
+        }
+
+        let (min, max) = match signed {
+            true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+            false => (0, (1i128 << bits) - 1),
+        };
+        let result = match op {
+            Op::Add => Some(lhs + rhs),
+            Op::Sub => Some(lhs - rhs),
+            Op::Star => lhs.checked_mul(rhs),
+            Op::Div => Some(lhs / rhs),
+            //The remainder of the minimum value divided by -1 is undefined like the quotient
+            Op::Mod if lhs == min && rhs == -1 => None,
+            Op::Mod => Some(lhs % rhs),
+            Op::ShLeft | Op::ShRight if rhs < 0 || rhs >= bits as i128 => None,
+            Op::ShLeft => lhs.checked_mul(1 << rhs),
+            _ => return Ok(()),
+        };
+
+        match result {
+            Some(result) if (min..=max).contains(&result) => Ok(()),
+            _ => Err(Diagnostic::error()
+                .with_message("Overflow in constant expression")
+                .with_labels(vec![Label::primary(self.file, span)])
+                .with_notes(vec![format!(
+                    "The result does not fit in type {}",
+                    self.spark.get_type_name(ty)
+                )])),
+        }
+    }
+
+    /// Fold a binary expression with two constant operands of the same type into a single
+    /// constant value, returning `None` if either operand is not constant or the operation
+    /// can't be folded
+    fn fold_const_bin_expr(
+        lhs: BasicValueEnum<'ctx>,
+        op: Op,
+        rhs: BasicValueEnum<'ctx>,
+        ty: &TypeData,
+    ) -> Option<BasicValueEnum<'ctx>> {
+        match (lhs, rhs, ty) {
+            (
+                BasicValueEnum::IntValue(lhs),
+                BasicValueEnum::IntValue(rhs),
+                TypeData::Integer { signed, .. },
+            ) if lhs.is_const() && rhs.is_const() => Some(
+                match op {
+                    Op::Add => lhs.const_add(rhs),
+                    Op::Sub => lhs.const_sub(rhs),
+                    Op::Star => lhs.const_mul(rhs),
+                    Op::Div if *signed => lhs.const_signed_div(rhs),
+                    Op::Div => lhs.const_unsigned_div(rhs),
+                    Op::Mod if *signed => lhs.const_signed_remainder(rhs),
+                    Op::Mod => lhs.const_unsigned_remainder(rhs),
+                    Op::ShLeft => lhs.const_shl(rhs),
+                    Op::ShRight if *signed => lhs.const_ashr(rhs),
+                    Op::ShRight => lhs.const_rshr(rhs),
+                    Op::Eq | Op::NotEq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq => lhs
+                        .const_int_compare(
+                            match (op, *signed) {
+                                (Op::Eq, _) => IntPredicate::EQ,
+                                (Op::NotEq, _) => IntPredicate::NE,
+                                (Op::Greater, true) => IntPredicate::SGT,
+                                (Op::Greater, false) => IntPredicate::UGT,
+                                (Op::GreaterEq, true) => IntPredicate::SGE,
+                                (Op::GreaterEq, false) => IntPredicate::UGE,
+                                (Op::Less, true) => IntPredicate::SLT,
+                                (Op::Less, false) => IntPredicate::ULT,
+                                (Op::LessEq, true) => IntPredicate::SLE,
+                                (Op::LessEq, false) => IntPredicate::ULE,
+                                _ => unreachable!(),
+                            },
+                            rhs,
+                        ),
+                    _ => return None,
+                }
+                .into(),
+            ),
+            (
+                BasicValueEnum::FloatValue(lhs),
+                BasicValueEnum::FloatValue(rhs),
+                TypeData::Float { .. },
+            ) if lhs.is_const() && rhs.is_const() => Some(match op {
+                Op::Add => lhs.const_add(rhs).into(),
+                Op::Sub => lhs.const_sub(rhs).into(),
+                Op::Star => lhs.const_mul(rhs).into(),
+                Op::Div => lhs.const_div(rhs).into(),
+                Op::Mod => lhs.const_remainder(rhs).into(),
+                Op::Eq | Op::NotEq | Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq => lhs
+                    .const_compare(
+                        match op {
+                            Op::Eq => FloatPredicate::OEQ,
+                            Op::NotEq => FloatPredicate::UNE,
+                            Op::Greater => FloatPredicate::OGT,
+                            Op::GreaterEq => FloatPredicate::OGE,
+                            Op::Less => FloatPredicate::OLT,
+                            Op::LessEq => FloatPredicate::OLE,
+                            _ => unreachable!(),
+                        },
+                        rhs,
+                    )
+                    .into(),
+                _ => return None,
+            }),
+            _ => None,
+        }
+    }
+
     /// Generate an lvalue expression, returning a [PointerValue] to the lval
-    fn gen_lval(
+    pub(super) fn gen_lval(
         &mut self,
         module: ModId,
         ast: &Ast<TypeId>,
     ) -> CompilerRes<PointerValue<'ctx>> {
         Ok(match &ast.node {
             AstNode::Access(path) => return self.gen_access(ast.span, path),
-            AstNode::Block(block) => {
-                if let Some(pv) = self.gen_block_ast(module, block)? {
+            AstNode::Block(label, block) => {
+                if let Some(pv) = self.gen_block_ast(module, *label, block)? {
                     pv
                 } else {
                     return Err(Diagnostic::error()
@@ -925,39 +2067,283 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             AstNode::MemberAccess(object, field) => {
                 self.gen_member(module, object, *field)?
             }
+            //A dereferenced pointer refers to the memory it points to
+            AstNode::UnaryExpr(Op::Star, pointer) if self.is_pointer(module, pointer)? => {
+                self.gen_expr(module, pointer)?.into_pointer_value()
+            }
+            //Lanes of a vector in memory are addressed by treating the vector as an array of
+            //its elements
+            AstNode::Index { object, index } if self.is_vector(module, object)? => {
+                let vector_ty = self.ast_type(module, object)?;
+                let (element, len) = match self.spark[self.spark.unwrap_alias(vector_ty)] {
+                    TypeData::Vector { element, len } => (element, len),
+                    _ => unreachable!(),
+                };
+                let llvm_element = Self::require_basictype(self.file, ast.span, self.llvm_ty(ast.span, element)?)?;
+                let vector = self.gen_lval(module, object)?;
+                let lane = self.gen_int_index(module, index, "vector")?;
+                self.check_const_index(lane, len as u64, index, vector_ty, "a vector")?;
+                let elements = self.builder.build_pointer_cast(
+                    vector,
+                    llvm_element.ptr_type(AddressSpace::Generic),
+                    "vector_elements",
+                );
+                unsafe { self.builder.build_in_bounds_gep(elements, &[lane], "vector_lane_ptr") }
+            }
+            AstNode::Index { object, index } if self.is_array(module, object)? => {
+                let array_ty = self.ast_type(module, object)?;
+                let len = match self.spark[self.spark.unwrap_alias(array_ty)] {
+                    TypeData::Array { len, .. } => len,
+                    _ => unreachable!(),
+                };
+                let array = self.gen_lval(module, object)?;
+                let element = self.gen_int_index(module, index, "array")?;
+                self.check_const_index(element, len, index, array_ty, "an array")?;
+                unsafe {
+                    self.builder.build_in_bounds_gep(
+                        array,
+                        &[self.ctx.i64_type().const_zero(), element],
+                        "array_element_ptr",
+                    )
+                }
+            }
             _ => {
+                let ty = self.ast_type(module, ast)?;
                 let expr = self.gen_expr(module, ast)?;
-                let alloca = self.builder.build_alloca(expr.get_type(), "lvalue_alloca");
+                let alloca = self.build_entry_alloca(expr.get_type(), "lvalue_alloca");
+                self.set_explicit_align(ty, alloca);
+                self.builder.build_store(alloca, expr);
                 alloca
             }
         })
     }
 
+    /// Check if an expression is of a pointer type
+    fn is_pointer(&mut self, module: ModId, ast: &Ast<TypeId>) -> CompilerRes<bool> {
+        let ty = self.ast_type(module, ast)?;
+        Ok(matches!(self.spark[self.spark.unwrap_alias(ty)], TypeData::Pointer(_)))
+    }
+
+    /// Check if an expression is of a vector type
+    fn is_vector(&mut self, module: ModId, ast: &Ast<TypeId>) -> CompilerRes<bool> {
+        let ty = self.ast_type(module, ast)?;
+        Ok(matches!(self.spark[self.spark.unwrap_alias(ty)], TypeData::Vector { .. }))
+    }
+
+    /// Check if an expression is of an array type
+    fn is_array(&mut self, module: ModId, ast: &Ast<TypeId>) -> CompilerRes<bool> {
+        let ty = self.ast_type(module, ast)?;
+        Ok(matches!(self.spark[self.spark.unwrap_alias(ty)], TypeData::Array { .. }))
+    }
+
+    /// Check that an index known at compile time is inside of an array or vector of the given
+    /// length, because an in-bounds GEP or lane access past the end produces poison
+    fn check_const_index(
+        &self,
+        index: IntValue<'ctx>,
+        len: u64,
+        index_ast: &Ast<TypeId>,
+        indexed_ty: TypeId,
+        indexed: &str,
+    ) -> CompilerRes<()> {
+        match index.get_sign_extended_constant() {
+            Some(idx) if idx < 0 || idx as u64 >= len => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Index {} is out of bounds for {} of length {}",
+                    idx, indexed, len
+                ))
+                .with_labels(vec![Label::primary(self.file, index_ast.span)
+                    .with_message(format!(
+                        "Value of type '{}' indexed here",
+                        self.spark.get_type_name(indexed_ty)
+                    ))])),
+            _ => Ok(()),
+        }
+    }
+
+    /// Generate the index of a vector lane or array element, which must be an integer. The
+    /// index is extended to 64 bits according to its signedness because LLVM treats all GEP
+    /// indices as signed
+    fn gen_int_index(
+        &mut self,
+        module: ModId,
+        index: &Ast<TypeId>,
+        indexed: &str,
+    ) -> CompilerRes<IntValue<'ctx>> {
+        let index_ty = self.ast_type(module, index)?;
+        match self.spark[self.spark.unwrap_alias(index_ty)] {
+            TypeData::Integer { signed, .. } => {
+                let index = self.gen_expr(module, index)?.into_int_value();
+                let i64_ty = self.ctx.i64_type();
+                Ok(match signed {
+                    true => self.builder.build_int_s_extend_or_bit_cast(index, i64_ty, "index_sext"),
+                    false => self.builder.build_int_z_extend_or_bit_cast(index, i64_ty, "index_zext"),
+                })
+            }
+            _ => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Indexing a {} with a value of type '{}'",
+                    indexed,
+                    self.spark.get_type_name(index_ty)
+                ))
+                .with_labels(vec![Label::primary(self.file, index.span)
+                    .with_message(format!("A {} must be indexed by an integer", indexed))])),
+        }
+    }
+
     fn gen_block_ast(
         &mut self,
         module: ModId,
+        label: Option<Symbol>,
         block: &[Ast<TypeId>],
     ) -> CompilerRes<Option<PointerValue<'ctx>>> {
-        let old_continue = self.continue_bb;
         let start_bb = self.builder.get_insert_block().unwrap();
         let body_bb = self
             .ctx
             .append_basic_block(self.current_fun.unwrap().0, "block");
-        self.continue_bb = Some(body_bb);
         let after_bb = self
             .ctx
             .append_basic_block(self.current_fun.unwrap().0, "after");
-        self.break_bb = Some(after_bb);
 
         //self.builder.position_at_end(start_bb);
 
-        let pv = self.gen_body(module, block, body_bb, after_bb)?;
+        let phi_data = match Self::block_phi_node(self.file, label, block) {
+            Ok(phi_node) => self.phi_data(module, phi_node, after_bb)?,
+            Err(_) => None,
+        };
+
+        self.loops.push(LoopData {
+            label,
+            continue_bb: body_bb,
+            break_bb: after_bb,
+            phi: phi_data,
+        });
+        let res = self.gen_body_with_phi(module, block, body_bb, after_bb, phi_data);
+        self.loops.pop();
+        res?;
         self.builder.position_at_end(start_bb);
         self.builder.build_unconditional_branch(body_bb);
 
         self.builder.position_at_end(after_bb);
-        self.continue_bb = old_continue;
-        Ok(pv.map(|phi| phi.alloca))
+        Ok(phi_data.map(|phi| phi.alloca))
+    }
+
+    /// Generate code for a while loop, evaluating the condition in the enclosing scope and
+    /// giving the body a scope that ends with every iteration
+    fn gen_while(
+        &mut self,
+        module: ModId,
+        label: Option<Symbol>,
+        cond: &Ast<TypeId>,
+        body: &[Ast<TypeId>],
+    ) -> CompilerRes<()> {
+        let cond_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "while_cond");
+        let body_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "while_body");
+        let after_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "after_while");
+        self.builder.build_unconditional_branch(cond_bb);
+        self.builder.position_at_end(cond_bb);
+
+        let cond_ty = self.ast_type(module, cond)?;
+        if self.spark.unwrap_alias(cond_ty) != SparkCtx::BOOL {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Using value of type {} as boolean condition for while loop",
+                    self.spark.get_type_name(cond_ty)
+                ))
+                .with_labels(vec![
+                    Label::primary(self.file, cond.span).with_message("Non-boolean value here")
+                ])
+                .with_notes(self.spark.non_bool_cond_notes(cond_ty)));
+        }
+        let cond_val = self.gen_expr(module, cond)?.into_int_value();
+        self.builder
+            .build_conditional_branch(cond_val, body_bb, after_bb);
+
+        self.loops.push(LoopData {
+            label,
+            continue_bb: cond_bb,
+            break_bb: after_bb,
+            phi: None,
+        });
+        let res = self.gen_body_with_phi(module, body, body_bb, cond_bb, None);
+        self.loops.pop();
+        res?;
+
+        self.builder.position_at_end(after_bb);
+        Ok(())
+    }
+
+    /// Find the innermost enclosing loop, or the innermost loop with the given label
+    fn find_loop(&self, label: Option<Symbol>) -> Option<LoopData<'ctx>> {
+        self.loops
+            .iter()
+            .rev()
+            .find(|loop_data| label.is_none() || loop_data.label == label)
+            .copied()
+    }
+
+    /// Create an error for a `break` or `continue` naming a label that no enclosing loop has
+    fn unknown_label(&self, label: Symbol, span: Span) -> Diagnostic<FileId> {
+        let mut diag = Diagnostic::error()
+            .with_message(format!("Unknown loop label '{}", label))
+            .with_labels(vec![Label::primary(self.file, span)]);
+        let labels = self
+            .loops
+            .iter()
+            .filter_map(|loop_data| loop_data.label.map(|label| format!("'{}", label)))
+            .collect::<Vec<_>>();
+        if !labels.is_empty() {
+            diag = diag.with_notes(vec![format!(
+                "Enclosing loop labels are {}",
+                labels.join(", ")
+            )]);
+        }
+        diag
+    }
+
+    /// Get the enum type and discriminant of the enum variant named by a path to an enum type
+    /// followed by the name of the variant's type, like `shape:circle`, or `None` if the path
+    /// does not name an enum variant
+    fn enum_variant(
+        &mut self,
+        span: Span,
+        path: &SymbolPath,
+    ) -> CompilerRes<Option<(TypeId, usize)>> {
+        let enum_ty = match path.parent().map(|parent| self.find_in_scope(span, &parent)) {
+            Some(Ok(ScopeDef::Def(SparkDef::TypeDef(_, ty)))) => ty,
+            _ => return Ok(None),
+        };
+        let parts = match &self.spark[self.spark.unwrap_alias(enum_ty)] {
+            TypeData::Enum { parts } => parts.clone(),
+            _ => return Ok(None),
+        };
+
+        let variant = path.last();
+        match parts.iter().position(|part| self.spark.variant_name(*part) == variant) {
+            Some(idx) => Ok(Some((enum_ty, idx))),
+            None => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Enum type '{}' has no variant named '{}'",
+                    self.spark.get_type_name(enum_ty),
+                    variant,
+                ))
+                .with_labels(vec![Label::primary(self.file, span)])
+                .with_notes(vec![format!(
+                    "Enum type '{}' contains variants: {}",
+                    self.spark.get_type_name(enum_ty),
+                    parts
+                        .iter()
+                        .map(|part| self.spark.variant_name(*part).to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )])),
+        }
     }
 
     /// Generate LLVM IR for a symbol access
@@ -968,11 +2354,25 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     ) -> CompilerRes<PointerValue<'ctx>> {
         let def = self.find_in_scope(span, path)?;
         Ok(match def {
-            ScopeDef::Def(SparkDef::FunDef(_, fun)) => {
-                let llvm_fun = self.llvm_funs[&fun];
-                llvm_fun.as_global_value().as_pointer_value()
-            }
+            //Only instances of generic functions are generated, never the functions themselves
+            ScopeDef::Def(SparkDef::FunDef(_, fun)) => match self.llvm_funs.get(&fun) {
+                Some(llvm_fun) => llvm_fun.as_global_value().as_pointer_value(),
+                None => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Cannot use generic function '{}' as a value",
+                            self.spark[fun].name
+                        ))
+                        .with_labels(vec![Label::primary(self.file, span)])
+                        .with_notes(vec![
+                            "A generic function must be instantiated or called before it can be used as a value".to_owned(),
+                        ]))
+                }
+            },
             ScopeDef::Value(_, ptr) => ptr,
+            ScopeDef::Def(SparkDef::GlobalDef(_, global)) => {
+                self.llvm_globals[&global].1.as_pointer_value()
+            }
             _ => {
                 return Err(Diagnostic::error()
                     .with_message(format!(
@@ -982,8 +2382,10 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                                 format!("module '{}'", self.spark[submod].name),
                             ScopeDef::Def(SparkDef::TypeDef(_, ty)) =>
                                 format!("type '{}'", self.spark.get_type_name(ty)),
+                            ScopeDef::Def(SparkDef::InterfaceDef(_, interface)) =>
+                                format!("interface '{}'", self.spark[interface].name),
                             ScopeDef::Value(..) => unreachable!(),
-                            ScopeDef::Def(SparkDef::FunDef(..)) => unreachable!(),
+                            ScopeDef::Def(SparkDef::FunDef(..) | SparkDef::GlobalDef(..)) => unreachable!(),
                         }
                     ))
                     .with_labels(vec![Label::primary(self.file, span)]))
@@ -1008,6 +2410,23 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             return self.gen_expr(module, rhs)
         }
 
+        //Integers that are not a variant of an enum are converted to and from the enum's
+        //discriminant
+        match (
+            &self.spark[self.spark.unwrap_alias(rhs_ty)],
+            &self.spark[self.spark.unwrap_alias(to_ty)],
+        ) {
+            (TypeData::Integer { .. }, TypeData::Enum { parts }) if !parts.contains(&rhs_ty) => {
+                let variants = parts.len();
+                return self.gen_int_to_enum(module, to_ty, variants, rhs)
+            }
+            (TypeData::Enum { parts }, TypeData::Integer { width, .. }) if !parts.contains(&to_ty) => {
+                let width = *width;
+                return self.gen_enum_to_int(module, rhs_ty, width, rhs)
+            }
+            _ => (),
+        }
+
         //Generate an enum literal from a cast to an enum that contains the casted
         //type as a variant
         if let TypeData::Enum { parts } = &self.spark[self.spark.unwrap_alias(to_ty)] {
@@ -1023,41 +2442,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 );
 
             if let Some(idx) = idx {
-                let enum_ty = Self::require_basictype(self.file, rhs.span, self.llvm_ty(rhs.span, to_ty)?)?;
-
-                let enum_literal = self.builder.build_alloca(enum_ty, "enum_literal_alloca");
-
-                let discrim = self
-                    .builder
-                    .build_struct_gep(enum_literal, 0, "enum_literal_get_discrim")
-                    .unwrap();
-                self.builder
-                    .build_store(discrim, self.ctx.i8_type().const_int(idx as u64, false));
-                
-                if self.size_of_type(rhs_ty) != 0 {
-                    let llvm_rhs = self.gen_expr(module, rhs)?;
-                    let llvm_rhs_ty = Self::require_basictype(self.file, rhs.span, self.llvm_ty(rhs.span, rhs_ty)?)?;
-                    let variant = self
-                        .builder
-                        .build_struct_gep(enum_literal, 1, "enum_literal_get_variant")
-                        .unwrap();
-
-                    let variant_ptr = self
-                        .builder
-                        .build_bitcast(
-                            variant,
-                            llvm_rhs_ty.ptr_type(AddressSpace::Generic),
-                            "enum_variant_bc",
-                        )
-                        .into_pointer_value();
-
-                    self.builder.build_store(variant_ptr, llvm_rhs);
-
-                    let enum_literal_load = self.builder.build_load(enum_literal, "enum_lit_load");
-                    return Ok(enum_literal_load.into())
-                } else {
-                    return Ok(self.builder.build_load(enum_literal, "enum_lit_load_no_variant"))
-                }
+                return self.gen_enum_literal(module, to_ty, idx, rhs);
             } else {
                 return Err(Diagnostic::error()
                     .with_message(
@@ -1067,15 +2452,32 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         "Attempted to cast type {} to enum type {}",
                         self.spark.get_type_name(rhs_ty),
                         self.spark.get_type_name(to_ty)
-                    ))]));
+                    ))])
+                    .with_notes(vec![format!(
+                        "Enum type {} contains variant types: {}",
+                        self.spark.get_type_name(to_ty),
+                        parts
+                            .iter()
+                            .map(|part| format!("'{}'", self.spark.get_type_name(*part)))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )]));
             }
         }
 
         //Generate a bitcast to the desired type if casting from enum
         if let TypeData::Enum { parts } = &self.spark[self.spark.unwrap_alias(rhs_ty)] {
             if let Some(_idx) = parts.iter().position(|part| *part == to_ty) {
-                let llvm_rhs = self.gen_lval(module, rhs)?;
                 let llvm_to_ty = Self::require_basictype(self.file, rhs.span, self.llvm_ty(rhs.span, to_ty)?)?;
+                //Every variant of a payload-less enum is a zero-sized aggregate
+                if self.is_payloadless_enum(rhs_ty) {
+                    return Ok(match llvm_to_ty {
+                        BasicTypeEnum::StructType(ty) => ty.const_zero().into(),
+                        BasicTypeEnum::ArrayType(ty) => ty.const_zero().into(),
+                        _ => unreachable!("Zero-sized enum variant of non-aggregate type"),
+                    })
+                }
+                let llvm_rhs = self.gen_lval(module, rhs)?;
 
                 let variant = self
                     .builder
@@ -1196,6 +2598,31 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                     unreachable!()
                 }
             }
+            //Booleans are always i1 values, and are never treated as a 1-bit integer
+            (TypeData::Bool, TypeData::Integer { width, .. }) => {
+                let llvm_to = self.llvm_int_ty(width);
+                if let BasicValueEnum::IntValue(iv) = llvm_rhs {
+                    self.builder
+                        .build_int_z_extend(iv, llvm_to, "bool_to_int")
+                        .into()
+                } else {
+                    unreachable!()
+                }
+            }
+            (TypeData::Integer { .. }, TypeData::Bool) => {
+                if let BasicValueEnum::IntValue(iv) = llvm_rhs {
+                    self.builder
+                        .build_int_compare(
+                            IntPredicate::NE,
+                            iv,
+                            iv.get_type().const_zero(),
+                            "int_to_bool",
+                        )
+                        .into()
+                } else {
+                    unreachable!()
+                }
+            }
             (TypeData::Pointer(..), TypeData::Integer { signed, width }) => {
                 let llvm_to = self.llvm_int_ty(width);
                 if let BasicValueEnum::PointerValue(pv) = llvm_rhs {
@@ -1223,6 +2650,260 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         })
     }
 
+    /// Generate an enum value with the discriminant given by an integer, trapping if the
+    /// integer is not the index of one of the enum's variants. The payload of the created enum
+    /// is left undefined
+    fn gen_int_to_enum(
+        &mut self,
+        module: ModId,
+        enum_ty: TypeId,
+        variants: usize,
+        rhs: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let int = self.gen_expr(module, rhs)?.into_int_value();
+
+        //Negative signed integers are out of bounds when compared as unsigned
+        let in_bounds = self.builder.build_int_compare(
+            IntPredicate::ULT,
+            int,
+            int.get_type().const_int(variants as u64, false),
+            "enum_discrim_in_bounds",
+        );
+        let valid_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "enum_discrim_valid");
+        let invalid_bb = self
+            .ctx
+            .append_basic_block(self.current_fun.unwrap().0, "enum_discrim_invalid");
+        self.builder
+            .build_conditional_branch(in_bounds, valid_bb, invalid_bb);
+        self.builder.position_at_end(invalid_bb);
+        self.gen_trap();
+        self.builder.position_at_end(valid_bb);
+
+        let discrim =
+            self.builder
+                .build_int_truncate_or_bit_cast(int, self.ctx.i8_type(), "enum_discrim");
+        if self.is_payloadless_enum(enum_ty) {
+            return Ok(discrim.into())
+        }
+
+        let llvm_enum_ty =
+            Self::require_basictype(self.file, rhs.span, self.llvm_ty(rhs.span, enum_ty)?)?;
+        let enum_alloca = self.build_entry_alloca(llvm_enum_ty, "enum_from_int_alloca");
+        let discrim_ptr = self
+            .builder
+            .build_struct_gep(enum_alloca, 0, "enum_from_int_discrim")
+            .unwrap();
+        self.builder.build_store(discrim_ptr, discrim);
+
+        Ok(self.builder.build_load(enum_alloca, "enum_from_int_load"))
+    }
+
+    /// Generate code reading the discriminant of an enum value as an integer of the given width
+    fn gen_enum_to_int(
+        &mut self,
+        module: ModId,
+        enum_ty: TypeId,
+        width: IntegerWidth,
+        rhs: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let discrim = if self.is_payloadless_enum(enum_ty) {
+            self.gen_expr(module, rhs)?.into_int_value()
+        } else {
+            let llvm_rhs = self.gen_lval(module, rhs)?;
+            let discrim_ptr = self
+                .builder
+                .build_struct_gep(llvm_rhs, 0, "enum_to_int_discrim")
+                .unwrap();
+            self.builder
+                .build_load(discrim_ptr, "enum_to_int_discrim_load")
+                .into_int_value()
+        };
+
+        Ok(self
+            .builder
+            .build_int_z_extend_or_bit_cast(discrim, self.llvm_int_ty(width), "enum_discrim_to_int")
+            .into())
+    }
+
+    /// Check that an array literal has at least one element and that all elements are of the
+    /// same type, returning the type of the elements
+    fn array_literal_elem_ty(
+        &mut self,
+        module: ModId,
+        elems: &[Ast<TypeId>],
+        span: Span,
+    ) -> CompilerRes<TypeId> {
+        if elems.len() == 0 {
+            return Err(Diagnostic::error()
+                .with_message("Cannot create array literal with zero elements")
+                .with_labels(vec![
+                    Label::primary(self.file, span)
+                ])
+            )
+        }
+
+        let elem_ty = self.ast_type(module, &elems[0])?;
+        for elem in elems.iter() {
+            let ty = self.ast_type(module, elem)?;
+            if ty != elem_ty {
+                return Err(Diagnostic::error()
+                    .with_message("Creating array literal with mismatched element types")
+                    .with_labels(vec![
+                        Label::primary(self.file, elem.span)
+                            .with_message(format!(
+                                "This element has type {}",
+                                self.spark.get_type_name(ty)
+                            )),
+                        Label::primary(self.file, elems[0].span)
+                            .with_message(format!(
+                                "First element has type {}",
+                                self.spark.get_type_name(elem_ty)
+                            ))
+                    ])
+                )
+            }
+        }
+
+        Ok(elem_ty)
+    }
+
+    /// Check the fields of a structure literal against its type and generate the value of each
+    /// field of the LLVM structure type, including fields given default values. Field values
+    /// are generated as constant expressions if `constant` is set
+    fn gen_struct_fields(
+        &mut self,
+        module: ModId,
+        ty: Option<TypeId>,
+        fields: &[(Symbol, Ast<TypeId>)],
+        literal: &Literal<TypeId>,
+        span: Span,
+        constant: bool,
+    ) -> CompilerRes<(StructType<'ctx>, Vec<Option<BasicValueEnum<'ctx>>>)> {
+        let named = ty;
+        let ty = match ty {
+            Some(ty) => self.spark.unwrap_alias(ty),
+            None => self.ast_type(module, &Ast {
+                span,
+                node: AstNode::Literal(literal.clone()),
+            })?,
+        };
+        let field_types = match &self.spark[ty] {
+            TypeData::Struct{fields, ..} => fields.clone(),
+            _ => return Err(Diagnostic::error()
+                .with_message(format!(
+                        "Cannot create structure literal with non-struct type {}",
+                        self.spark.get_type_name(ty)
+                    )
+                )
+                .with_labels(vec![
+                    Label::primary(self.file, span)
+                        .with_message("Structure literal encountered here")
+                ])
+            )
+        };
+
+        let missing = field_types
+            .iter()
+            .filter(|(_, field_name)| {
+                !fields.iter().any(|(name, _)| name == field_name)
+                    && named
+                        .and_then(|named| self.spark.field_default(named, *field_name))
+                        .is_none()
+            })
+            .map(|(_, field_name)| format!("'{}'", field_name))
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Structure literal of type {} is missing fields {}",
+                    self.spark.get_type_name(ty),
+                    missing.join(", ")
+                ))
+                .with_labels(vec![
+                    Label::primary(self.file, span)
+                        .with_message("Structure literal encountered here")
+                ])
+                .with_notes(vec![
+                    "Assign the fields in the literal or give them default values in the type definition".to_owned(),
+                ])
+            );
+        }
+
+        let llvm_ty = self.llvm_ty(span, ty)?.into_struct_type();
+        let layout = self.struct_layout(ty);
+        let mut field_values = vec![None; llvm_ty.count_fields() as usize];
+
+        for (name, fieldexpr) in fields {
+            if let Some(idx) = field_types.iter().position(|(_ty, fname)| fname == name) {
+                let field_ty = self.ast_type(module, fieldexpr)?;
+                if field_ty != field_types[idx].0 {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                                "Assigning value of type {} to non-compatible field type {}",
+                                self.spark.get_type_name(field_ty),
+                                self.spark.get_type_name(field_types[idx].0)
+                            )
+                        )
+                        .with_labels(vec![
+                            Label::primary(self.file, fieldexpr.span)
+                                .with_message("Assignment to field here")
+                        ])
+                    )
+                }
+
+                let fieldexpr_llvm = if constant {
+                    self.gen_const_expr(module, fieldexpr)?
+                } else {
+                    self.gen_expr(module, fieldexpr)?
+                };
+                if let Some(idx) = layout[idx] {
+                    field_values[idx as usize] = Some(fieldexpr_llvm);
+                }
+            } else {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                            "Assigning to field {} not contained in structure type {}",
+                            name,
+                            self.spark.get_type_name(ty)
+                        )
+                    )
+                    .with_labels(vec![
+                        Label::primary(self.file, fieldexpr.span)
+                            .with_message("Field assigned here")
+                    ])
+                )
+            }
+        }
+
+        //Fields left out of the literal take the default value given in the
+        //structure's type definition
+        for (idx, (field_ty, field_name)) in field_types.iter().enumerate() {
+            if fields.iter().any(|(name, _)| name == field_name) {
+                continue;
+            }
+            let named = named.unwrap();
+            let (default_file, default) = self.spark.field_default(named, *field_name).unwrap();
+            let default = self.spark.coerce_literal(default, *field_ty).unwrap_or_else(|| default.clone());
+
+            let type_module = match self.spark[named] {
+                TypeData::Alias(_, type_module, _) => type_module,
+                _ => module,
+            };
+            let old_file = std::mem::replace(&mut self.file, default_file);
+            let default_llvm = self.gen_field_default(type_module, &default, *field_ty);
+            self.file = old_file;
+            let default_llvm = default_llvm?;
+
+            if let Some(idx) = layout[idx] {
+                field_values[idx as usize] = Some(default_llvm);
+            }
+        }
+
+        Ok((llvm_ty, field_values))
+    }
+
     /// Generate code for a single if expression or statement
     fn gen_if_expr(
         &mut self,
@@ -1295,7 +2976,8 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 ))
                 .with_labels(vec![
                     Label::primary(self.file, if_expr.cond.span).with_message("Non-boolean value here")
-                ]));
+                ])
+                .with_notes(self.spark.non_bool_cond_notes(cond_ty)));
         }
     }
 
@@ -1308,15 +2990,19 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     ) -> CompilerRes<PointerValue<'ctx>> {
         let obj_ty = self.ast_type(module, object)?;
         let obj_ty = self.spark.unwrap_alias(obj_ty);
-        if let TypeData::Struct { ref fields } = self.spark[obj_ty] {
+        if let TypeData::Struct { ref fields, .. } = self.spark[obj_ty] {
             let fields = fields.clone();
             let struct_pv = self.gen_lval(module, object)?;
+            let layout = self.struct_layout(obj_ty);
 
             for (i, (_, name)) in fields.iter().enumerate() {
                 if *name == field {
+                    let idx = layout[i].ok_or_else(|| Diagnostic::error()
+                        .with_message(format!("Cannot access zero-sized field '{}'", field))
+                        .with_labels(vec![Label::primary(self.file, object.span)]))?;
                     return Ok(self
                         .builder
-                        .build_struct_gep(struct_pv, i as u32, "struct_field_access")
+                        .build_struct_gep(struct_pv, idx, "struct_field_access")
                         .unwrap());
                 }
             }
@@ -1332,6 +3018,41 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         self.spark.get_type_name(obj_ty)
                     ),
                 )]))
+        } else if let TypeData::Union { ref fields } = self.spark[obj_ty] {
+            let field_ty = fields
+                .iter()
+                .find_map(|(ty, name)| if *name == field { Some(*ty) } else { None });
+            match field_ty {
+                Some(field_ty) => {
+                    let union_pv = self.gen_lval(module, object)?;
+                    let bytes = self
+                        .builder
+                        .build_struct_gep(union_pv, 0, "union_bytes")
+                        .unwrap();
+                    let llvm_field_ty =
+                        Self::require_basictype(self.file, object.span, self.llvm_ty(object.span, field_ty)?)?;
+                    Ok(self
+                        .builder
+                        .build_bitcast(
+                            bytes,
+                            llvm_field_ty.ptr_type(AddressSpace::Generic),
+                            "union_field_access",
+                        )
+                        .into_pointer_value())
+                }
+                None => Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Union type {} has no field named {}",
+                        self.spark.get_type_name(obj_ty),
+                        field
+                    ))
+                    .with_labels(vec![Label::primary(self.file, object.span).with_message(
+                        format!(
+                            "Expression of union type {} encountered here",
+                            self.spark.get_type_name(obj_ty)
+                        ),
+                    )])),
+            }
         } else {
             Err(Diagnostic::error()
                 .with_message(format!(
@@ -1348,6 +3069,242 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         }
     }
 
+    /// Generate an enum value holding the variant with the given discriminant, with a payload
+    /// generated from `payload` if the variant type is not zero-sized
+    fn gen_enum_literal(
+        &mut self,
+        module: ModId,
+        enum_ty: TypeId,
+        idx: usize,
+        payload: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        if self.is_payloadless_enum(enum_ty) {
+            return Ok(self.ctx.i8_type().const_int(idx as u64, false).into())
+        }
+
+        let llvm_enum_ty = Self::require_basictype(self.file, payload.span, self.llvm_ty(payload.span, enum_ty)?)?;
+        let enum_literal = self.build_entry_alloca(llvm_enum_ty, "enum_literal_alloca");
+
+        let discrim = self
+            .builder
+            .build_struct_gep(enum_literal, 0, "enum_literal_get_discrim")
+            .unwrap();
+        self.builder
+            .build_store(discrim, self.ctx.i8_type().const_int(idx as u64, false));
+
+        let payload_ty = self.ast_type(module, payload)?;
+        if self.size_of_type(payload_ty) != 0 {
+            let llvm_payload = self.gen_expr(module, payload)?;
+            let llvm_payload_ty = Self::require_basictype(self.file, payload.span, self.llvm_ty(payload.span, payload_ty)?)?;
+            let variant = self
+                .builder
+                .build_struct_gep(enum_literal, 1, "enum_literal_get_variant")
+                .unwrap();
+
+            let variant_ptr = self
+                .builder
+                .build_bitcast(
+                    variant,
+                    llvm_payload_ty.ptr_type(AddressSpace::Generic),
+                    "enum_variant_bc",
+                )
+                .into_pointer_value();
+
+            self.builder.build_store(variant_ptr, llvm_payload);
+        }
+
+        Ok(self.builder.build_load(enum_literal, "enum_lit_load"))
+    }
+
+    /// Generate code constructing an enum value from a call to a variant of the enum, passing
+    /// the variant's value if its type is not zero-sized
+    fn gen_variant_constructor(
+        &mut self,
+        module: ModId,
+        enum_ty: TypeId,
+        idx: usize,
+        called: &Ast<TypeId>,
+        args: &[Ast<TypeId>],
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let variant_ty = match &self.spark[self.spark.unwrap_alias(enum_ty)] {
+            TypeData::Enum { parts } => parts[idx],
+            _ => unreachable!(),
+        };
+
+        if self.size_of_type(variant_ty) == 0 {
+            if !args.is_empty() {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Passing a value to variant '{}' of enum type '{}', which has no data",
+                        self.spark.variant_name(variant_ty),
+                        self.spark.get_type_name(enum_ty),
+                    ))
+                    .with_labels(vec![Label::primary(self.file, called.span).with_message(
+                        format!("Expecting 0 arguments, found {}", args.len()),
+                    )]));
+            }
+            return self.gen_enum_literal(module, enum_ty, idx, &Ast {
+                span: called.span,
+                node: AstNode::Literal(Literal::Unit),
+            });
+        }
+
+        if args.len() != 1 {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid number of arguments to variant '{}' of enum type '{}'",
+                    self.spark.variant_name(variant_ty),
+                    self.spark.get_type_name(enum_ty),
+                ))
+                .with_labels(vec![Label::primary(self.file, called.span).with_message(
+                    format!("Expecting 1 argument, found {}", args.len()),
+                )]));
+        }
+
+        let coerced = self.spark.coerce_literal(&args[0], variant_ty);
+        let payload = coerced.as_ref().unwrap_or(&args[0]);
+        let payload_ty = self.ast_type(module, payload)?;
+        if self.spark.unwrap_alias(payload_ty) != self.spark.unwrap_alias(variant_ty) {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid value of type '{}' to variant '{}' of enum type '{}', expecting '{}'",
+                    self.spark.get_type_name(payload_ty),
+                    self.spark.variant_name(variant_ty),
+                    self.spark.get_type_name(enum_ty),
+                    self.spark.get_type_name(variant_ty),
+                ))
+                .with_labels(vec![Label::primary(self.file, payload.span)]));
+        }
+
+        self.gen_enum_literal(module, enum_ty, idx, payload)
+    }
+
+    /// Get the generic function that a called expression names with its type arguments
+    /// inferred from the types of the arguments passed to it, or `None` if the called
+    /// expression does not name a generic function
+    fn generic_call(
+        &mut self,
+        module: ModId,
+        called: &Ast<TypeId>,
+        args: &[Ast<TypeId>],
+    ) -> CompilerRes<Option<(FileId, FunId, Vec<TypeId>)>> {
+        let (file, fun) = match &called.node {
+            AstNode::Access(path) => match self.find_in_scope(called.span, path) {
+                Ok(ScopeDef::Def(SparkDef::FunDef(file, fun))) => (file, fun),
+                _ => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        let params = match &self.spark[fun].generic {
+            Some(generic) => generic.params.clone(),
+            None => return Ok(None),
+        };
+
+        let fun_ty = self.spark[fun].ty.clone();
+        if fun_ty.args.len() != args.len() {
+            return Err(Diagnostic::error()
+                .with_message("Passing invalid number of arguments to function")
+                .with_labels(vec![Label::primary(self.file, called.span).with_message(
+                    format!("Expecting {} arguments, found {}", fun_ty.args.len(), args.len()),
+                )]));
+        }
+
+        let mut bindings = HashMap::new();
+        for (expecting, arg) in fun_ty.args.iter().zip(args) {
+            let passed = self.ast_type(module, arg)?;
+            if !self.spark.unify(*expecting, passed, &mut bindings) {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Passing invalid argument type '{}', expecting '{}'",
+                        self.spark.get_type_name(passed),
+                        self.spark.get_type_name(*expecting)
+                    ))
+                    .with_labels(vec![Label::primary(self.file, arg.span)]));
+            }
+        }
+
+        let type_args = params
+            .iter()
+            .map(|param| {
+                bindings.get(param).copied().ok_or_else(|| {
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "Cannot infer type parameter '{}' of generic function '{}'",
+                            self.spark.get_type_name(*param),
+                            self.spark[fun].name,
+                        ))
+                        .with_labels(vec![Label::primary(self.file, called.span)])
+                        .with_notes(vec![
+                            "Type parameters are inferred from the types of the arguments passed"
+                                .to_owned(),
+                        ])
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Some((file, fun, type_args)))
+    }
+
+    /// Get the instance of a generic function with the given type arguments, declaring it and
+    /// queueing its body to be generated if it has not been used before. Functions declared in
+    /// interfaces are resolved to the implementation for the type that `Self` is bound to
+    fn instantiate(
+        &mut self,
+        span: Span,
+        file: FileId,
+        generic: FunId,
+        type_args: Vec<TypeId>,
+    ) -> CompilerRes<FunctionValue<'ctx>> {
+        let params = self.spark[generic].generic.clone().unwrap();
+        let (module, file, instance) = match params.interface {
+            Some(interface) => match self.spark.implementation(generic, type_args[0]) {
+                Some(implementation) => implementation,
+                None => {
+                    let interface = self.spark[interface].name;
+                    let ty = self.spark.get_type_name(type_args[0]);
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Type '{}' does not implement interface '{}'",
+                            ty, interface
+                        ))
+                        .with_labels(vec![Label::primary(self.file, span)])
+                        .with_notes(vec![format!(
+                            "Implement it with `impl {} for {} {{ ... }}`",
+                            interface, ty
+                        )]))
+                }
+            },
+            None => (
+                params.module,
+                file,
+                self.spark.instantiate(generic, type_args),
+            ),
+        };
+        if let Some(llvm_fun) = self.llvm_funs.get(&instance) {
+            return Ok(*llvm_fun);
+        }
+
+        self.reachable.insert(instance);
+        let mut llvm = self.llvm_module.take().unwrap();
+        let forwarded = self.forward_fun(instance, &mut llvm, None);
+        self.llvm_module = Some(llvm);
+        forwarded?;
+
+        self.pending_instances.push((module, file, instance));
+        Ok(self.llvm_funs[&instance])
+    }
+
+    /// Get the LLVM function that a called expression names, or `None` if the expression is not
+    /// the name of a function
+    fn direct_callee(&self, called: &Ast<TypeId>) -> Option<FunctionValue<'ctx>> {
+        match &called.node {
+            AstNode::Access(path) => match self.find_in_scope(called.span, path) {
+                Ok(ScopeDef::Def(SparkDef::FunDef(_, fun))) => self.llvm_funs.get(&fun).copied(),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
     /// Generate code for a single function call and return the return value of the function or
     /// `None` if the function called returns the unit type
     fn gen_call(
@@ -1356,6 +3313,27 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         called: &Ast<TypeId>,
         args: &[Ast<TypeId>],
     ) -> CompilerRes<Option<BasicValueEnum<'ctx>>> {
+        if let Some(builtin) = self.called_builtin(called) {
+            return self.gen_builtin_call(module, builtin, called, args);
+        }
+        if let AstNode::Access(path) = &called.node {
+            if let Some((enum_ty, idx)) = self.enum_variant(called.span, path)? {
+                return self
+                    .gen_variant_constructor(module, enum_ty, idx, called, args)
+                    .map(Some);
+            }
+        }
+        if let Some((file, generic, type_args)) = self.generic_call(module, called, args)? {
+            let instance = self.instantiate(called.span, file, generic, type_args)?;
+            let args = args
+                .iter()
+                .map(|arg| self.gen_expr(module, arg).map(|v| v.into()))
+                .collect::<Result<Vec<_>, _>>()?;
+            let call = self.builder.build_call(instance, &args, "fn_call");
+            call.set_call_convention(instance.get_call_conventions());
+            return Ok(call.try_as_basic_value().left());
+        }
+
         let called_ty = self.ast_type(module, called)?;
         if let TypeData::Function(f) = &self.spark[called_ty] {
             let f = f.clone();
@@ -1367,6 +3345,14 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                     )]));
             }
 
+            //Literals take on the type of the parameter they are passed to, letting an anonymous
+            //structure literal be passed where a named structure type is expected
+            let args = args
+                .iter()
+                .zip(f.args.iter())
+                .map(|(arg, expecting)| self.spark.coerce_literal(arg, *expecting).unwrap_or_else(|| arg.clone()))
+                .collect::<Vec<_>>();
+
             let passed_types = args
                 .iter()
                 .map(|arg| match self.ast_type(module, arg) {
@@ -1388,6 +3374,9 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .with_labels(vec![Label::primary(self.file, passed_span)]));
                 }
             }
+            //Calling conventions are not part of function types, so only calls naming a function
+            //directly use its calling convention
+            let direct = self.direct_callee(called);
             let called = self.gen_expr(module, called)?;
             match called {
                 BasicValueEnum::PointerValue(pv) => match CallableValue::try_from(pv) {
@@ -1396,11 +3385,11 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                             .iter()
                             .map(|arg| self.gen_expr(module, arg).map(|v| v.into()))
                             .collect::<Result<Vec<_>, _>>()?;
-                        return Ok(self
-                            .builder
-                            .build_call(callable, &args, "fn_call")
-                            .try_as_basic_value()
-                            .left());
+                        let call = self.builder.build_call(callable, &args, "fn_call");
+                        if let Some(direct) = direct {
+                            call.set_call_convention(direct.get_call_conventions());
+                        }
+                        return Ok(call.try_as_basic_value().left());
                     }
                     _ => (),
                 },
@@ -1408,14 +3397,69 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             }
         }
 
-        Err(Diagnostic::error()
-            .with_message("Cannot call a value of non-function type")
-            .with_labels(vec![Label::primary(self.file, called.span).with_message(
-                format!(
-                    "Value of type {} found here",
-                    self.spark.get_type_name(called_ty)
-                ),
-            )]))
+        Err(self.not_callable(called, called_ty))
+    }
+
+    /// Create an error for calling a value that is not a function, suggesting the member
+    /// access or indexing expression that may have been meant based on the called value's type
+    fn not_callable(&self, called: &Ast<TypeId>, called_ty: TypeId) -> Diagnostic<FileId> {
+        let is_fun = |ty: TypeId| {
+            matches!(self.spark[self.spark.unwrap_alias(ty)], TypeData::Function(_))
+        };
+        let name = Self::access_text(called).unwrap_or_else(|| "value".to_owned());
+        let notes = match &self.spark[self.spark.unwrap_alias(called_ty)] {
+            TypeData::Struct { fields, .. } | TypeData::Union { fields } => {
+                match fields.iter().find(|(ty, _)| is_fun(*ty)) {
+                    Some((_, field)) => vec![format!(
+                        "To call a function stored in a field, access the field first like `{}.{}.()`",
+                        name, field
+                    )],
+                    None if !fields.is_empty() => vec![format!(
+                        "Access a field with `{}.{}` instead, fields of type {} are: {}",
+                        name,
+                        fields[0].1,
+                        self.spark.get_type_name(called_ty),
+                        fields
+                            .iter()
+                            .map(|(_, field)| format!("'{}'", field))
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    )],
+                    None => vec![],
+                }
+            }
+            TypeData::Array { element, .. } if is_fun(*element) => vec![format!(
+                "To call a function stored in the array, index it first like `{}[0].()`",
+                name
+            )],
+            TypeData::Array { .. } => {
+                vec![format!("Index the array with `{}[index]` instead", name)]
+            }
+            _ => vec![],
+        };
+
+        Diagnostic::error()
+            .with_message(format!(
+                "Cannot call a value of non-function type {}",
+                self.spark.get_type_name(called_ty)
+            ))
+            .with_labels(vec![Label::primary(self.file, called.span).with_message(format!(
+                "Value of type {} found here",
+                self.spark.get_type_name(called_ty)
+            ))])
+            .with_notes(notes)
+    }
+
+    /// Get the source text of a variable access or member access expression, or `None` if
+    /// the expression is anything else
+    fn access_text(ast: &Ast<TypeId>) -> Option<String> {
+        match &ast.node {
+            AstNode::Access(path) => Some(path.to_string()),
+            AstNode::MemberAccess(object, field) => {
+                Self::access_text(object).map(|object| format!("{}.{}", object, field))
+            }
+            _ => None,
+        }
     }
     
     /// Generate a body, creating a phi alloca automatically
@@ -1428,33 +3472,55 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     ) -> CompilerRes<Option<PhiData<'ctx>>> {
         let phi_data = match Self::phi_node(self.file, body) {
             Err(_) => None,
-            Ok(phi_node) => {
-                let ty = self.ast_type(module, phi_node).unwrap();
-                if let Ok(llvm_ty) = BasicTypeEnum::try_from(self.llvm_ty(phi_node.span, ty)?) {
-                    let phi_alloca = self.builder.build_alloca(llvm_ty, "phi_alloca");
-
-                    Some(PhiData {
-                        break_bb: after_bb,
-                        phi_ty: ty,
-                        alloca: phi_alloca,
-                    })
-                } else {
-                    None
-                }
-            }
+            Ok(phi_node) => self.phi_data(module, phi_node, after_bb)?,
         };
+
+        self.gen_body_with_phi(module, body, to_bb, after_bb, phi_data)?;
+        Ok(phi_data)
+    }
+
+    /// Create the alloca that a block's value is stored to, with a type inferred from the
+    /// given phi statement or break value
+    fn phi_data(
+        &mut self,
+        module: ModId,
+        phi_node: &Ast<TypeId>,
+        after_bb: BasicBlock<'ctx>,
+    ) -> CompilerRes<Option<PhiData<'ctx>>> {
+        let ty = self.ast_type(module, phi_node)?;
+        Ok(
+            if let Ok(llvm_ty) = BasicTypeEnum::try_from(self.llvm_ty(phi_node.span, ty)?) {
+                let phi_alloca = self.build_entry_alloca(llvm_ty, "phi_alloca");
+                self.set_explicit_align(ty, phi_alloca);
+
+                Some(PhiData {
+                    break_bb: after_bb,
+                    phi_ty: ty,
+                    phi_span: phi_node.span,
+                    alloca: phi_alloca,
+                })
+            } else {
+                None
+            },
+        )
+    }
+
+    /// Generate LLVM IR for a block of statements that store phi values using the given phi data
+    fn gen_body_with_phi(
+        &mut self,
+        module: ModId,
+        body: &[Ast<TypeId>],
+        to_bb: BasicBlock<'ctx>,
+        after_bb: BasicBlock<'ctx>,
+        phi_data: Option<PhiData<'ctx>>,
+    ) -> CompilerRes<()> {
         let old_phi_data = self.phi_data;
         self.phi_data = phi_data;
 
-        self.gen_body_no_phi(
-            module,
-            body,
-            to_bb,
-            after_bb,
-        )?;
- 
+        let res = self.gen_body_no_phi(module, body, to_bb, after_bb);
+
         self.phi_data = old_phi_data;
-        Ok(phi_data)
+        res
     }
 
     /// Generate LLVM IR for a block of statements
@@ -1468,19 +3534,23 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         self.builder.position_at_end(to_bb);
 
         self.current_scope.push_layer();
+        self.scope_allocas.push(vec![]);
 
-        for stmt in body.iter() {
+        for (idx, stmt) in body.iter().enumerate() {
             if let Err(e) = self.gen_stmt(module, stmt) {
                 self.current_scope.pop_layer();
+                self.scope_allocas.pop();
                 self.builder.position_at_end(after_bb);
                 return Err(e);
             }
             if self.placed_terminator {
+                self.warn_unreachable(stmt, &body[idx + 1..]);
                 break;
             }
         }
 
         self.current_scope.pop_layer();
+        self.end_scope_lifetimes();
         if !self.placed_terminator {
             self.builder.build_unconditional_branch(after_bb);
         } else {
@@ -1491,8 +3561,97 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         Ok(())
     }
 
+    /// Allocate stack space at the start of the current function's entry block, so that the
+    /// allocation happens once per call even if it is reached multiple times in a loop
+    pub(super) fn build_entry_alloca<T: BasicType<'ctx>>(&self, ty: T, name: &str) -> PointerValue<'ctx> {
+        let entry = self
+            .current_fun
+            .unwrap()
+            .0
+            .get_first_basic_block()
+            .expect("Function has no entry block");
+        let builder = self.ctx.create_builder();
+        match entry.get_first_instruction() {
+            Some(first) => builder.position_before(&first),
+            None => builder.position_at_end(entry),
+        }
+        builder.build_alloca(ty, name)
+    }
+
+    /// Allocate stack space for a variable, marking the start of its lifetime if it is declared
+    /// inside of a block and its address is never taken so that its stack slot can be reused
+    /// after the block ends
+    fn build_var_alloca(
+        &mut self,
+        name: Symbol,
+        ty: TypeId,
+        llvm_ty: BasicTypeEnum<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let pv = self.build_entry_alloca(llvm_ty, name.as_str());
+        self.set_explicit_align(ty, pv);
+        if self.addressed_vars.contains(&name) {
+            return pv;
+        }
+        if let (Some((lifetime_start, _)), false) = (self.lifetime_funs, self.scope_allocas.is_empty()) {
+            let size = self.target.get_target_data().get_store_size(&llvm_ty);
+            self.build_lifetime_call(lifetime_start, pv, size);
+            self.scope_allocas.last_mut().unwrap().push((pv, size));
+        }
+        pv
+    }
+
+    /// Apply the alignment requested for a type to a stack allocation of that type, if any
+    pub(super) fn set_explicit_align(&self, ty: TypeId, alloca: PointerValue<'ctx>) {
+        if let Some(align) = self.explicit_align(ty) {
+            alloca.as_instruction().unwrap().set_alignment(align).unwrap();
+        }
+    }
+
+    /// Mark the end of the lifetimes of all variables declared in the block that is ending, if
+    /// control can reach the end of the block
+    fn end_scope_lifetimes(&mut self) {
+        let allocas = self.scope_allocas.pop().unwrap_or_default();
+        if let (Some((_, lifetime_end)), false) = (self.lifetime_funs, self.placed_terminator) {
+            for (pv, size) in allocas.into_iter().rev() {
+                self.build_lifetime_call(lifetime_end, pv, size);
+            }
+        }
+    }
+
+    fn build_lifetime_call(&self, fun: FunctionValue<'ctx>, pv: PointerValue<'ctx>, size: u64) {
+        let ptr = self.builder.build_pointer_cast(
+            pv,
+            self.ctx.i8_type().ptr_type(AddressSpace::Generic),
+            "lifetime_ptr",
+        );
+        self.builder.build_call(
+            fun,
+            &[self.ctx.i64_type().const_int(size, false).into(), ptr.into()],
+            "",
+        );
+    }
+
+    /// Emit a warning if any statements follow a statement that unconditionally leaves the
+    /// current block, as they are never executed
+    pub(super) fn warn_unreachable(&mut self, terminator: &Ast<TypeId>, rest: &[Ast<TypeId>]) {
+        //Nested functions and type aliases are only definitions, so they are not dead code
+        let dead = rest
+            .iter()
+            .find(|stmt| !matches!(stmt.node, AstNode::FunDef(..) | AstNode::TypeDef(..)));
+        if let Some(dead) = dead {
+            self.diags.emit(Diagnostic::warning()
+                .with_message("Unreachable statement")
+                .with_labels(vec![
+                    Label::primary(self.file, dead.span)
+                        .with_message("This statement is never executed"),
+                    Label::secondary(self.file, terminator.span)
+                        .with_message("Any code after this statement is unreachable"),
+                ]));
+        }
+    }
+
     /// Generate an LLVM integer type to match an IR integer type
-    fn llvm_int_ty(&self, width: IntegerWidth) -> IntType<'ctx> {
+    pub(super) fn llvm_int_ty(&self, width: IntegerWidth) -> IntType<'ctx> {
         match width {
             IntegerWidth::Eight => self.ctx.i8_type(),
             IntegerWidth::Sixteen => self.ctx.i16_type(),
@@ -1502,7 +3661,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     }
 
     /// Get the type of an AST expression
-    fn ast_type(
+    pub(super) fn ast_type(
         &mut self,
         module: ModId,
         ast: &Ast<TypeId>,
@@ -1520,7 +3679,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                                 Err(e) => Err(e)
                             })
                             .collect::<Result<Vec<_>, _>>()?;
-                        self.spark.new_type(TypeData::Struct {fields})
+                        self.spark.new_type(TypeData::Struct {fields, repr: StructRepr::Default, align: None})
                     }
                 }
             AstNode::Literal(Literal::Unit) => SparkCtx::UNIT,
@@ -1549,6 +3708,13 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 self.spark.new_type(TypeData::Pointer(SparkCtx::U8))
             }
             AstNode::Literal(Literal::Bool(_)) => SparkCtx::BOOL,
+            AstNode::Literal(Literal::Tuple(elems)) => {
+                let elements = elems
+                    .iter()
+                    .map(|elem| self.ast_type(module, elem))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.spark.new_type(TypeData::Tuple { elements })
+            }
             AstNode::Literal(Literal::Array(parts)) => {
                 let first_type = self.ast_type(module, parts.first().ok_or_else(||
                     Diagnostic::error()
@@ -1561,25 +3727,32 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 })
             }
             AstNode::CastExpr(ty, ..) => *ty,
-            AstNode::FunCall(called, ..) => {
+            AstNode::FunCall(called, args) => {
+                if let Some(builtin) = self.called_builtin(called) {
+                    return self.builtin_return_ty(module, builtin, called, args);
+                }
+                if let AstNode::Access(path) = &called.node {
+                    if let Some((enum_ty, _)) = self.enum_variant(called.span, path)? {
+                        return Ok(enum_ty);
+                    }
+                }
+                if let Some((_, generic, type_args)) = self.generic_call(module, called, args)? {
+                    let params = self.spark[generic].generic.as_ref().unwrap().params.clone();
+                    let substitutions = params.into_iter().zip(type_args).collect::<HashMap<_, _>>();
+                    let return_ty = self.spark[generic].ty.return_ty;
+                    return Ok(self.spark.substitute(return_ty, &substitutions));
+                }
                 let called_ty = self.ast_type(module, called)?;
                 if let TypeData::Function(f_ty) = &self.spark[called_ty] {
                     f_ty.return_ty
                 } else {
-                    return Err(Diagnostic::error()
-                        .with_message(format!(
-                            "Attempting to call a value of type '{}' as a function",
-                            self.spark.get_type_name(called_ty)
-                        ))
-                        .with_labels(vec![Label::primary(self.file, called.span).with_message(
-                            format!(
-                                "Called value of type '{}' here",
-                                self.spark.get_type_name(called_ty)
-                            ),
-                        )]));
+                    return Err(self.not_callable(called, called_ty));
                 }
             }
             AstNode::Access(path) => {
+                if self.enum_variant(ast.span, path)?.is_some() {
+                    return Ok(SparkCtx::U8);
+                }
                 let def = self.find_in_scope(ast.span, path)?;
 
                 match def {
@@ -1587,7 +3760,8 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .spark
                         .new_type(TypeData::Function(self.spark[f].ty.clone())),
                     ScopeDef::Value(ty, _) => ty,
-                    ScopeDef::Def(SparkDef::TypeDef(_file, ty)) if self.size_of_type(ty) == 0 => ty,
+                    ScopeDef::Def(SparkDef::GlobalDef(_, global)) => self.llvm_globals[&global].0,
+                    ScopeDef::Def(SparkDef::TypeDef(_file, ty)) if self.spark.type_params(ty).is_none() && self.size_of_type(ty) == 0 => ty,
                     _ => {
                         return Err(Diagnostic::error()
                             .with_message("Cannot infer type of definition")
@@ -1598,7 +3772,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             AstNode::MemberAccess(lhs, name) => {
                 let lhs_ty = self.ast_type(module, lhs)?;
                 let lhs_ty = self.spark.unwrap_alias(lhs_ty);
-                if let TypeData::Struct { fields } = &self.spark[lhs_ty] {
+                if let TypeData::Struct { fields, .. } | TypeData::Union { fields } = &self.spark[lhs_ty] {
                     fields.iter().find_map(|(ty, field_name)| if name == field_name {
                         Some(*ty)
                     } else {
@@ -1631,7 +3805,9 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             }
             AstNode::Index { object, index: _ } => {
                 let object_ty = self.ast_type(module, object)?;
-                if let TypeData::Array { element, len: _ } = self.spark[object_ty] {
+                if let TypeData::Array { element, len: _ } | TypeData::Vector { element, len: _ } =
+                    self.spark[self.spark.unwrap_alias(object_ty)]
+                {
                     element
                 } else {
                     return Err(Diagnostic::error()
@@ -1649,10 +3825,22 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             }
             AstNode::BinExpr(
                 _,
-                Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq | Op::Eq,
+                Op::Greater
+                | Op::GreaterEq
+                | Op::Less
+                | Op::LessEq
+                | Op::Eq
+                | Op::NotEq
+                | Op::LogicalAnd
+                | Op::LogicalOr,
+                _,
                 _,
             ) => SparkCtx::BOOL,
-            AstNode::BinExpr(lhs, ..) => self.ast_type(module, lhs)?,
+            AstNode::BinExpr(lhs, _, _, rhs) => {
+                let lhs_ty = self.ast_type(module, lhs)?;
+                let rhs_ty = self.ast_type(module, rhs)?;
+                self.promoted_int_ty(lhs_ty, rhs_ty).unwrap_or(lhs_ty)
+            }
             AstNode::UnaryExpr(op, rhs) => {
                 let rhs_ty = self.ast_type(module, rhs)?;
                 match op {
@@ -1693,16 +3881,20 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             AstNode::VarDeclaration { ty: Some(ty), .. } => *ty,
             AstNode::PhiExpr(phid) => self.ast_type(module, phid)?,
             AstNode::Return(..)
-            | AstNode::Break
-            | AstNode::Continue
+            | AstNode::Break(..)
+            | AstNode::Continue(_)
+            | AstNode::While { .. }
             | AstNode::VarDeclaration { .. }
-            | AstNode::Assignment { .. } => {
+            | AstNode::Assignment { .. }
+            | AstNode::Destructure { .. }
+            | AstNode::FunDef(..)
+            | AstNode::TypeDef(..) => {
                 return Err(Diagnostic::error()
                     .with_message("Cannot find type of statement")
                     .with_labels(vec![Label::primary(self.file, ast.span)]))
             }
-            AstNode::Block(body) => {
-                let phi_node = Self::phi_node(self.file, &body).map_err(|e| {
+            AstNode::Block(label, body) => {
+                let phi_node = Self::block_phi_node(self.file, *label, &body).map_err(|e| {
                     e.with_labels(vec![
                         Label::secondary(self.file, ast.span).with_message("In loop body here")
                     ])
@@ -1715,8 +3907,216 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         .with_message("Failed to infer type of match expression")
                         .with_labels(vec![Label::primary(self.file, ast.span)])
                 })?;
-                self.ast_type(module, &case_1.1)?
+                self.ast_type(module, &case_1.2)?
+            }
+        })
+    }
+
+    /// Get the name of the variable that contains the place of an expression whose address
+    /// is taken, if the place is a variable or a field or element of one
+    pub(super) fn addressed_var(addressed: &Ast<TypeId>) -> Option<Symbol> {
+        let mut root = addressed;
+        while let AstNode::MemberAccess(object, _) | AstNode::Index { object, .. } = &root.node {
+            root = object;
+        }
+        match &root.node {
+            AstNode::Access(path) if path.len() == 1 => Some(path.last()),
+            _ => None,
+        }
+    }
+
+    /// If an expression evaluates to the address of a stack-allocated local variable of the
+    /// current function, get the span where the address was taken
+    fn local_address_of(&self, ast: &Ast<TypeId>) -> Option<Span> {
+        match &ast.node {
+            AstNode::UnaryExpr(Op::AND, addressed) => {
+                let name = Self::addressed_var(addressed)?;
+                match self.current_scope.get(&name) {
+                    //Globals are defined as values in the function's scope but are not stack
+                    //allocated
+                    Some(ScopeDef::Value(_, ptr))
+                        if !self
+                            .llvm_globals
+                            .values()
+                            .any(|(_, global)| global.as_pointer_value() == *ptr) =>
+                    {
+                        Some(ast.span)
+                    }
+                    _ => None,
+                }
+            }
+            AstNode::Access(path) if path.len() == 1 => self.local_refs.get(&path.last()).copied(),
+            _ => None,
+        }
+    }
+
+    /// Check if an expression refers to a memory location that outlives the expression,
+    /// meaning that its address can be taken
+    fn is_lvalue(ast: &Ast<TypeId>) -> bool {
+        match &ast.node {
+            AstNode::Access(_) | AstNode::UnaryExpr(Op::Star, _) => true,
+            AstNode::MemberAccess(object, _) | AstNode::Index { object, .. } => {
+                Self::is_lvalue(object)
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if two expressions always refer to the same memory location, ignoring indices
+    /// that are not literals or plain variables since they may change between evaluations
+    fn same_lvalue(lhs: &Ast<TypeId>, rhs: &Ast<TypeId>) -> bool {
+        match (&lhs.node, &rhs.node) {
+            (AstNode::Access(lhs), AstNode::Access(rhs)) => lhs == rhs,
+            (AstNode::UnaryExpr(Op::Star, lhs), AstNode::UnaryExpr(Op::Star, rhs)) => {
+                Self::same_lvalue(lhs, rhs)
+            }
+            (AstNode::MemberAccess(lhs, lhs_field), AstNode::MemberAccess(rhs, rhs_field)) => {
+                lhs_field == rhs_field && Self::same_lvalue(lhs, rhs)
+            }
+            (
+                AstNode::Index {
+                    object: lhs,
+                    index: lhs_index,
+                },
+                AstNode::Index {
+                    object: rhs,
+                    index: rhs_index,
+                },
+            ) => {
+                let same_index = match (&lhs_index.node, &rhs_index.node) {
+                    (AstNode::Literal(lhs), AstNode::Literal(rhs)) => lhs == rhs,
+                    (AstNode::Access(lhs), AstNode::Access(rhs)) => lhs == rhs,
+                    _ => false,
+                };
+                same_index && Self::same_lvalue(lhs, rhs)
+            }
+            _ => false,
+        }
+    }
+
+    /// Check if control flow can reach the end of a block of statements, returning the span of
+    /// the statement that control falls through after, or `end` if the block is empty
+    pub(super) fn fallthrough_span(body: &[Ast<TypeId>], end: Span) -> Option<Span> {
+        if body.iter().any(Self::diverges) {
+            return None;
+        }
+
+        Some(match body.last() {
+            Some(Ast {
+                node: AstNode::IfExpr(if_expr),
+                span,
+            }) => Self::if_fallthrough_span(if_expr, *span),
+            Some(last) => last.span,
+            None => end,
+        })
+    }
+
+    /// Get the span of the first branch of an if expression that falls through
+    fn if_fallthrough_span(if_expr: &IfExpr<TypeId>, span: Span) -> Span {
+        if let Some(fallthrough) = Self::fallthrough_span(&if_expr.body, span) {
+            return fallthrough;
+        }
+
+        match &if_expr.else_expr {
+            Some(ElseExpr::ElseIf(else_if)) => Self::if_fallthrough_span(else_if, span),
+            Some(ElseExpr::Else(body)) => Self::fallthrough_span(body, span).unwrap_or(span),
+            None => span,
+        }
+    }
+
+    /// Check if a statement never passes control to the statement after it
+    fn diverges(stmt: &Ast<TypeId>) -> bool {
+        match &stmt.node {
+            AstNode::Return(_) | AstNode::Break(..) | AstNode::Continue(_) => true,
+            AstNode::IfExpr(if_expr) => Self::if_diverges(if_expr),
+            //Blocks only repeat when continued, and exit when broken out of
+            AstNode::Block(label, body) => {
+                let mut breaks = vec![];
+                body.iter()
+                    .for_each(|stmt| Self::collect_breaks(stmt, *label, true, &mut breaks));
+                body.iter().any(Self::diverges) && breaks.is_empty()
+            }
+            AstNode::Match { cases, .. } => {
+                !cases.is_empty() && cases.iter().all(|(_, _, case)| Self::diverges(case))
+            }
+            _ => false,
+        }
+    }
+
+    fn if_diverges(if_expr: &IfExpr<TypeId>) -> bool {
+        if_expr.body.iter().any(Self::diverges)
+            && match &if_expr.else_expr {
+                Some(ElseExpr::ElseIf(else_if)) => Self::if_diverges(else_if),
+                Some(ElseExpr::Else(body)) => body.iter().any(Self::diverges),
+                None => false,
+            }
+    }
+
+    /// Collect all break statements that exit the enclosing block with the given label, where
+    /// `innermost` is true when the block is the innermost one around the statement
+    fn collect_breaks<'a>(
+        stmt: &'a Ast<TypeId>,
+        label: Option<Symbol>,
+        innermost: bool,
+        breaks: &mut Vec<&'a Ast<TypeId>>,
+    ) {
+        match &stmt.node {
+            AstNode::Break(None, _) if innermost => breaks.push(stmt),
+            AstNode::Break(Some(target), _) if label == Some(*target) => breaks.push(stmt),
+            AstNode::IfExpr(if_expr) => Self::if_collect_breaks(if_expr, label, innermost, breaks),
+            AstNode::Match { cases, .. } => cases
+                .iter()
+                .for_each(|(_, _, case)| Self::collect_breaks(case, label, innermost, breaks)),
+            //A nested block with the same label shadows the enclosing one
+            AstNode::Block(inner, body) | AstNode::While { label: inner, body, .. }
+                if label.is_some() && *inner != label =>
+            {
+                body.iter()
+                    .for_each(|stmt| Self::collect_breaks(stmt, label, false, breaks))
+            }
+            _ => (),
+        }
+    }
+
+    fn if_collect_breaks<'a>(
+        if_expr: &'a IfExpr<TypeId>,
+        label: Option<Symbol>,
+        innermost: bool,
+        breaks: &mut Vec<&'a Ast<TypeId>>,
+    ) {
+        if_expr
+            .body
+            .iter()
+            .for_each(|stmt| Self::collect_breaks(stmt, label, innermost, breaks));
+        match &if_expr.else_expr {
+            Some(ElseExpr::ElseIf(else_if)) => {
+                Self::if_collect_breaks(else_if, label, innermost, breaks)
             }
+            Some(ElseExpr::Else(body)) => body
+                .iter()
+                .for_each(|stmt| Self::collect_breaks(stmt, label, innermost, breaks)),
+            None => (),
+        }
+    }
+
+    /// Get the node that a block's value type is inferred from, either a phi statement or the
+    /// value of a break out of the block
+    fn block_phi_node(
+        file: FileId,
+        label: Option<Symbol>,
+        body: &[Ast<TypeId>],
+    ) -> CompilerRes<&Ast<TypeId>> {
+        Self::phi_node(file, body).or_else(|e| {
+            let mut breaks = vec![];
+            body.iter()
+                .for_each(|stmt| Self::collect_breaks(stmt, label, true, &mut breaks));
+            breaks
+                .into_iter()
+                .find_map(|brk| match &brk.node {
+                    AstNode::Break(_, Some(value)) => Some(&**value),
+                    _ => None,
+                })
+                .ok_or(e)
         })
     }
 
@@ -54,7 +54,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 match self.opts.out_type {
                     OutputFileType::Assembly => FileType::Assembly,
                     OutputFileType::Object => FileType::Object,
-                    OutputFileType::LLVMIR => unreachable!(),
+                    OutputFileType::LLVMIR | OutputFileType::C => unreachable!(),
                 },
                 &self.opts.out_file,
             )
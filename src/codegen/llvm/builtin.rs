@@ -0,0 +1,828 @@
+//! Generating LLVM IR for calls to builtin functions that are lowered directly to intrinsics
+
+use std::convert::TryFrom;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use inkwell::{
+    types::IntType,
+    values::{BasicMetadataValueEnum, CallableValue, IntValue},
+    AtomicOrdering, AtomicRMWBinOp, IntPredicate,
+};
+
+use crate::{
+    ast::{Ast, AstNode, Literal},
+    codegen::CompilerRes,
+    parse::token::Op,
+};
+
+use super::*;
+
+/// A function that is always available without being defined and is implemented by the compiler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Builtin {
+    /// `memcpy(dst, src, n)`, copying `n` bytes between non-overlapping buffers
+    Memcpy,
+    /// `memset(dst, byte, n)`, setting `n` bytes of a buffer to a single byte value
+    Memset,
+    /// `memmove(dst, src, n)`, copying `n` bytes between possibly overlapping buffers
+    Memmove,
+    /// `fma(a, b, c)`, computing `a * b + c` for floats with a single rounding
+    Fma,
+    /// `sqrt(x)`, the square root of a float
+    Sqrt,
+    /// `sin(x)`, the sine of a float in radians
+    Sin,
+    /// `cos(x)`, the cosine of a float in radians
+    Cos,
+    /// `pow(x, y)`, raising a float to the power of another float
+    Pow,
+    /// `fabs(x)`, the absolute value of a float
+    Fabs,
+    /// `min(a, b)`, the smaller of two integers
+    Min,
+    /// `max(a, b)`, the larger of two integers
+    Max,
+    /// `abs(x)`, the absolute value of a signed integer
+    Abs,
+    /// `popcount(x)`, the number of bits set in an integer
+    Popcount,
+    /// `leading_zeros(x)`, the number of zero bits before the most significant set bit
+    LeadingZeros,
+    /// `trailing_zeros(x)`, the number of zero bits after the least significant set bit
+    TrailingZeros,
+    /// `bswap(x)`, reversing the order of bytes in an integer of at least 16 bits
+    Bswap,
+    /// `volatile_load(ptr)`, reading the value behind a pointer with a load that is never
+    /// removed or reordered by optimizations
+    VolatileLoad,
+    /// `volatile_store(ptr, val)`, writing a value behind a pointer with a store that is never
+    /// removed or reordered by optimizations
+    VolatileStore,
+    /// `atomic_load(ptr, ordering)`, atomically reading the integer or pointer behind a pointer
+    AtomicLoad,
+    /// `atomic_store(ptr, val, ordering)`, atomically writing an integer or pointer behind a
+    /// pointer
+    AtomicStore,
+    /// `atomic_add(ptr, val, ordering)`, atomically adding to the integer behind a pointer and
+    /// returning the previous value
+    AtomicAdd,
+    /// `atomic_cas(ptr, expected, new, ordering)`, atomically replacing the integer or pointer
+    /// behind a pointer if it is equal to an expected value, returning the previous value
+    AtomicCas,
+    /// `alloc(T)`, allocating memory for a single value of type `T` on the heap with `malloc`
+    Alloc,
+    /// `alloc_array(T, n)`, allocating memory for `n` values of type `T` on the heap with
+    /// `malloc`
+    AllocArray,
+    /// `free(ptr)`, releasing heap memory that was allocated with `alloc` or `alloc_array`
+    Free,
+    /// `discriminant_of(x)`, the index of the variant that an enum value holds as a `u8`
+    DiscriminantOf,
+}
+
+impl Builtin {
+    /// Get the builtin function with the given name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "memcpy" => Self::Memcpy,
+            "memset" => Self::Memset,
+            "memmove" => Self::Memmove,
+            "fma" => Self::Fma,
+            "sqrt" => Self::Sqrt,
+            "sin" => Self::Sin,
+            "cos" => Self::Cos,
+            "pow" => Self::Pow,
+            "fabs" => Self::Fabs,
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "abs" => Self::Abs,
+            "popcount" => Self::Popcount,
+            "leading_zeros" => Self::LeadingZeros,
+            "trailing_zeros" => Self::TrailingZeros,
+            "bswap" => Self::Bswap,
+            "volatile_load" => Self::VolatileLoad,
+            "volatile_store" => Self::VolatileStore,
+            "atomic_load" => Self::AtomicLoad,
+            "atomic_store" => Self::AtomicStore,
+            "atomic_add" => Self::AtomicAdd,
+            "atomic_cas" => Self::AtomicCas,
+            "alloc" => Self::Alloc,
+            "alloc_array" => Self::AllocArray,
+            "free" => Self::Free,
+            "discriminant_of" => Self::DiscriminantOf,
+            _ => return None,
+        })
+    }
+
+    /// Get the name that this builtin is called by
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Memcpy => "memcpy",
+            Self::Memset => "memset",
+            Self::Memmove => "memmove",
+            Self::Fma => "fma",
+            Self::Sqrt => "sqrt",
+            Self::Sin => "sin",
+            Self::Cos => "cos",
+            Self::Pow => "pow",
+            Self::Fabs => "fabs",
+            Self::Min => "min",
+            Self::Max => "max",
+            Self::Abs => "abs",
+            Self::Popcount => "popcount",
+            Self::LeadingZeros => "leading_zeros",
+            Self::TrailingZeros => "trailing_zeros",
+            Self::Bswap => "bswap",
+            Self::VolatileLoad => "volatile_load",
+            Self::VolatileStore => "volatile_store",
+            Self::AtomicLoad => "atomic_load",
+            Self::AtomicStore => "atomic_store",
+            Self::AtomicAdd => "atomic_add",
+            Self::AtomicCas => "atomic_cas",
+            Self::Alloc => "alloc",
+            Self::AllocArray => "alloc_array",
+            Self::Free => "free",
+            Self::DiscriminantOf => "discriminant_of",
+        }
+    }
+
+    /// Get the number of arguments that this builtin takes
+    pub const fn arg_count(&self) -> usize {
+        match self {
+            Self::AtomicCas => 4,
+            Self::Memcpy
+            | Self::Memset
+            | Self::Memmove
+            | Self::Fma
+            | Self::AtomicStore
+            | Self::AtomicAdd => 3,
+            Self::Pow
+            | Self::Min
+            | Self::Max
+            | Self::VolatileStore
+            | Self::AtomicLoad
+            | Self::AllocArray => 2,
+            Self::Sqrt
+            | Self::Sin
+            | Self::Cos
+            | Self::Fabs
+            | Self::Abs
+            | Self::Popcount
+            | Self::LeadingZeros
+            | Self::TrailingZeros
+            | Self::Bswap
+            | Self::VolatileLoad
+            | Self::Alloc
+            | Self::Free
+            | Self::DiscriminantOf => 1,
+        }
+    }
+
+    /// Get the type of value returned by a call to this builtin, or `None` if the returned type
+    /// depends on the arguments passed
+    pub const fn return_ty(&self) -> Option<TypeId> {
+        match self {
+            Self::Memcpy
+            | Self::Memset
+            | Self::Memmove
+            | Self::VolatileStore
+            | Self::AtomicStore
+            | Self::Free => Some(SparkCtx::UNIT),
+            Self::DiscriminantOf => Some(SparkCtx::U8),
+            Self::Fma
+            | Self::Sqrt
+            | Self::Sin
+            | Self::Cos
+            | Self::Pow
+            | Self::Fabs
+            | Self::Min
+            | Self::Max
+            | Self::Abs
+            | Self::Popcount
+            | Self::LeadingZeros
+            | Self::TrailingZeros
+            | Self::Bswap
+            | Self::VolatileLoad
+            | Self::AtomicLoad
+            | Self::AtomicAdd
+            | Self::AtomicCas
+            | Self::Alloc
+            | Self::AllocArray => None,
+        }
+    }
+}
+
+impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
+    /// Get the builtin function that a called expression names, if the name is not shadowed by
+    /// a user definition
+    pub(super) fn called_builtin(&self, called: &Ast<TypeId>) -> Option<Builtin> {
+        match &called.node {
+            AstNode::Access(path) if path.len() == 1 => {
+                let builtin = Builtin::from_name(path.last().as_str())?;
+                match self.find_in_scope(called.span, path) {
+                    Ok(_) => None,
+                    Err(_) => Some(builtin),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Get the type of value returned by a call to a builtin function
+    pub(super) fn builtin_return_ty(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        called: &Ast<TypeId>,
+        args: &[Ast<TypeId>],
+    ) -> CompilerRes<TypeId> {
+        match builtin.return_ty() {
+            Some(ty) => Ok(ty),
+            None => {
+                self.check_builtin_arg_count(builtin, called, args)?;
+                match builtin {
+                    Builtin::VolatileLoad
+                    | Builtin::AtomicLoad
+                    | Builtin::AtomicAdd
+                    | Builtin::AtomicCas => self.builtin_pointee_ty(module, builtin, &args[0]),
+                    Builtin::Alloc | Builtin::AllocArray => {
+                        let ty = self.builtin_type_arg(builtin, &args[0])?;
+                        Ok(self.spark.new_type(TypeData::Pointer(ty)))
+                    }
+                    _ => self.ast_type(module, &args[0]),
+                }
+            }
+        }
+    }
+
+    /// Check that the right number of arguments are passed to a builtin function
+    fn check_builtin_arg_count(
+        &self,
+        builtin: Builtin,
+        called: &Ast<TypeId>,
+        args: &[Ast<TypeId>],
+    ) -> CompilerRes<()> {
+        if args.len() != builtin.arg_count() {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid number of arguments to builtin {}",
+                    builtin.name()
+                ))
+                .with_labels(vec![Label::primary(self.file, called.span).with_message(
+                    format!("Expecting {} arguments, found {}", builtin.arg_count(), args.len()),
+                )]));
+        }
+        Ok(())
+    }
+
+    /// Generate LLVM IR for a call to a builtin function
+    pub(super) fn gen_builtin_call(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        called: &Ast<TypeId>,
+        args: &[Ast<TypeId>],
+    ) -> CompilerRes<Option<BasicValueEnum<'ctx>>> {
+        self.check_builtin_arg_count(builtin, called, args)?;
+        match builtin {
+            Builtin::Memcpy | Builtin::Memmove | Builtin::Memset => {
+                let dst = self.gen_builtin_ptr_arg(module, builtin, &args[0])?;
+                let size = self.gen_builtin_int_arg(module, builtin, &args[2])?;
+
+                let res = if let Builtin::Memset = builtin {
+                    let byte = self.gen_builtin_int_arg(module, builtin, &args[1])?;
+                    let byte = self
+                        .builder
+                        .build_int_truncate_or_bit_cast(byte, self.ctx.i8_type(), "memset_byte");
+                    self.builder.build_memset(dst, 1, byte, size)
+                } else {
+                    let src = self.gen_builtin_ptr_arg(module, builtin, &args[1])?;
+                    if let Builtin::Memcpy = builtin {
+                        self.builder.build_memcpy(dst, 1, src, 1, size)
+                    } else {
+                        self.builder.build_memmove(dst, 1, src, 1, size)
+                    }
+                };
+
+                res.map_err(|e| {
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "Failed to generate call to builtin {}: {}",
+                            builtin.name(),
+                            e
+                        ))
+                        .with_labels(vec![Label::primary(self.file, called.span)])
+                })?;
+                Ok(None)
+            }
+            Builtin::Fma
+            | Builtin::Sqrt
+            | Builtin::Sin
+            | Builtin::Cos
+            | Builtin::Pow
+            | Builtin::Fabs => {
+                let (ty, args) = self.gen_builtin_same_ty_args(module, builtin, args, "a float", |ty| {
+                    matches!(ty, TypeData::Float { .. })
+                })?;
+                let intrinsic = format!("llvm.{}", builtin.name());
+                Ok(Some(self.call_intrinsic(&intrinsic, ty, &args)))
+            }
+            //The llvm.smin family of intrinsics was added in LLVM 12, so integer min, max, and
+            //abs are lowered to a comparison and select
+            Builtin::Min | Builtin::Max => {
+                let (ty, args) = self.gen_builtin_same_ty_args(module, builtin, args, "an integer", |ty| {
+                    matches!(ty, TypeData::Integer { .. })
+                })?;
+                let signed = matches!(self.spark[ty], TypeData::Integer { signed: true, .. });
+                let predicate = match (builtin, signed) {
+                    (Builtin::Min, true) => IntPredicate::SLT,
+                    (Builtin::Min, false) => IntPredicate::ULT,
+                    (_, true) => IntPredicate::SGT,
+                    (_, false) => IntPredicate::UGT,
+                };
+                let (lhs, rhs) = (args[0].into_int_value(), args[1].into_int_value());
+                let cmp = self.builder.build_int_compare(predicate, lhs, rhs, "cmp");
+                Ok(Some(self.builder.build_select(cmp, lhs, rhs, builtin.name())))
+            }
+            Builtin::Abs => {
+                let (_, args) = self.gen_builtin_same_ty_args(module, builtin, args, "a signed integer", |ty| {
+                    matches!(ty, TypeData::Integer { signed: true, .. })
+                })?;
+                let val = args[0].into_int_value();
+                let negative = self.builder.build_int_compare(
+                    IntPredicate::SLT,
+                    val,
+                    val.get_type().const_zero(),
+                    "is_negative",
+                );
+                let negated = self.builder.build_int_neg(val, "neg");
+                Ok(Some(self.builder.build_select(negative, negated, val, "abs")))
+            }
+            Builtin::Popcount | Builtin::LeadingZeros | Builtin::TrailingZeros => {
+                let (ty, mut args) = self.gen_builtin_same_ty_args(module, builtin, args, "an integer", |ty| {
+                    matches!(ty, TypeData::Integer { .. })
+                })?;
+                let intrinsic = match builtin {
+                    Builtin::Popcount => "llvm.ctpop",
+                    Builtin::LeadingZeros => "llvm.ctlz",
+                    _ => "llvm.cttz",
+                };
+                //ctlz and cttz take a flag that makes a zero operand produce poison
+                if builtin != Builtin::Popcount {
+                    args.push(self.ctx.bool_type().const_zero().into());
+                }
+                Ok(Some(self.call_intrinsic(intrinsic, ty, &args)))
+            }
+            Builtin::Bswap => {
+                let (ty, args) = self.gen_builtin_same_ty_args(
+                    module,
+                    builtin,
+                    args,
+                    "a 16, 32, or 64 bit integer",
+                    |ty| {
+                        matches!(
+                            ty,
+                            TypeData::Integer {
+                                width: IntegerWidth::Sixteen
+                                    | IntegerWidth::ThirtyTwo
+                                    | IntegerWidth::SixtyFour,
+                                ..
+                            }
+                        )
+                    },
+                )?;
+                Ok(Some(self.call_intrinsic("llvm.bswap", ty, &args)))
+            }
+            Builtin::VolatileLoad => {
+                let ptr = self.gen_builtin_ptr_arg(module, builtin, &args[0])?;
+                let val = self.builder.build_load(ptr, "volatile_load");
+                val.as_instruction_value()
+                    .unwrap()
+                    .set_volatile(true)
+                    .unwrap();
+                Ok(Some(val))
+            }
+            Builtin::VolatileStore => {
+                let pointee = self.builtin_pointee_ty(module, builtin, &args[0])?;
+                let ptr = self.gen_builtin_ptr_arg(module, builtin, &args[0])?;
+                let val = self.gen_builtin_pointee_arg(module, builtin, pointee, &args[1])?;
+                self.builder.build_store(ptr, val).set_volatile(true).unwrap();
+                Ok(None)
+            }
+            Builtin::AtomicLoad => {
+                let ptr = self.gen_builtin_atomic_ptr_arg(module, builtin, &args[0], false)?;
+                let ordering = self.builtin_ordering_arg(builtin, &args[1])?;
+                if matches!(
+                    ordering,
+                    AtomicOrdering::Release | AtomicOrdering::AcquireRelease
+                ) {
+                    return Err(Diagnostic::error()
+                        .with_message("Atomic loads cannot have release ordering")
+                        .with_labels(vec![Label::primary(self.file, args[1].span)]));
+                }
+                let val = self.builder.build_load(ptr, "atomic_load");
+                val.as_instruction_value()
+                    .unwrap()
+                    .set_atomic_ordering(ordering)
+                    .unwrap();
+                Ok(Some(val))
+            }
+            Builtin::AtomicStore => {
+                let pointee = self.builtin_pointee_ty(module, builtin, &args[0])?;
+                let ptr = self.gen_builtin_atomic_ptr_arg(module, builtin, &args[0], false)?;
+                let val = self.gen_builtin_pointee_arg(module, builtin, pointee, &args[1])?;
+                let ordering = self.builtin_ordering_arg(builtin, &args[2])?;
+                if matches!(
+                    ordering,
+                    AtomicOrdering::Acquire | AtomicOrdering::AcquireRelease
+                ) {
+                    return Err(Diagnostic::error()
+                        .with_message("Atomic stores cannot have acquire ordering")
+                        .with_labels(vec![Label::primary(self.file, args[2].span)]));
+                }
+                self.builder
+                    .build_store(ptr, val)
+                    .set_atomic_ordering(ordering)
+                    .unwrap();
+                Ok(None)
+            }
+            Builtin::AtomicAdd => {
+                let pointee = self.builtin_pointee_ty(module, builtin, &args[0])?;
+                let ptr = self.gen_builtin_atomic_ptr_arg(module, builtin, &args[0], true)?;
+                let val = self.gen_builtin_pointee_arg(module, builtin, pointee, &args[1])?;
+                let ordering = self.builtin_ordering_arg(builtin, &args[2])?;
+                Ok(Some(
+                    self.builder
+                        .build_atomicrmw(AtomicRMWBinOp::Add, ptr, val.into_int_value(), ordering)
+                        .unwrap()
+                        .into(),
+                ))
+            }
+            Builtin::AtomicCas => {
+                let pointee = self.builtin_pointee_ty(module, builtin, &args[0])?;
+                let ptr = self.gen_builtin_atomic_ptr_arg(module, builtin, &args[0], false)?;
+                let expected = self.gen_builtin_pointee_arg(module, builtin, pointee, &args[1])?;
+                let new = self.gen_builtin_pointee_arg(module, builtin, pointee, &args[2])?;
+                let ordering = self.builtin_ordering_arg(builtin, &args[3])?;
+                //The ordering used when the comparison fails cannot contain a release
+                let failure = match ordering {
+                    AtomicOrdering::Release => AtomicOrdering::Monotonic,
+                    AtomicOrdering::AcquireRelease => AtomicOrdering::Acquire,
+                    other => other,
+                };
+                let pair = self
+                    .builder
+                    .build_cmpxchg(ptr, expected, new, ordering, failure)
+                    .unwrap();
+                Ok(Some(
+                    self.builder
+                        .build_extract_value(pair, 0, "atomic_cas_prev")
+                        .unwrap(),
+                ))
+            }
+            Builtin::Alloc | Builtin::AllocArray => {
+                let ty = self.builtin_type_arg(builtin, &args[0])?;
+                let llvm_ty = Self::require_basictype(self.file, args[0].span, self.llvm_ty(args[0].span, ty)?)?;
+                let size_ty = self.size_ty();
+                let mut size = self.builder.build_int_cast(
+                    llvm_ty.size_of().unwrap(),
+                    size_ty,
+                    "alloc_size",
+                );
+                if let Builtin::AllocArray = builtin {
+                    let count = self.gen_builtin_int_arg(module, builtin, &args[1])?;
+                    let count = self.builder.build_int_cast(count, size_ty, "alloc_count");
+                    size = self.builder.build_int_mul(size, count, "alloc_array_size");
+                }
+
+                let malloc = self.libc_fun(
+                    "malloc",
+                    self.ctx
+                        .i8_type()
+                        .ptr_type(AddressSpace::Generic)
+                        .fn_type(&[size_ty.into()], false),
+                );
+                let ptr = self
+                    .builder
+                    .build_call(malloc, &[size.into()], "malloc")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_pointer_value();
+                Ok(Some(
+                    self.builder
+                        .build_pointer_cast(ptr, llvm_ty.ptr_type(AddressSpace::Generic), "alloc_ptr")
+                        .into(),
+                ))
+            }
+            Builtin::Free => {
+                let ptr = self.gen_builtin_ptr_arg(module, builtin, &args[0])?;
+                let i8_ptr_ty = self.ctx.i8_type().ptr_type(AddressSpace::Generic);
+                let ptr = self.builder.build_pointer_cast(ptr, i8_ptr_ty, "free_ptr");
+                let free = self.libc_fun(
+                    "free",
+                    self.ctx.void_type().fn_type(&[i8_ptr_ty.into()], false),
+                );
+                self.builder.build_call(free, &[ptr.into()], "");
+                Ok(None)
+            }
+            Builtin::DiscriminantOf => {
+                let ty = self.ast_type(module, &args[0])?;
+                let enum_ty = self.spark.unwrap_alias(ty);
+                if !matches!(self.spark[enum_ty], TypeData::Enum { .. }) {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Passing invalid argument type '{}' to builtin {}, expecting an enum",
+                            self.spark.get_type_name(ty),
+                            builtin.name(),
+                        ))
+                        .with_labels(vec![Label::primary(self.file, args[0].span)]));
+                }
+
+                //Enums without variant data are represented by their discriminant alone
+                if self.is_payloadless_enum(enum_ty) {
+                    return Ok(Some(self.gen_expr(module, &args[0])?));
+                }
+                let enum_ptr = self.gen_lval(module, &args[0])?;
+                let discr = self
+                    .builder
+                    .build_struct_gep(enum_ptr, 0, "enum_discr")
+                    .unwrap();
+                Ok(Some(self.builder.build_load(discr, "enum_discr_load")))
+            }
+        }
+    }
+
+    /// Get the integer type that sizes passed to the C library's allocation functions have
+    fn size_ty(&self) -> IntType<'ctx> {
+        self.ctx
+            .ptr_sized_int_type(&self.target.get_target_data(), None)
+    }
+
+    /// Get a function from the C library, declaring it in the current module if it has not been
+    /// used before
+    ///
+    /// A program may declare a function with the same name but a different signature, which
+    /// is then called through a pointer cast to the signature that the C library defines
+    pub(super) fn libc_fun(&self, name: &str, ty: InkwellFunctionType<'ctx>) -> CallableValue<'ctx> {
+        let module = self.llvm_module.as_ref().unwrap();
+        match module.get_function(name) {
+            Some(fun) if fun.get_type() != ty => {
+                let ptr = fun
+                    .as_global_value()
+                    .as_pointer_value()
+                    .const_cast(ty.ptr_type(AddressSpace::Generic));
+                CallableValue::try_from(ptr).unwrap()
+            }
+            Some(fun) => fun.into(),
+            None => module.add_function(name, ty, Some(Linkage::External)).into(),
+        }
+    }
+
+    /// Get the type named by an argument to a builtin function that takes a type, like `i32`,
+    /// a user-defined type's name, or a pointer to either written with `*`
+    fn builtin_type_arg(&mut self, builtin: Builtin, arg: &Ast<TypeId>) -> CompilerRes<TypeId> {
+        let ty = match &arg.node {
+            AstNode::Access(path) if path.len() == 1 => match path.last().as_str() {
+                "i8" => Some(SparkCtx::I8),
+                "i16" => Some(SparkCtx::I16),
+                "i32" => Some(SparkCtx::I32),
+                "i64" => Some(SparkCtx::I64),
+                "u8" => Some(SparkCtx::U8),
+                "u16" => Some(SparkCtx::U16),
+                "u32" => Some(SparkCtx::U32),
+                "u64" => Some(SparkCtx::U64),
+                "f32" => Some(SparkCtx::F32),
+                "f64" => Some(SparkCtx::F64),
+                "bool" => Some(SparkCtx::BOOL),
+                _ => None,
+            },
+            _ => None,
+        };
+        if let Some(ty) = ty {
+            return Ok(ty);
+        }
+
+        match &arg.node {
+            AstNode::Access(path) => {
+                if let Ok(ScopeDef::Def(SparkDef::TypeDef(_, ty))) = self.find_in_scope(arg.span, path) {
+                    return Ok(ty);
+                }
+            }
+            AstNode::UnaryExpr(Op::Star, pointee) => {
+                let pointee = self.builtin_type_arg(builtin, pointee)?;
+                return Ok(self.spark.new_type(TypeData::Pointer(pointee)));
+            }
+            _ => (),
+        }
+
+        Err(Diagnostic::error()
+            .with_message(format!(
+                "Passing invalid argument to builtin {}, expecting a type name",
+                builtin.name()
+            ))
+            .with_labels(vec![Label::primary(self.file, arg.span)]))
+    }
+
+    /// Generate a pointer argument to an atomic builtin, which must point to an integer or,
+    /// unless `int_only` is set, a pointer
+    fn gen_builtin_atomic_ptr_arg(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        arg: &Ast<TypeId>,
+        int_only: bool,
+    ) -> CompilerRes<PointerValue<'ctx>> {
+        let pointee = self.builtin_pointee_ty(module, builtin, arg)?;
+        match self.spark[self.spark.unwrap_alias(pointee)] {
+            TypeData::Integer { .. } => (),
+            TypeData::Pointer(_) if !int_only => (),
+            _ => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Passing pointer to '{}' to builtin {}, expecting a pointer to an integer{}",
+                        self.spark.get_type_name(pointee),
+                        builtin.name(),
+                        if int_only { "" } else { " or pointer" },
+                    ))
+                    .with_labels(vec![Label::primary(self.file, arg.span)]))
+            }
+        }
+        Ok(self.gen_expr(module, arg)?.into_pointer_value())
+    }
+
+    /// Get the memory ordering of an atomic operation from a string literal argument to a builtin
+    fn builtin_ordering_arg(
+        &self,
+        builtin: Builtin,
+        arg: &Ast<TypeId>,
+    ) -> CompilerRes<AtomicOrdering> {
+        let ordering = match &arg.node {
+            AstNode::Literal(Literal::String(ordering)) => match ordering.as_str() {
+                "relaxed" => Some(AtomicOrdering::Monotonic),
+                "acquire" => Some(AtomicOrdering::Acquire),
+                "release" => Some(AtomicOrdering::Release),
+                "acq_rel" => Some(AtomicOrdering::AcquireRelease),
+                "seq_cst" => Some(AtomicOrdering::SequentiallyConsistent),
+                _ => None,
+            },
+            _ => None,
+        };
+        ordering.ok_or_else(|| {
+            Diagnostic::error()
+                .with_message(format!("Invalid memory ordering passed to builtin {}", builtin.name()))
+                .with_labels(vec![Label::primary(self.file, arg.span).with_message(
+                    "Expecting one of \"relaxed\", \"acquire\", \"release\", \"acq_rel\", or \"seq_cst\"",
+                )])
+        })
+    }
+
+    /// Generate arguments to a builtin function that must all be of the same type, checked by
+    /// `valid`, returning the type of the arguments
+    fn gen_builtin_same_ty_args(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        args: &[Ast<TypeId>],
+        expecting: &str,
+        valid: fn(&TypeData) -> bool,
+    ) -> CompilerRes<(TypeId, Vec<BasicValueEnum<'ctx>>)> {
+        let ty = self.ast_type(module, &args[0])?;
+        let ty = self.spark.unwrap_alias(ty);
+        let mut vals = vec![];
+        for arg in args {
+            let arg_ty = self.ast_type(module, arg)?;
+            if self.spark.unwrap_alias(arg_ty) != ty || !valid(&self.spark[ty]) {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Passing invalid argument type '{}' to builtin {}, expecting {} with the same type as the first argument",
+                        self.spark.get_type_name(arg_ty),
+                        builtin.name(),
+                        expecting,
+                    ))
+                    .with_labels(vec![Label::primary(self.file, arg.span)]));
+            }
+            vals.push(self.gen_expr(module, arg)?);
+        }
+        Ok((ty, vals))
+    }
+
+    /// Call an overloaded LLVM intrinsic that returns a value of type `ty`, declaring it in the
+    /// current module if it has not been called before
+    fn call_intrinsic(
+        &mut self,
+        name: &str,
+        ty: TypeId,
+        args: &[BasicValueEnum<'ctx>],
+    ) -> BasicValueEnum<'ctx> {
+        let llvm_ty = match self.spark[ty] {
+            TypeData::Integer { width, .. } => BasicTypeEnum::from(self.llvm_int_ty(width)),
+            TypeData::Float { doublewide: false } => self.ctx.f32_type().into(),
+            TypeData::Float { doublewide: true } => self.ctx.f64_type().into(),
+            _ => unreachable!("Intrinsic called on a non-numeric type"),
+        };
+        //Overloaded intrinsics are suffixed with the name of the type they operate on
+        let name = format!(
+            "{}.{}",
+            name,
+            match llvm_ty {
+                BasicTypeEnum::IntType(i) => format!("i{}", i.get_bit_width()),
+                BasicTypeEnum::FloatType(f) if f == self.ctx.f32_type() => "f32".to_owned(),
+                _ => "f64".to_owned(),
+            }
+        );
+
+        let module = self.llvm_module.as_ref().unwrap();
+        let intrinsic = module.get_function(&name).unwrap_or_else(|| {
+            let arg_tys = args
+                .iter()
+                .map(|arg| arg.get_type().into())
+                .collect::<Vec<BasicMetadataTypeEnum>>();
+            module.add_function(&name, llvm_ty.fn_type(&arg_tys, false), None)
+        });
+
+        self.builder
+            .build_call(
+                intrinsic,
+                &args.iter().map(|arg| (*arg).into()).collect::<Vec<BasicMetadataValueEnum>>(),
+                "intrinsic_call",
+            )
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+    }
+
+    /// Generate an argument to a builtin function that must be of pointer type
+    fn gen_builtin_ptr_arg(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        arg: &Ast<TypeId>,
+    ) -> CompilerRes<PointerValue<'ctx>> {
+        self.builtin_pointee_ty(module, builtin, arg)?;
+        Ok(self.gen_expr(module, arg)?.into_pointer_value())
+    }
+
+    /// Generate an argument to a builtin function that must have the type pointed to by a
+    /// pointer argument
+    fn gen_builtin_pointee_arg(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        pointee: TypeId,
+        arg: &Ast<TypeId>,
+    ) -> CompilerRes<BasicValueEnum<'ctx>> {
+        let ty = self.ast_type(module, arg)?;
+        if self.spark.unwrap_alias(ty) != self.spark.unwrap_alias(pointee) {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid argument type '{}' to builtin {}, expecting the pointed-to type '{}'",
+                    self.spark.get_type_name(ty),
+                    builtin.name(),
+                    self.spark.get_type_name(pointee),
+                ))
+                .with_labels(vec![Label::primary(self.file, arg.span)]));
+        }
+        self.gen_expr(module, arg)
+    }
+
+    /// Get the type pointed to by an argument to a builtin function that must be of pointer type
+    fn builtin_pointee_ty(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        arg: &Ast<TypeId>,
+    ) -> CompilerRes<TypeId> {
+        let ty = self.ast_type(module, arg)?;
+        match self.spark[self.spark.unwrap_alias(ty)] {
+            TypeData::Pointer(pointee) => Ok(pointee),
+            _ => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid argument type '{}' to builtin {}, expecting a pointer",
+                    self.spark.get_type_name(ty),
+                    builtin.name(),
+                ))
+                .with_labels(vec![Label::primary(self.file, arg.span)])),
+        }
+    }
+
+    /// Generate an argument to a builtin function that must be of integer type
+    fn gen_builtin_int_arg(
+        &mut self,
+        module: ModId,
+        builtin: Builtin,
+        arg: &Ast<TypeId>,
+    ) -> CompilerRes<IntValue<'ctx>> {
+        let ty = self.ast_type(module, arg)?;
+        match self.spark[self.spark.unwrap_alias(ty)] {
+            TypeData::Integer { .. } => Ok(self.gen_expr(module, arg)?.into_int_value()),
+            _ => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Passing invalid argument type '{}' to builtin {}, expecting an integer",
+                    self.spark.get_type_name(ty),
+                    builtin.name(),
+                ))
+                .with_labels(vec![Label::primary(self.file, arg.span)])),
+        }
+    }
+}
@@ -2,6 +2,8 @@
 
 pub mod astgen;
 pub mod bingen;
+pub mod builtin;
+mod reach;
 
 use std::convert::TryFrom;
 
@@ -11,23 +13,28 @@ use inkwell::{
     basic_block::BasicBlock,
     builder::Builder,
     context::Context,
-    module::{Linkage, Module},
-    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine},
+    module::{FlagBehavior, Linkage, Module},
+    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple},
     types::{AnyTypeEnum, BasicType, BasicTypeEnum, FunctionType as InkwellFunctionType, BasicMetadataTypeEnum},
-    values::{BasicValueEnum, FunctionValue, PointerValue},
-    AddressSpace, OptimizationLevel,
+    values::{BasicValueEnum, FunctionValue, GlobalValue, PointerValue},
+    attributes::{Attribute, AttributeLoc},
+    AddressSpace, GlobalVisibility, OptimizationLevel,
 };
 use quickscope::ScopeMap;
 use hashbrown::HashSet;
 use crate::{
-    ast::{FunFlags, IntegerWidth, SymbolPath},
-    codegen::ir::{FunId, FunctionType, ModId, SparkCtx, SparkDef, TypeData, TypeId},
+    ast::{AstNode, FunFlags, IntegerWidth, SymbolPath},
+    codegen::ir::{
+        CallConv, DefLinkage, DefLookupError, DefOptimization, DefVisibility, FunId, FunctionType, GlobalFlags, GlobalId, ModId, SparkCtx,
+        SparkDef, StructRepr, TypeData, TypeId,
+    },
     error::DiagnosticManager,
+    parse::token::Op,
     util::{
         files::{FileId, Files},
         loc::Span,
     },
-    CompileOpts, OutputOptimizationLevel, Symbol,
+    CompileOpts, OutputOptimizationLevel, Symbol, TrapBehavior,
 };
 
 use super::CompilerRes;
@@ -51,14 +58,43 @@ pub struct LlvmCodeGenerator<'ctx, 'files> {
     /// The currently compiled file
     pub file: FileId,
     llvm_funs: HashMap<FunId, FunctionValue<'ctx>>,
+    /// All generated global variables and their types
+    llvm_globals: HashMap<GlobalId, (TypeId, GlobalValue<'ctx>)>,
     target: TargetMachine,
     current_scope: ScopeMap<Symbol, ScopeDef<'ctx>>,
     current_fun: Option<(FunctionValue<'ctx>, FunId)>,
     phi_data: Option<PhiData<'ctx>>,
-    continue_bb: Option<BasicBlock<'ctx>>,
-    break_bb: Option<BasicBlock<'ctx>>,
+    /// All enclosing loops, innermost last
+    loops: Vec<LoopData<'ctx>>,
     placed_terminator: bool,
     codegened_funs: HashSet<FunId>,
+    /// Functions that can be called from outside of the generated module, all other functions
+    /// are not generated
+    reachable: HashSet<FunId>,
+    /// Variables of the current function that hold the address of a stack-allocated local,
+    /// mapped to the span where the address was taken
+    local_refs: HashMap<Symbol, Span>,
+    /// The `llvm.lifetime.start` and `llvm.lifetime.end` intrinsics, or `None` if lifetime
+    /// markers are not emitted
+    lifetime_funs: Option<(FunctionValue<'ctx>, FunctionValue<'ctx>)>,
+    /// Variables declared in each enclosing block of the current function with their sizes,
+    /// whose lifetimes end when the block ends
+    scope_allocas: Vec<Vec<(PointerValue<'ctx>, u64)>>,
+    /// Names of variables in the current function that have their address taken, which can
+    /// be used through a pointer after their block ends and so don't get lifetime markers
+    addressed_vars: HashSet<Symbol>,
+    /// The module that function bodies are currently being generated into, used to declare
+    /// intrinsics when they are first called
+    llvm_module: Option<Module<'ctx>>,
+    /// Instances of generic functions that have been declared but not yet had their bodies
+    /// generated, with the module and file that the generic function is defined in
+    pending_instances: Vec<(ModId, FileId, FunId)>,
+    /// Globals holding the contents of string literals already generated in the current
+    /// module, reused when an identical literal appears again
+    string_consts: HashMap<String, GlobalValue<'ctx>>,
+    /// Constants whose initializers are currently being evaluated, used to detect constants
+    /// that depend on their own value
+    evaluating_consts: Vec<GlobalId>,
 }
 
 /// Data needed to use a phi / break / continue statement
@@ -67,20 +103,84 @@ struct PhiData<'ctx> {
     pub break_bb: BasicBlock<'ctx>,
     pub alloca: PointerValue<'ctx>,
     pub phi_ty: TypeId,
+    /// Span of the phi statement that the phi type was inferred from
+    pub phi_span: Span,
+}
+
+/// Targets of the `break` and `continue` statements in a block
+#[derive(Clone, Copy)]
+struct LoopData<'ctx> {
+    pub label: Option<Symbol>,
+    pub continue_bb: BasicBlock<'ctx>,
+    pub break_bb: BasicBlock<'ctx>,
+    /// Where values broken out of the block are stored, if the block produces a value
+    pub phi: Option<PhiData<'ctx>>,
 }
 
 impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
-    /// Create a new code generator from an LLVM context
+    /// Create a new code generator from an LLVM context, failing if LLVM can't generate code
+    /// for the target given in the compile options
     pub fn new(
         spark: SparkCtx,
         ctx: &'ctx Context,
         files: &'files Files,
         opts: CompileOpts,
-    ) -> Self {
-        Target::initialize_native(&InitializationConfig::default())
-            .expect("LLVM: failed to initialize native compilation target");
+    ) -> CompilerRes<Self> {
+        let triple = match opts.target {
+            Some(ref target) => {
+                Target::initialize_all(&InitializationConfig::default());
+                TargetTriple::create(target)
+            }
+            None => {
+                Target::initialize_native(&InitializationConfig::default()).map_err(|e| {
+                    Diagnostic::error()
+                        .with_message(format!("Failed to initialize the native compilation target: {}", e))
+                })?;
+                TargetMachine::get_default_triple()
+            }
+        };
+        let (cpu, features) = match opts.target {
+            Some(_) => (String::from("generic"), String::new()),
+            None => (
+                TargetMachine::get_host_cpu_name().to_str().unwrap().to_owned(),
+                TargetMachine::get_host_cpu_features().to_str().unwrap().to_owned(),
+            ),
+        };
+
+        let triple_name = triple.as_str().to_string_lossy().into_owned();
+        let target = Target::from_triple(&triple).map_err(|e| {
+            Diagnostic::error()
+                .with_message(format!("Unsupported compilation target '{}'", triple_name))
+                .with_notes(vec![e.to_string()])
+        })?;
+        let target = target
+            .create_target_machine(
+                &triple,
+                &cpu,
+                &features,
+                match opts.opt_lvl {
+                    OutputOptimizationLevel::Size => OptimizationLevel::Less,
+                    OutputOptimizationLevel::Medium => OptimizationLevel::Less,
+                    OutputOptimizationLevel::Debug => OptimizationLevel::None,
+                    OutputOptimizationLevel::Release => OptimizationLevel::Aggressive,
+                },
+                match opts.pic {
+                    true => RelocMode::PIC,
+                    false => RelocMode::Default,
+                },
+                match opts.opt_lvl {
+                    OutputOptimizationLevel::Size => CodeModel::Small,
+                    _ => CodeModel::Default,
+                },
+            )
+            .ok_or_else(|| {
+                Diagnostic::error().with_message(format!(
+                    "LLVM failed to create a target machine for target '{}'",
+                    triple_name
+                ))
+            })?;
 
-        Self {
+        Ok(Self {
             current_scope: ScopeMap::new(),
             current_fun: None,
             builder: ctx.create_builder(),
@@ -89,35 +189,23 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             file: unsafe { FileId::from_raw(0) },
             diags: DiagnosticManager::new(files),
             llvm_funs: HashMap::new(),
+            llvm_globals: HashMap::new(),
             phi_data: None,
-            break_bb: None,
-            continue_bb: None,
+            loops: vec![],
             placed_terminator: false,
-            target: Target::from_triple(&TargetMachine::get_default_triple())
-                .unwrap()
-                .create_target_machine(
-                    &TargetMachine::get_default_triple(),
-                    TargetMachine::get_host_cpu_name().to_str().unwrap(),
-                    TargetMachine::get_host_cpu_features().to_str().unwrap(),
-                    match opts.opt_lvl {
-                        OutputOptimizationLevel::Size => OptimizationLevel::Less,
-                        OutputOptimizationLevel::Medium => OptimizationLevel::Less,
-                        OutputOptimizationLevel::Debug => OptimizationLevel::None,
-                        OutputOptimizationLevel::Release => OptimizationLevel::Aggressive,
-                    },
-                    match opts.pic {
-                        true => RelocMode::PIC,
-                        false => RelocMode::Default,
-                    },
-                    match opts.opt_lvl {
-                        OutputOptimizationLevel::Size => CodeModel::Small,
-                        _ => CodeModel::Default,
-                    },
-                )
-                .unwrap(),
+            local_refs: HashMap::new(),
+            lifetime_funs: None,
+            scope_allocas: vec![],
+            addressed_vars: HashSet::new(),
+            llvm_module: None,
+            pending_instances: vec![],
+            string_consts: HashMap::new(),
+            evaluating_consts: vec![],
+            target,
             opts,
-            codegened_funs: HashSet::new()
-        }
+            codegened_funs: HashSet::new(),
+            reachable: HashSet::new(),
+        })
     }
 
     /// Find a name in the current scope
@@ -138,11 +226,16 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                     match *def {
                         ScopeDef::Def(SparkDef::ModDef(submod)) => self
                             .spark
-                            .get_def_impl(submod, iter)
+                            .get_def_impl(submod, iter, true)
                             .map(|d| ScopeDef::Def(d))
-                            .map_err(|name| {
+                            .map_err(|e| {
                                 Diagnostic::error()
-                                    .with_message(format!("'{}' not found in current scope", name))
+                                    .with_message(match e {
+                                        DefLookupError::NotFound(name) => {
+                                            format!("'{}' not found in current scope", name)
+                                        }
+                                        DefLookupError::Private(..) => e.to_string(),
+                                    })
                                     .with_labels(vec![Label::primary(self.file, span)])
                             }),
                         _ => Err(Diagnostic::error()
@@ -164,43 +257,14 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
     
     /// Generate code for definitions
     fn codegen_defs(&mut self, module: ModId) {
-        let defs = self.spark[module].defs.clone();
-
         self.current_scope.push_layer();
+        self.define_module_defs(module);
 
-        for (name, def) in defs.iter() {
-            self.current_scope.define(name.clone(), ScopeDef::Def(*def));
-        }
-
-        let defs = self.spark[module].defs.clone();
-        for (name, def) in defs.iter() {
+        let defs = self.spark[module].ordered_defs();
+        for (_name, def) in defs.iter() {
             if let SparkDef::FunDef(file, fun) = def {
                 self.file = *file;
-                if let Some(ref body) = self.spark[*fun].body {
-                    self.placed_terminator = false;
-                    let llvm_fun = *self.llvm_funs.get(fun).unwrap();
-                    let entry = self.ctx.append_basic_block(llvm_fun, "entry_bb");
-                    self.builder.position_at_end(entry);
-
-                    self.current_fun = Some((llvm_fun, *fun));
-                    self.current_scope.push_layer();
-                    for (arg, (arg_name, arg_ty)) in self.llvm_funs[fun].get_param_iter()
-                        .zip(self.spark[*fun].arg_names.iter().zip(self.spark[*fun].ty.args.iter())) {
-                        if let Some(arg_name) = arg_name {
-                            let arg_alloca = self.builder.build_alloca(arg.get_type(), "arg_alloca");
-                            self.builder.build_store(arg_alloca, arg);
-                            self.current_scope.define(*arg_name, ScopeDef::Value(*arg_ty, arg_alloca));
-                        }
-                    }
-
-                    for stmt in body.clone() {
-                        if let Err(e) = self.gen_stmt(module, &stmt) {
-                            self.diags
-                                .emit(e.with_notes(vec![format!("In function {}", name)]));
-                        }
-                    }
-                    self.current_scope.pop_layer();
-                }
+                self.gen_fun_body(module, *fun);
             }
         }
 
@@ -213,20 +277,177 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
         }
     }
 
+    /// Define all definitions of a module in the current scope layer
+    fn define_module_defs(&mut self, module: ModId) {
+        let defs = self.spark[module].defs.clone();
+        for (name, def) in defs.iter() {
+            match def {
+                SparkDef::GlobalDef(_, global) => {
+                    let (ty, llvm_global) = self.llvm_globals[global];
+                    self.current_scope.define(
+                        name.clone(),
+                        ScopeDef::Value(ty, llvm_global.as_pointer_value()),
+                    );
+                }
+                _ => self.current_scope.define(name.clone(), ScopeDef::Def(*def)),
+            }
+        }
+    }
+
+    /// Generate the bodies of all declared instances of generic functions, including instances
+    /// that are first used in the bodies of other instances
+    fn gen_pending_instances(&mut self) {
+        while let Some((module, file, instance)) = self.pending_instances.pop() {
+            self.file = file;
+            self.current_scope.push_layer();
+            self.define_module_defs(module);
+            self.gen_fun_body(module, instance);
+            self.current_scope.pop_layer();
+        }
+    }
+
+    /// Generate code for the body of a function if it has one, then generate all functions
+    /// nested inside of it
+    fn gen_fun_body(&mut self, module: ModId, fun: FunId) {
+        //Generic functions are only generated as instances with concrete types
+        if !self.reachable.contains(&fun) || self.spark[fun].generic.is_some() {
+            return;
+        }
+        if let Some(ref body) = self.spark[fun].body {
+            let body = body.clone();
+            self.placed_terminator = false;
+            self.local_refs.clear();
+            self.scope_allocas.clear();
+            self.addressed_vars.clear();
+            for stmt in body.iter() {
+                stmt.walk(&mut |node| {
+                    if let AstNode::UnaryExpr(Op::AND, addressed) = &node.node {
+                        self.addressed_vars.extend(Self::addressed_var(addressed));
+                    }
+                });
+            }
+            let llvm_fun = *self.llvm_funs.get(&fun).unwrap();
+            let entry = self.ctx.append_basic_block(llvm_fun, "entry_bb");
+            self.builder.position_at_end(entry);
+
+            self.current_fun = Some((llvm_fun, fun));
+            let return_ty = self.spark[fun].ty.return_ty;
+            if return_ty != SparkCtx::UNIT {
+                if let Some(fallthrough) = Self::fallthrough_span(&body, self.spark[fun].span) {
+                    self.diags.emit(Diagnostic::error()
+                        .with_message(format!(
+                            "Function '{}' does not return a value on every path",
+                            self.spark[fun].name,
+                        ))
+                        .with_labels(vec![
+                            Label::primary(self.file, self.spark[fun].span)
+                                .with_message(format!(
+                                    "Function returning '{}' declared here",
+                                    self.spark.get_type_name(return_ty),
+                                )),
+                            Label::secondary(self.file, fallthrough)
+                                .with_message("Control reaches the end of the function after this without returning"),
+                        ])
+                        .with_notes(vec!["Add a return statement to the end of this path".to_owned()]));
+                }
+            }
+            self.current_scope.push_layer();
+            for (arg, (arg_name, arg_ty)) in self.llvm_funs[&fun].get_param_iter()
+                .zip(self.spark[fun].arg_names.iter().zip(self.spark[fun].ty.args.iter())) {
+                if let Some(arg_name) = arg_name {
+                    let arg_alloca = self.build_entry_alloca(arg.get_type(), "arg_alloca");
+                    self.set_explicit_align(*arg_ty, arg_alloca);
+                    self.builder.build_store(arg_alloca, arg);
+                    self.current_scope.define(*arg_name, ScopeDef::Value(*arg_ty, arg_alloca));
+                }
+            }
+
+            for (idx, stmt) in body.iter().enumerate() {
+                if let Err(e) = self.gen_stmt(module, stmt) {
+                    self.diags
+                        .emit(e.with_notes(vec![format!("In function {}", self.spark[fun].name)]));
+                }
+                if self.placed_terminator {
+                    self.warn_unreachable(stmt, &body[idx + 1..]);
+                    break;
+                }
+            }
+            //Unit functions implicitly return at the end of their body, and non-unit functions
+            //can only reach this point from blocks with no predecessors
+            if !self.placed_terminator {
+                if return_ty == SparkCtx::UNIT {
+                    self.builder.build_return(None);
+                } else {
+                    self.builder.build_unreachable();
+                }
+            }
+            self.current_scope.pop_layer();
+        }
+
+        //Nested functions can't capture the enclosing function's variables, so they are
+        //generated after it with only themselves in scope
+        for nested in self.spark[fun].nested.clone() {
+            self.current_scope.push_layer();
+            self.current_scope.define(
+                self.spark[nested].name,
+                ScopeDef::Def(SparkDef::FunDef(self.file, nested)),
+            );
+            self.gen_fun_body(module, nested);
+            self.current_scope.pop_layer();
+        }
+    }
+
     /// Codegen LLVM IR from a type-lowered module
     pub fn codegen_module(&mut self, module: ModId) -> CompilerRes<Module<'ctx>> {
         let mut llvm_mod = self.ctx.create_module(self.spark[module].name.as_str());
+        llvm_mod.set_triple(&self.target.get_triple());
+        llvm_mod.set_data_layout(&self.target.get_target_data().get_data_layout());
+        //The relocation model only applies when this target machine generates code, the module
+        //flag keeps the module position independent when it is compiled elsewhere, like in LTO
+        if self.opts.pic {
+            llvm_mod.add_basic_value_flag(
+                "PIC Level",
+                FlagBehavior::Error,
+                self.ctx.i32_type().const_int(2, false),
+            );
+        }
+        //Lifetime markers only let the optimizer reuse stack slots, so they are left out of
+        //unoptimized output
+        self.lifetime_funs = match self.opts.opt_lvl {
+            OutputOptimizationLevel::Debug => None,
+            _ => {
+                let lifetime_fun_ty = self.ctx.void_type().fn_type(
+                    &[
+                        self.ctx.i64_type().into(),
+                        self.ctx.i8_type().ptr_type(AddressSpace::Generic).into(),
+                    ],
+                    false,
+                );
+                Some((
+                    llvm_mod.add_function("llvm.lifetime.start.p0i8", lifetime_fun_ty, None),
+                    llvm_mod.add_function("llvm.lifetime.end.p0i8", lifetime_fun_ty, None),
+                ))
+            }
+        };
+        self.reachable = self.reachable_funs(module);
+        self.string_consts.clear();
         if let Err(e) = self.forward_funs(module, &mut llvm_mod) {
             self.diags.emit(e.clone());
             return Err(e)
         }
+        if let Err(e) = self.forward_globals(module, &mut llvm_mod) {
+            self.diags.emit(e.clone());
+            return Err(e)
+        }
+        self.llvm_module = Some(llvm_mod);
         self.codegen_defs(module);
-        Ok(llvm_mod)
+        self.gen_pending_instances();
+        Ok(self.llvm_module.take().unwrap())
     }
 
     /// Generate code for all function prototypes
     fn forward_funs(&mut self, module: ModId, llvm: &mut Module<'ctx>) -> CompilerRes<()> {
-        let defs = self.spark[module].defs.clone();
+        let defs = self.spark[module].ordered_defs();
 
         for fun_id in defs.iter().filter_map(|(_, def)| {
             if let SparkDef::FunDef(_, id) = def {
@@ -235,35 +456,276 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 None
             }
         }) {
-            if self.codegened_funs.contains(&fun_id) {
-                    continue
-            }
-            self.codegened_funs.insert(fun_id);
-            let fun = self.spark[fun_id].clone();
-            let llvm_fun_ty = self.gen_fun_ty(fun.span, &fun.ty)?;
-            let llvm_fun = if fun.flags.contains(FunFlags::EXTERN) {
-                llvm.add_function(fun.name.as_str(), llvm_fun_ty, Some(Linkage::External))
+            self.forward_fun(fun_id, llvm, None)?;
+        }
+
+        for child in defs.iter() {
+            if let SparkDef::ModDef(child) = child.1 {
+                self.forward_funs(child, llvm)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate LLVM globals for all global variables in a module and its children
+    fn forward_globals(&mut self, module: ModId, llvm: &mut Module<'ctx>) -> CompilerRes<()> {
+        let defs = self.spark[module].ordered_defs();
+
+        for (file, global_id) in defs.iter().filter_map(|(_, def)| {
+            if let SparkDef::GlobalDef(file, id) = def {
+                Some((*file, *id))
             } else {
-                llvm.add_function(
-                    format!("{}-{}", fun.name, uuid::Uuid::new_v4()).as_str(),
-                    llvm_fun_ty,
-                    Some(Linkage::Internal),
-                )
+                None
+            }
+        }) {
+            if self.llvm_globals.contains_key(&global_id) {
+                continue
+            }
+            self.file = file;
+            let global = self.spark[global_id].clone();
+
+            let ty = match &global.init {
+                Some(init) => {
+                    let init_ty = self.ast_type(module, init)?;
+                    let ty = global.ty.unwrap_or(init_ty);
+                    if self.spark.unwrap_alias(ty) != self.spark.unwrap_alias(init_ty) {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Global variable '{}' of type {} initialized with value of type {}",
+                                global.name,
+                                self.spark.get_type_name(ty),
+                                self.spark.get_type_name(init_ty),
+                            ))
+                            .with_labels(vec![Label::primary(self.file, init.span)
+                                .with_message("Initializer encountered here")]));
+                    }
+                    ty
+                }
+                //The lowerer requires external globals to declare their type
+                None => global.ty.unwrap(),
             };
-            self.llvm_funs.insert(fun_id, llvm_fun);
+
+            let llvm_ty = Self::require_basictype(self.file, global.span, self.llvm_ty(global.span, ty)?)?;
+            let init = match &global.init {
+                Some(init) => Some(self.gen_const_expr(module, init)?),
+                None => None,
+            };
+
+            let llvm_global = match global.linkage {
+                //External globals are defined in another object, so they keep their name
+                //and are not given an initializer
+                _ if init.is_none() => {
+                    let llvm_global = llvm.add_global(llvm_ty, None, global.name.as_str());
+                    llvm_global.set_linkage(match global.linkage {
+                        None | Some(DefLinkage::External) => Linkage::External,
+                        Some(DefLinkage::Weak) => Linkage::ExternalWeak,
+                        Some(DefLinkage::LinkOnceOdr) => {
+                            return Err(Diagnostic::error()
+                                .with_message(format!(
+                                    "External global '{}' cannot have linkonce_odr linkage",
+                                    global.name
+                                ))
+                                .with_labels(vec![Label::primary(self.file, global.span)]))
+                        }
+                    });
+                    llvm_global
+                }
+                Some(linkage) => {
+                    let llvm_global = llvm.add_global(llvm_ty, None, global.name.as_str());
+                    llvm_global.set_linkage(match linkage {
+                        DefLinkage::External => Linkage::External,
+                        DefLinkage::Weak => Linkage::WeakAny,
+                        DefLinkage::LinkOnceOdr => Linkage::LinkOnceODR,
+                    });
+                    llvm_global
+                }
+                //Internal globals are implicitly dso_local, like internal functions
+                None => {
+                    let llvm_global = llvm.add_global(
+                        llvm_ty,
+                        None,
+                        format!("{}-{}", global.name, uuid::Uuid::new_v4()).as_str(),
+                    );
+                    llvm_global.set_linkage(Linkage::Internal);
+                    llvm_global
+                }
+            };
+            if let Some(visibility) = global.visibility {
+                llvm_global.set_visibility(Self::llvm_visibility(visibility));
+            }
+            if let Some(align) = self.explicit_align(ty) {
+                llvm_global.set_alignment(align);
+            }
+            if let Some(init) = init {
+                llvm_global.set_initializer(&init);
+            }
+            llvm_global.set_constant(!global.mutable);
+            llvm_global.set_thread_local(global.flags.contains(GlobalFlags::THREAD_LOCAL));
+
+            self.llvm_globals.insert(global_id, (ty, llvm_global));
         }
 
         for child in defs.iter() {
             if let SparkDef::ModDef(child) = child.1 {
-                self.forward_funs(*child, llvm)?;
+                self.forward_globals(child, llvm)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the LLVM visibility of a definition's visibility
+    fn llvm_visibility(visibility: DefVisibility) -> GlobalVisibility {
+        match visibility {
+            DefVisibility::Hidden => GlobalVisibility::Hidden,
+            DefVisibility::Protected => GlobalVisibility::Protected,
+        }
+    }
+
+    /// Get the LLVM calling convention ID of a function's calling convention
+    fn llvm_call_conv(call_conv: CallConv) -> u32 {
+        match call_conv {
+            CallConv::Fast => 8,
+            CallConv::Cold => 9,
+            CallConv::Stdcall => 64,
+            CallConv::Fastcall => 65,
+            CallConv::Thiscall => 70,
+            CallConv::PtxKernel => 71,
+            CallConv::Sysv64 => 78,
+            CallConv::Win64 => 79,
+            CallConv::Vectorcall => 80,
+            CallConv::AmdgpuKernel => 91,
+        }
+    }
+
+    /// Generate an LLVM function prototype for a function and all functions nested inside of it,
+    /// mangling the names of nested functions with the name of their enclosing function
+    fn forward_fun(
+        &mut self,
+        fun_id: FunId,
+        llvm: &mut Module<'ctx>,
+        enclosing: Option<&str>,
+    ) -> CompilerRes<()> {
+        if self.codegened_funs.contains(&fun_id)
+            || !self.reachable.contains(&fun_id)
+            || self.spark[fun_id].generic.is_some()
+        {
+            return Ok(())
+        }
+        self.codegened_funs.insert(fun_id);
+        let fun = self.spark[fun_id].clone();
+        let llvm_fun_ty = self.gen_fun_ty(fun.span, &fun.ty)?;
+        let name = match enclosing {
+            Some(enclosing) => format!("{}-{}", enclosing, fun.name),
+            None => fun.name.to_string(),
+        };
+        let llvm_fun = if let Some(linkage) = fun.linkage {
+            let linkage = match (linkage, fun.body.is_some()) {
+                (DefLinkage::External, _) => Linkage::External,
+                (DefLinkage::Weak, true) => Linkage::WeakAny,
+                (DefLinkage::Weak, false) => Linkage::ExternalWeak,
+                (DefLinkage::LinkOnceOdr, true) => Linkage::LinkOnceODR,
+                (DefLinkage::LinkOnceOdr, false) => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Function declaration '{}' without a body cannot have linkonce_odr linkage",
+                            fun.name
+                        ))
+                        .with_labels(vec![Label::primary(self.file, fun.span)]))
+                }
+            };
+            llvm.add_function(name.as_str(), llvm_fun_ty, Some(linkage))
+        } else if fun.flags.contains(FunFlags::EXTERN) && enclosing.is_none() {
+            llvm.add_function(name.as_str(), llvm_fun_ty, Some(Linkage::External))
+        } else {
+            //LLVM 11's C API cannot set dso_local, but LLVM marks every symbol with local linkage
+            //dso_local, so position independent code reaches functions that are not exported
+            //directly instead of through the GOT or PLT
+            llvm.add_function(
+                format!("{}-{}", name, uuid::Uuid::new_v4()).as_str(),
+                llvm_fun_ty,
+                Some(Linkage::Internal),
+            )
+        };
+        if let Some(visibility) = fun.visibility {
+            llvm_fun
+                .as_global_value()
+                .set_visibility(Self::llvm_visibility(visibility));
+        }
+        if let Some(call_conv) = fun.call_conv {
+            llvm_fun.set_call_conventions(Self::llvm_call_conv(call_conv));
+        }
+        //LLVM 11 offers no way to set fast-math flags on single instructions through the C API,
+        //so the function is relaxed with attributes instead. The code generator reads these
+        //attributes into its target options for each function, so instruction selection may
+        //reassociate, fuse multiply-adds, and ignore NaNs, infinities, and signed zeros. The
+        //IR optimization passes only read per-instruction flags and keep strict semantics
+        if self.opts.fast_math && fun.body.is_some() {
+            for attr in [
+                "unsafe-fp-math",
+                "no-nans-fp-math",
+                "no-infs-fp-math",
+                "no-signed-zeros-fp-math",
+                "less-precise-fpmad",
+            ] {
+                llvm_fun.add_attribute(
+                    AttributeLoc::Function,
+                    self.ctx.create_string_attribute(attr, "true"),
+                );
+            }
+        }
+        if let Some(optimization) = fun.optimization {
+            //LLVM 11 has no hot attribute, so hot functions are only hinted to be inlined
+            let attrs: &[&str] = match optimization {
+                DefOptimization::None => &["optnone", "noinline"],
+                DefOptimization::Size => &["optsize"],
+                DefOptimization::Hot => &["inlinehint"],
+                DefOptimization::Cold => &["cold"],
+            };
+            for attr in attrs {
+                llvm_fun.add_attribute(
+                    AttributeLoc::Function,
+                    self.ctx
+                        .create_enum_attribute(Attribute::get_named_enum_kind_id(attr), 0),
+                );
             }
         }
+        self.llvm_funs.insert(fun_id, llvm_fun);
+
+        for nested in fun.nested {
+            self.forward_fun(nested, llvm, Some(name.as_str()))?;
+        }
 
         Ok(())
     }
 
     /// Create an LLVM type from a type ID
     fn llvm_ty(&mut self, span: Span, id: TypeId) -> CompilerRes<AnyTypeEnum<'ctx>> {
+        //Explicit alignment is only applied to stack slots and globals, so it can't be honoured
+        //for values stored inside of another type
+        let contained = match &self.spark[id] {
+            TypeData::Struct { fields, .. } | TypeData::Union { fields } => {
+                fields.iter().map(|(field, _)| *field).collect()
+            }
+            TypeData::Tuple { elements } => elements.clone(),
+            TypeData::Enum { parts } => parts.clone(),
+            TypeData::Array { element, .. } => vec![*element],
+            _ => vec![],
+        };
+        if let Some(aligned) = contained.into_iter().find(|ty| self.explicit_align(*ty).is_some()) {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Type {} with an explicit alignment cannot be stored inside of type {}",
+                    self.spark.get_type_name(aligned),
+                    self.spark.get_type_name(id),
+                ))
+                .with_labels(vec![Label::primary(self.file, span)])
+                .with_notes(vec![
+                    "#[repr(align = \"N\")] only applies to variables and globals of the type, store a pointer to the value instead".to_owned(),
+                ]));
+        }
+
         Ok(match self.spark[id].clone() {
             TypeData::Integer { signed: _, width } => match width {
                 IntegerWidth::Eight => self.ctx.i8_type().into(),
@@ -272,22 +734,67 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                 IntegerWidth::SixtyFour => self.ctx.i64_type().into(),
             },
             TypeData::Bool => self.ctx.bool_type().into(),
-            TypeData::Struct { fields } => {
-                let fields = fields
+            //Fields are placed in the order given by the structure's layout, and zero-sized
+            //fields are left out
+            TypeData::Struct { fields, .. } => {
+                let mut physical = fields
                     .iter()
-                    .map(|(id, _)| match self.llvm_ty(span, *id) {
-                        Ok(ty) => Ok(BasicTypeEnum::try_from(ty).ok()),
-                        Err(e) => Err(e)
-                    })
-                    .filter_map(|i| match i {
-                        Ok(Some(e)) => Some(Ok(e)),
-                        Err(e) => Some(Err(e)),
-                        _ => None
-                    }) //Filter None out
+                    .zip(self.struct_layout(id))
+                    .filter_map(|((field, _), idx)| idx.map(|idx| (idx, *field)))
+                    .collect::<Vec<_>>();
+                physical.sort_by_key(|(idx, _)| *idx);
+                let fields = physical
+                    .into_iter()
+                    .map(|(_, field)| Self::require_basictype(self.file, span, self.llvm_ty(span, field)?))
                     .collect::<Result<Vec<_>, _>>()?;
                 self.ctx.struct_type(&fields, false).into()
             }
-            TypeData::Alias(_, id) => self.llvm_ty(span, id)?,
+            //Unions are stored as their most aligned field followed by enough bytes to hold the
+            //largest field, and fields are accessed by casting a pointer to the union. Sizes and
+            //alignments come from the target so that padding inside of fields is counted
+            TypeData::Union { fields } => {
+                let target_data = self.target.get_target_data();
+                let mut size = 0;
+                let mut aligned: Option<(BasicTypeEnum<'ctx>, u32)> = None;
+                for (field, _) in fields {
+                    if self.size_of_type(field) == 0 {
+                        continue;
+                    }
+                    let llvm_field = Self::require_basictype(self.file, span, self.llvm_ty(span, field)?)?;
+                    size = size.max(target_data.get_abi_size(&llvm_field));
+                    let align = target_data.get_abi_alignment(&llvm_field);
+                    if aligned.map_or(true, |(_, most)| align >= most) {
+                        aligned = Some((llvm_field, align));
+                    }
+                }
+
+                let mut members = vec![];
+                let mut padding = size;
+                if let Some((aligned, align)) = aligned {
+                    let align = align as u64;
+                    members.push(aligned);
+                    padding = (size + align - 1) / align * align - target_data.get_abi_size(&aligned);
+                }
+                if padding > 0 || members.is_empty() {
+                    members.push(self.ctx.i8_type().array_type(padding as u32).into());
+                }
+                self.ctx.struct_type(&members, false).into()
+            }
+            TypeData::Tuple { elements } => {
+                let elements = elements
+                    .iter()
+                    .map(|id| match self.llvm_ty(span, *id) {
+                        Ok(ty) => Self::require_basictype(self.file, span, ty),
+                        Err(e) => Err(e),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.ctx.struct_type(&elements, false).into()
+            }
+            TypeData::Alias(_, _, id) => self.llvm_ty(span, id)?,
+            //Pointers to the unit type are opaque and lowered like C's void*
+            TypeData::Pointer(id) if self.spark[self.spark.unwrap_alias(id)] == TypeData::Unit => {
+                self.ctx.i8_type().ptr_type(AddressSpace::Generic).into()
+            }
             TypeData::Pointer(id) => {
                 let pointee = Self::require_basictype(self.file, span, self.llvm_ty(span, id)?)?;
 
@@ -296,7 +803,25 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             TypeData::Array { element, len } => Self::require_basictype(self.file, span, self.llvm_ty(span, element)?)?
                 .array_type(len as u32)
                 .into(),
+            TypeData::Vector { element, len } => match self.llvm_ty(span, element)? {
+                AnyTypeEnum::IntType(int) => int.vec_type(len).into(),
+                AnyTypeEnum::FloatType(float) => float.vec_type(len).into(),
+                _ => unreachable!("Vector of non-numeric elements"),
+            },
             TypeData::Unit => self.ctx.void_type().into(),
+            //Generic functions are only generated once their type parameters are substituted,
+            //so a type parameter can only be reached through a generic function used as a value
+            TypeData::Generic(name) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Type parameter '{}' has no concrete type here",
+                        name
+                    ))
+                    .with_labels(vec![Label::primary(self.file, span)])
+                    .with_notes(vec![
+                        "A generic function must be instantiated or called before it can be used as a value".to_owned(),
+                    ]))
+            }
             TypeData::Invalid => unreachable!(),
             TypeData::Float { doublewide } => match doublewide {
                 true => self.ctx.f64_type().into(),
@@ -310,6 +835,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                     .max()
                     .unwrap_or(0);
 
+                //Enums without any variant data are represented by their discriminant alone
                 if max > 0 {
                     self.ctx
                         .struct_type(
@@ -321,9 +847,7 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
                         )
                         .into()
                 } else {
-                    self.ctx
-                        .struct_type(&[self.ctx.i8_type().into()], true)
-                        .into()
+                    self.ctx.i8_type().into()
                 }
             }
         })
@@ -356,18 +880,104 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             TypeData::Float { doublewide: false } => 4,
             TypeData::Enum { parts } => self.biggest_size(parts),
             TypeData::Bool => 1,
-            TypeData::Struct { fields } => {
+            TypeData::Struct { fields, .. } => {
                 fields.iter().map(|field| self.size_of_type(field.0)).sum()
             }
+            //The largest field is padded to a multiple of the union's alignment
+            TypeData::Union { fields } => {
+                let size = fields
+                    .iter()
+                    .map(|field| self.size_of_type(field.0))
+                    .max()
+                    .unwrap_or(0);
+                let align = self.align_of_type(ty);
+                (size + align - 1) / align * align
+            }
+            TypeData::Tuple { elements } => {
+                elements.iter().map(|element| self.size_of_type(*element)).sum()
+            }
             TypeData::Unit => 0,
             TypeData::Pointer(_) => self.ptr_size(),
             TypeData::Array { element, len } => self.size_of_type(*element) * *len as u32,
-            TypeData::Alias(_, ty) => self.size_of_type(*ty),
+            TypeData::Vector { element, len } => self.size_of_type(*element) * *len,
+            TypeData::Alias(_, _, ty) => self.size_of_type(*ty),
             TypeData::Function(_) => self.ptr_size(),
-            TypeData::Invalid => unreachable!(),
+            TypeData::Generic(_) | TypeData::Invalid => unreachable!(),
         }
     }
             
+    /// Check if a type is an enum where every variant is zero-sized, making the enum's value its
+    /// discriminant
+    fn is_payloadless_enum(&self, ty: TypeId) -> bool {
+        match &self.spark[self.spark.unwrap_alias(ty)] {
+            TypeData::Enum { parts } => self.biggest_size(parts) == 0,
+            _ => false,
+        }
+    }
+
+    /// Get the alignment of a type in bytes
+    fn align_of_type(&self, ty: TypeId) -> u32 {
+        match &self.spark[ty] {
+            TypeData::Struct { fields, align, .. } => fields
+                .iter()
+                .map(|(field, _)| self.align_of_type(*field))
+                .chain(*align)
+                .max()
+                .unwrap_or(1),
+            TypeData::Tuple { elements } => elements
+                .iter()
+                .map(|element| self.align_of_type(*element))
+                .max()
+                .unwrap_or(1),
+            TypeData::Union { fields } => fields
+                .iter()
+                .map(|(field, _)| self.align_of_type(*field))
+                .max()
+                .unwrap_or(1),
+            TypeData::Array { element, .. } => self.align_of_type(*element),
+            TypeData::Alias(_, _, ty) => self.align_of_type(*ty),
+            //Enums are generated as byte arrays
+            TypeData::Enum { .. } | TypeData::Unit => 1,
+            _ => self.size_of_type(ty).max(1),
+        }
+    }
+
+    /// Get the alignment explicitly requested for a type with `#[repr(align = "N")]`, which
+    /// must be applied to every stack slot and global of the type
+    fn explicit_align(&self, ty: TypeId) -> Option<u32> {
+        match &self.spark[self.spark.unwrap_alias(ty)] {
+            TypeData::Struct { align, .. } => *align,
+            _ => None,
+        }
+    }
+
+    /// Get the index in the generated LLVM structure of every field of a structure type, in
+    /// declaration order, with `None` for zero-sized fields that are left out
+    ///
+    /// Structures without `#[repr(C)]` have their fields sorted by descending alignment when
+    /// optimizing for size, removing padding between fields
+    fn struct_layout(&self, ty: TypeId) -> Vec<Option<u32>> {
+        let (fields, repr) = match &self.spark[self.spark.unwrap_alias(ty)] {
+            TypeData::Struct { fields, repr, .. } => (fields, *repr),
+            _ => return vec![],
+        };
+
+        let mut order = (0..fields.len()).collect::<Vec<_>>();
+        if repr == StructRepr::Default && self.opts.opt_lvl == OutputOptimizationLevel::Size {
+            order.sort_by_key(|idx| std::cmp::Reverse(self.align_of_type(fields[*idx].0)));
+        }
+
+        let mut layout = vec![None; fields.len()];
+        let mut physical = 0;
+        for idx in order {
+            if self.spark[self.spark.unwrap_alias(fields[idx].0)] != TypeData::Unit {
+                layout[idx] = Some(physical);
+                physical += 1;
+            }
+        }
+        layout
+    }
+
     /// Get the largest type of a list of types
     fn biggest_size(&self, types: &[TypeId]) -> u32 {
         types
@@ -395,3 +1005,1719 @@ impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
             })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Range, path::PathBuf, process::Command};
+
+    use codespan_reporting::diagnostic::{Diagnostic, LabelStyle};
+    use inkwell::{
+        attributes::{Attribute, AttributeLoc},
+        basic_block::BasicBlock,
+        context::Context,
+        module::{Linkage, Module},
+        passes::PassManager,
+        targets::FileType,
+        types::StructType,
+        values::{FunctionValue, GlobalValue, InstructionOpcode},
+        GlobalVisibility, OptimizationLevel,
+    };
+
+    use crate::{
+        codegen::{ir::SparkCtx, lower::Lowerer},
+        parse::Parser,
+        util::files::{CompiledFile, FileId, Files},
+        CompileOpts, OutputFileType, OutputOptimizationLevel, Symbol, TrapBehavior,
+    };
+
+    use super::LlvmCodeGenerator;
+
+    /// Get the compile options used for tests, generating position independent code if `pic`
+    /// is set
+    fn test_opts(pic: bool) -> CompileOpts {
+        CompileOpts {
+            out_type: OutputFileType::Object,
+            out_file: PathBuf::from("out.o"),
+            opt_lvl: OutputOptimizationLevel::Debug,
+            pic,
+            stripped: false,
+            trap: TrapBehavior::Abort,
+            target: None,
+            int_promotion: false,
+            fast_math: false,
+            warn_self_assign: false,
+        }
+    }
+
+    /// Generate an LLVM module for a program contained in a single file, passing the module
+    /// and the code generator that created it to `f`
+    fn with_llvm<T>(
+        src: &str,
+        opts: CompileOpts,
+        f: impl FnOnce(&LlvmCodeGenerator<'_, '_>, &Module<'_>) -> T,
+    ) -> T {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = Lowerer::new(&mut ctx, &files)
+            .lower_module(&module)
+            .unwrap_or_else(|_| panic!("Failed to lower module"));
+        let llvm_ctx = Context::create();
+        let mut generator = LlvmCodeGenerator::new(ctx, &llvm_ctx, &files, opts).unwrap();
+        let llvm_mod = generator
+            .codegen_module(root)
+            .unwrap_or_else(|_| panic!("Failed to generate LLVM IR"));
+        llvm_mod.verify().unwrap();
+        f(&generator, &llvm_mod)
+    }
+
+    /// Generate the textual LLVM IR of a program contained in a single file
+    fn llvm_ir(src: &str, opts: CompileOpts) -> String {
+        with_llvm(src, opts, |_, module| module.print_to_string().to_string())
+    }
+
+    /// Everything reported while generating code for a program that may contain errors
+    struct Report {
+        /// Textual LLVM IR of the module, if one was generated
+        ir: Option<String>,
+        /// Message of every diagnostic that was emitted
+        messages: Vec<String>,
+        /// Notes of every diagnostic that was emitted
+        notes: Vec<String>,
+        /// Message and source range of the primary label of every diagnostic that was emitted
+        primary_labels: Vec<(String, Range<usize>)>,
+    }
+
+    impl Report {
+        /// Collect the messages, notes and primary labels of diagnostics in the order they
+        /// were emitted
+        fn new(ir: Option<String>, diags: &[Diagnostic<FileId>]) -> Self {
+            Self {
+                ir,
+                messages: diags.iter().map(|diag| diag.message.clone()).collect(),
+                notes: diags.iter().flat_map(|diag| diag.notes.iter().cloned()).collect(),
+                primary_labels: diags
+                    .iter()
+                    .flat_map(|diag| {
+                        diag.labels
+                            .iter()
+                            .filter(|label| label.style == LabelStyle::Primary)
+                            .map(move |label| (diag.message.clone(), label.range.clone()))
+                    })
+                    .collect(),
+            }
+        }
+    }
+
+    /// Generate code for a program that may contain errors, returning the textual LLVM IR if a
+    /// module was generated and the messages of every diagnostic that was emitted
+    fn codegen_diags(src: &str, opts: CompileOpts) -> (Option<String>, Vec<String>) {
+        let report = codegen_report(src, opts);
+        (report.ir, report.messages)
+    }
+
+    /// Generate code for a program that may contain errors, returning everything that was
+    /// reported
+    fn codegen_report(src: &str, opts: CompileOpts) -> Report {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src).parse(Symbol::from("root"), file).unwrap();
+        let mut ctx = SparkCtx::new();
+        let root = match Lowerer::new(&mut ctx, &files).lower_module(&module) {
+            Ok(root) => root,
+            Err(e) => return Report::new(None, &[e]),
+        };
+        let llvm_ctx = Context::create();
+        let mut generator = LlvmCodeGenerator::new(ctx, &llvm_ctx, &files, opts).unwrap();
+        let ir = generator
+            .codegen_module(root)
+            .ok()
+            .map(|llvm_mod| llvm_mod.print_to_string().to_string());
+        Report::new(ir, generator.diags.emitted())
+    }
+
+    /// Compile a program with the JIT execution engine and call an exported function defined
+    /// in it that takes no arguments, returning the value it returns
+    fn jit_call<R>(src: &str, name: &str) -> R {
+        with_llvm(src, test_opts(false), |_, module| {
+            let engine = module
+                .create_jit_execution_engine(OptimizationLevel::None)
+                .unwrap_or_else(|e| panic!("Failed to create JIT execution engine: {}", e));
+            unsafe {
+                engine
+                    .get_function::<unsafe extern "C" fn() -> R>(name)
+                    .unwrap_or_else(|e| panic!("Failed to find function {}: {:?}", name, e))
+                    .call()
+            }
+        })
+    }
+
+    /// Find an unexported function by the name it was given in the source, before the unique
+    /// suffix was appended
+    fn find_fun<'ctx>(module: &Module<'ctx>, name: &str) -> FunctionValue<'ctx> {
+        let prefix = format!("{}-", name);
+        module
+            .get_functions()
+            .find(|f| f.get_name().to_str().unwrap().starts_with(prefix.as_str()))
+            .unwrap_or_else(|| panic!("No function named {}", name))
+    }
+
+    /// Find an unexported global by the name it was given in the source, before the unique
+    /// suffix was appended
+    fn find_global<'ctx>(module: &Module<'ctx>, name: &str) -> GlobalValue<'ctx> {
+        let prefix = format!("{}-", name);
+        module
+            .get_globals()
+            .find(|g| g.get_name().to_str().unwrap().starts_with(prefix.as_str()))
+            .unwrap_or_else(|| panic!("No global named {}", name))
+    }
+
+    /// Source of a program with exported and unexported definitions
+    const PIC_SRC: &str = "mut calls = 0u32
+fun count_call() -> u32 {
+    calls = calls + 1u32
+    return calls
+}
+fun ext bump() -> u32 {
+    return count_call.()
+}";
+
+    #[test]
+    fn module_has_the_target_data_layout() {
+        with_llvm(
+            "fun ext add(i32 a, i32 b) -> i32 { return a + b }",
+            test_opts(false),
+            |generator, module| {
+                let layout = module.get_data_layout();
+                assert!(!layout.as_str().to_bytes().is_empty());
+                assert_eq!(
+                    layout.as_str(),
+                    generator.target.get_target_data().get_data_layout().as_str()
+                );
+                assert_eq!(module.get_triple(), generator.target.get_triple());
+            },
+        );
+    }
+
+    #[test]
+    fn empty_bodied_functions_are_defined() {
+        let src = "fun does_nothing() {}
+fun ext call_does_nothing() -> i32 {
+    does_nothing.()
+    return 0
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let does_nothing = find_fun(module, "does_nothing");
+            assert_eq!(does_nothing.count_basic_blocks(), 1);
+            assert_eq!(does_nothing.get_linkage(), Linkage::Internal);
+        });
+    }
+
+    #[test]
+    fn pic_keeps_unexported_definitions_local() {
+        with_llvm(PIC_SRC, test_opts(true), |_, module| {
+            assert!(module.get_flag("PIC Level").is_some());
+            let count_call = find_fun(module, "count_call");
+            assert_eq!(count_call.get_linkage(), Linkage::Internal);
+            let calls = find_global(module, "calls");
+            assert_eq!(calls.get_linkage(), Linkage::Internal);
+
+            let bump = module.get_function("bump").unwrap();
+            assert_eq!(bump.get_linkage(), Linkage::External);
+            assert_eq!(
+                bump.as_global_value().get_visibility(),
+                GlobalVisibility::Default
+            );
+        });
+    }
+
+    #[test]
+    fn pic_output_links_into_a_shared_library() {
+        let dir = std::env::temp_dir();
+        let obj_path = dir.join(format!("spark-pic-{}.o", std::process::id()));
+        let lib_path = obj_path.with_extension("so");
+        with_llvm(PIC_SRC, test_opts(true), |generator, module| {
+            generator
+                .target
+                .write_to_file(module, FileType::Object, &obj_path)
+                .unwrap();
+        });
+        let status = Command::new("cc")
+            .arg("-shared")
+            .arg("-o")
+            .arg(&lib_path)
+            .arg(&obj_path)
+            .status();
+        std::fs::remove_file(&obj_path).ok();
+        std::fs::remove_file(&lib_path).ok();
+        //Skip the test when there is no C compiler to drive the linker
+        if let Ok(status) = status {
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn nested_functions_are_called_from_their_enclosing_function() {
+        let src = "fun ext sum_squares(i32 a, i32 b) -> i32 {
+    fun square(i32 n) -> i32 {
+        return n * n
+    }
+    return square.(a) + square.(b)
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let square = find_fun(module, "sum_squares-square");
+            assert_eq!(square.get_linkage(), Linkage::Internal);
+            let sum_squares = module
+                .get_function("sum_squares")
+                .unwrap()
+                .print_to_string()
+                .to_string();
+            assert_eq!(
+                sum_squares.matches("call i32 @\"sum_squares-square-").count(),
+                2
+            );
+        });
+    }
+
+    #[test]
+    fn boolean_literals_are_i1_conditions() {
+        let src = "fun ext pick() -> i32 {
+    if true {
+        return 1
+    }
+    return 0
+}
+fun ext yes() -> bool {
+    return true
+}";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("br i1 true"), "Unexpected IR: {}", ir);
+        assert!(ir.contains("ret i1 true"), "Unexpected IR: {}", ir);
+    }
+
+    #[test]
+    fn constant_arithmetic_is_folded() {
+        with_llvm(
+            "fun ext ten() -> i32 { return 2 * 3 + 4 }",
+            test_opts(false),
+            |_, module| {
+                let ten = module.get_function("ten").unwrap().print_to_string().to_string();
+                assert!(ten.contains("ret i32 10"), "Unexpected IR: {}", ten);
+                assert!(!ten.contains(" mul ") && !ten.contains(" add "));
+            },
+        );
+    }
+
+    #[test]
+    fn tuples_return_multiple_values() {
+        let src = "fun divmod(i32 a, i32 b) -> (i32, i32) {
+    return (a / b, a % b)
+}
+fun ext divmod_sum(i32 a, i32 b) -> i32 {
+    let (q, r) = divmod.(a, b)
+    return q + r
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let divmod = find_fun(module, "divmod").print_to_string().to_string();
+            assert!(divmod.contains("define internal { i32, i32 }"), "Unexpected IR: {}", divmod);
+            let divmod_sum = module
+                .get_function("divmod_sum")
+                .unwrap()
+                .print_to_string()
+                .to_string();
+            assert!(divmod_sum.contains("call { i32, i32 }"));
+            assert_eq!(divmod_sum.matches("extractvalue").count(), 2);
+        });
+    }
+
+    #[test]
+    fn structures_and_tuples_are_destructured() {
+        let src = "type point = { i32 x, i64 y }
+fun ext manhattan(point p) -> i64 {
+    let { x, y } = p
+    return ($i64 x) + y
+}
+fun ext first((i32, i32) pair) -> i32 {
+    let (a, _) = pair
+    return a
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let manhattan = module
+                .get_function("manhattan")
+                .unwrap()
+                .print_to_string()
+                .to_string();
+            assert!(manhattan.contains("extractvalue { i32, i64 } %"), "Unexpected IR: {}", manhattan);
+            assert_eq!(manhattan.matches("extractvalue").count(), 2);
+            let first = module.get_function("first").unwrap().print_to_string().to_string();
+            assert_eq!(first.matches("extractvalue").count(), 1);
+        });
+    }
+
+    #[test]
+    fn signed_integer_matches_cover_negative_values() {
+        let src = "fun ext describe(i32 code) -> i32 {
+    return match code {
+        0 -> phi 10
+        1 -> phi 20
+        _ -> phi 30
+    }
+}
+fun ext sign(i32 n) -> i32 {
+    return match n {
+        -2147483648..=-1 -> phi -1
+        0 -> phi 0
+        1..=2147483647 -> phi 1
+    }
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let describe = module.get_function("describe").unwrap().print_to_string().to_string();
+            assert!(describe.contains("switch i32"), "Unexpected IR: {}", describe);
+            assert!(describe.contains("i32 0, label") && describe.contains("i32 1, label"));
+            let sign = module.get_function("sign").unwrap().print_to_string().to_string();
+            assert!(sign.contains("icmp sge i32"), "Unexpected IR: {}", sign);
+            assert!(sign.contains("icmp sle i32"));
+        });
+
+        let (_, messages) = codegen_diags(
+            "fun ext positive(i32 n) -> bool {
+    return match n {
+        0..=2147483647 -> phi true
+    }
+}",
+            test_opts(false),
+        );
+        assert_eq!(messages, ["Match expression on type i32 is not exhaustive"]);
+
+        let (_, messages) = codegen_diags(
+            "fun ext tiny(i8 n) -> bool {
+    return match n {
+        -129 -> phi true
+        _ -> phi false
+    }
+}",
+            test_opts(false),
+        );
+        assert_eq!(messages, ["Pattern -129 can never match a value of type i8"]);
+    }
+
+    #[test]
+    fn range_patterns_are_checked_in_order() {
+        let src = "fun ext classify(u8 n) -> i32 {
+    return match n {
+        0..=9 -> phi 0
+        10..=99 -> phi 1
+        _ -> phi 2
+    }
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let classify = module.get_function("classify").unwrap();
+            let ir = classify.print_to_string().to_string();
+            assert_eq!(ir.matches("icmp uge i8").count(), 2, "Unexpected IR: {}", ir);
+            assert_eq!(ir.matches("icmp ule i8").count(), 2);
+            assert!(ir.contains(", 9\n") && ir.contains(", 10\n") && ir.contains(", 99\n"));
+            //The second range is only checked after the value is found to be outside the first
+            let blocks = classify
+                .get_basic_blocks()
+                .into_iter()
+                .map(|bb| bb.get_name().to_str().unwrap().to_owned())
+                .collect::<Vec<_>>();
+            let first = blocks.iter().position(|bb| bb == "match_range_check").unwrap();
+            assert!(blocks[first + 1..].iter().any(|bb| bb.starts_with("match_range_check")));
+        });
+    }
+
+    #[test]
+    fn matches_without_a_default_must_be_exhaustive() {
+        let (_, messages) = codegen_diags(
+            "fun ext small(u8 n) -> bool {
+    return match n {
+        0..=99 -> phi true
+    }
+}",
+            test_opts(false),
+        );
+        assert_eq!(messages, ["Match expression on type u8 is not exhaustive"]);
+
+        let src = "fun ext halves(u8 n) -> i32 {
+    return match n {
+        0..=127 -> phi 0
+        128..=255 -> phi 1
+    }
+}";
+        let (ir, messages) = codegen_diags(src, test_opts(false));
+        assert!(messages.is_empty(), "Unexpected diagnostics: {:?}", messages);
+        //Values outside of every arm can't be produced, so they reach the trap
+        assert!(ir.unwrap().contains("match_invalid"));
+    }
+
+    #[test]
+    fn phi_outside_of_a_block_is_reported() {
+        let (_, messages) = codegen_diags(
+            "fun ext one() -> i32 {
+    phi 1
+}",
+            test_opts(false),
+        );
+        assert!(
+            messages.iter().any(|msg| msg == "Phi statement not in a value-producing block"),
+            "Unexpected diagnostics: {:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn opaque_pointers_round_trip() {
+        let src = "type point = { i32 x, i32 y }
+fun ext opaque_roundtrip(*point p) -> i32 {
+    let opaque = $*() p
+    let back = $*point opaque
+    return (*back).x
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let ir = module
+                .get_function("opaque_roundtrip")
+                .unwrap()
+                .print_to_string()
+                .to_string();
+            assert!(ir.contains("to i8*"), "Unexpected IR: {}", ir);
+            assert!(ir.contains("i8* %"), "Unexpected IR: {}", ir);
+            assert!(ir.contains("to { i32, i32 }*"), "Unexpected IR: {}", ir);
+        });
+    }
+
+    #[test]
+    fn memory_builtins_call_intrinsics() {
+        let src = "fun ext clear(*u8 buf, u64 n) {
+    memset.(buf, 0u8, n)
+}
+fun ext copy(*u8 dst, *u8 src, u64 n) {
+    memcpy.(dst, src, n)
+}
+fun ext shift(*u8 dst, *u8 src, u64 n) {
+    memmove.(dst, src, n)
+}";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("call void @llvm.memset.p0i8.i64("), "Unexpected IR: {}", ir);
+        assert!(ir.contains("call void @llvm.memcpy.p0i8.p0i8.i64("));
+        assert!(ir.contains("call void @llvm.memmove.p0i8.p0i8.i64("));
+    }
+
+    #[test]
+    fn only_places_can_have_their_address_taken() {
+        llvm_ir(
+            "fun ext through_pointer(i32 a) -> i32 {
+    let p = &a
+    return *p
+}",
+            test_opts(false),
+        );
+
+        let (_, messages) = codegen_diags(
+            "fun ext sum_pointer(i32 a, i32 b) -> i32 {
+    let p = &(a + b)
+    return *p
+}",
+            test_opts(false),
+        );
+        assert!(
+            messages.iter().any(|msg| msg == "Cannot take the address of a temporary value"),
+            "Unexpected diagnostics: {:?}",
+            messages
+        );
+    }
+
+    /// Source of a program with a runtime check that fails for values outside of every arm
+    const TRAP_SRC: &str = "fun ext halves(u8 n) -> i32 {
+    return match n {
+        0..=127 -> phi 0
+        128..=255 -> phi 1
+    }
+}";
+
+    #[test]
+    fn trap_behavior_selects_the_failed_check_code() {
+        let opts = |trap| CompileOpts {
+            trap,
+            ..test_opts(false)
+        };
+
+        let ir = llvm_ir(TRAP_SRC, opts(TrapBehavior::Abort));
+        assert!(ir.contains("call void @abort()"), "Unexpected IR: {}", ir);
+        assert!(ir.contains("unreachable"));
+
+        let ir = llvm_ir(TRAP_SRC, opts(TrapBehavior::Trap));
+        assert!(ir.contains("call void @llvm.trap()"), "Unexpected IR: {}", ir);
+        assert!(!ir.contains("@abort"));
+
+        let ir = llvm_ir(TRAP_SRC, opts(TrapBehavior::Unreachable));
+        assert!(!ir.contains("@abort") && !ir.contains("@llvm.trap"));
+        assert!(ir.contains("unreachable"));
+    }
+
+    #[test]
+    fn abort_declared_with_another_signature_is_called_through_a_cast() {
+        let src = format!(
+            "extern \"C\" {{
+    fun abort(i32 code) -> i32
+}}
+{}",
+            TRAP_SRC
+        );
+        let ir = llvm_ir(&src, test_opts(false));
+        assert!(ir.contains("declare i32 @abort(i32)"), "Unexpected IR: {}", ir);
+        assert!(
+            ir.contains("call void bitcast (i32 (i32)* @abort to void ()*)()"),
+            "Unexpected IR: {}",
+            ir
+        );
+    }
+
+    #[test]
+    fn thread_local_globals_are_marked() {
+        let src = "#[threadlocal]
+mut calls = 0u32
+mut shared = 0u32
+fun ext count_call() -> u32 {
+    calls = calls + 1u32
+    shared = shared + 1u32
+    return calls
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            assert!(find_global(module, "calls").is_thread_local());
+            assert!(!find_global(module, "shared").is_thread_local());
+            let ir = module.print_to_string().to_string();
+            assert_eq!(ir.matches("thread_local").count(), 1, "Unexpected IR: {}", ir);
+        });
+    }
+
+    #[test]
+    fn linkage_attributes_set_symbol_linkage() {
+        let src = "#[weak]
+fun default_handler(i32 code) -> i32 {
+    return code
+}
+#[weak]
+fun ext optional_handler(i32 code) -> i32
+#[external]
+fun exported(i32 code) -> i32 {
+    return code
+}
+#[weak]
+mut handler_calls = 0u32";
+        with_llvm(src, test_opts(false), |_, module| {
+            let linkage = |name: &str| module.get_function(name).unwrap().get_linkage();
+            assert_eq!(linkage("default_handler"), Linkage::WeakAny);
+            assert_eq!(linkage("optional_handler"), Linkage::ExternalWeak);
+            assert_eq!(linkage("exported"), Linkage::External);
+            assert_eq!(
+                module.get_global("handler_calls").unwrap().get_linkage(),
+                Linkage::WeakAny
+            );
+        });
+    }
+
+    #[test]
+    fn visibility_attributes_set_symbol_visibility() {
+        let src = "#[external]
+#[hidden]
+fun internal_helper(i32 a) -> i32 {
+    return a * 2
+}
+#[external]
+#[protected]
+fun protected_helper(i32 a) -> i32 {
+    return a * 3
+}
+fun ext public_helper(i32 a) -> i32 {
+    return a * 4
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let visibility = |name: &str| {
+                module
+                    .get_function(name)
+                    .unwrap()
+                    .as_global_value()
+                    .get_visibility()
+            };
+            assert_eq!(visibility("internal_helper"), GlobalVisibility::Hidden);
+            assert_eq!(visibility("protected_helper"), GlobalVisibility::Protected);
+            assert_eq!(visibility("public_helper"), GlobalVisibility::Default);
+        });
+
+        let (_, messages) = codegen_diags(
+            "#[hidden]
+fun local_helper(i32 a) -> i32 {
+    return a
+}",
+            test_opts(false),
+        );
+        assert_eq!(
+            messages,
+            ["Cannot set visibility of 'local_helper' which is not externally visible"]
+        );
+    }
+
+    #[test]
+    fn repr_c_structures_keep_declaration_order() {
+        let src = "#[repr(C)]
+type header = { u8 tag, i64 len, u8 flags }
+type packed = { u8 tag, i64 len, u8 flags }
+fun ext header_len(header h) -> i64 {
+    return h.len
+}
+fun ext packed_len(packed p) -> i64 {
+    return p.len
+}";
+        let opts = CompileOpts {
+            opt_lvl: OutputOptimizationLevel::Size,
+            ..test_opts(false)
+        };
+        with_llvm(src, opts, |generator, module| {
+            let param = |name: &str| {
+                module
+                    .get_function(name)
+                    .unwrap()
+                    .get_first_param()
+                    .unwrap()
+                    .into_struct_value()
+                    .get_type()
+            };
+            let widths = |ty: StructType<'_>| {
+                ty.get_field_types()
+                    .into_iter()
+                    .map(|field| field.into_int_type().get_bit_width())
+                    .collect::<Vec<_>>()
+            };
+            let target_data = generator.target.get_target_data();
+
+            let header = param("header_len");
+            assert_eq!(widths(header), [8, 64, 8]);
+            assert_eq!(target_data.offset_of_element(&header, 1), Some(8));
+            assert_eq!(target_data.offset_of_element(&header, 2), Some(16));
+
+            //Without repr(C) the fields may be reordered to remove padding
+            assert_eq!(widths(param("packed_len")), [64, 8, 8]);
+        });
+    }
+
+    #[test]
+    fn union_fields_share_storage() {
+        let src = "type bits = union { i32 i, f32 f }
+fun ext int_bits(i32 i) -> f32 {
+    let (bits) b
+    b.i = i
+    return b.f
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let ir = module
+                .get_function("int_bits")
+                .unwrap()
+                .print_to_string()
+                .to_string();
+            //The union is stored as its last most aligned field, which the other field is
+            //accessed through a cast of
+            assert!(ir.contains("alloca { float }"), "Unexpected IR: {}", ir);
+            assert!(ir.contains("to i32*"));
+            assert!(ir.contains("store i32") && ir.contains("load float"));
+        });
+    }
+
+    #[test]
+    fn unreferenced_internal_functions_are_not_generated() {
+        let src = "fun unused_helper() -> i32 {\n    return 1\n}\nfun used_helper() -> i32 {\n    return 2\n}\nfun ext entry() -> i32 {\n    return used_helper.()\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            let names = module
+                .get_functions()
+                .map(|f| f.get_name().to_str().unwrap().to_owned())
+                .collect::<Vec<_>>();
+            assert!(names.iter().any(|name| name == "entry"));
+            assert!(names.iter().any(|name| name.starts_with("used_helper-")));
+            assert!(
+                !names.iter().any(|name| name.starts_with("unused_helper")),
+                "Unreferenced function was generated: {:?}",
+                names
+            );
+        });
+    }
+
+    #[test]
+    fn statements_after_a_return_are_unreachable() {
+        let src = "fun ext early(i32 a) -> i32 {\n    return a\n    let b = a + 1\n}\n";
+        let (ir, messages) = codegen_diags(src, test_opts(false));
+        assert!(ir.is_some());
+        assert!(messages.iter().any(|m| m == "Unreachable statement"), "{:?}", messages);
+
+        let (_, messages) = codegen_diags("fun ext early(i32 a) -> i32 {\n    return a\n}\n", test_opts(false));
+        assert!(messages.is_empty(), "{:?}", messages);
+    }
+
+    #[test]
+    fn missing_return_on_a_branch_is_reported() {
+        let src = "fun ext sign(i32 a) -> i32 {\n    if a < 0 {\n        return -1\n    } else {\n        let b = a\n    }\n}\n";
+        let (_, messages) = codegen_diags(src, test_opts(false));
+        assert!(
+            messages.iter().any(|m| m == "Function 'sign' does not return a value on every path"),
+            "{:?}",
+            messages
+        );
+
+        let src = "fun ext sign(i32 a) -> i32 {\n    if a < 0 {\n        return -1\n    } else {\n        return 1\n    }\n}\n";
+        let (ir, messages) = codegen_diags(src, test_opts(false));
+        assert!(ir.is_some());
+        assert!(messages.is_empty(), "{:?}", messages);
+    }
+
+    #[test]
+    fn size_optimization_removes_structure_padding() {
+        let src = "type wide = { u8 a, i64 b, u8 c }\nfun ext wide_a(wide w) -> u8 {\n    return w.a\n}\n";
+        let size = |opt_lvl| {
+            let opts = CompileOpts {
+                opt_lvl,
+                ..test_opts(false)
+            };
+            with_llvm(src, opts, |generator, module| {
+                let wide = module
+                    .get_function("wide_a")
+                    .unwrap()
+                    .get_first_param()
+                    .unwrap()
+                    .into_struct_value()
+                    .get_type();
+                generator.target.get_target_data().get_abi_size(&wide)
+            })
+        };
+
+        assert_eq!(size(OutputOptimizationLevel::Debug), 24);
+        assert_eq!(size(OutputOptimizationLevel::Size), 16);
+    }
+
+    #[test]
+    fn block_variables_have_lifetime_markers() {
+        let src = "fun ext pick(i32 a) -> i32 {\n    mut r = 0\n    if a > 0 {\n        let b = a * 2\n        r = b\n    } else {\n        let c = a * 3\n        r = c\n    }\n    return r\n}\n";
+        let opts = CompileOpts {
+            opt_lvl: OutputOptimizationLevel::Release,
+            ..test_opts(false)
+        };
+        let ir = llvm_ir(src, opts);
+        assert_eq!(ir.matches("call void @llvm.lifetime.start.p0i8").count(), 2, "{}", ir);
+        assert_eq!(ir.matches("call void @llvm.lifetime.end.p0i8").count(), 2, "{}", ir);
+
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(!ir.contains("call void @llvm.lifetime.start.p0i8"), "{}", ir);
+    }
+
+    #[test]
+    fn loop_variables_are_allocated_in_the_entry_block() {
+        let src = "fun ext sum_below(i32 n) -> i32 {\n    mut i = 0\n    mut sum = 0\n    while i < n {\n        let square = i * i\n        sum = sum + square\n        i = i + 1\n    }\n    return sum\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            let fun = module.get_function("sum_below").unwrap();
+            let allocas = |bb: BasicBlock<'_>| {
+                let mut count = 0;
+                let mut inst = bb.get_first_instruction();
+                while let Some(i) = inst {
+                    if i.get_opcode() == InstructionOpcode::Alloca {
+                        count += 1;
+                    }
+                    inst = i.get_next_instruction();
+                }
+                count
+            };
+
+            let blocks = fun.get_basic_blocks();
+            assert!(allocas(blocks[0]) >= 3);
+            assert!(blocks[1..].iter().all(|bb| allocas(*bb) == 0));
+        });
+    }
+
+    #[test]
+    fn mixed_width_integers_are_promoted_when_enabled() {
+        let src = "fun ext widen(i16 a, i32 b) -> i32 {\n    return a + b\n}\n";
+        let opts = CompileOpts {
+            int_promotion: true,
+            ..test_opts(false)
+        };
+        let ir = llvm_ir(src, opts);
+        assert!(ir.contains("sext i16 %"), "{}", ir);
+        assert!(ir.contains("add i32"), "{}", ir);
+
+        let (_, messages) = codegen_diags(src, test_opts(false));
+        assert!(!messages.is_empty(), "Mixed-width addition compiled in strict mode");
+    }
+
+    #[test]
+    fn fma_calls_the_intrinsic() {
+        let src = "fun ext fused() -> f64 {\n    return fma.(2.0, 3.0, 1.0)\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("call double @llvm.fma.f64("), "{}", ir);
+        assert_eq!(jit_call::<f64>(src, "fused"), 7.0);
+    }
+
+    #[test]
+    fn fast_math_relaxes_float_functions() {
+        let src = "fun ext scale(f64 a, f64 b) -> f64 {\n    return a * b + 1.0\n}\n";
+        let opts = CompileOpts {
+            fast_math: true,
+            ..test_opts(false)
+        };
+        let ir = llvm_ir(src, opts);
+        assert!(ir.contains("\"unsafe-fp-math\"=\"true\""), "{}", ir);
+        assert!(ir.contains("\"less-precise-fpmad\"=\"true\""), "{}", ir);
+
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(!ir.contains("unsafe-fp-math"), "{}", ir);
+    }
+
+    #[test]
+    fn math_builtins_call_intrinsics() {
+        let src = "fun ext root_of_four() -> f64 {\n    return sqrt.(4.0)\n}\nfun ext trig(f64 x) -> f64 {\n    return sin.(x) + cos.(x)\n}\nfun ext cube(f32 x) -> f32 {\n    return pow.(x, 3.0f32)\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("call double @llvm.sqrt.f64("), "{}", ir);
+        assert!(ir.contains("call double @llvm.sin.f64("), "{}", ir);
+        assert!(ir.contains("call double @llvm.cos.f64("), "{}", ir);
+        assert!(ir.contains("call float @llvm.pow.f32("), "{}", ir);
+        assert_eq!(jit_call::<f64>(src, "root_of_four"), 2.0);
+    }
+
+    #[test]
+    fn integer_min_max_and_abs_select_by_signedness() {
+        let src = "fun ext larger() -> i32 {\n    return max.(3i32, 5i32)\n}\nfun ext magnitude() -> i32 {\n    return abs.(-4i32)\n}\nfun ext smaller(u8 a, u8 b) -> u8 {\n    return min.(a, b)\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("icmp ult i8"), "{}", ir);
+        assert_eq!(jit_call::<i32>(src, "larger"), 5);
+        assert_eq!(jit_call::<i32>(src, "magnitude"), 4);
+    }
+
+    #[test]
+    fn bit_count_builtins_call_intrinsics() {
+        let src = "fun ext ones() -> u8 {\n    return popcount.(0b1011u8)\n}\nfun ext leading(u32 x) -> u32 {\n    return leading_zeros.(x)\n}\nfun ext trailing(u32 x) -> u32 {\n    return trailing_zeros.(x)\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("call i8 @llvm.ctpop.i8("), "{}", ir);
+        assert!(ir.contains("call i32 @llvm.ctlz.i32(i32 %"), "{}", ir);
+        assert!(ir.contains(", i1 false)"), "{}", ir);
+        assert!(ir.contains("call i32 @llvm.cttz.i32("), "{}", ir);
+        assert_eq!(jit_call::<u8>(src, "ones"), 3);
+    }
+
+    #[test]
+    fn bswap_reverses_bytes_of_wide_integers() {
+        let src = "fun ext swapped() -> u16 {\n    return bswap.(0x1234u16)\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("call i16 @llvm.bswap.i16("), "{}", ir);
+        assert_eq!(jit_call::<u16>(src, "swapped"), 0x3412);
+
+        let (_, messages) = codegen_diags("fun ext swapped(u8 x) -> u8 {\n    return bswap.(x)\n}\n", test_opts(false));
+        assert!(!messages.is_empty(), "bswap of a byte was accepted");
+    }
+
+    #[test]
+    fn volatile_builtins_mark_memory_accesses() {
+        let src = "fun ext write_register(*u32 reg, u32 val) {\n    volatile_store.(reg, val)\n}\nfun ext read_register(*u32 reg) -> u32 {\n    return volatile_load.(reg)\n}\nfun ext write_plain(*u32 reg, u32 val) {\n    (*reg) = val\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("store volatile i32"), "{}", ir);
+        assert!(ir.contains("load volatile i32, i32*"), "{}", ir);
+        assert_eq!(ir.matches("volatile").count(), 2, "{}", ir);
+    }
+
+    #[test]
+    fn atomic_add_emits_atomicrmw() {
+        let src = "fun ext increment(*u64 counter) -> u64 {\n    return atomic_add.(counter, 1u64, \"seq_cst\")\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("atomicrmw add i64*"), "{}", ir);
+        assert!(ir.contains("seq_cst"), "{}", ir);
+
+        let (_, messages) = codegen_diags(
+            "fun ext increment(*u64 counter) -> u64 {\n    return atomic_add.(counter, 1u64, \"eventually\")\n}\n",
+            test_opts(false),
+        );
+        assert!(
+            messages.iter().any(|m| m == "Invalid memory ordering passed to builtin atomic_add"),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn explicitly_aligned_stack_slots_have_the_requested_alignment() {
+        let src = "#[repr(align = \"32\")]\ntype wide = { i32 x }\nfun ext wide_x(i32 x) -> i32 {\n    let w = #wide { x = x }\n    return w.x\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("alloca { i32 }, align 32"), "{}", ir);
+    }
+
+    #[test]
+    fn vectors_are_added_element_wise() {
+        let src = "fun ext add_lanes(<4 x i32> a, <4 x i32> b) -> <4 x i32> {\n    return a + b\n}\nfun ext second_lane(<4 x i32> v) -> i32 {\n    return v[1]\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("define <4 x i32> @add_lanes(<4 x i32>"), "{}", ir);
+        assert!(ir.contains("add <4 x i32>"), "{}", ir);
+        assert!(ir.contains("extractelement <4 x i32>"), "{}", ir);
+    }
+
+    #[test]
+    fn payloadless_enums_are_bare_discriminants() {
+        let src = "type red = ()\ntype green = ()\ntype color = red | green\nfun ext make_green() -> color {\n    return color:green.()\n}\nfun ext is_green(color c) -> bool {\n    return match c {\n        green -> phi true\n        red -> phi false\n    }\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("define i8 @make_green()"), "{}", ir);
+        assert!(ir.contains("@is_green(i8 "), "{}", ir);
+        assert!(ir.contains("switch i8 "), "{}", ir);
+    }
+
+    #[test]
+    fn invalid_enum_casts_list_the_variant_types() {
+        let src = "type celsius = f32\ntype kelvin = f32\ntype temperature = celsius | kelvin\nfun ext bad(bool b) -> temperature {\n    return $temperature b\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(
+            report
+                .messages
+                .iter()
+                .any(|m| m == "Attempting to cast to an enum type that does not contain castee type"),
+            "{:?}",
+            report.messages
+        );
+        assert!(
+            report
+                .notes
+                .iter()
+                .any(|note| note.contains("variant types: 'celsius', 'kelvin'")),
+            "{:?}",
+            report.notes
+        );
+    }
+
+    /// Source of a program matching an enum with a structure variant
+    const MAYBE_POINT_SRC: &str = "type point = { i32 x, i32 y }
+type none = ()
+type maybe_point = point | none
+fun ext sum_of_point() -> i32 {
+    return sum_or_zero.(maybe_point:point.(#point { x = 3, y = 4 }))
+}
+fun ext sum_of_none() -> i32 {
+    return sum_or_zero.(maybe_point:none.())
+}
+";
+
+    #[test]
+    fn match_arms_destructure_variant_structures() {
+        let src = format!(
+            "{}fun sum_or_zero(maybe_point p) -> i32 {{\n    return match p {{\n        point {{ x, y }} -> phi x + y\n        none -> phi 0\n    }}\n}}\n",
+            MAYBE_POINT_SRC
+        );
+        assert_eq!(jit_call::<i32>(&src, "sum_of_point"), 7);
+        assert_eq!(jit_call::<i32>(&src, "sum_of_none"), 0);
+
+        let src = format!(
+            "{}fun sum_or_zero(maybe_point p) -> i32 {{\n    return match p {{\n        point {{ x, z }} -> phi x + z\n        none -> phi 0\n    }}\n}}\n",
+            MAYBE_POINT_SRC
+        );
+        let (_, messages) = codegen_diags(&src, test_opts(false));
+        assert!(!messages.is_empty(), "Destructured a field that the variant does not have");
+    }
+
+    #[test]
+    fn match_guards_select_between_arms_of_one_variant() {
+        let src = format!(
+            "{}fun sum_or_zero(maybe_point p) -> i32 {{\n    return match p {{\n        point {{ x, y }} if x > y -> phi x - y\n        point {{ x, y }} -> phi x + y\n        none -> phi 0\n    }}\n}}\nfun ext difference() -> i32 {{\n    return sum_or_zero.(maybe_point:point.(#point {{ x = 9, y = 4 }}))\n}}\n",
+            MAYBE_POINT_SRC
+        );
+        assert_eq!(jit_call::<i32>(&src, "difference"), 5);
+        assert_eq!(jit_call::<i32>(&src, "sum_of_point"), 7);
+    }
+
+    #[test]
+    fn break_carries_a_value_out_of_a_loop() {
+        let src = "fun first_multiple(i32 of, i32 above) -> i32 {\n    mut n = above\n    return {\n        if n % of == 0 { break n }\n        n = n + 1\n        continue\n    }\n}\nfun ext multiple_of_seven() -> i32 {\n    return first_multiple.(7, 20)\n}\n";
+        assert_eq!(jit_call::<i32>(src, "multiple_of_seven"), 21);
+    }
+
+    #[test]
+    fn discarded_call_values_are_statements() {
+        let src = "mut calls = 0\nfun compute() -> i32 {\n    calls = calls + 1\n    return 42\n}\nfun ext call_twice() -> i32 {\n    compute.()\n    compute.()\n    return calls\n}\n";
+        let (ir, messages) = codegen_diags(src, test_opts(false));
+        assert!(ir.is_some());
+        assert!(messages.is_empty(), "{:?}", messages);
+        assert_eq!(jit_call::<i32>(src, "call_twice"), 2);
+    }
+
+    #[test]
+    fn binary_operator_errors_label_the_operator() {
+        let src = "fun ext mix(i32 a, bool b) -> i32 {\n    return a * b\n}\n";
+        let op = src.find('*').unwrap();
+        let report = codegen_report(src, test_opts(false));
+        assert!(
+            report.primary_labels.iter().any(|(message, range)| {
+                message == "Binary operator * cannot be applied to the given types"
+                    && *range == (op..op + 1)
+            }),
+            "{:?}",
+            report.primary_labels
+        );
+    }
+
+    #[test]
+    fn anonymous_structure_literals_coerce_to_named_parameters() {
+        let src = "type point = { i32 x, i32 y }\nfun manhattan(point p) -> i32 {\n    return p.x + p.y\n}\nfun ext literal_manhattan() -> i32 {\n    return manhattan.(#{ x = 3, y = 4 })\n}\n";
+        assert_eq!(jit_call::<i32>(src, "literal_manhattan"), 7);
+
+        let src = "type point = { i32 x, i32 y }\nfun manhattan(point p) -> i32 {\n    return p.x + p.y\n}\nfun ext literal_manhattan() -> i32 {\n    return manhattan.(#{ x = 3, z = 4 })\n}\n";
+        let (_, messages) = codegen_diags(src, test_opts(false));
+        assert!(!messages.is_empty(), "Literal with different fields was coerced");
+    }
+
+    #[test]
+    fn heap_builtins_allocate_and_free_values() {
+        let src = "fun ext heap_roundtrip() -> i32 {\n    let value = alloc.(i32)\n    (*value) = 41\n    let values = alloc_array.(i32, 4u64)\n    (*values) = *value + 1\n    let read = *values\n    free.(value)\n    free.(values)\n    return read\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert_eq!(ir.matches("call i8* @malloc(").count(), 2, "{}", ir);
+        assert_eq!(ir.matches("call void @free(").count(), 2, "{}", ir);
+        assert_eq!(jit_call::<i32>(src, "heap_roundtrip"), 42);
+    }
+
+    #[test]
+    fn pointer_conditions_suggest_an_address_comparison() {
+        let src = "fun ext non_null(*i32 p) -> bool {\n    if p {\n        return true\n    }\n    return false\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(
+            report
+                .messages
+                .iter()
+                .any(|m| m == "Using value of type *i32 as boolean condition for if expression"),
+            "{:?}",
+            report.messages
+        );
+        assert!(
+            report.notes.iter().any(|note| note.contains("($u64 p) != 0u64")),
+            "{:?}",
+            report.notes
+        );
+
+        let src = "fun ext non_null(*i32 p) -> bool {\n    if ($u64 p) != 0u64 {\n        return true\n    }\n    return false\n}\n";
+        let (ir, messages) = codegen_diags(src, test_opts(false));
+        assert!(ir.is_some());
+        assert!(messages.is_empty(), "{:?}", messages);
+    }
+
+    /// Source of a program with an enum of variants with and without data
+    const READING_SRC: &str = "type celsius = f32
+type kelvin = f32
+type missing = ()
+type reading = celsius | kelvin | missing
+fun ext is_celsius(reading r) -> bool {
+    return discriminant_of.(r) == reading:celsius
+}
+fun ext celsius_code(reading r) -> i32 {
+    if is_celsius.(r) {
+        return 1
+    }
+    return 0
+}
+";
+
+    #[test]
+    fn variant_paths_are_discriminant_constants() {
+        let src = format!(
+            "{}fun ext celsius_is_celsius() -> i32 {{\n    return celsius_code.(reading:celsius.(1.5f32))\n}}\nfun ext kelvin_is_celsius() -> i32 {{\n    return celsius_code.(reading:kelvin.(1.5f32))\n}}\n",
+            READING_SRC
+        );
+        assert_eq!(jit_call::<i32>(&src, "celsius_is_celsius"), 1);
+        assert_eq!(jit_call::<i32>(&src, "kelvin_is_celsius"), 0);
+    }
+
+    #[test]
+    fn variants_are_constructed_by_calling_their_path() {
+        let src = format!(
+            "{}fun ext celsius_payload() -> celsius {{\n    let r = reading:celsius.(2.5f32)\n    return $celsius r\n}}\nfun ext missing_discriminant() -> u8 {{\n    let r = reading:missing.()\n    return discriminant_of.(r)\n}}\n",
+            READING_SRC
+        );
+        assert_eq!(jit_call::<f32>(&src, "celsius_payload"), 2.5);
+        assert_eq!(jit_call::<u8>(&src, "missing_discriminant"), 2);
+    }
+
+    #[test]
+    fn recursive_enums_build_linked_lists() {
+        let src = "type nil = ()
+type cons = { i32 val, *list next }
+type list = cons | nil
+fun list_sum(*list l) -> i32 {
+    return match *l {
+        cons { val, next } -> phi val + list_sum.(next)
+        nil -> phi 0
+    }
+}
+fun ext two_element_list() -> i32 {
+    let last = alloc.(list)
+    (*last) = list:nil.()
+    let second = alloc.(list)
+    (*second) = list:cons.(#{ val = 2, next = last })
+    let first = alloc.(list)
+    (*first) = list:cons.(#{ val = 1, next = second })
+    return list_sum.(first)
+}
+";
+        assert_eq!(jit_call::<i32>(src, "two_element_list"), 3);
+
+        let src = "type nil = ()\ntype node = { i32 val, list next }\ntype list = node | nil\n";
+        let (_, messages) = codegen_diags(src, test_opts(false));
+        assert!(
+            messages.iter().any(|m| m.ends_with("contains itself without indirection")),
+            "{:?}",
+            messages
+        );
+    }
+
+    #[test]
+    fn generic_functions_are_instantiated_per_type() {
+        let src = "fun id<T>(T x) -> T {\n    return x\n}\nfun ext identities() -> f64 {\n    let whole = id.(2)\n    let fraction = id.(0.5)\n    return ($f64 whole) + fraction\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            let mut instances = module
+                .get_functions()
+                .filter_map(|f| {
+                    let name = f.get_name().to_str().unwrap().to_owned();
+                    name.starts_with("id<").then(|| name[..name.find('-').unwrap()].to_owned())
+                })
+                .collect::<Vec<_>>();
+            instances.sort();
+            assert_eq!(instances, ["id<f64>", "id<i32>"]);
+        });
+        assert_eq!(jit_call::<f64>(src, "identities"), 2.5);
+    }
+
+    #[test]
+    fn generic_structures_are_instantiated_per_type() {
+        let src = "type box<T> = { T value }\nfun unbox<T>(box<T> b) -> T {\n    return b.value\n}\nfun ext box_values() -> f64 {\n    let whole = #box<i32> { value = 2 }\n    let fraction = #box<f64> { value = 0.5 }\n    return ($f64 unbox.(whole)) + fraction.value\n}\n";
+        assert_eq!(jit_call::<f64>(src, "box_values"), 2.5);
+    }
+
+    #[test]
+    fn interface_calls_dispatch_on_the_argument_type() {
+        let src = "type celsius = f32
+type box<T> = { T value }
+interface Show {
+    fun show(Self value) -> i32
+}
+impl Show for celsius {
+    fun show(celsius value) -> i32 {
+        return $i32 value
+    }
+}
+impl Show for box<i32> {
+    fun show(box<i32> value) -> i32 {
+        return value.value
+    }
+}
+fun show_twice<T>(T value) -> i32 {
+    return show.(value) + show.(value)
+}
+fun ext shown() -> i32 {
+    return show_twice.($celsius 3.0f32) + show_twice.(#box<i32> { value = 4 })
+}
+";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(!ir.contains("call i32 %"), "Interface call was not statically dispatched: {}", ir);
+        assert_eq!(jit_call::<i32>(src, "shown"), 14);
+    }
+
+    #[test]
+    fn call_conv_is_set_on_functions_and_calls() {
+        let src = "#[callconv(\"fast\")]\nfun fast_square(i32 x) -> i32 {\n    return x * x\n}\nfun ext fast_squares(i32 x) -> i32 {\n    return fast_square.(x) + fast_square.(x + 1)\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            assert_eq!(find_fun(module, "fast_square").get_call_conventions(), 8);
+            let caller = module.get_function("fast_squares").unwrap().print_to_string().to_string();
+            assert_eq!(caller.matches("call fastcc i32").count(), 2, "{}", caller);
+        });
+    }
+
+    #[test]
+    fn omitted_fields_take_their_default_values() {
+        let src = "type window = { i32 width = 640, i32 height = 480, bool visible = true }\nfun ext window_area() -> i32 {\n    let w = #window { height = 200 }\n    if w.visible {\n        return w.width * w.height\n    }\n    return 0\n}\n";
+        assert_eq!(jit_call::<i32>(src, "window_area"), 128000);
+    }
+
+    #[test]
+    fn missing_fields_are_listed_in_one_diagnostic() {
+        let src = "type rect = { i32 x, i32 y = 0, i32 width, i32 height }\nfun ext make() -> i32 {\n    let r = #rect { x = 1 }\n    return r.x\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        let missing = report
+            .messages
+            .iter()
+            .filter(|message| message.contains("is missing fields"))
+            .collect::<Vec<_>>();
+        assert_eq!(missing.len(), 1, "{:?}", report.messages);
+        assert!(missing[0].contains("'width', 'height'"), "{}", missing[0]);
+        assert!(!missing[0].contains("'y'"), "{}", missing[0]);
+
+        let literal = src.find("#rect").unwrap();
+        assert!(
+            report
+                .primary_labels
+                .iter()
+                .any(|(message, range)| message == "Structure literal encountered here" && range.start == literal),
+            "{:?}",
+            report.primary_labels
+        );
+    }
+
+    #[test]
+    fn repeated_string_literals_share_one_global() {
+        let src = "fun ext same_greeting() -> bool {\n    let first = $u64 \"hello\"\n    let second = $u64 \"hello\"\n    let other = $u64 \"goodbye\"\n    return first == second && first != other\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert_eq!(ir.matches("c\"hello\\00\"").count(), 1, "{}", ir);
+        assert_eq!(ir.matches("c\"goodbye\\00\"").count(), 1, "{}", ir);
+        assert!(jit_call::<bool>(src, "same_greeting"));
+    }
+
+    #[test]
+    fn optnone_functions_are_not_inlined() {
+        let src = "#[optnone]\nfun ext unoptimized_add(i32 a, i32 b) -> i32 {\n    return a + b\n}\nfun ext add_three() -> i32 {\n    return unoptimized_add.(1, 2)\n}\n";
+        let opts = CompileOpts {
+            opt_lvl: OutputOptimizationLevel::Release,
+            ..test_opts(false)
+        };
+        with_llvm(src, opts, |_, module| {
+            let callee = module.get_function("unoptimized_add").unwrap();
+            for attr in &["optnone", "noinline"] {
+                assert!(
+                    callee
+                        .get_enum_attribute(AttributeLoc::Function, Attribute::get_named_enum_kind_id(attr))
+                        .is_some(),
+                    "unoptimized_add is missing the {} attribute",
+                    attr
+                );
+            }
+
+            let passes = PassManager::create(());
+            passes.add_function_inlining_pass();
+            passes.run_on(module);
+            let caller = module.get_function("add_three").unwrap().print_to_string().to_string();
+            assert!(caller.contains("call i32 @unoptimized_add"), "{}", caller);
+        });
+    }
+
+    #[test]
+    fn external_globals_are_declared_and_read() {
+        static COUNTER: i32 = 42;
+
+        let src = "let (i32) ext counter\nfun ext read_counter() -> i32 {\n    return counter + 1\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            let counter = module.get_global("counter").unwrap();
+            assert_eq!(counter.get_linkage(), Linkage::External);
+            assert!(counter.get_initializer().is_none());
+
+            let engine = module
+                .create_jit_execution_engine(OptimizationLevel::None)
+                .unwrap_or_else(|e| panic!("Failed to create JIT execution engine: {}", e));
+            engine.add_global_mapping(&counter.as_pointer_value(), &COUNTER as *const i32 as usize);
+            let read = unsafe {
+                engine
+                    .get_function::<unsafe extern "C" fn() -> i32>("read_counter")
+                    .unwrap()
+                    .call()
+            };
+            assert_eq!(read, 43);
+        });
+    }
+
+    #[test]
+    fn local_type_aliases_are_scoped_to_their_function() {
+        let src = "fun ext local_alias() -> i64 {\n    type wide = i64\n    let (wide) x = $wide 40\n    return x + $wide 2\n}\n";
+        assert_eq!(jit_call::<i64>(src, "local_alias"), 42);
+
+        let src = "fun ext local_alias() -> i64 {\n    type wide = i64\n    return $wide 40\n}\nfun ext other() -> i64 {\n    let (wide) x = $wide 2\n    return x\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(report.ir.is_none());
+        assert!(!report.messages.is_empty());
+    }
+
+    #[test]
+    fn logical_conditions_short_circuit() {
+        let src = "mut (i32) calls = 0
+fun bump() -> bool {
+    calls = calls + 1
+    return true
+}
+fun ext short_circuit() -> i32 {
+    mut never = false
+    if never && bump.() {
+        return 10
+    }
+    if bump.() || bump.() {
+        if true && bump.() {
+            return calls
+        }
+    }
+    return 20
+}
+";
+        assert_eq!(jit_call::<i32>(src, "short_circuit"), 2);
+    }
+
+    #[test]
+    fn calling_a_structure_suggests_member_access() {
+        let src = "type point = { i32 x, i32 y }\nfun ext call_point() -> i32 {\n    let p = #point { x = 1, y = 2 }\n    return p.()\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(
+            report.messages.iter().any(|message| message == "Cannot call a value of non-function type point"),
+            "{:?}",
+            report.messages
+        );
+        assert!(
+            report.notes.iter().any(|note| note.contains("`p.x`") && note.contains("'x', 'y'")),
+            "{:?}",
+            report.notes
+        );
+
+        let src = "fun double(i32 x) -> i32 {\n    return x * 2\n}\ntype handler = { i32 id, fun(i32) -> i32 callback }\nfun ext call_handler() -> i32 {\n    let h = #handler { id = 1, callback = double }\n    return h.(2)\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(
+            report.notes.iter().any(|note| note.contains("`h.callback.()`")),
+            "{:?}",
+            report.notes
+        );
+    }
+
+    #[test]
+    fn address_of_a_call_result_is_rejected() {
+        let src = "fun five() -> i32 {\n    return 5\n}\nfun ext read_five() -> i32 {\n    let p = &five.()\n    return *p\n}\n";
+        let report = codegen_report(src, test_opts(false));
+        assert!(report.ir.is_none());
+        assert!(
+            report.messages.iter().any(|message| message == "Cannot take the address of a function's return value"),
+            "{:?}",
+            report.messages
+        );
+        let call = src.find("five.()").unwrap();
+        assert!(
+            report.primary_labels.iter().any(|(_, range)| range.start == call),
+            "{:?}",
+            report.primary_labels
+        );
+    }
+
+    #[test]
+    fn constant_array_literals_are_stored_at_once() {
+        let elems = (0..100).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+        let src = format!(
+            "fun ext last_elem() -> i32 {{\n    let elems = [{}]\n    return elems[99]\n}}\n",
+            elems
+        );
+        with_llvm(&src, test_opts(false), |_, module| {
+            let fun = module.get_function("last_elem").unwrap().print_to_string().to_string();
+            assert_eq!(fun.matches("store [100 x i32]").count(), 1, "{}", fun);
+            assert!(!fun.contains("store i32"), "{}", fun);
+        });
+        assert_eq!(jit_call::<i32>(&src, "last_elem"), 99);
+    }
+
+    #[test]
+    fn constant_struct_literals_are_stored_at_once() {
+        let src = "type point = { i32 x, i32 y }\nfun ext point_sum() -> i32 {\n    let p = #point { x = 3, y = 4 }\n    return p.x + p.y\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            let fun = module.get_function("point_sum").unwrap().print_to_string().to_string();
+            assert_eq!(fun.matches("store %").count(), 1, "{}", fun);
+            assert!(fun.contains("{ i32 3, i32 4 }"), "{}", fun);
+            assert!(!fun.contains("store i32"), "{}", fun);
+        });
+        assert_eq!(jit_call::<i32>(src, "point_sum"), 7);
+    }
+
+    #[test]
+    fn extern_block_functions_have_external_linkage() {
+        let src = "extern \"C\" {\n    fun strlen(*u8 s) -> u64\n    fun abs(i32 n) -> i32\n}\nfun ext lengths() -> u64 {\n    return strlen.(\"hello\") + $u64 abs.(5)\n}\n";
+        with_llvm(src, test_opts(false), |_, module| {
+            for name in &["strlen", "abs"] {
+                let fun = module
+                    .get_function(name)
+                    .unwrap_or_else(|| panic!("No function named {}", name));
+                assert_eq!(fun.get_linkage(), Linkage::External);
+                assert_eq!(fun.count_basic_blocks(), 0);
+            }
+        });
+        assert_eq!(jit_call::<u64>(src, "lengths"), 10);
+    }
+
+    #[test]
+    fn function_pointers_compare_by_address() {
+        let src = "fun twice(i32 n) -> i32 {
+    return n * 2
+}
+fun thrice(i32 n) -> i32 {
+    return n * 3
+}
+fun is_twice(fun(i32) -> i32 callback) -> bool {
+    let (fun(i32) -> i32) stored = callback
+    return stored == twice
+}
+fun is_not_twice(fun(i32) -> i32 callback) -> bool {
+    return callback != twice
+}
+fun ext compare_funs() -> bool {
+    return is_twice.(twice) && is_not_twice.(thrice) && (is_twice.(thrice) == false) && (is_not_twice.(twice) == false)
+}
+";
+        assert!(jit_call::<bool>(src, "compare_funs"));
+    }
+
+    #[test]
+    fn self_assignment_warns_when_enabled() {
+        let src = "type point = { i32 x, i32 y }\nfun ext unchanged(i32 n) -> i32 {\n    mut x = n\n    x = x\n    mut p = #point { x = n, y = n }\n    p.y = p.y\n    p.x = p.y\n    return x + p.x\n}\n";
+        let opts = CompileOpts {
+            warn_self_assign: true,
+            ..test_opts(false)
+        };
+        let report = codegen_report(src, opts);
+        assert!(report.ir.is_some());
+        let warned = report
+            .primary_labels
+            .iter()
+            .filter(|(message, _)| message == "Assigning a value to itself has no effect")
+            .map(|(_, range)| &src[range.clone()])
+            .collect::<Vec<_>>();
+        assert_eq!(warned, ["x = x", "p.y = p.y"]);
+
+        let report = codegen_report(src, test_opts(false));
+        assert!(report.messages.is_empty(), "{:?}", report.messages);
+    }
+
+    #[test]
+    fn functions_are_printed_in_source_order() {
+        let names = ["zeta", "alpha", "mid", "beta", "omega"];
+        let src = names
+            .iter()
+            .map(|name| format!("fun ext {}() -> i32 {{\n    return 1\n}}\n", name))
+            .collect::<String>();
+        let ir = llvm_ir(&src, test_opts(false));
+        let positions = names
+            .iter()
+            .map(|name| {
+                ir.find(&format!("@{}()", name))
+                    .unwrap_or_else(|| panic!("{} was not defined: {}", name, ir))
+            })
+            .collect::<Vec<_>>();
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]), "{}", ir);
+    }
+
+    #[test]
+    fn integers_convert_to_and_from_enums() {
+        let src = "type red = ()
+type green = ()
+type blue = ()
+type color = red | green | blue
+fun ext round_trip() -> u8 {
+    mut idx = 2u8
+    let c = $color idx
+    return $u8 c
+}
+fun ext checked_color(u8 idx) -> i32 {
+    let c = $color idx
+    return $i32 c
+}
+";
+        assert_eq!(jit_call::<u8>(src, "round_trip"), 2);
+
+        with_llvm(src, test_opts(false), |_, module| {
+            let fun = module.get_function("checked_color").unwrap().print_to_string().to_string();
+            assert!(fun.contains("icmp ult i8") && fun.contains(", 3"), "{}", fun);
+            assert!(fun.contains("enum_discrim_invalid:"), "{}", fun);
+            assert!(fun.contains("call void @abort()"), "{}", fun);
+        });
+    }
+
+    #[test]
+    fn constant_arrays_of_structures_are_evaluated() {
+        let src = "type point = { i32 x, i32 y }
+const corners = [#point { x = 0, y = 0 }, #point { x = 640, y = 480 }]
+fun ext far_corner() -> i32 {
+    let far = corners[1]
+    return far.x + far.y + corners[0].x
+}
+";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("{ i32 640, i32 480 }"), "{}", ir);
+        assert_eq!(jit_call::<i32>(src, "far_corner"), 1120);
+    }
+
+    #[test]
+    fn returning_the_address_of_a_local_warns() {
+        const WARNING: &str = "Returning the address of a stack-allocated local variable";
+
+        for src in &[
+            "fun ext dangling() -> *i32 {\n    let x = 5\n    return &x\n}\n",
+            "fun ext dangling() -> *i32 {\n    let x = 5\n    let p = &x\n    return p\n}\n",
+        ] {
+            let (ir, messages) = codegen_diags(src, test_opts(false));
+            assert!(ir.is_some());
+            assert_eq!(messages, [WARNING], "{}", src);
+        }
+
+        for src in &[
+            "mut (i32) counter = 0\nfun ext global_ptr() -> *i32 {\n    return &counter\n}\n",
+            "fun ext passed_on(*i32 ptr) -> *i32 {\n    let p = ptr\n    return p\n}\n",
+            "mut (i32) counter = 0\nfun ext reassigned() -> *i32 {\n    let x = 5\n    mut p = &x\n    p = &counter\n    return p\n}\n",
+        ] {
+            let (ir, messages) = codegen_diags(src, test_opts(false));
+            assert!(ir.is_some());
+            assert!(messages.is_empty(), "{}: {:?}", src, messages);
+        }
+    }
+
+    #[test]
+    fn union_storage_holds_padded_structure_fields() {
+        let src = "type padded = { i8 a, i32 b, i8 c }
+type holder = union { padded s, i32 x }
+fun ext last_byte() -> i8 {
+    let (holder) h
+    h.s = #padded { a = 1i8, b = 2, c = 3i8 }
+    return h.s.c
+}";
+        with_llvm(src, test_opts(false), |_, module| {
+            let fun = module.get_function("last_byte").unwrap().print_to_string().to_string();
+            //The structure is 12 bytes once padding is counted, not the 6 bytes of its fields
+            assert!(fun.contains("alloca { i32, [8 x i8] }"), "Unexpected IR: {}", fun);
+        });
+        assert_eq!(jit_call::<i8>(src, "last_byte"), 3);
+    }
+
+    #[test]
+    fn unsupported_targets_are_reported() {
+        let files = Files::new();
+        let llvm_ctx = Context::create();
+        let opts = CompileOpts {
+            target: Some("bogus-unknown-nowhere".to_owned()),
+            ..test_opts(false)
+        };
+        match LlvmCodeGenerator::new(SparkCtx::new(), &llvm_ctx, &files, opts) {
+            Ok(_) => panic!("Created a code generator for an unsupported target"),
+            Err(e) => assert_eq!(e.message, "Unsupported compilation target 'bogus-unknown-nowhere'"),
+        }
+    }
+
+    #[test]
+    fn promoted_expressions_take_the_wider_operand_type() {
+        let opts = || CompileOpts {
+            int_promotion: true,
+            ..test_opts(false)
+        };
+
+        let src = "fun ext unsigned_narrow() -> i32 {\n    let (u8) a = 200u8\n    let (i32) b = -1\n    return a + b\n}\nfun ext signed_narrow() -> u32 {\n    let (i8) a = -1i8\n    let (u32) b = 1u32\n    return a + b\n}\n";
+        with_llvm(src, opts(), |_, module| {
+            let engine = module
+                .create_jit_execution_engine(OptimizationLevel::None)
+                .unwrap_or_else(|e| panic!("Failed to create JIT execution engine: {}", e));
+            unsafe {
+                let unsigned_narrow = engine
+                    .get_function::<unsafe extern "C" fn() -> i32>("unsigned_narrow")
+                    .unwrap();
+                assert_eq!(unsigned_narrow.call(), 199);
+                let signed_narrow = engine
+                    .get_function::<unsafe extern "C" fn() -> u32>("signed_narrow")
+                    .unwrap();
+                assert_eq!(signed_narrow.call(), 0);
+            }
+        });
+
+        //The result has the wider operand's signedness, not the narrower one's
+        let (_, messages) = codegen_diags(
+            "fun ext wrong(u8 a, i32 b) -> u32 {\n    return a + b\n}\n",
+            opts(),
+        );
+        assert!(!messages.is_empty(), "u8 + i32 was typed as u32");
+
+        let (_, messages) = codegen_diags(
+            "fun ext same_width(i32 a, u32 b) -> i32 {\n    return a + b\n}\n",
+            opts(),
+        );
+        assert!(!messages.is_empty(), "Mixed signedness of the same width was promoted");
+    }
+
+    #[test]
+    fn constant_vector_lanes_are_bounds_checked() {
+        let (ir, messages) = codegen_diags(
+            "fun ext last_lane(<4 x i32> v) -> i32 {\n    return v[3]\n}\n",
+            test_opts(false),
+        );
+        assert!(ir.is_some(), "{:?}", messages);
+
+        for src in &[
+            "fun ext past_lane(<4 x i32> v) -> i32 {\n    return v[4]\n}\n",
+            "fun ext set_past_lane(<4 x i32> v) -> <4 x i32> {\n    mut w = v\n    w[4] = 1\n    return w\n}\n",
+        ] {
+            let (ir, messages) = codegen_diags(src, test_opts(false));
+            assert!(ir.is_none());
+            assert_eq!(messages, ["Index 4 is out of bounds for a vector of length 4"], "{}", src);
+        }
+    }
+
+    #[test]
+    fn labeled_break_exits_every_enclosing_loop() {
+        let src = "fun find_product(i32 target) -> i32 {
+    mut i = 0
+    mut found = -1
+    'outer: {
+        mut j = 0
+        {
+            if i * j == target {
+                found = i * 10 + j
+                break 'outer
+            }
+            j = j + 1
+            if j == 10 { break }
+            continue
+        }
+        i = i + 1
+        if i == 10 { break }
+        continue
+    }
+    return found
+}
+fun ext product_of_42() -> i32 {
+    return find_product.(42)
+}
+";
+        //Breaking only the inner loop would let the outer loop overwrite 67 with 76
+        assert_eq!(jit_call::<i32>(src, "product_of_42"), 67);
+
+        let (ir, messages) = codegen_diags(
+            "fun ext spin() -> i32 {\n    'outer: {\n        break 'missing\n    }\n    return 0\n}\n",
+            test_opts(false),
+        );
+        assert!(ir.is_none());
+        assert_eq!(messages, ["Unknown loop label 'missing"]);
+    }
+
+    #[test]
+    fn returned_literals_take_the_return_type() {
+        let src = "fun ext zero_wide() -> i64 {\n    return 0\n}\n";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("ret i64 0"), "{}", ir);
+        assert_eq!(jit_call::<i64>(src, "zero_wide"), 0);
+    }
+
+    #[test]
+    fn array_elements_are_loaded_and_stored_by_index() {
+        let src = "fun ext array_indices() -> i32 {
+    mut table = [1, 2, 3, 4]
+    let (u8) i = 3u8
+    let (i64) j = $i64 1
+    table[i] = 10
+    table[0] = table[j] + table[i]
+    return table[0] + table[2]
+}
+";
+        let ir = llvm_ir(src, test_opts(false));
+        assert!(ir.contains("getelementptr inbounds [4 x i32]"), "{}", ir);
+        assert_eq!(jit_call::<i32>(src, "array_indices"), 15);
+
+        for (index, message) in &[
+            ("4", "Index 4 is out of bounds for an array of length 4"),
+            ("-1", "Index -1 is out of bounds for an array of length 4"),
+        ] {
+            let src = format!(
+                "fun ext past_end() -> i32 {{\n    let table = [1, 2, 3, 4]\n    return table[{}]\n}}\n",
+                index
+            );
+            let (ir, messages) = codegen_diags(&src, test_opts(false));
+            assert!(ir.is_none());
+            assert_eq!(messages, [*message]);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Finding which functions can be called from outside of the generated module, so that
+//! internal functions that are never referenced can be skipped
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{
+    ast::{Ast, AstNode, FunFlags},
+    codegen::ir::{FunId, ModId, SparkDef, TypeId},
+};
+
+use super::LlvmCodeGenerator;
+
+impl<'ctx, 'files> LlvmCodeGenerator<'ctx, 'files> {
+    /// Find all functions that are reachable from external functions and global initializers
+    /// in a module and its children
+    ///
+    /// Names are resolved without local variable scopes, so a function is kept if any
+    /// reachable function references a name that may refer to it
+    pub(super) fn reachable_funs(&self, root: ModId) -> HashSet<FunId> {
+        let mut fun_modules = HashMap::<FunId, Vec<ModId>>::new();
+        let mut enclosing = HashMap::new();
+        let mut worklist = vec![];
+        let mut visited = HashSet::new();
+        self.collect_roots(root, &mut visited, &mut fun_modules, &mut enclosing, &mut worklist);
+
+        let mut reachable = HashSet::new();
+        while let Some(fun) = worklist.pop() {
+            if !reachable.insert(fun) {
+                continue;
+            }
+
+            let modules = fun_modules.get(&fun).cloned().unwrap_or_default();
+            if let Some(body) = &self.spark[fun].body {
+                for stmt in body {
+                    self.referenced_funs(stmt, fun, &modules, &enclosing, &mut worklist);
+                }
+            }
+            //Calls to interface functions are resolved by argument type during code
+            //generation, so every implementation is kept
+            for (module, implementation) in self.spark.implementations_of(fun) {
+                self.record_fun(implementation, module, &mut fun_modules, &mut enclosing);
+                worklist.push(implementation);
+            }
+        }
+
+        reachable
+    }
+
+    /// Record the modules that every function is defined in and add all functions that are
+    /// visible outside of the program to the worklist
+    fn collect_roots(
+        &self,
+        module: ModId,
+        visited: &mut HashSet<ModId>,
+        fun_modules: &mut HashMap<FunId, Vec<ModId>>,
+        enclosing: &mut HashMap<FunId, FunId>,
+        worklist: &mut Vec<FunId>,
+    ) {
+        if !visited.insert(module) {
+            return;
+        }
+
+        for (_, def) in self.spark[module].defs.iter() {
+            match *def {
+                SparkDef::FunDef(_, fun) => {
+                    self.record_fun(fun, module, fun_modules, enclosing);
+                    let f = &self.spark[fun];
+                    if f.flags.contains(FunFlags::EXTERN)
+                        || f.linkage.is_some()
+                        || f.name.as_str() == "main"
+                    {
+                        worklist.push(fun);
+                    }
+                }
+                SparkDef::GlobalDef(_, global) => {
+                    if let Some(init) = &self.spark[global].init {
+                        self.referenced_module_funs(init, &[module], worklist);
+                    }
+                }
+                SparkDef::ModDef(submod) => {
+                    self.collect_roots(submod, visited, fun_modules, enclosing, worklist)
+                }
+                SparkDef::TypeDef(..) | SparkDef::InterfaceDef(..) => (),
+            }
+        }
+    }
+
+    fn record_fun(
+        &self,
+        fun: FunId,
+        module: ModId,
+        fun_modules: &mut HashMap<FunId, Vec<ModId>>,
+        enclosing: &mut HashMap<FunId, FunId>,
+    ) {
+        let modules = fun_modules.entry(fun).or_default();
+        if modules.contains(&module) {
+            return;
+        }
+        modules.push(module);
+
+        for nested in self.spark[fun].nested.iter() {
+            enclosing.insert(*nested, fun);
+            self.record_fun(*nested, module, fun_modules, enclosing);
+        }
+    }
+
+    /// Add all functions that may be referenced by name in a statement of a function to the
+    /// worklist, including functions nested in the function or any function enclosing it
+    fn referenced_funs(
+        &self,
+        stmt: &Ast<TypeId>,
+        fun: FunId,
+        modules: &[ModId],
+        enclosing: &HashMap<FunId, FunId>,
+        worklist: &mut Vec<FunId>,
+    ) {
+        stmt.walk(&mut |ast| {
+            if let AstNode::Access(path) = &ast.node {
+                if path.len() == 1 {
+                    let mut scope = Some(fun);
+                    while let Some(parent) = scope {
+                        worklist.extend(
+                            self.spark[parent]
+                                .nested
+                                .iter()
+                                .copied()
+                                .filter(|nested| self.spark[*nested].name == path.last()),
+                        );
+                        scope = enclosing.get(&parent).copied();
+                    }
+                }
+            }
+        });
+        self.referenced_module_funs(stmt, modules, worklist);
+    }
+
+    /// Add all module-level functions that an expression may reference to the worklist
+    fn referenced_module_funs(
+        &self,
+        ast: &Ast<TypeId>,
+        modules: &[ModId],
+        worklist: &mut Vec<FunId>,
+    ) {
+        ast.walk(&mut |ast| {
+            if let AstNode::Access(path) = &ast.node {
+                for module in modules {
+                    if let Ok(SparkDef::FunDef(_, referenced)) = self.spark.get_def(*module, path) {
+                        worklist.push(referenced);
+                    }
+                }
+            }
+        });
+    }
+}
@@ -1,20 +1,29 @@
 use codespan_reporting::diagnostic::{Diagnostic, Label};
+use hashbrown::{HashMap, HashSet};
 
 
 use crate::{ast::{
-        Ast, AstNode, DefData, ElseExpr, FunProto, IfExpr, IntegerWidth, Literal, ParsedModule,
-        UnresolvedType,
-    }, error::DiagnosticManager, util::{
+        ArrayLen, Ast, AstNode, Attribute, Def, DefData, ElseExpr, FunFlags, FunProto, IfExpr, IntegerWidth, Literal, MatchPattern,
+        NumberLiteral, ParsedModule, SymbolPath, UnresolvedType,
+    }, error::DiagnosticManager, parse::token::Op, util::{
         files::{FileId, Files},
         loc::Span,
-    }};
+        suggest,
+    }, Symbol};
 
-use super::{ir::{FunId, FunctionType, ModId, SparkCtx, SparkDef, TypeData, TypeId}, CompilerRes};
+use super::{ir::{CallConv, DefLinkage, DefLookupError, DefOptimization, DefVisibility, FunId, FunctionType, GenericParams, GlobalFlags, GlobalId, Implementation, InterfaceId, ModId, SparkCtx, SparkDef, StructRepr, TypeData, TypeId}, CompilerRes};
 
 /// Structure for lowering a parsed AST's types
 pub struct Lowerer<'ctx, 'files> {
     ctx: &'ctx mut SparkCtx,
     diags: DiagnosticManager<'files>,
+    /// Nested functions defined in the function body currently being lowered
+    nested: Vec<FunId>,
+    /// Names and types of the type parameters of the generic function currently being lowered
+    type_params: Vec<(Symbol, TypeId)>,
+    /// Names and aliased types of the type aliases defined in the function bodies currently
+    /// being lowered, innermost last
+    local_types: Vec<(Symbol, TypeId)>,
 }
 
 
@@ -24,6 +33,9 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         Self {
             ctx,
             diags: DiagnosticManager::new(files),
+            nested: vec![],
+            type_params: vec![],
+            local_types: vec![],
         }
     }
 
@@ -39,21 +51,49 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                         unreachable!()
                     };
 
-                    let body = body
-                        .iter()
-                        .map(|expr| self.lower_ast(id, expr, def.file))
-                        .collect::<CompilerRes<_>>()?;
-                    self.ctx[fun].body = Some(body);
+                    self.type_params = self.generic_scope(fun);
+                    let lowered = self.lower_fun_body(id, fun, body, def.file);
+                    self.type_params.clear();
+                    lowered?;
                 }
-                DefData::AliasDef { name, aliased } => {
+                DefData::AliasDef {
+                    name,
+                    aliased,
+                    defaults,
+                    ..
+                } => {
                     let ty = if let SparkDef::TypeDef(_, id) = self.ctx[id].defs.get(name).unwrap()
                     {
                         *id
                     } else {
                         unreachable!()
                     };
-                    let aliased = self.lower_type(id, Some(def.span), aliased, def.file)?;
-                    self.ctx[ty] = TypeData::Alias(*name, aliased);
+                    self.type_params = self.generic_type_scope(ty);
+                    let lowered = self
+                        .lower_type(id, Some(def.span), aliased, def.file)
+                        .and_then(|aliased| {
+                            let defaults = defaults
+                                .iter()
+                                .map(|(field, default)| {
+                                    Ok((*field, self.lower_ast(id, default, def.file)?))
+                                })
+                                .collect::<CompilerRes<Vec<_>>>()?;
+                            Ok((aliased, defaults))
+                        });
+                    self.type_params.clear();
+                    let (mut aliased, defaults) = lowered?;
+                    if let Some(repr) = def.attrs.iter().find(|attr| attr.name.as_str() == "repr") {
+                        aliased = self.lower_repr(def, repr, aliased)?;
+                    }
+                    if !defaults.is_empty() {
+                        self.check_field_defaults(def, aliased, &defaults)?;
+                        self.ctx.set_field_defaults(ty, def.file, defaults);
+                    }
+                    self.ctx[ty] = TypeData::Alias(*name, id, aliased);
+                    self.ctx.fill_type_instances(ty);
+                }
+                DefData::ImplDef { interface, ty, funs } => {
+                    self.lower_impl(id, def, interface, ty, funs)?
                 }
                 _ => continue,
             }
@@ -69,6 +109,9 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         Ok(())
     }
 
+    /// Lower a parsed module and all of its children to IR, forward declaring all types,
+    /// functions, and globals before lowering function bodies so that definitions can be
+    /// referenced before they appear in source
     pub fn lower_module(&mut self, parsed: &ParsedModule) -> CompilerRes<ModId> {
         let id = match self.gen_forward_decls(parsed) {
             Ok(id) => id,
@@ -77,23 +120,112 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                 return Err(e);
             }
         };
-        if let Err(e) = self.lower_defs(parsed, id) {
+        if let Err(e) = self
+            .lower_defs(parsed, id)
+            .and_then(|_| self.check_recursive_types(parsed, id))
+        {
             self.diags.emit(e.clone());
             return Err(e);
         }
         Ok(id)
     }
 
-    /// Generate forward declarations for all function definitions and declarations
-    fn gen_forward_funs(&mut self, parsed: &ParsedModule, module_id: ModId) -> CompilerRes<()> {
+    /// Check that no type definition in a module or its children contains itself without
+    /// indirection, which would make the type infinitely large
+    ///
+    /// Types may only refer to themselves through pointers, like `type list = { i32 val, *list
+    /// next } | ()`, and this is checked after all type definitions are lowered so that types
+    /// referring to each other can be declared in any order
+    fn check_recursive_types(&self, parsed: &ParsedModule, id: ModId) -> CompilerRes<()> {
         for def in parsed.defs.iter().map(|(_, v)| v) {
+            if let DefData::AliasDef { name, .. } = &def.data {
+                if let Some(SparkDef::TypeDef(_, ty)) = self.ctx[id].defs.get(name) {
+                    //Generic types can only contain themselves through one of their instances
+                    let recursive = std::iter::once(*ty)
+                        .chain(self.ctx.type_instances_of(*ty))
+                        .any(|ty| self.ctx.contains_by_value(ty, ty, &mut HashSet::new()));
+                    if recursive {
+                        return Err(Diagnostic::error()
+                            .with_message(format!(
+                                "Type '{}' contains itself without indirection",
+                                name
+                            ))
+                            .with_labels(vec![Label::primary(def.file, def.span)
+                                .with_message("Recursive type defined here")])
+                            .with_notes(vec![format!(
+                                "Use a pointer like '*{}' to refer to the type inside of itself",
+                                name
+                            )]));
+                    }
+                }
+            }
+        }
+
+        for child in &parsed.children {
+            if let Some(SparkDef::ModDef(child_id)) = self.ctx[id].defs.get(child.0) {
+                self.check_recursive_types(child.1, *child_id)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Generate forward declarations for all function definitions and declarations, and
+    /// global variable definitions
+    fn gen_forward_funs(&mut self, parsed: &ParsedModule, module_id: ModId) -> CompilerRes<()> {
+        //Constants are declared first so that they can be used as array lengths in the types
+        //of other definitions
+        let mut defs = parsed.defs_in_order();
+        defs.sort_by_key(|def| !matches!(def.data, DefData::GlobalDef { constant: true, .. }));
+
+        for def in defs {
             match &def.data {
                 DefData::FunDec(proto) | DefData::FunDef(proto, _) => {
                     let fun_id = self.lower_funproto(module_id, def.span, proto, def.file)?;
+                    self.ctx[fun_id].linkage = Self::def_linkage(def)?;
+                    let external = self.ctx[fun_id].linkage.is_some()
+                        || proto.flags.contains(FunFlags::EXTERN);
+                    self.ctx[fun_id].visibility = Self::def_visibility(def, external)?;
+                    self.ctx[fun_id].call_conv = Self::def_call_conv(def)?;
+                    self.ctx[fun_id].optimization = Self::def_optimization(def)?;
                     self.ctx[module_id]
                         .defs
                         .define(proto.name, SparkDef::FunDef(def.file, fun_id));
                 }
+                DefData::InterfaceDef { name, funs } => {
+                    let interface = self.lower_interface(module_id, *name, funs)?;
+                    self.ctx[module_id]
+                        .defs
+                        .define(*name, SparkDef::InterfaceDef(def.file, interface));
+                }
+                DefData::GlobalDef {
+                    name,
+                    ty,
+                    mutable,
+                    constant,
+                    init,
+                } => {
+                    let ty = match ty {
+                        Some(ty) => Some(self.lower_type(module_id, Some(def.span), ty, def.file)?),
+                        None => None,
+                    };
+                    let init = match init {
+                        Some(init) => Some(self.lower_ast(module_id, init, def.file)?),
+                        None => {
+                            Self::check_extern_global(def, *name, ty.is_some(), *constant)?;
+                            None
+                        }
+                    };
+                    let flags = Self::global_flags(def);
+                    let linkage = Self::def_linkage(def)?;
+                    let visibility = Self::def_visibility(def, linkage.is_some())?;
+                    let global_id = self.ctx.new_global(
+                        *name, ty, *mutable, flags, linkage, visibility, init, def.span,
+                    );
+                    self.ctx[module_id]
+                        .defs
+                        .define(*name, SparkDef::GlobalDef(def.file, global_id));
+                }
                 _ => (),
             }
         }
@@ -112,14 +244,538 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         Ok(())
     }
 
+    /// Check that every field given a default value in a type definition is a field of the
+    /// defined structure type
+    fn check_field_defaults(
+        &self,
+        def: &Def,
+        ty: TypeId,
+        defaults: &[(Symbol, Ast<TypeId>)],
+    ) -> CompilerRes<()> {
+        let fields = match &self.ctx[ty] {
+            TypeData::Struct { fields, .. } => fields,
+            _ => {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Field default values can only be given for structure types, found '{}'",
+                        self.ctx.get_type_name(ty)
+                    ))
+                    .with_labels(vec![Label::primary(def.file, def.span)]))
+            }
+        };
+
+        for (field, default) in defaults {
+            if !fields.iter().any(|(_, name)| name == field) {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Default value given for field '{}' not contained in structure type '{}'",
+                        field,
+                        def.data.name()
+                    ))
+                    .with_labels(vec![Label::primary(def.file, default.span)]));
+            }
+        }
+        Ok(())
+    }
+
+    /// Declare the functions of an interface as generic functions over `Self` and define them
+    /// in the module so that calls to them can be resolved to an implementation
+    fn lower_interface(
+        &mut self,
+        module: ModId,
+        name: Symbol,
+        funs: &[Def],
+    ) -> CompilerRes<InterfaceId> {
+        let interface = self.ctx.new_interface(name);
+        for def in funs {
+            let proto = match &def.data {
+                DefData::FunDec(proto)
+                    if proto.type_params.is_empty() && !proto.flags.contains(FunFlags::EXTERN) =>
+                {
+                    proto
+                }
+                _ => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Interface '{}' can only declare functions without bodies",
+                            name
+                        ))
+                        .with_labels(vec![Label::primary(def.file, def.span)])
+                        .with_notes(vec![
+                            "Interface functions are generic over the implementing type `Self` and cannot have other type parameters".to_owned(),
+                        ]))
+                }
+            };
+
+            let proto = FunProto {
+                type_params: vec![Symbol::from("Self")],
+                ..proto.clone()
+            };
+            let fun = self.lower_funproto(module, def.span, &proto, def.file)?;
+            self.ctx[fun].generic.as_mut().unwrap().interface = Some(interface);
+            self.ctx[interface].funs.push(fun);
+            self.ctx[module]
+                .defs
+                .define(proto.name, SparkDef::FunDef(def.file, fun));
+        }
+        Ok(interface)
+    }
+
+    /// Lower the functions defined in an implementation of an interface, checking that every
+    /// function declared in the interface is defined with `Self` replaced by the implementing
+    /// type
+    fn lower_impl(
+        &mut self,
+        module: ModId,
+        def: &Def,
+        interface: &SymbolPath,
+        ty: &UnresolvedType,
+        funs: &[Def],
+    ) -> CompilerRes<()> {
+        let interface = match self.ctx.get_def(module, interface) {
+            Ok(SparkDef::InterfaceDef(_, id)) => id,
+            Ok(_) => {
+                return Err(Diagnostic::error()
+                    .with_message(format!("'{}' is not an interface", interface))
+                    .with_labels(vec![Label::primary(def.file, def.span)]))
+            }
+            Err(e) => {
+                return Err(Diagnostic::error()
+                    .with_message(match e {
+                        DefLookupError::NotFound(_) => {
+                            format!("interface '{}' not found", interface)
+                        }
+                        DefLookupError::Private(..) => format!("interface {}", e),
+                    })
+                    .with_labels(vec![Label::primary(def.file, def.span)]))
+            }
+        };
+        let ty = self.lower_type(module, Some(def.span), ty, def.file)?;
+        let interface_name = self.ctx[interface].name;
+        if self.ctx[interface].impls.contains_key(&ty) {
+            return Err(Diagnostic::error()
+                .with_message(format!(
+                    "Interface '{}' is implemented more than once for type '{}'",
+                    interface_name,
+                    self.ctx.get_type_name(ty)
+                ))
+                .with_labels(vec![Label::primary(def.file, def.span)]));
+        }
+
+        let declared = self.ctx[interface].funs.clone();
+        let mut defined = vec![None; declared.len()];
+        for fun_def in funs {
+            let (proto, body) = match &fun_def.data {
+                DefData::FunDef(proto, body) if proto.type_params.is_empty() => (proto, body),
+                _ => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Implementation of interface '{}' can only define functions",
+                            interface_name
+                        ))
+                        .with_labels(vec![Label::primary(fun_def.file, fun_def.span)]))
+                }
+            };
+            let idx = match declared
+                .iter()
+                .position(|declared| self.ctx[*declared].name == proto.name)
+            {
+                Some(idx) if defined[idx].is_none() => idx,
+                Some(_) => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Function '{}' is defined more than once in implementation",
+                            proto.name
+                        ))
+                        .with_labels(vec![Label::primary(fun_def.file, fun_def.span)]))
+                }
+                None => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Function '{}' is not declared in interface '{}'",
+                            proto.name, interface_name
+                        ))
+                        .with_labels(vec![Label::primary(fun_def.file, fun_def.span)]))
+                }
+            };
+
+            let fun = self.lower_funproto(module, fun_def.span, proto, fun_def.file)?;
+            let generic = &self.ctx[declared[idx]];
+            let substitutions = generic
+                .generic
+                .as_ref()
+                .unwrap()
+                .params
+                .iter()
+                .map(|param| (*param, ty))
+                .collect::<HashMap<_, _>>();
+            let declared_ty = generic.ty.clone();
+            let expected = FunctionType {
+                return_ty: self.ctx.substitute(declared_ty.return_ty, &substitutions),
+                args: declared_ty
+                    .args
+                    .iter()
+                    .map(|arg| self.ctx.substitute(*arg, &substitutions))
+                    .collect(),
+            };
+            if self.ctx[fun].ty != expected {
+                let expected = self.ctx.new_type(TypeData::Function(expected));
+                let found = self.ctx.new_type(TypeData::Function(self.ctx[fun].ty.clone()));
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Function '{}' does not match its declaration in interface '{}'",
+                        proto.name, interface_name
+                    ))
+                    .with_labels(vec![Label::primary(fun_def.file, fun_def.span)])
+                    .with_notes(vec![
+                        format!("Expected type '{}'", self.ctx.get_type_name(expected)),
+                        format!("Found type '{}'", self.ctx.get_type_name(found)),
+                    ]));
+            }
+
+            self.ctx[fun].name =
+                Symbol::from(&format!("{}<{}>", proto.name, self.ctx.get_type_name(ty)));
+            self.lower_fun_body(module, fun, body, fun_def.file)?;
+            defined[idx] = Some(fun);
+        }
+
+        let funs = defined
+            .into_iter()
+            .zip(declared)
+            .map(|(fun, declared)| {
+                fun.ok_or_else(|| {
+                    Diagnostic::error()
+                        .with_message(format!(
+                            "Implementation of interface '{}' for type '{}' is missing function '{}'",
+                            interface_name,
+                            self.ctx.get_type_name(ty),
+                            self.ctx[declared].name
+                        ))
+                        .with_labels(vec![Label::primary(def.file, def.span)])
+                })
+            })
+            .collect::<CompilerRes<Vec<_>>>()?;
+        self.ctx[interface].impls.insert(
+            ty,
+            Implementation {
+                module,
+                file: def.file,
+                funs,
+            },
+        );
+        Ok(())
+    }
+
+    /// Get the names of all attributes that can be applied to a definition
+    fn allowed_attrs(data: &DefData) -> &'static [&'static str] {
+        match data {
+            DefData::GlobalDef { .. } => &[
+                "cfg",
+                "threadlocal",
+                "external",
+                "weak",
+                "linkonce_odr",
+                "hidden",
+                "protected",
+            ],
+            DefData::FunDef(..) | DefData::FunDec(..) => &[
+                "cfg",
+                "external",
+                "weak",
+                "linkonce_odr",
+                "hidden",
+                "protected",
+                "callconv",
+                "optnone",
+                "optsize",
+                "hot",
+                "cold",
+            ],
+            DefData::AliasDef { .. } => &["cfg", "repr"],
+            _ => &["cfg"],
+        }
+    }
+
+    /// Check that all definitions in a module and its children only have attributes that
+    /// can be applied to them
+    fn check_attrs(&self, parsed: &ParsedModule) -> CompilerRes<()> {
+        for def in parsed.defs.iter().map(|(_, v)| v) {
+            Self::check_def_attrs(def, Self::allowed_attrs(&def.data))?;
+            if let DefData::InterfaceDef { funs, .. } | DefData::ImplDef { funs, .. } = &def.data {
+                for fun in funs {
+                    Self::check_def_attrs(fun, &["cfg"])?;
+                }
+            }
+        }
+
+        for child in parsed.children.iter().map(|(_, c)| c) {
+            self.check_attrs(child)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that a definition only has attributes from the allowed list
+    fn check_def_attrs(def: &Def, allowed: &[&str]) -> CompilerRes<()> {
+        for attr in def.attrs.iter() {
+            if !allowed.contains(&attr.name.as_str()) {
+                return Err(Diagnostic::error()
+                    .with_message(format!("Unknown attribute '{}'", attr.name))
+                    .with_labels(vec![
+                        Label::primary(def.file, attr.span).with_message("Attribute used here"),
+                        Label::secondary(def.file, def.span)
+                            .with_message("Applied to this definition"),
+                    ])
+                    .with_notes(vec![format!(
+                        "Allowed attributes are: {}",
+                        allowed.join(", ")
+                    )]));
+            }
+            if attr.name.as_str() == "cfg" {
+                Self::check_cfg(def, attr)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that a `#[cfg(...)]` attribute only contains predicates that can be evaluated
+    fn check_cfg(def: &Def, attr: &Attribute) -> CompilerRes<()> {
+        let valid = attr.args.is_empty()
+            && !attr.named.is_empty()
+            && attr.named.iter().all(|(key, _)| key.as_str() == "target");
+        if valid {
+            Ok(())
+        } else {
+            Err(Diagnostic::error()
+                .with_message("Invalid arguments to cfg attribute")
+                .with_labels(vec![Label::primary(def.file, attr.span)])
+                .with_notes(vec![
+                    "Valid predicates are: #[cfg(target = \"<architecture or triple>\")]"
+                        .to_owned(),
+                ]))
+        }
+    }
+
+    /// Check that an external global declared with `ext` has a declared type and can't be
+    /// used as a constant, because its value is only known once the program is linked
+    fn check_extern_global(def: &Def, name: Symbol, typed: bool, constant: bool) -> CompilerRes<()> {
+        if constant {
+            return Err(Diagnostic::error()
+                .with_message(format!("External global '{}' cannot be declared const", name))
+                .with_labels(vec![Label::primary(def.file, def.span)])
+                .with_notes(vec![
+                    "Declare it with `let` or `mut` instead".to_owned(),
+                ]));
+        }
+        if !typed {
+            return Err(Diagnostic::error()
+                .with_message(format!("External global '{}' must declare its type", name))
+                .with_labels(vec![Label::primary(def.file, def.span)])
+                .with_notes(vec![format!("Declare its type like `let (i32) ext {}`", name)]));
+        }
+        Ok(())
+    }
+
+    /// Get the flags of a global variable definition from its attributes
+    fn global_flags(def: &Def) -> GlobalFlags {
+        let mut flags = GlobalFlags::empty();
+        if let DefData::GlobalDef { constant: true, .. } = def.data {
+            flags |= GlobalFlags::CONSTANT;
+        }
+        for attr in def.attrs.iter() {
+            if attr.name.as_str() == "threadlocal" {
+                flags |= GlobalFlags::THREAD_LOCAL;
+            }
+        }
+        flags
+    }
+
+    /// Apply a `#[repr(...)]` attribute to the structure type defined by a type definition,
+    /// returning the new structure type
+    fn lower_repr(&mut self, def: &Def, attr: &Attribute, ty: TypeId) -> CompilerRes<TypeId> {
+        let invalid = || {
+            Diagnostic::error()
+                .with_message("Invalid arguments to repr attribute")
+                .with_labels(vec![Label::primary(def.file, attr.span)])
+                .with_notes(vec![
+                    "Valid representations are: #[repr(C)], #[repr(align = \"N\")], #[repr(C, align = \"N\")]"
+                        .to_owned(),
+                ])
+        };
+
+        let repr = match attr.args.as_slice() {
+            [] if !attr.named.is_empty() => StructRepr::Default,
+            [repr] if repr.as_str() == "C" => StructRepr::C,
+            _ => return Err(invalid()),
+        };
+        let align = match attr.named.as_slice() {
+            [] => None,
+            [(key, value)] if key.as_str() == "align" => match value.as_str().parse::<u32>() {
+                Ok(align) if align.is_power_of_two() => Some(align),
+                _ => {
+                    return Err(Diagnostic::error()
+                        .with_message(format!("Invalid alignment '{}' in repr attribute", value))
+                        .with_labels(vec![Label::primary(def.file, attr.span)])
+                        .with_notes(vec!["Alignment must be a power of two".to_owned()]))
+                }
+            },
+            _ => return Err(invalid()),
+        };
+
+        match &self.ctx[ty] {
+            TypeData::Struct { fields, .. } => {
+                let fields = fields.clone();
+                Ok(self.ctx.new_type(TypeData::Struct { fields, repr, align }))
+            }
+            _ => Err(Diagnostic::error()
+                .with_message(format!(
+                    "Cannot apply repr attribute to non-structure type {}",
+                    self.ctx.get_type_name(ty)
+                ))
+                .with_labels(vec![
+                    Label::primary(def.file, attr.span).with_message("Attribute used here"),
+                    Label::secondary(def.file, def.span),
+                ])),
+        }
+    }
+
+    /// Get the linkage of a function or global definition from its attributes
+    fn def_linkage(def: &Def) -> CompilerRes<Option<DefLinkage>> {
+        let mut linkage = None;
+        for attr in def.attrs.iter() {
+            let attr_linkage = match attr.name.as_str() {
+                "external" => DefLinkage::External,
+                "weak" => DefLinkage::Weak,
+                "linkonce_odr" => DefLinkage::LinkOnceOdr,
+                _ => continue,
+            };
+
+            if linkage.is_some() {
+                return Err(Diagnostic::error()
+                    .with_message("Definition has more than one linkage attribute")
+                    .with_labels(vec![
+                        Label::primary(def.file, attr.span)
+                            .with_message("Second linkage attribute here"),
+                        Label::secondary(def.file, def.span),
+                    ]));
+            }
+            linkage = Some(attr_linkage);
+        }
+        Ok(linkage)
+    }
+
+    /// Get the calling convention of a function definition from its `#[callconv("name")]`
+    /// attribute
+    fn def_call_conv(def: &Def) -> CompilerRes<Option<CallConv>> {
+        let attr = match def.attrs.iter().find(|attr| attr.name.as_str() == "callconv") {
+            Some(attr) => attr,
+            None => return Ok(None),
+        };
+
+        let call_conv = match (attr.args.as_slice(), attr.named.is_empty()) {
+            ([name], true) => CallConv::NAMES
+                .iter()
+                .find(|(conv_name, _)| *conv_name == name.as_str())
+                .map(|(_, conv)| *conv),
+            _ => None,
+        };
+        match call_conv {
+            Some(call_conv) => Ok(Some(call_conv)),
+            None => Err(Diagnostic::error()
+                .with_message("Invalid arguments to callconv attribute")
+                .with_labels(vec![Label::primary(def.file, attr.span)])
+                .with_notes(vec![format!(
+                    "Valid calling conventions are: {}",
+                    CallConv::NAMES
+                        .iter()
+                        .map(|(name, _)| format!("\"{}\"", name))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )])),
+        }
+    }
+
+    /// Get the optimization of a function definition from its attributes
+    fn def_optimization(def: &Def) -> CompilerRes<Option<DefOptimization>> {
+        let mut optimization = None;
+        for attr in def.attrs.iter() {
+            let attr_optimization = match attr.name.as_str() {
+                "optnone" => DefOptimization::None,
+                "optsize" => DefOptimization::Size,
+                "hot" => DefOptimization::Hot,
+                "cold" => DefOptimization::Cold,
+                _ => continue,
+            };
+
+            if optimization.is_some() {
+                return Err(Diagnostic::error()
+                    .with_message("Function has more than one optimization attribute")
+                    .with_labels(vec![
+                        Label::primary(def.file, attr.span)
+                            .with_message("Second optimization attribute here"),
+                        Label::secondary(def.file, def.span),
+                    ]));
+            }
+            optimization = Some(attr_optimization);
+        }
+        Ok(optimization)
+    }
+
+    /// Get the visibility of a function or global definition from its attributes, `external`
+    /// is set if the definition is visible outside of the compiled object
+    fn def_visibility(def: &Def, external: bool) -> CompilerRes<Option<DefVisibility>> {
+        let mut visibility = None;
+        for attr in def.attrs.iter() {
+            let attr_visibility = match attr.name.as_str() {
+                "hidden" => DefVisibility::Hidden,
+                "protected" => DefVisibility::Protected,
+                _ => continue,
+            };
+
+            if visibility.is_some() {
+                return Err(Diagnostic::error()
+                    .with_message("Definition has more than one visibility attribute")
+                    .with_labels(vec![
+                        Label::primary(def.file, attr.span)
+                            .with_message("Second visibility attribute here"),
+                        Label::secondary(def.file, def.span),
+                    ]));
+            }
+            if !external {
+                return Err(Diagnostic::error()
+                    .with_message(format!(
+                        "Cannot set visibility of '{}' which is not externally visible",
+                        def.data.name()
+                    ))
+                    .with_labels(vec![Label::primary(def.file, attr.span)
+                        .with_message("Visibility attribute here")])
+                    .with_notes(vec![
+                        "Add a linkage attribute like #[external] to the definition".to_owned(),
+                    ]));
+            }
+            visibility = Some(attr_visibility);
+        }
+        Ok(visibility)
+    }
+
     /// Generate forward declarations for all type definitions
-    fn gen_forward_types(&mut self, parsed: &ParsedModule) -> ModId {
-        let module_id = self.ctx.new_module(parsed.name);
+    fn gen_forward_types(&mut self, parsed: &ParsedModule, parent: Option<ModId>) -> ModId {
+        let module_id = self.ctx.new_module(parsed.name, parent);
+        for (idx, def) in parsed.defs_in_order().into_iter().enumerate() {
+            self.ctx[module_id].order.insert(def.data.name(), idx);
+        }
 
         for def in parsed.defs.iter().map(|(_, v)| v) {
             match &def.data {
-                DefData::AliasDef { name, aliased: _ } => {
+                DefData::AliasDef { name, params, .. } => {
                     let ty = self.ctx.new_empty_type();
+                    if !params.is_empty() {
+                        let params = params
+                            .iter()
+                            .map(|param| self.ctx.new_type(TypeData::Generic(*param)))
+                            .collect();
+                        self.ctx.set_type_params(ty, params);
+                    }
                     self.ctx[module_id]
                         .defs
                         .define(name.clone(), SparkDef::TypeDef(def.file, ty));
@@ -129,7 +785,7 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         }
 
         for child in parsed.children.iter().map(|(_, c)| c) {
-            let child_id = self.gen_forward_types(child);
+            let child_id = self.gen_forward_types(child, Some(module_id));
             self.ctx[module_id]
                 .defs
                 .define(child.name.clone(), SparkDef::ModDef(child_id));
@@ -138,47 +794,131 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         module_id
     }
 
-    /// Resolve all imports of a module
-    fn gen_imports(&mut self, parsed: &ParsedModule, module_id: ModId) {
+    /// Mark all definitions declared with `pub` in a module and its children as public
+    fn gen_exports(&mut self, parsed: &ParsedModule, module_id: ModId) {
         for def in parsed.defs.iter().map(|(_, v)| v) {
-            if let DefData::ImportDef { name } = &def.data {
-                let imported = match self.ctx.get_def(module_id, name) {
-                    Ok(id) => id,
-                    Err(name) => {
-                        self.diags.emit(
-                            Diagnostic::error()
-                                .with_message(format!("Imported item '{}' not found", name))
-                                .with_labels(vec![Label::primary(def.file, def.span)]),
-                        );
-                        panic!()
+            if def.public {
+                self.ctx[module_id].public.insert(def.data.name());
+                if let DefData::InterfaceDef { funs, .. } = &def.data {
+                    for fun in funs {
+                        self.ctx[module_id].public.insert(fun.data.name());
                     }
-                };
-                self.ctx[module_id].defs.define(name.last(), imported);
+                }
             }
         }
 
         for child in parsed.children.iter().map(|(_, c)| c) {
-            let child_def = self.ctx[module_id].defs.get(&child.name).unwrap();
-            if let SparkDef::ModDef(child_id) = child_def {
+            if let Some(SparkDef::ModDef(child_id)) = self.ctx[module_id].defs.get(&child.name) {
                 let child_id = *child_id;
-                drop(child_def);
-                self.gen_imports(child, child_id);
+                self.gen_exports(child, child_id);
+            } else {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Collect all imports of a module and its children, along with the module that they are
+    /// imported into
+    fn collect_imports<'a>(
+        &self,
+        parsed: &'a ParsedModule,
+        module_id: ModId,
+        imports: &mut Vec<(ModId, &'a Def, &'a SymbolPath)>,
+    ) {
+        for def in parsed.defs.iter().map(|(_, v)| v) {
+            if let DefData::ImportDef { name } = &def.data {
+                imports.push((module_id, def, name));
+            }
+        }
+
+        for child in parsed.children.iter().map(|(_, c)| c) {
+            if let Some(SparkDef::ModDef(child_id)) = self.ctx[module_id].defs.get(&child.name) {
+                let child_id = *child_id;
+                self.collect_imports(child, child_id, imports);
             } else {
                 unreachable!()
             }
         }
     }
 
+    /// Resolve all imports of a module and its children, repeating until every import that
+    /// names another import has been resolved
+    fn gen_imports(&mut self, parsed: &ParsedModule, module_id: ModId) -> CompilerRes<()> {
+        let mut pending = vec![];
+        self.collect_imports(parsed, module_id, &mut pending);
+
+        loop {
+            let before = pending.len();
+            let mut unresolved = vec![];
+            for (module_id, def, name) in pending {
+                match self.ctx.get_def(module_id, name) {
+                    Ok(imported) => self.ctx[module_id].defs.define(name.last(), imported),
+                    Err(_) => unresolved.push((module_id, def, name)),
+                }
+            }
+            pending = unresolved;
+            if pending.is_empty() {
+                return Ok(());
+            }
+            if pending.len() == before {
+                break;
+            }
+        }
+
+        //Follow the chain of imports starting at the first unresolved import, if it leads
+        //back to an import that was already visited then the imports are circular
+        let mut chain: Vec<usize> = vec![0];
+        loop {
+            let (module_id, _, name) = pending[*chain.last().unwrap()];
+            let next = self.ctx.path_module(module_id, name).and_then(|source| {
+                pending
+                    .iter()
+                    .position(|(m, _, other)| *m == source && other.last() == name.last())
+            });
+            match next {
+                Some(next) if chain.contains(&next) => {
+                    let cycle = &chain[chain.iter().position(|i| *i == next).unwrap()..];
+                    let (_, _, name) = pending[cycle[0]];
+                    return Err(Diagnostic::error()
+                        .with_message(format!("Circular import of '{}'", name))
+                        .with_labels(
+                            cycle
+                                .iter()
+                                .enumerate()
+                                .map(|(idx, i)| {
+                                    let (_, import, path) = pending[*i];
+                                    let label = if idx == 0 {
+                                        Label::primary(import.file, import.span)
+                                    } else {
+                                        Label::secondary(import.file, import.span)
+                                    };
+                                    label.with_message(format!("'{}' imported here", path))
+                                })
+                                .collect(),
+                        )
+                        .with_notes(vec![format!(
+                            "'{}' must be defined in one of the modules it is imported from",
+                            name.last()
+                        )]));
+                }
+                Some(next) => chain.push(next),
+                None => {
+                    let (module_id, def, name) = pending[0];
+                    let err = self.ctx.get_def(module_id, name).unwrap_err();
+                    return Err(Diagnostic::error()
+                        .with_message(format!("Imported item {}", err))
+                        .with_labels(vec![Label::primary(def.file, def.span)
+                            .with_message(format!("'{}' imported here", name))]));
+                }
+            }
+        }
+    }
+
     /// Lower a parsed module's definitions and return an ID for the lowered
     /// module
     fn gen_forward_decls(&mut self, parsed: &ParsedModule) -> CompilerRes<ModId> {
-        let module_id = self.gen_forward_types(parsed);
-        for child in &parsed.children {
-            let child_id = self.gen_forward_types(&child.1);
-            self.ctx[module_id]
-                .defs
-                .define(child.0.clone(), SparkDef::ModDef(child_id));
-        }
+        self.check_attrs(parsed)?;
+        let module_id = self.gen_forward_types(parsed, None);
         self.gen_forward_funs(parsed, module_id)?;
         for child in &parsed.children {
             let child_id = *self.ctx[module_id].defs.get(child.0).unwrap();
@@ -186,18 +926,14 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                 self.gen_forward_funs(child.1, child_id)?;
             }
         }
-        self.gen_imports(parsed, module_id);
-        for child in &parsed.children {
-            let child_id = *self.ctx[module_id].defs.get(child.0).unwrap();
-            if let SparkDef::ModDef(child_id) = child_id {
-                self.gen_imports(child.1, child_id);
-            }
-        }
+        self.gen_exports(parsed, module_id);
+        self.gen_imports(parsed, module_id)?;
 
         Ok(module_id)
     }
 
-    /// Lower AST types to type IDs
+    /// Lower an expression parsed in the given module, resolving every type it contains to a
+    /// type ID. Type names must already have been forward declared with [lower_module](Self::lower_module)
     pub fn lower_ast(&mut self, module: ModId, ast: &Ast, file: FileId) -> CompilerRes<Ast<TypeId>> {
         Ok(Ast {
             span: ast.span,
@@ -228,6 +964,10 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                         },
                     mutable: *mutable,
                 },
+                AstNode::Destructure { pattern, mutable } => AstNode::Destructure {
+                    pattern: pattern.clone(),
+                    mutable: *mutable,
+                },
                 AstNode::Assignment { lhs, rhs } => AstNode::Assignment {
                     lhs: Box::new(self.lower_ast(module, lhs, file)?),
                     rhs: Box::new(self.lower_ast(module, rhs, file)?),
@@ -235,11 +975,18 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                 AstNode::Literal(l) => {
                     AstNode::Literal(self.lower_literal(module, ast.span, l, file)?)
                 } //AstNode:: => AstNode::UnitLiteral,
-                AstNode::Continue => AstNode::Continue,
-                AstNode::Break => AstNode::Break,
-                AstNode::BinExpr(lhs, op, rhs) => AstNode::BinExpr(
+                AstNode::Continue(label) => AstNode::Continue(*label),
+                AstNode::Break(label, value) => AstNode::Break(
+                    *label,
+                    match value {
+                        Some(value) => Some(Box::new(self.lower_ast(module, value, file)?)),
+                        None => None,
+                    },
+                ),
+                AstNode::BinExpr(lhs, op, op_span, rhs) => AstNode::BinExpr(
                     Box::new(self.lower_ast(module, lhs, file)?),
                     *op,
+                    *op_span,
                     Box::new(self.lower_ast(module, rhs, file)?),
                 ),
                 AstNode::UnaryExpr(op, rhs) => {
@@ -255,31 +1002,102 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                 AstNode::Return(expr) => {
                     AstNode::Return(Box::new(self.lower_ast(module, expr, file)?))
                 }
-                AstNode::Block(body) => AstNode::Block(
-                    body.iter()
-                        .map(|expr| self.lower_ast(module, expr, file))
-                        .collect::<CompilerRes<_>>()?,
-                ),
+                AstNode::Block(label, body) => {
+                    AstNode::Block(*label, self.lower_block(module, body, file)?)
+                }
                 AstNode::Match { matched, cases } => AstNode::Match {
                     matched: Box::new(self.lower_ast(module, matched, file)?),
                     cases: cases
                         .iter()
-                        .map(|(arm, case)| 
-                            self.lower_type(module, Some(ast.span), arm, file)
-                                .and_then(|ty| match self.lower_ast(module, case, file) {
-                                    Ok(arm) => Ok((ty, arm)),
-                                    Err(e) => Err(e)
-                                })
-                        )
+                        .map(|(arm, guard, case)| {
+                            let pattern = self.lower_match_pattern(module, ast.span, arm, file)?;
+                            let guard = match guard {
+                                Some(guard) => Some(self.lower_ast(module, guard, file)?),
+                                None => None,
+                            };
+                            Ok((pattern, guard, self.lower_ast(module, case, file)?))
+                        })
                         .collect::<CompilerRes<_>>()?,
                 },
                 AstNode::IfExpr(if_expr) => {
                     AstNode::IfExpr(self.lower_if_ast(module, if_expr, file)?)
                 }
+                AstNode::While { label, cond, body } => AstNode::While {
+                    label: *label,
+                    cond: Box::new(self.lower_ast(module, cond, file)?),
+                    body: self.lower_block(module, body, file)?,
+                },
+                AstNode::TypeDef(name, aliased) => {
+                    let aliased = self.lower_type(module, Some(ast.span), aliased, file)?;
+                    self.local_types.push((*name, aliased));
+                    AstNode::TypeDef(*name, aliased)
+                }
+                AstNode::FunDef(..) if !self.type_params.is_empty() => {
+                    return Err(Diagnostic::error()
+                        .with_message("Functions cannot be defined inside of generic functions")
+                        .with_labels(vec![Label::primary(file, ast.span)]));
+                }
+                AstNode::FunDef(proto, body) => {
+                    let fun = self.lower_funproto(module, ast.span, proto, file)?;
+                    self.type_params = self.generic_scope(fun);
+                    let lowered = self.lower_fun_body(module, fun, body, file);
+                    self.type_params.clear();
+                    lowered?;
+                    self.nested.push(fun);
+
+                    let fun = &self.ctx[fun];
+                    AstNode::FunDef(
+                        FunProto {
+                            name: fun.name,
+                            flags: fun.flags,
+                            args: proto
+                                .args
+                                .iter()
+                                .map(|(name, _)| *name)
+                                .zip(fun.ty.args.iter().copied())
+                                .collect(),
+                            return_ty: fun.ty.return_ty,
+                            type_params: proto.type_params.clone(),
+                        },
+                        vec![],
+                    )
+                }
             },
         })
     }
 
+    /// Lower the body of a function, collecting all functions nested inside of it
+    fn lower_fun_body(
+        &mut self,
+        module: ModId,
+        fun: FunId,
+        body: &[Ast],
+        file: FileId,
+    ) -> CompilerRes<()> {
+        let outer_nested = std::mem::take(&mut self.nested);
+        let body = self.lower_block(module, body, file)?;
+        self.ctx[fun].body = Some(body);
+        self.ctx[fun].nested = std::mem::replace(&mut self.nested, outer_nested);
+        Ok(())
+    }
+
+    /// Lower the statements of a block, removing the type aliases defined in it from scope
+    /// once the block ends
+    fn lower_block(
+        &mut self,
+        module: ModId,
+        body: &[Ast],
+        file: FileId,
+    ) -> CompilerRes<Vec<Ast<TypeId>>> {
+        let outer_types = self.local_types.len();
+        let body = body
+            .iter()
+            .map(|stmt| self.lower_ast(module, stmt, file))
+            .collect::<CompilerRes<_>>();
+        self.local_types.truncate(outer_types);
+        body
+    }
+
     /// Lower a literal AST
     fn lower_literal(
         &mut self,
@@ -295,6 +1113,12 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                     .map(|elem| self.lower_ast(module, elem, file))
                     .collect::<CompilerRes<_>>()?,
             ),
+            Literal::Tuple(elems) => Literal::Tuple(
+                elems
+                    .iter()
+                    .map(|elem| self.lower_ast(module, elem, file))
+                    .collect::<CompilerRes<_>>()?,
+            ),
             Literal::String(s) => Literal::String(s.clone()),
             Literal::Number(num) => Literal::Number(num.clone()),
             Literal::Bool(b) => Literal::Bool(*b),
@@ -319,6 +1143,25 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         })
     }
 
+    /// Lower the types contained in a match arm's pattern
+    fn lower_match_pattern(
+        &mut self,
+        module: ModId,
+        span: Span,
+        pattern: &MatchPattern<UnresolvedType>,
+        file: FileId,
+    ) -> CompilerRes<MatchPattern<TypeId>> {
+        Ok(match pattern {
+            MatchPattern::Type(ty, pattern) => MatchPattern::Type(
+                self.lower_type(module, Some(span), ty, file)?,
+                pattern.clone(),
+            ),
+            MatchPattern::Integer(num) => MatchPattern::Integer(*num),
+            MatchPattern::Range(start, end) => MatchPattern::Range(*start, *end),
+            MatchPattern::Default => MatchPattern::Default,
+        })
+    }
+
     /// Lower a parsed if expression's types to TypeIds
     fn lower_if_ast(
         &mut self,
@@ -327,20 +1170,14 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         file: FileId,
     ) -> CompilerRes<IfExpr<TypeId>> {
         let cond = Box::new(self.lower_ast(module, &if_expr.cond, file)?);
-        let body = if_expr
-            .body
-            .iter()
-            .map(|expr| self.lower_ast(module, expr, file))
-            .collect::<CompilerRes<_>>()?;
+        let body = self.lower_block(module, &if_expr.body, file)?;
         let else_expr = match &if_expr.else_expr {
             Some(ElseExpr::ElseIf(else_if_expr)) => Some(ElseExpr::ElseIf(Box::new(
                 self.lower_if_ast(module, else_if_expr, file)?,
             ))),
-            Some(ElseExpr::Else(body)) => Some(ElseExpr::Else(
-                body.iter()
-                    .map(|expr| self.lower_ast(module, expr, file))
-                    .collect::<CompilerRes<_>>()?,
-            )),
+            Some(ElseExpr::Else(body)) => {
+                Some(ElseExpr::Else(self.lower_block(module, body, file)?))
+            }
             None => None,
         };
         
@@ -359,18 +1196,24 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
         proto: &FunProto<UnresolvedType>,
         file: FileId,
     ) -> CompilerRes<FunId> {
-        let fun_ty = FunctionType {
-            return_ty: self.lower_type(module, Some(span), &proto.return_ty, file)?,
-            args: proto
-                .args
-                .iter()
-                .map(|(_, ty)| self.lower_type(module, Some(span), ty, file))
-                .collect::<CompilerRes<_>>()?,
-        };
+        if !proto.type_params.is_empty() && proto.flags.contains(FunFlags::EXTERN) {
+            return Err(Diagnostic::error()
+                .with_message(format!("External function '{}' cannot be generic", proto.name))
+                .with_labels(vec![Label::primary(file, span)]));
+        }
 
-        Ok(self.ctx.new_fun(
+        let type_params = proto
+            .type_params
+            .iter()
+            .map(|name| (*name, self.ctx.new_type(TypeData::Generic(*name))))
+            .collect::<Vec<_>>();
+        let outer_params = std::mem::replace(&mut self.type_params, type_params.clone());
+        let fun_ty = self.lower_fun_ty(module, span, proto, file);
+        self.type_params = outer_params;
+
+        let fun = self.ctx.new_fun(
             proto.name,
-            fun_ty,
+            fun_ty?,
             proto.flags,
             proto
                 .args
@@ -378,12 +1221,171 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                 .map(|(name, _)| Some(name.clone()))
                 .collect(),
             span,
-        ))
+        );
+        if !type_params.is_empty() {
+            self.ctx[fun].generic = Some(GenericParams {
+                params: type_params.into_iter().map(|(_, ty)| ty).collect(),
+                module,
+                interface: None,
+            });
+        }
+        Ok(fun)
+    }
+
+    /// Lower the argument and return types of a function prototype
+    fn lower_fun_ty(
+        &mut self,
+        module: ModId,
+        span: Span,
+        proto: &FunProto<UnresolvedType>,
+        file: FileId,
+    ) -> CompilerRes<FunctionType> {
+        Ok(FunctionType {
+            return_ty: self.lower_type(module, Some(span), &proto.return_ty, file)?,
+            args: proto
+                .args
+                .iter()
+                .map(|(_, ty)| self.lower_type(module, Some(span), ty, file))
+                .collect::<CompilerRes<_>>()?,
+        })
+    }
+
+    /// Get the names and types of a type definition's type parameters, empty if the type is
+    /// not generic
+    fn generic_type_scope(&self, ty: TypeId) -> Vec<(Symbol, TypeId)> {
+        self.ctx
+            .type_params(ty)
+            .unwrap_or(&[])
+            .iter()
+            .map(|param| match self.ctx[*param] {
+                TypeData::Generic(name) => (name, *param),
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    /// Get the names and types of a function's type parameters, empty if the function is not
+    /// generic
+    fn generic_scope(&self, fun: FunId) -> Vec<(Symbol, TypeId)> {
+        match &self.ctx[fun].generic {
+            Some(generic) => generic
+                .params
+                .iter()
+                .map(|param| match self.ctx[*param] {
+                    TypeData::Generic(name) => (name, *param),
+                    _ => unreachable!(),
+                })
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Evaluate an integer expression that must be known at compile time, such as an array
+    /// length. `evaluating` holds all constants whose initializers are currently being evaluated
+    /// to detect constants that depend on themselves
+    fn eval_const_int(
+        &self,
+        module: ModId,
+        ast: &Ast<TypeId>,
+        file: FileId,
+        evaluating: &mut Vec<GlobalId>,
+    ) -> CompilerRes<u64> {
+        let not_const = || {
+            Diagnostic::error()
+                .with_message("Expression cannot be evaluated at compile time")
+                .with_labels(vec![Label::primary(file, ast.span)
+                    .with_message("Constant integer expression expected here")])
+        };
+
+        match &ast.node {
+            AstNode::Literal(Literal::Number(NumberLiteral::Integer(num, _))) if !num.sign => {
+                Ok(num.val)
+            }
+            AstNode::BinExpr(lhs, op, _, rhs) => {
+                let lhs = self.eval_const_int(module, lhs, file, evaluating)?;
+                let rhs = self.eval_const_int(module, rhs, file, evaluating)?;
+                match op {
+                    Op::Add => lhs.checked_add(rhs),
+                    Op::Sub => lhs.checked_sub(rhs),
+                    Op::Star => lhs.checked_mul(rhs),
+                    Op::Div => lhs.checked_div(rhs),
+                    Op::Mod => lhs.checked_rem(rhs),
+                    Op::ShLeft => lhs.checked_shl(rhs as u32),
+                    Op::ShRight => lhs.checked_shr(rhs as u32),
+                    _ => return Err(not_const()),
+                }
+                .ok_or_else(|| {
+                    Diagnostic::error()
+                        .with_message("Overflow in constant expression")
+                        .with_labels(vec![Label::primary(file, ast.span)])
+                })
+            }
+            AstNode::Access(path) => {
+                let (global_file, global) = match self.ctx.get_def(module, path) {
+                    Ok(SparkDef::GlobalDef(global_file, global)) => (global_file, global),
+                    Ok(_) => return Err(not_const()),
+                    Err(e) => {
+                        return Err(Diagnostic::error()
+                            .with_message(format!("Constant {}", e))
+                            .with_labels(vec![Label::primary(file, ast.span)]))
+                    }
+                };
+                let global_def = &self.ctx[global];
+                if !global_def.flags.contains(GlobalFlags::CONSTANT) {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Global variable '{}' is not a constant",
+                            global_def.name
+                        ))
+                        .with_labels(vec![Label::primary(file, ast.span)
+                            .with_message("Constant integer expression expected here")])
+                        .with_notes(vec![format!(
+                            "Declare '{}' with `const` to use it in constant expressions",
+                            global_def.name
+                        )]));
+                }
+                if evaluating.contains(&global) {
+                    return Err(Diagnostic::error()
+                        .with_message(format!(
+                            "Constant '{}' depends on its own value",
+                            global_def.name
+                        ))
+                        .with_labels(vec![Label::primary(file, ast.span)]));
+                }
+
+                let source = self.ctx.path_module(module, path).unwrap_or(module);
+                evaluating.push(global);
+                //Constants always have an initializer because external globals can't be const
+                let init = global_def.init.as_ref().unwrap();
+                let val = self.eval_const_int(source, init, global_file, evaluating)?;
+                evaluating.pop();
+                Ok(val)
+            }
+            _ => Err(not_const()),
+        }
+    }
+
+    /// Find the name of a type that is spelled similarly to an unresolved type path, searching
+    /// the module that the last part of the path would be defined in
+    fn similar_type_name(&self, module: ModId, path: &SymbolPath) -> Option<Symbol> {
+        const BUILTIN_TYPES: &[&str] = &[
+            "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64", "bool",
+        ];
+
+        let source = self.ctx.path_module(module, path)?;
+        let defs = &self.ctx[source].defs;
+        let user_types = defs
+            .iter()
+            .filter(|(_, def)| matches!(def, SparkDef::TypeDef(..)))
+            .map(|(name, _)| name.as_str());
+        let builtin_types = BUILTIN_TYPES.iter().copied().filter(|_| path.len() == 1);
+
+        suggest::closest(path.last().as_str(), user_types.chain(builtin_types)).map(Symbol::from)
     }
 
     /// Lower a type either by resolving the path to the type or
     /// converting an integral type
-    fn lower_type(
+    pub fn lower_type(
         &mut self,
         module: ModId,
         span: Option<Span>,
@@ -399,7 +1401,28 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
                         Err(e) => Err(e)
                     })
                     .collect::<CompilerRes<_>>()?;
-                self.ctx.new_type(TypeData::Struct { fields })
+                self.ctx.new_type(TypeData::Struct {
+                    fields,
+                    repr: StructRepr::Default,
+                    align: None,
+                })
+            }
+            UnresolvedType::Union { fields } => {
+                let fields = fields
+                    .iter()
+                    .map(|(ty, name)| match self.lower_type(module, span, ty, file) {
+                        Ok(ty) => Ok((ty, name.clone())),
+                        Err(e) => Err(e)
+                    })
+                    .collect::<CompilerRes<_>>()?;
+                self.ctx.new_type(TypeData::Union { fields })
+            }
+            UnresolvedType::Tuple { elements } => {
+                let elements = elements
+                    .iter()
+                    .map(|ty| self.lower_type(module, span, ty, file))
+                    .collect::<CompilerRes<_>>()?;
+                self.ctx.new_type(TypeData::Tuple { elements })
             }
             UnresolvedType::Enum { variants } => {
                 let parts = variants
@@ -444,28 +1467,124 @@ impl<'ctx, 'files> Lowerer<'ctx, 'files> {
             }
             UnresolvedType::Array { elements, len } => {
                 let element = self.lower_type(module, span, elements, file)?;
-                self.ctx.new_type(TypeData::Array { element, len: *len })
+                let len = match len {
+                    ArrayLen::Literal(len) => *len,
+                    ArrayLen::Const(name) => {
+                        let access = Ast {
+                            node: AstNode::Access(name.clone()),
+                            span: span.unwrap_or(Span::single(0)),
+                        };
+                        self.eval_const_int(module, &access, file, &mut vec![])?
+                    }
+                };
+                self.ctx.new_type(TypeData::Array { element, len })
             }
-            UnresolvedType::UserDefined { name } => match self.ctx.get_def(module, name) {
-                Ok(SparkDef::TypeDef(_, type_id)) => type_id,
-                Ok(..) => {
-                    self.diags.emit({
+            UnresolvedType::Vector { element, len } => {
+                let element = self.lower_type(module, span, element, file)?;
+                match self.ctx[self.ctx.unwrap_alias(element)] {
+                    TypeData::Integer { .. } | TypeData::Float { .. }
+                        if *len > 0 && *len <= u32::MAX as u64 => {}
+                    _ => {
                         let diag = Diagnostic::error()
-                            .with_message(format!("definition '{}' found but is not a type", name));
-                        if let Some(span) = span {
-                            diag.with_labels(vec![Label::primary(file, span)])
-                        } else {
-                            diag
-                        }
-                    });
-                    std::process::exit(-1);
+                            .with_message(format!(
+                                "Invalid vector type <{} x {}>",
+                                len,
+                                self.ctx.get_type_name(element)
+                            ))
+                            .with_notes(vec![format!(
+                                "Vectors must have between 1 and {} integer or float elements",
+                                u32::MAX
+                            )]);
+                        return Err(match span {
+                            Some(span) => diag.with_labels(vec![Label::primary(file, span)]),
+                            None => diag,
+                        });
+                    }
                 }
-                Err(_) => {
-                    let diag =
-                        Diagnostic::error().with_message(format!(
-                            "type '{}' not found",
+                self.ctx.new_type(TypeData::Vector {
+                    element,
+                    len: *len as u32,
+                })
+            }
+            UnresolvedType::UserDefined { name, args } if name.len() == 1 && args.is_empty() && self
+                .local_types
+                .iter()
+                .any(|(alias, _)| *alias == name.last()) =>
+            {
+                self.local_types
+                    .iter()
+                    .rev()
+                    .find(|(alias, _)| *alias == name.last())
+                    .unwrap()
+                    .1
+            }
+            UnresolvedType::UserDefined { name, args } if name.len() == 1 && args.is_empty() && self
+                .type_params
+                .iter()
+                .any(|(param, _)| *param == name.last()) =>
+            {
+                self.type_params
+                    .iter()
+                    .find(|(param, _)| *param == name.last())
+                    .unwrap()
+                    .1
+            }
+            UnresolvedType::UserDefined { name, args } => match self.ctx.get_def(module, name) {
+                Ok(SparkDef::TypeDef(_, type_id)) => {
+                    let expected = self.ctx.type_params(type_id).map_or(0, |params| params.len());
+                    if expected != args.len() {
+                        let diag = Diagnostic::error().with_message(format!(
+                            "Type '{}' expects {} type arguments, found {}",
                             name,
+                            expected,
+                            args.len()
                         ));
+                        return Err(match span {
+                            Some(span) => diag.with_labels(vec![Label::primary(file, span)]),
+                            None => diag,
+                        });
+                    }
+                    if args.is_empty() {
+                        type_id
+                    } else {
+                        let args = args
+                            .iter()
+                            .map(|arg| self.lower_type(module, span, arg, file))
+                            .collect::<CompilerRes<Vec<_>>>()?;
+                        self.ctx.instantiate_type(type_id, args)
+                    }
+                }
+                Ok(def) => {
+                    let diag = Diagnostic::error()
+                        .with_message(format!("definition '{}' found but is not a type", name))
+                        .with_notes(vec![format!(
+                            "'{}' is {}",
+                            name,
+                            match def {
+                                SparkDef::FunDef(..) => "a function",
+                                SparkDef::GlobalDef(..) => "a global variable",
+                                SparkDef::ModDef(..) => "a module",
+                                SparkDef::InterfaceDef(..) => "an interface",
+                                SparkDef::TypeDef(..) => unreachable!(),
+                            }
+                        )]);
+                    return Err(match span {
+                        Some(span) => diag.with_labels(vec![Label::primary(file, span)]),
+                        None => diag,
+                    });
+                }
+                Err(e) => {
+                    let diag =
+                        Diagnostic::error().with_message(match e {
+                            DefLookupError::NotFound(_) => format!("type '{}' not found", name),
+                            DefLookupError::Private(..) => format!("type {}", e),
+                        });
+                    let diag = match (e, self.similar_type_name(module, name)) {
+                        (DefLookupError::NotFound(_), Some(similar)) => {
+                            diag.with_notes(vec![format!("Did you mean '{}'?", similar)])
+                        }
+                        _ => diag,
+                    };
                     if let Some(span) = span {
                         return Err(diag.with_labels(vec![Label::primary(file, span)]))
                     } else {
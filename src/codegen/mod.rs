@@ -2,6 +2,8 @@ use codespan_reporting::diagnostic::Diagnostic;
 
 use crate::util::files::FileId;
 
+pub mod body;
+pub mod c;
 pub mod ir;
 pub mod llvm;
 pub mod lower;
@@ -1,8 +1,10 @@
 //! Module defining error structures and error handlers for displaying
 //! error / warn messages as they occur
 
+use std::ops::Range;
+
 use codespan_reporting::{
-    diagnostic::Diagnostic,
+    diagnostic::{Diagnostic, LabelStyle},
     term::{
         termcolor::{ColorChoice, StandardStream},
         Chars, DisplayStyle, Styles,
@@ -16,17 +18,32 @@ use crate::util::files::{FileId, Files};
 pub struct DiagnosticManager<'files> {
     /// A collection of compiled files
     files: &'files Files,
+    /// Every diagnostic emitted so far, in the order they were emitted
+    emitted: Vec<Diagnostic<FileId>>,
 }
 
 impl<'files> DiagnosticManager<'files> {
     /// Create a new diagnostic manager using a reference to all
     /// currently compiled files
     pub fn new(files: &'files Files) -> Self {
-        Self { files }
+        Self {
+            files,
+            emitted: Vec::new(),
+        }
     }
 
-    /// Emit a diagnostic to the console
+    /// Emit a diagnostic to the console, skipping it if a diagnostic with the same message
+    /// and primary label location was already emitted
     pub fn emit(&mut self, diag: Diagnostic<FileId>) {
+        let primary = Self::primary_location(&diag);
+        if self
+            .emitted
+            .iter()
+            .any(|emitted| emitted.message == diag.message && Self::primary_location(emitted) == primary)
+        {
+            return;
+        }
+
         codespan_reporting::term::emit(
             &mut StandardStream::stderr(ColorChoice::Auto),
             &codespan_reporting::term::Config {
@@ -40,5 +57,58 @@ impl<'files> DiagnosticManager<'files> {
             &diag,
         )
         .expect("Failed to write compiler output to stderr");
+        self.emitted.push(diag);
+    }
+
+    /// Get every diagnostic emitted so far, in the order they were emitted
+    pub fn emitted(&self) -> &[Diagnostic<FileId>] {
+        &self.emitted
+    }
+
+    /// Get the file and byte range of a diagnostic's primary label, if it has one
+    fn primary_location(diag: &Diagnostic<FileId>) -> Option<(FileId, Range<usize>)> {
+        diag.labels
+            .iter()
+            .find(|label| label.style == LabelStyle::Primary)
+            .map(|label| (label.file_id, label.range.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use codespan_reporting::diagnostic::Label;
+
+    use super::*;
+    use crate::util::files::CompiledFile;
+
+    #[test]
+    fn repeated_diagnostics_are_reported_once() {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory("let x = y".to_owned()));
+        let mut diags = DiagnosticManager::new(&files);
+        let unknown = |range: Range<usize>| {
+            Diagnostic::error()
+                .with_message("Unknown variable")
+                .with_labels(vec![Label::primary(file, range)])
+        };
+
+        diags.emit(unknown(8..9));
+        diags.emit(unknown(8..9));
+        assert_eq!(diags.emitted.len(), 1);
+
+        //The same message at another location is a different diagnostic
+        diags.emit(unknown(4..5));
+        assert_eq!(diags.emitted.len(), 2);
+
+        diags.emit(Diagnostic::error().with_message("Unknown variable"));
+        assert_eq!(diags.emitted.len(), 3);
+
+        //Diagnostics are kept in the order they were first emitted
+        let ranges = diags
+            .emitted()
+            .iter()
+            .map(|diag| diag.labels.first().map(|label| label.range.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [Some(8..9), Some(4..5), None]);
     }
 }
@@ -17,6 +17,8 @@ pub enum OutputFileType {
     Assembly,
     Object,
     LLVMIR,
+    /// C source code, generated without LLVM
+    C,
 }
 
 /// Enumeration representing all supported optimization profiles for the
@@ -29,6 +31,18 @@ pub enum OutputOptimizationLevel {
     Debug = 0,
 }
 
+/// Enumeration of the ways that generated code can stop the program when a
+/// runtime check fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapBehavior {
+    /// Call the C standard library's `abort` function
+    Abort,
+    /// Use the `llvm.trap` intrinsic, which does not depend on libc
+    Trap,
+    /// Mark the failed check as unreachable, assuming that it never happens
+    Unreachable,
+}
+
 /// Structure with all configurable properties of code generation
 #[derive(Clone, Debug)]
 pub struct CompileOpts {
@@ -42,4 +56,17 @@ pub struct CompileOpts {
     pub pic: bool,
     /// If symbols should be stripped from the output
     pub stripped: bool,
+    /// What generated code does when a runtime check fails
+    pub trap: TrapBehavior,
+    /// Target triple to generate code for, or `None` to compile for the host
+    pub target: Option<String>,
+    /// Implicitly widen the narrower operand of a binary expression with integers of
+    /// different widths instead of reporting an error
+    pub int_promotion: bool,
+    /// Allow machine code generation to reassociate and contract floating point operations,
+    /// assuming that no NaN, infinite, or signed zero values are produced. IR optimizations
+    /// are unaffected
+    pub fast_math: bool,
+    /// Warn when a location is assigned to itself, as in `x = x`
+    pub warn_self_assign: bool,
 }
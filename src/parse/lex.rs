@@ -1,8 +1,11 @@
-use std::{iter::Peekable, str::CharIndices};
+use std::{iter::Peekable, ops::Range, str::CharIndices};
 
-use crate::util::loc::Span;
+use crate::util::{
+    files::{FileId, Files},
+    loc::Span,
+};
 
-use super::token::{BracketType, Op, Token, TokenData};
+use super::token::{BracketType, Op, Token, TokenData, TokenType};
 
 /// Lexer responsible for tokenizing an input string to be parsed
 #[derive(Debug, Clone)]
@@ -15,20 +18,63 @@ pub struct Lexer<'src> {
     current: Option<Token<'src>>,
     /// The token after `current`, returned with `peek2`
     peek2: Option<Token<'src>>,
+    /// How many curly brackets that have been consumed are not yet closed
+    depth: usize,
 }
 
 impl<'src> Lexer<'src> {
     /// Create a new `Lexer` to tokenize the given source string
     pub fn new(src: &'src str) -> Self {
-        let mut this = Self {
+        let mut this = Self::unbuffered(src);
+        this.current = this.token();
+        this.peek2 = this.token();
+        this
+    }
+
+    /// Create a new `Lexer` that only tokenizes the given byte range of the source string,
+    /// producing tokens with spans that are positions in the whole string. The range must be
+    /// inside of the string and start and end on character boundaries
+    pub fn in_range(src: &'src str, range: Range<usize>) -> Self {
+        let mut this = Self::unbuffered(&src[..range.end]);
+        while this.offset() < range.start {
+            this.next_char();
+        }
+        this.current = this.token();
+        this.peek2 = this.token();
+        this
+    }
+
+    /// Create a `Lexer` that has not lexed any tokens ahead, for use by [SpannedLexer]
+    fn unbuffered(src: &'src str) -> Self {
+        Self {
             chars: src.char_indices().peekable(),
             src,
             current: None,
             peek2: None,
-        };
-        this.current = this.token();
-        this.peek2 = this.token();
-        this
+            depth: 0,
+        }
+    }
+
+    /// Get the byte position of the next character that has not been lexed
+    fn offset(&mut self) -> usize {
+        match self.chars.peek() {
+            Some((pos, _)) => *pos,
+            None => self.src.len(),
+        }
+    }
+
+    /// Get a zero-length span pointing to the end of the source string, used to
+    /// label errors that occur when the input ends unexpectedly
+    pub fn eof_span(&self) -> Span {
+        Span::single(self.src.len())
+    }
+
+    /// Check if the source text between two byte positions contains a newline
+    pub fn newline_between(&self, from: usize, to: usize) -> bool {
+        self.src
+            .get(from..to)
+            .map(|text| text.contains('\n'))
+            .unwrap_or(false)
     }
 
     /// Consume one character from the character iterator if one exists,
@@ -59,7 +105,6 @@ impl<'src> Lexer<'src> {
             '*' => Token::new(start_loc, TokenData::Op(Op::Star)),
             '/' => Token::new(start_loc, TokenData::Op(Op::Div)),
             '%' => Token::new(start_loc, TokenData::Op(Op::Mod)),
-            '!' => Token::new(start_loc, TokenData::Op(Op::LogicalNot)),
             '~' => Token::new(start_loc, TokenData::Op(Op::NOT)),
             '^' => Token::new(start_loc, TokenData::Op(Op::XOR)),
             '$' => Token::new(start_loc, TokenData::Dollar),
@@ -70,7 +115,7 @@ impl<'src> Lexer<'src> {
             '#' => Token::new(start_loc, TokenData::Pound),
 
             // Multi or single character tokens
-            '&' | '|' | '>' | '<' | '-' | '=' => {
+            '&' | '|' | '>' | '<' | '-' | '=' | '!' => {
                 let peek = self.chars.peek().map(|(_, peek)| *peek);
                 match (next, peek) {
                     ('>', Some('=')) => {
@@ -99,6 +144,10 @@ impl<'src> Lexer<'src> {
                         self.next_char();
                         Token::new(startpos..startpos + 1, TokenData::Op(Op::Eq))
                     }
+                    ('!', Some('=')) => {
+                        self.next_char();
+                        Token::new(startpos..startpos + 1, TokenData::Op(Op::NotEq))
+                    }
 
                     ('<', Some('<')) => {
                         self.next_char();
@@ -115,6 +164,7 @@ impl<'src> Lexer<'src> {
                     ('>', _) => Token::new(start_loc, TokenData::Op(Op::Greater)),
                     ('-', _) => Token::new(start_loc, TokenData::Op(Op::Sub)),
                     ('=', _) => Token::new(start_loc, TokenData::Assign),
+                    ('!', _) => Token::new(start_loc, TokenData::Op(Op::LogicalNot)),
 
                     (next, peek) => unreachable!(
                         "Not possible, checked all options of next, next is {}, peek is {:?}",
@@ -142,9 +192,26 @@ impl<'src> Lexer<'src> {
                 }),
             ),
 
-            //Character literal
+            //Character literal or loop label
             '\'' => {
                 let (firstpos, first) = self.next_char()?;
+                if (first.is_ascii_alphabetic() || first == '_')
+                    && !matches!(self.chars.peek(), Some((_, '\'')))
+                {
+                    let mut endpos = firstpos;
+                    while let Some((peeked_pos, peeked)) = self.chars.peek() {
+                        if !(peeked.is_ascii_alphanumeric() || *peeked == '_') {
+                            break;
+                        }
+                        endpos = *peeked_pos;
+                        self.next_char();
+                    }
+
+                    return Some(Token::new(
+                        startpos..endpos,
+                        TokenData::Label(&self.src[firstpos..=endpos]),
+                    ));
+                }
                 if first == '\\' {
                     self.next_char()?; //Consume the escaped character
                 }
@@ -205,6 +272,11 @@ impl<'src> Lexer<'src> {
 
                 loop {
                     match self.chars.peek() {
+                        //Two periods after a number begin a range instead of a decimal point
+                        Some((pos, '.')) if self.src[pos + 1..].starts_with('.') => {
+                            endpos = *pos;
+                            break;
+                        }
                         Some((_, digit)) if digit.is_digit(radix) || *digit == '.' => {
                             self.next_char();
                         }
@@ -260,10 +332,27 @@ impl<'src> Lexer<'src> {
         self.current.as_ref()
     }
 
+    /// Get how many curly brackets are open before the next token
+    pub const fn depth(&self) -> usize {
+        self.depth
+    }
+
     /// Peek the token two spots ahead
     pub fn peek2(&self) -> Option<&Token<'src>> {
         self.peek2.as_ref()
     }
+
+    /// Consume the first `>` of a peeked `>>` token, leaving the second `>` as the current
+    /// token. Used to close nested type argument lists like `box<box<i32>>`
+    pub fn split_shift_right(&mut self) {
+        if let Some(Token {
+            span,
+            data: TokenData::Op(Op::ShRight),
+        }) = self.current
+        {
+            self.current = Some(Token::new(span.to..span.to, TokenData::Op(Op::Greater)));
+        }
+    }
 }
 
 impl<'src> Iterator for Lexer<'src> {
@@ -282,6 +371,151 @@ impl<'src> Iterator for Lexer<'src> {
             self.current.take()?
         };
 
+        match next.data {
+            TokenData::OpenBracket(BracketType::Curly) => self.depth += 1,
+            TokenData::CloseBracket(BracketType::Curly) => {
+                self.depth = self.depth.saturating_sub(1)
+            }
+            _ => (),
+        }
+
         Some(next)
     }
 }
+
+/// Lexer for editor tooling that yields the [TokenType] and span of every token in a file,
+/// independently of the parser. Spans end one byte past the last character of their token,
+/// and whitespace between tokens can be kept so that the spans cover the whole file
+#[derive(Debug, Clone)]
+pub struct SpannedLexer<'src> {
+    file: FileId,
+    lexer: Lexer<'src>,
+    /// If runs of whitespace are yielded as [Whitespace](TokenType::Whitespace) tokens
+    whitespace: bool,
+    /// A token lexed after the whitespace that was yielded before it
+    pending: Option<(TokenType, Span)>,
+    done: bool,
+}
+
+impl<'src> SpannedLexer<'src> {
+    /// Create a new `SpannedLexer` over the text of a file, yielding whitespace between
+    /// tokens if `whitespace` is set
+    pub fn new(files: &'src Files, file: FileId, whitespace: bool) -> Self {
+        Self {
+            file,
+            lexer: Lexer::unbuffered(&files.get(file).text),
+            whitespace,
+            pending: None,
+            done: false,
+        }
+    }
+
+    /// Get the ID of the file being lexed
+    pub fn file(&self) -> FileId {
+        self.file
+    }
+
+    /// Get the type of the source text skipped between two tokens, or `None` if it is
+    /// whitespace that should not be yielded
+    fn skipped(&self, from: usize, to: usize) -> Option<(TokenType, Span)> {
+        let skipped = &self.lexer.src[from..to];
+        if skipped.is_empty() {
+            None
+        } else if skipped.chars().all(char::is_whitespace) {
+            self.whitespace
+                .then(|| (TokenType::Whitespace, Span::new(from, to)))
+        } else {
+            Some((TokenType::Invalid, Span::new(from, to)))
+        }
+    }
+}
+
+impl Iterator for SpannedLexer<'_> {
+    type Item = (TokenType, Span);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(pending) = self.pending.take() {
+            return Some(pending);
+        }
+
+        while !self.done {
+            let start = self.lexer.offset();
+            let (from, token) = match self.lexer.token() {
+                Some(token) => {
+                    let end = self.lexer.offset();
+                    (
+                        token.span.from,
+                        Some((TokenType::from(&token.data), Span::new(token.span.from, end))),
+                    )
+                }
+                //Either the end of the file or an unterminated literal that consumed the rest
+                //of it
+                None => {
+                    self.done = true;
+                    (self.lexer.src.len(), None)
+                }
+            };
+
+            match self.skipped(start, from) {
+                Some(skipped) => {
+                    self.pending = token;
+                    return Some(skipped);
+                }
+                None if token.is_some() => return token,
+                None => (),
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::files::CompiledFile;
+
+    /// Lex a source string with a [SpannedLexer], returning each token's type and the
+    /// `(from, to)` byte positions of its span
+    fn lex_spanned(src: &str, whitespace: bool) -> Vec<(TokenType, (usize, usize))> {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        SpannedLexer::new(&files, file, whitespace)
+            .map(|(ty, span)| (ty, (span.from, span.to)))
+            .collect()
+    }
+
+    #[test]
+    fn spanned_tokens_have_kinds_and_spans() {
+        assert_eq!(
+            lex_spanned("let x = f.(10)", false),
+            vec![
+                (TokenType::Keyword, (0, 3)),
+                (TokenType::Ident, (4, 5)),
+                (TokenType::Operator, (6, 7)),
+                (TokenType::Ident, (8, 9)),
+                (TokenType::Punctuation, (9, 10)),
+                (TokenType::Bracket, (10, 11)),
+                (TokenType::Number, (11, 13)),
+                (TokenType::Bracket, (13, 14)),
+            ]
+        );
+    }
+
+    #[test]
+    fn whitespace_is_kept_when_requested() {
+        let src = "return  x\n";
+        let tokens = lex_spanned(src, true);
+        assert_eq!(
+            tokens,
+            vec![
+                (TokenType::Keyword, (0, 6)),
+                (TokenType::Whitespace, (6, 8)),
+                (TokenType::Ident, (8, 9)),
+                (TokenType::Whitespace, (9, 10)),
+            ]
+        );
+        //The spans of all tokens cover the whole source string
+        assert_eq!(tokens.last().unwrap().1 .1, src.len());
+    }
+}
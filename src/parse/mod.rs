@@ -4,13 +4,14 @@ use crate::{
     ast::{BigInt, Literal},
     Symbol,
 };
+use codespan_reporting::diagnostic::{Diagnostic, Label};
 use smallvec::SmallVec;
 
 use crate::{
     ast::{
-        Ast, AstNode, Def, DefData, ElseExpr, FunFlags, FunProto, IfExpr, IntegerWidth,
-        NumberLiteral, NumberLiteralAnnotation, ParsedModule, SymbolPath, UnresolvedFunType,
-        UnresolvedType,
+        ArrayLen, Ast, AstNode, Attribute, Def, DefData, DestructurePattern, ElseExpr, FunFlags, FunProto, IfExpr,
+        IntegerWidth, MatchPattern, NumberLiteral, NumberLiteralAnnotation, ParsedModule,
+        SymbolPath, UnresolvedFunType, UnresolvedType,
     },
     parse::token::Op,
     util::{files::FileId, loc::Span},
@@ -24,6 +25,12 @@ use self::{
 pub mod lex;
 pub mod token;
 
+/// All identifiers that have a special meaning to the parser and cannot be used as names
+pub const KEYWORDS: &[&str] = &[
+    "fun", "type", "const", "imp", "pub", "let", "mut", "if", "else", "match", "phi", "return",
+    "break", "continue", "true", "false", "extern", "while", "for", "interface", "impl",
+];
+
 /// A structure consuming a token stream from a lexer and transforming it to an Abstract Syntax Tree
 #[derive(Debug)]
 pub struct Parser<'src> {
@@ -31,6 +38,10 @@ pub struct Parser<'src> {
     toks: Lexer<'src>,
     /// The current parse trace used for error and debug backtraces
     trace: SmallVec<[Cow<'static, str>; 24]>,
+    /// Target triple used to drop definitions with non-matching `cfg` attributes
+    target: Option<String>,
+    /// If the next number literal parsed directly follows a unary minus
+    negated_literal: bool,
 }
 
 pub type ParseResult<'src, T> = Result<T, ParseError<'src>>;
@@ -51,8 +62,13 @@ impl<'src> Parser<'src> {
         TokenData::OpenBracket(BracketType::Curly),
     ];
 
-    /// Parse the input source code into a full AST
-    pub fn parse(&mut self, name: Symbol, file: FileId) -> ParseResult<'src, ParsedModule> {
+    /// Parse the input source code into a full AST, returning all errors encountered if any
+    /// definitions failed to parse
+    pub fn parse(
+        &mut self,
+        name: Symbol,
+        file: FileId,
+    ) -> Result<ParsedModule, Vec<ParseError<'src>>> {
         let mut module = ParsedModule::new(name);
 
         self.parse_to(&mut module, file)?;
@@ -65,14 +81,116 @@ impl<'src> Parser<'src> {
         self.toks = Lexer::new(src);
     }
 
-    /// Parse and add items to a module
-    pub fn parse_to(&mut self, to: &mut ParsedModule, file: FileId) -> ParseResult<'src, ()> {
-        while self.toks.peek().is_some() {
-            let def = self.parse_decl(file)?;
-            to.defs.insert(def.data.name(), def);
+    /// Parse a single function body, including its braces, that spans the given byte range of
+    /// the source string. Used to reparse an edited function without reparsing the rest of its
+    /// file, the spans of the returned AST are positions in the whole source string
+    pub fn parse_body_in(
+        &mut self,
+        src: &'src str,
+        range: Span,
+    ) -> ParseResult<'src, (Vec<Ast>, Span)> {
+        //Nothing can follow the body
+        const EXPECTING_AFTER_BODY: &[TokenData<'static>] = &[];
+
+        self.trace.clear();
+        let valid = range.from <= range.to
+            && src.is_char_boundary(range.from)
+            && src.is_char_boundary(range.to);
+        if !valid {
+            return Err(ParseError {
+                highlighted_span: None,
+                backtrace: self.trace.clone(),
+                error: ParseErrorKind::InvalidRange {
+                    range,
+                    len: src.len(),
+                },
+            });
+        }
+
+        self.toks = Lexer::in_range(src, range.into());
+        self.trace.push("function body".into());
+        let body = self.parse_body()?;
+        if let Some(after) = self.toks.next() {
+            return Err(ParseError {
+                highlighted_span: Some(after.span),
+                backtrace: self.trace.clone(),
+                error: ParseErrorKind::UnexpectedToken {
+                    found: after,
+                    expecting: ExpectingOneOf(EXPECTING_AFTER_BODY),
+                },
+            });
         }
+        self.trace.pop();
 
-        Ok(())
+        Ok(body)
+    }
+
+    /// Parse and add items to a module. When a definition fails to parse, the error is
+    /// recorded and parsing continues from the start of the next definition so that all syntax
+    /// errors in the file are reported at once
+    pub fn parse_to(
+        &mut self,
+        to: &mut ParsedModule,
+        file: FileId,
+    ) -> Result<(), Vec<ParseError<'src>>> {
+        let mut errors = vec![];
+        while let Some(tok) = self.toks.peek() {
+            let defs = match tok.data {
+                TokenData::Ident("extern") => self.parse_extern_block(file),
+                _ => self.parse_decl(file).map(|def| vec![def]),
+            };
+            let defs = match defs {
+                Ok(defs) => defs,
+                Err(e) => {
+                    errors.push(e);
+                    self.recover();
+                    continue;
+                }
+            };
+            for def in defs {
+                if let Some(ref target) = self.target {
+                    if !def.cfg_matches(target) {
+                        continue;
+                    }
+                }
+                to.defs.insert(def.data.name(), def);
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Skip tokens after a parse error until a token that begins a new top-level definition
+    /// is found outside of any curly brackets, so that nested functions and declarations in a
+    /// body are skipped with the rest of the body
+    fn recover(&mut self) {
+        self.trace.clear();
+        while let Some(tok) = self.toks.peek() {
+            match tok.data {
+                TokenData::Ident("fun")
+                | TokenData::Ident("type")
+                | TokenData::Ident("const")
+                | TokenData::Ident("let")
+                | TokenData::Ident("mut")
+                | TokenData::Ident("imp")
+                | TokenData::Ident("interface")
+                | TokenData::Ident("impl")
+                | TokenData::Ident("extern")
+                | TokenData::Ident("pub")
+                | TokenData::Pound
+                    if self.toks.depth() == 0 =>
+                {
+                    break
+                }
+                _ => {
+                    self.toks.next();
+                }
+            }
+        }
     }
 
     /// Create a new `Parser` from the given source string
@@ -80,16 +198,25 @@ impl<'src> Parser<'src> {
         Self {
             toks: Lexer::new(src),
             trace: SmallVec::new(),
+            target: None,
+            negated_literal: false,
         }
     }
 
+    /// Set the target triple that definitions with a `#[cfg(target = "...")]` attribute must
+    /// match to be added to parsed modules
+    pub fn set_target(&mut self, target: impl Into<String>) {
+        self.target = Some(target.into());
+    }
+
     /// Consume the next token from the token stream or an [error](ParseErrorKind::UnexpectedEOF) if there are no more tokens to be lexed
     fn next_tok(
         &mut self,
         expecting: &'static [TokenData<'static>],
     ) -> ParseResult<'src, Token<'src>> {
+        let eof = self.toks.eof_span();
         self.toks.next().ok_or_else(|| ParseError {
-            highlighted_span: None,
+            highlighted_span: Some(eof),
             backtrace: self.trace.clone(),
             error: ParseErrorKind::UnexpectedEOF {
                 expecting: ExpectingOneOf(expecting),
@@ -104,8 +231,9 @@ impl<'src> Parser<'src> {
     ) -> ParseResult<'src, &Token<'src>> {
         let Self { toks, trace, .. } = self;
 
+        let eof = toks.eof_span();
         toks.peek().ok_or_else(|| ParseError {
-            highlighted_span: None,
+            highlighted_span: Some(eof),
             backtrace: trace.clone(),
             error: ParseErrorKind::UnexpectedEOF {
                 expecting: ExpectingOneOf(expecting),
@@ -120,6 +248,16 @@ impl<'src> Parser<'src> {
     ) -> ParseResult<'src, &'src str> {
         let next = self.next_tok(expected)?;
         if let TokenData::Ident(name) = next.data {
+            if KEYWORDS.contains(&name) && !expected.iter().any(|tok| *tok == next.data) {
+                return Err(ParseError {
+                    highlighted_span: Some(next.span),
+                    backtrace: self.trace.clone(),
+                    error: ParseErrorKind::ReservedKeyword {
+                        keyword: name,
+                        expecting: ExpectingOneOf(expected),
+                    },
+                });
+            }
             Ok(name)
         } else {
             Err(ParseError {
@@ -199,10 +337,82 @@ impl<'src> Parser<'src> {
             TokenData::Ident("type"),
             TokenData::Ident("const"),
             TokenData::Ident("imp"),
+            TokenData::Ident("interface"),
+            TokenData::Ident("impl"),
+            TokenData::Ident("let"),
+            TokenData::Ident("mut"),
+            TokenData::Ident("pub"),
+            TokenData::Pound,
         ];
 
+        let attrs = self.parse_attributes()?;
+        let public = match self.toks.peek() {
+            Some(Token {
+                data: TokenData::Ident("pub"),
+                ..
+            }) => {
+                self.toks.next();
+                true
+            }
+            _ => false,
+        };
+
         let next = self.next_tok(EXPECTING_NEXT)?;
         match next.data {
+            TokenData::Ident("let") | TokenData::Ident("mut") | TokenData::Ident("const") => {
+                let mutable = next.data == TokenData::Ident("mut");
+                let constant = next.data == TokenData::Ident("const");
+                let ty = match self.peek_tok(&[TokenData::Ident("global name")])?.data.clone() {
+                    TokenData::OpenBracket(BracketType::Smooth) => {
+                        self.toks.next();
+                        self.trace.push("global variable type".into());
+                        let ty = self.parse_typename()?;
+                        self.expect_next(&[TokenData::CloseBracket(BracketType::Smooth)])?;
+                        self.trace.pop();
+                        Some(ty)
+                    }
+                    _ => None,
+                };
+
+                let mut name_span = self.peek_tok(&[TokenData::Ident("global name")])?.span;
+                let (name, external) =
+                    match self.expect_next_ident(&[TokenData::Ident("global name")])? {
+                        "ext" => {
+                            name_span = self.peek_tok(&[TokenData::Ident("global name")])?.span;
+                            (
+                                self.expect_next_ident(&[TokenData::Ident("global name")])?,
+                                true,
+                            )
+                        }
+                        other => (other, false),
+                    };
+                self.trace
+                    .push(format!("global variable '{}'", name).into());
+                //External globals are defined in another object and have no initializer
+                let (init, end_loc) = if external {
+                    (None, name_span.to)
+                } else {
+                    self.expect_next(&[TokenData::Assign])?;
+                    let init = self.parse_expr()?;
+                    let end_loc = init.span.to;
+                    (Some(init), end_loc)
+                };
+                self.trace.pop();
+
+                Ok(Def {
+                    span: (next.span.from, end_loc).into(),
+                    data: DefData::GlobalDef {
+                        name: self.symbol(name),
+                        ty,
+                        mutable,
+                        constant,
+                        init,
+                    },
+                    file,
+                    attrs,
+                    public,
+                })
+            }
             TokenData::Ident("imp") => {
                 self.trace.push("import statement".into());
                 let imported = self.expect_next_path(&[TokenData::Ident("imported module")])?;
@@ -212,89 +422,16 @@ impl<'src> Parser<'src> {
                     file,
                     span: next.span,
                     data: DefData::ImportDef { name: imported },
+                    attrs,
+                    public,
                 })
             }
             TokenData::Ident("fun") => {
-                let (name, flags) =
-                    match self.expect_next_ident(&[TokenData::Ident("function name")])? {
-                        "ext" => (
-                            self.expect_next_ident(&[TokenData::Ident("function name")])?,
-                            FunFlags::EXTERN,
-                        ),
-                        other => (other, FunFlags::empty()),
-                    };
-
-                self.trace
-                    .push(format!("function declaration '{}'", name).into());
-
-                const ARGS_EXPECTING: &[TokenData<'static>] = &[
-                    TokenData::Ident("argument typename"),
-                    TokenData::Arrow,
-                    TokenData::OpenBracket(BracketType::Curly),
-                ];
-
-                self.expect_next(&[TokenData::OpenBracket(BracketType::Smooth)])?;
-
-                let mut args = Vec::new();
-
-                loop {
-                    let peeked = self.peek_tok(ARGS_EXPECTING)?;
-                    match peeked.data {
-                        TokenData::CloseBracket(BracketType::Smooth) => {
-                            self.toks.next();
-                            break;
-                        }
-                        _ => {
-                            self.trace.push("function argument typename".into());
-                            let arg_type = self.parse_typename()?;
-                            self.trace.pop();
-
-                            self.trace.push("function argument name".into());
-                            let arg_name = self
-                                .expect_next_ident(&[TokenData::Ident("function argument name")])?;
-                            self.trace.pop();
-
-                            args.push((self.symbol(arg_name), arg_type));
-
-                            const EXPECTING_AFTER_ARG: &[TokenData<'static>] = &[
-                                TokenData::OpenBracket(BracketType::Curly),
-                                TokenData::Comma,
-                                TokenData::Arrow,
-                            ];
-
-                            let after_arg = self.peek_tok(EXPECTING_AFTER_ARG)?;
-                            if let TokenData::Comma = after_arg.data {
-                                self.next_tok(EXPECTING_AFTER_ARG)?;
-                            }
-                        }
-                    }
-                }
+                let proto = self.parse_fun_proto()?;
 
                 const EXPECTING_AFTER_ARGS: &[TokenData<'static>] =
                     &[TokenData::OpenBracket(BracketType::Curly), TokenData::Arrow];
 
-                let after_args = self
-                    .peek_tok(EXPECTING_AFTER_ARGS)
-                    .map(|tok| tok.data.clone());
-                let return_ty = if let Ok(TokenData::Arrow) = after_args {
-                    self.next_tok(EXPECTING_AFTER_ARGS)?;
-                    self.trace.push("function return typename".into());
-                    let return_ty = self.parse_typename()?;
-                    self.trace.pop();
-                    return_ty
-                } else {
-                    UnresolvedType::Unit
-                };
-
-                let proto = FunProto {
-                    name: self.symbol(name),
-                    args,
-                    return_ty,
-                    flags,
-                };
-
-                self.trace.pop();
-
                 if let Ok(TokenData::OpenBracket(BracketType::Curly)) =
                     self.peek_tok(EXPECTING_AFTER_ARGS).map(|a| a.data.clone())
                 {
@@ -306,31 +443,103 @@ impl<'src> Parser<'src> {
                         file,
                         span: body.1,
                         data: DefData::FunDef(proto, body.0),
+                        attrs,
+                        public,
                     })
                 } else {
                     Ok(Def {
                         file,
                         span: next.span,
                         data: DefData::FunDec(proto),
+                        attrs,
+                        public,
                     })
                 }
             }
+            TokenData::Ident("interface") => {
+                let name = self.expect_next_ident(&[TokenData::Ident("interface name")])?;
+                self.trace
+                    .push(format!("interface definition '{}'", name).into());
+
+                let funs = self.parse_def_block(file)?;
+
+                self.trace.pop();
+                Ok(Def {
+                    span: next.span,
+                    data: DefData::InterfaceDef {
+                        name: self.symbol(name),
+                        funs,
+                    },
+                    file,
+                    attrs,
+                    public,
+                })
+            }
+            TokenData::Ident("impl") => {
+                let interface = self.expect_next_path(&[TokenData::Ident("interface name")])?;
+                self.trace
+                    .push(format!("implementation of '{}'", interface).into());
+
+                let for_tok = self.next_tok(&[TokenData::Ident("for")])?;
+                if for_tok.data != TokenData::Ident("for") {
+                    return Err(ParseError {
+                        highlighted_span: Some(for_tok.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: for_tok,
+                            expecting: ExpectingOneOf(&[TokenData::Ident("for")]),
+                        },
+                    });
+                }
+                let ty = self.parse_typename()?;
+                let funs = self.parse_def_block(file)?;
+
+                self.trace.pop();
+                Ok(Def {
+                    span: next.span,
+                    data: DefData::ImplDef {
+                        interface,
+                        ty,
+                        funs,
+                    },
+                    file,
+                    attrs,
+                    public,
+                })
+            }
             TokenData::Ident("type") => {
                 let name = self.expect_next_ident(&[TokenData::Ident("type name")])?;
                 self.trace
                     .push(format!("type definition '{}'", name).into());
 
+                let params = self.parse_type_params()?;
                 self.expect_next(&[TokenData::Assign])?;
-                let aliased = self.parse_typename()?;
+
+                //Only fields of a structure type definition can be given default values
+                let mut defaults = vec![];
+                let aliased = match self.toks.peek().map(|tok| &tok.data) {
+                    Some(TokenData::OpenBracket(BracketType::Curly)) => {
+                        self.toks.next();
+                        self.trace.push("structure typename".into());
+                        let fields = self.parse_fields(Some(&mut defaults))?;
+                        self.trace.pop();
+                        self.parse_enum_typename_rest(UnresolvedType::Struct { fields })?
+                    }
+                    _ => self.parse_typename()?,
+                };
 
                 self.trace.pop();
                 Ok(Def {
                     span: next.span,
                     data: DefData::AliasDef {
                         name: self.symbol(name),
+                        params,
                         aliased,
+                        defaults,
                     },
                     file,
+                    attrs,
+                    public,
                 })
             }
             _ => Err(ParseError {
@@ -344,6 +553,323 @@ impl<'src> Parser<'src> {
         }
     }
 
+    /// Parse any number of `#[name]` or `#[name(args)]` attributes applied to the following
+    /// definition
+    fn parse_attributes(&mut self) -> ParseResult<'src, Vec<Attribute>> {
+        let mut attrs = vec![];
+        while let Some(TokenData::Pound) = self.toks.peek().map(|tok| &tok.data) {
+            let start = self.toks.next().unwrap().span.from;
+            self.trace.push("attribute".into());
+            self.expect_next(&[TokenData::OpenBracket(BracketType::Square)])?;
+            let name = self.expect_next_ident(&[TokenData::Ident("attribute name")])?;
+            let name = self.symbol(name);
+
+            let mut args = vec![];
+            let mut named = vec![];
+            if let Some(TokenData::OpenBracket(BracketType::Smooth)) =
+                self.toks.peek().map(|tok| &tok.data)
+            {
+                const EXPECTING_AFTER_ARG: &[TokenData<'static>] = &[
+                    TokenData::Comma,
+                    TokenData::CloseBracket(BracketType::Smooth),
+                ];
+
+                self.toks.next();
+                loop {
+                    if let Some(TokenData::String(_)) = self.toks.peek().map(|tok| &tok.data) {
+                        let arg = self.parse_string_literal()?;
+                        args.push(Symbol::from(&arg));
+                    } else {
+                        let arg =
+                            self.expect_next_ident(&[TokenData::Ident("attribute argument")])?;
+                        let arg = self.symbol(arg);
+                        if let Some(TokenData::Assign) = self.toks.peek().map(|tok| &tok.data) {
+                            self.toks.next();
+                            let value = self.parse_string_literal()?;
+                            named.push((arg, Symbol::from(&value)));
+                        } else {
+                            args.push(arg);
+                        }
+                    }
+
+                    let next = self.next_tok(EXPECTING_AFTER_ARG)?;
+                    match next.data {
+                        TokenData::Comma => (),
+                        TokenData::CloseBracket(BracketType::Smooth) => break,
+                        _ => {
+                            return Err(ParseError {
+                                highlighted_span: Some(next.span),
+                                backtrace: self.trace.clone(),
+                                error: ParseErrorKind::UnexpectedToken {
+                                    found: next,
+                                    expecting: ExpectingOneOf(EXPECTING_AFTER_ARG),
+                                },
+                            })
+                        }
+                    }
+                }
+            }
+
+            let end = self
+                .peek_tok(&[TokenData::CloseBracket(BracketType::Square)])?
+                .span
+                .to;
+            self.expect_next(&[TokenData::CloseBracket(BracketType::Square)])?;
+            self.trace.pop();
+
+            attrs.push(Attribute {
+                name,
+                args,
+                named,
+                span: (start, end).into(),
+            });
+        }
+        Ok(attrs)
+    }
+
+    /// Parse definitions enclosed in curly braces, like the functions of an interface or
+    /// implementation, skipping definitions with non-matching `cfg` attributes
+    fn parse_def_block(&mut self, file: FileId) -> ParseResult<'src, Vec<Def>> {
+        self.expect_next(&[TokenData::OpenBracket(BracketType::Curly)])?;
+
+        let mut defs = vec![];
+        loop {
+            if let TokenData::CloseBracket(BracketType::Curly) = self
+                .peek_tok(&[TokenData::CloseBracket(BracketType::Curly)])?
+                .data
+            {
+                self.toks.next();
+                break Ok(defs);
+            }
+
+            let def = self.parse_decl(file)?;
+            if let Some(ref target) = self.target {
+                if !def.cfg_matches(target) {
+                    continue;
+                }
+            }
+            defs.push(def);
+        }
+    }
+
+    /// Parse an `extern { ... }` or `extern "C" { ... }` block of function declarations,
+    /// marking every declared function as external
+    fn parse_extern_block(&mut self, file: FileId) -> ParseResult<'src, Vec<Def>> {
+        self.expect_next(&[TokenData::Ident("extern")])?;
+        self.trace.push("extern block".into());
+
+        if let TokenData::String(_) = self
+            .peek_tok(&[TokenData::OpenBracket(BracketType::Curly)])?
+            .data
+        {
+            let abi = self.toks.next().unwrap();
+            if abi.data != TokenData::String("C") {
+                return Err(ParseError {
+                    highlighted_span: Some(abi.span),
+                    backtrace: self.trace.clone(),
+                    error: ParseErrorKind::UnexpectedToken {
+                        found: abi,
+                        expecting: ExpectingOneOf(&[TokenData::String("C")]),
+                    },
+                });
+            }
+        }
+
+        let mut defs = self.parse_def_block(file)?;
+        for def in defs.iter_mut() {
+            match &mut def.data {
+                DefData::FunDec(proto) => proto.flags |= FunFlags::EXTERN,
+                _ => {
+                    return Err(ParseError {
+                        highlighted_span: Some(def.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::ExternBlockDefinition,
+                    })
+                }
+            }
+        }
+
+        self.trace.pop();
+        Ok(defs)
+    }
+
+    /// Parse an optional list of type parameter names in angle brackets, like `<T, U>`,
+    /// returning an empty list if the next token is not `<`
+    fn parse_type_params(&mut self) -> ParseResult<'src, Vec<Symbol>> {
+        let mut type_params = Vec::new();
+        if let Some(TokenData::Op(Op::Less)) = self.toks.peek().map(|tok| &tok.data) {
+            self.toks.next();
+            self.trace.push("type parameters".into());
+
+            const EXPECTING_AFTER_TYPE_PARAM: &[TokenData<'static>] =
+                &[TokenData::Comma, TokenData::Op(Op::Greater)];
+
+            loop {
+                let param = self.expect_next_ident(&[TokenData::Ident("type parameter name")])?;
+                type_params.push(self.symbol(param));
+
+                let after_param = self.next_tok(EXPECTING_AFTER_TYPE_PARAM)?;
+                match after_param.data {
+                    TokenData::Comma => (),
+                    TokenData::Op(Op::Greater) => break,
+                    _ => {
+                        return Err(ParseError {
+                            highlighted_span: Some(after_param.span),
+                            backtrace: self.trace.clone(),
+                            error: ParseErrorKind::UnexpectedToken {
+                                found: after_param,
+                                expecting: ExpectingOneOf(EXPECTING_AFTER_TYPE_PARAM),
+                            },
+                        })
+                    }
+                }
+            }
+            self.trace.pop();
+        }
+        Ok(type_params)
+    }
+
+    /// Parse an optional list of type arguments to a generic type in angle brackets, like
+    /// `<i32, *u8>`, returning an empty list if the next token is not `<`
+    fn parse_type_args(&mut self) -> ParseResult<'src, Vec<UnresolvedType>> {
+        let mut type_args = Vec::new();
+        if let Some(TokenData::Op(Op::Less)) = self.toks.peek().map(|tok| &tok.data) {
+            self.toks.next();
+            self.trace.push("type arguments".into());
+
+            const EXPECTING_AFTER_TYPE_ARG: &[TokenData<'static>] =
+                &[TokenData::Comma, TokenData::Op(Op::Greater)];
+
+            loop {
+                type_args.push(self.parse_typename()?);
+
+                match self.peek_tok(EXPECTING_AFTER_TYPE_ARG)?.data {
+                    TokenData::Comma => {
+                        self.toks.next();
+                    }
+                    TokenData::Op(Op::Greater) => {
+                        self.toks.next();
+                        break;
+                    }
+                    //The closing brackets of nested type arguments are lexed as one token
+                    TokenData::Op(Op::ShRight) => {
+                        self.toks.split_shift_right();
+                        break;
+                    }
+                    _ => {
+                        let after_arg = self.next_tok(EXPECTING_AFTER_TYPE_ARG)?;
+                        return Err(ParseError {
+                            highlighted_span: Some(after_arg.span),
+                            backtrace: self.trace.clone(),
+                            error: ParseErrorKind::UnexpectedToken {
+                                found: after_arg,
+                                expecting: ExpectingOneOf(EXPECTING_AFTER_TYPE_ARG),
+                            },
+                        });
+                    }
+                }
+            }
+            self.trace.pop();
+        }
+        Ok(type_args)
+    }
+
+    /// Parse a function prototype after the `fun` keyword has been consumed
+    fn parse_fun_proto(&mut self) -> ParseResult<'src, FunProto<UnresolvedType>> {
+        let (name, flags) =
+            match self.expect_next_ident(&[TokenData::Ident("function name")])? {
+                "ext" => (
+                    self.expect_next_ident(&[TokenData::Ident("function name")])?,
+                    FunFlags::EXTERN,
+                ),
+                other => (other, FunFlags::empty()),
+            };
+
+        self.trace
+            .push(format!("function declaration '{}'", name).into());
+
+        let type_params = self.parse_type_params()?;
+
+        const ARGS_EXPECTING: &[TokenData<'static>] = &[
+            TokenData::Ident("argument typename"),
+            TokenData::CloseBracket(BracketType::Smooth),
+        ];
+
+        self.expect_next(&[TokenData::OpenBracket(BracketType::Smooth)])?;
+
+        let mut args = Vec::new();
+
+        loop {
+            let peeked = self.peek_tok(ARGS_EXPECTING)?;
+            match peeked.data {
+                TokenData::CloseBracket(BracketType::Smooth) => {
+                    self.toks.next();
+                    break;
+                }
+                _ => {
+                    self.trace.push("function argument typename".into());
+                    let arg_type = self.parse_typename()?;
+                    self.trace.pop();
+
+                    self.trace.push("function argument name".into());
+                    let arg_name = self
+                        .expect_next_ident(&[TokenData::Ident("function argument name")])?;
+                    self.trace.pop();
+
+                    args.push((self.symbol(arg_name), arg_type));
+
+                    const EXPECTING_AFTER_ARG: &[TokenData<'static>] = &[
+                        TokenData::Comma,
+                        TokenData::CloseBracket(BracketType::Smooth),
+                    ];
+
+                    let after_arg = self.next_tok(EXPECTING_AFTER_ARG)?;
+                    match after_arg.data {
+                        TokenData::Comma => (),
+                        TokenData::CloseBracket(BracketType::Smooth) => break,
+                        _ => {
+                            return Err(ParseError {
+                                highlighted_span: Some(after_arg.span),
+                                backtrace: self.trace.clone(),
+                                error: ParseErrorKind::UnexpectedToken {
+                                    found: after_arg,
+                                    expecting: ExpectingOneOf(EXPECTING_AFTER_ARG),
+                                },
+                            })
+                        }
+                    }
+                }
+            }
+        }
+
+        const EXPECTING_AFTER_ARGS: &[TokenData<'static>] =
+            &[TokenData::OpenBracket(BracketType::Curly), TokenData::Arrow];
+
+        let after_args = self
+            .peek_tok(EXPECTING_AFTER_ARGS)
+            .map(|tok| tok.data.clone());
+        let return_ty = if let Ok(TokenData::Arrow) = after_args {
+            self.next_tok(EXPECTING_AFTER_ARGS)?;
+            self.trace.push("function return typename".into());
+            let return_ty = self.parse_typename()?;
+            self.trace.pop();
+            return_ty
+        } else {
+            UnresolvedType::Unit
+        };
+
+        let proto = FunProto {
+            name: self.symbol(name),
+            args,
+            return_ty,
+            flags,
+            type_params,
+        };
+
+        self.trace.pop();
+        Ok(proto)
+    }
+
     /// Parse a curly brace enclosed AST body
     fn parse_body(&mut self) -> ParseResult<'src, (Vec<Ast>, Span)> {
         const EXPECTING_FOR_BODY: &[TokenData<'static>] =
@@ -379,6 +905,42 @@ impl<'src> Parser<'src> {
         Ok((body, Span::new(start_loc, end_loc)))
     }
     
+    /// Parse an optional label naming the loop that a `break` or `continue` statement
+    /// spanning `span` targets, returning the label and the span of the whole statement
+    fn parse_loop_label(&mut self, span: Span) -> (Option<Symbol>, Span) {
+        match self.toks.peek().map(|tok| (tok.data.clone(), tok.span)) {
+            Some((TokenData::Label(label), label_span)) => {
+                self.toks.next();
+                (Some(self.symbol(label)), (span.from, label_span.to).into())
+            }
+            _ => (None, span),
+        }
+    }
+
+    /// Parse the value of a `break` statement spanning `span` if an expression follows it on
+    /// the same line
+    fn parse_break_value(&mut self, span: Span) -> ParseResult<'src, Option<Ast>> {
+        let has_value = match self.toks.peek() {
+            Some(next) => {
+                !self.toks.newline_between(span.to, next.span.from)
+                    && !matches!(
+                        next.data,
+                        TokenData::CloseBracket(_) | TokenData::Comma | TokenData::Arrow
+                    )
+            }
+            None => false,
+        };
+
+        if has_value {
+            self.trace.push("break value".into());
+            let value = self.parse_expr()?;
+            self.trace.pop();
+            Ok(Some(value))
+        } else {
+            Ok(None)
+        }
+    }
+
     /// Parse a statement from the token stream
     fn parse_stmt(&mut self) -> ParseResult<'src, Ast> {
         const EXPECTING_FOR_STMT: &[TokenData<'static>] = &[
@@ -387,11 +949,15 @@ impl<'src> Parser<'src> {
             TokenData::Ident("mut"),
             TokenData::Ident("phi"),
             TokenData::Ident("match"),
+            TokenData::Ident("while"),
             TokenData::Ident("return"),
             TokenData::Ident("break"),
             TokenData::Ident("continue"),
+            TokenData::Ident("fun"),
+            TokenData::Ident("type"),
             TokenData::Ident("variable / function name"),
             TokenData::OpenBracket(BracketType::Smooth),
+            TokenData::Label("block label"),
         ];
 
         let peeked = self.peek_tok(EXPECTING_FOR_STMT)?.clone();
@@ -399,16 +965,22 @@ impl<'src> Parser<'src> {
         let stmt = match peeked.data {
             TokenData::Ident("break") => {
                 self.toks.next();
+                let (label, span) = self.parse_loop_label(peeked.span);
+                let value = self.parse_break_value(span)?;
                 Ok(Ast {
-                    span: peeked.span,
-                    node: AstNode::Break,
+                    span: match &value {
+                        Some(value) => (span.from, value.span.to).into(),
+                        None => span,
+                    },
+                    node: AstNode::Break(label, value.map(Box::new)),
                 })
             }
             TokenData::Ident("continue") => {
                 self.toks.next();
+                let (label, span) = self.parse_loop_label(peeked.span);
                 Ok(Ast {
-                    span: peeked.span,
-                    node: AstNode::Continue,
+                    span,
+                    node: AstNode::Continue(label),
                 })
             }
             TokenData::Ident("if") => {
@@ -419,10 +991,40 @@ impl<'src> Parser<'src> {
                 })
             }
             TokenData::Ident("match") => self.parse_match(),
+            TokenData::Ident("while") => self.parse_while(None, peeked.span),
+            TokenData::Ident("fun") => {
+                self.toks.next();
+                let proto = self.parse_fun_proto()?;
+
+                self.trace
+                    .push(format!("nested function body '{}'", proto.name).into());
+                let body = self.parse_body()?;
+                self.trace.pop();
+
+                Ok(Ast {
+                    span: (peeked.span.from, body.1.to).into(),
+                    node: AstNode::FunDef(proto, body.0),
+                })
+            }
+            TokenData::Ident("type") => {
+                self.toks.next();
+                let name_span = self.peek_tok(&[TokenData::Ident("type alias name")])?.span;
+                let name = self.expect_next_ident(&[TokenData::Ident("type alias name")])?;
+                self.trace.push(format!("local type alias '{}'", name).into());
+                self.expect_next(&[TokenData::Assign])?;
+                let aliased = self.parse_typename()?;
+                self.trace.pop();
+
+                Ok(Ast {
+                    span: (peeked.span.from, name_span.to).into(),
+                    node: AstNode::TypeDef(self.symbol(name), aliased),
+                })
+            }
             TokenData::Ident("let") | TokenData::Ident("mut") => {
                 const EXPECTING_AFTER_LET: &[TokenData<'static>] = &[
                     TokenData::Ident("variable name"),
                     TokenData::OpenBracket(BracketType::Smooth),
+                    TokenData::OpenBracket(BracketType::Curly),
                 ];
 
                 self.toks.next();
@@ -434,14 +1036,68 @@ impl<'src> Parser<'src> {
                 let mut var_type = None;
                 let name = match next.data {
                     TokenData::Ident(name) => self.symbol(name),
+                    TokenData::OpenBracket(BracketType::Curly) => {
+                        let (fields, end_loc) = self.parse_struct_pattern()?;
+
+                        self.trace.pop();
+                        return self.parse_assignment(Ast {
+                            span: (next.span.from, end_loc).into(),
+                            node: AstNode::Destructure {
+                                pattern: DestructurePattern::Struct(fields),
+                                mutable,
+                            },
+                        });
+                    }
                     TokenData::OpenBracket(BracketType::Smooth) => {
-                        let typename = self.parse_typename()?;
-                        self.expect_next(&[TokenData::CloseBracket(BracketType::Smooth)])?;
+                        //A list of names in parentheses followed by anything but the variable
+                        //name is a tuple destructuring pattern instead of a type
+                        let first = self.parse_typename()?;
+                        let mut typenames = self.parse_tuple_typename_rest(first)?;
+
+                        if let Some(TokenData::Ident(_)) = self.toks.peek().map(|tok| &tok.data) {
+                            var_type = Some(if typenames.len() == 1 {
+                                typenames.remove(0)
+                            } else {
+                                UnresolvedType::Tuple {
+                                    elements: typenames,
+                                }
+                            });
 
-                        var_type = Some(typename);
+                            let name =
+                                self.expect_next_ident(&[TokenData::Ident("variable name")])?;
+                            self.symbol(name)
+                        } else {
+                            let pattern = typenames
+                                .into_iter()
+                                .map(|ty| match ty {
+                                    UnresolvedType::UserDefined { name, args } if name.len() == 1 && args.is_empty() => {
+                                        Ok(match name.first().as_str() {
+                                            "_" => None,
+                                            _ => Some(name.first()),
+                                        })
+                                    }
+                                    _ => Err(ParseError {
+                                        highlighted_span: Some(next.span),
+                                        backtrace: self.trace.clone(),
+                                        error: ParseErrorKind::UnexpectedToken {
+                                            found: next.clone(),
+                                            expecting: ExpectingOneOf(&[TokenData::Ident(
+                                                "destructured variable name",
+                                            )]),
+                                        },
+                                    }),
+                                })
+                                .collect::<ParseResult<'src, Vec<_>>>()?;
 
-                        let name = self.expect_next_ident(&[TokenData::Ident("variable name")])?;
-                        self.symbol(name)
+                            self.trace.pop();
+                            return self.parse_assignment(Ast {
+                                span: next.span,
+                                node: AstNode::Destructure {
+                                    pattern: DestructurePattern::Tuple(pattern),
+                                    mutable,
+                                },
+                            });
+                        }
                     }
                     _ => {
                         return Err(ParseError {
@@ -488,10 +1144,11 @@ impl<'src> Parser<'src> {
                     node: AstNode::Return(Box::new(returned)),
                 })
             }
-            //Parse an assignment expression
+            //Parse an assignment or an expression evaluated for its side effects
             TokenData::Ident(_)
+            | TokenData::Label(_)
             | TokenData::OpenBracket(BracketType::Curly)
-            | TokenData::OpenBracket(BracketType::Smooth) => self.parse_prefix_expr(),
+            | TokenData::OpenBracket(BracketType::Smooth) => self.parse_expr(),
             _ => Err(ParseError {
                 highlighted_span: Some(peeked.span),
                 backtrace: self.trace.clone(),
@@ -502,6 +1159,11 @@ impl<'src> Parser<'src> {
             }),
         }?;
 
+        self.parse_assignment(stmt)
+    }
+
+    /// Parse an assignment to the given statement if there is one
+    fn parse_assignment(&mut self, stmt: Ast) -> ParseResult<'src, Ast> {
         if let Some(TokenData::Assign) = self.toks.peek().map(|tok| &tok.data) {
             self.trace.push("assignment statement".into());
             self.toks.next();
@@ -563,6 +1225,12 @@ impl<'src> Parser<'src> {
             TokenData::Op(unaryop) => {
                 self.toks.next();
                 self.trace.push("unary operation".into());
+                //Negated literals may hold the minimum value of a signed type
+                self.negated_literal = *unaryop == Op::Sub
+                    && matches!(
+                        self.toks.peek().map(|tok| &tok.data),
+                        Some(TokenData::Number(_))
+                    );
                 let rhs = self.parse_expr()?;
                 self.trace.pop();
 
@@ -636,7 +1304,7 @@ impl<'src> Parser<'src> {
             },
             TokenData::Pound => {
                 const EXPECTING_AFTER_POUND: &[TokenData<'static>] = &[
-                    TokenData::Ident("typename"), TokenData::OpenBracket(BracketType::Curly)
+                    TokenData::Ident("type name"), TokenData::OpenBracket(BracketType::Curly)
                 ];
                 const EXPECTING_AFTER_BRACE: &[TokenData<'static>] = &[
                     TokenData::Ident("field name"), TokenData::CloseBracket(BracketType::Curly)
@@ -695,6 +1363,7 @@ impl<'src> Parser<'src> {
             },
             TokenData::OpenBracket(BracketType::Smooth)
             | TokenData::Ident(_)
+            | TokenData::Label(_)
             | TokenData::OpenBracket(BracketType::Curly) => self.parse_prefix_expr()?,
             _ => {
                 return Err(ParseError {
@@ -776,18 +1445,51 @@ impl<'src> Parser<'src> {
         Ok(unescaped)
     }
 
+    /// Check if the given operator compares its operands, producing a boolean
+    const fn is_comparison(op: Op) -> bool {
+        matches!(
+            op,
+            Op::Greater | Op::GreaterEq | Op::Less | Op::LessEq | Op::Eq | Op::NotEq
+        )
+    }
+
     /// Parse the right hand side of an expression if there is one
     fn parse_expr_rhs(&mut self, lhs: Ast) -> ParseResult<'src, Ast> {
         let peeked = self.toks.peek();
         if let Some(peeked) = peeked {
             match peeked.data {
+                //A minus sign starting a new line is a negative match pattern or a negation
+                //in the next statement, not a subtraction from this expression
+                TokenData::Op(Op::Sub)
+                    if self.toks.newline_between(lhs.span.to, peeked.span.from) =>
+                {
+                    Ok(lhs)
+                }
                 TokenData::Op(operator) => {
+                    let op_span = peeked.span;
                     self.toks.next();
 
                     let rhs = self.parse_expr()?;
+                    //Parenthesized expressions include the brackets in their span, so a
+                    //comparison that begins where the rhs begins was not parenthesized
+                    if let AstNode::BinExpr(ref chained_lhs, chained, ..) = rhs.node {
+                        if Self::is_comparison(operator)
+                            && Self::is_comparison(chained)
+                            && chained_lhs.span.from == rhs.span.from
+                        {
+                            return Err(ParseError {
+                                highlighted_span: Some((lhs.span.from, rhs.span.to).into()),
+                                backtrace: self.trace.clone(),
+                                error: ParseErrorKind::ChainedComparison {
+                                    first: operator,
+                                    second: chained,
+                                },
+                            });
+                        }
+                    }
                     Ok(Ast {
                         span: (lhs.span.from, rhs.span.to).into(),
-                        node: AstNode::BinExpr(Box::new(lhs), operator, Box::new(rhs)),
+                        node: AstNode::BinExpr(Box::new(lhs), operator, op_span, Box::new(rhs)),
                     })
                 }
                 _ => Ok(lhs),
@@ -808,7 +1510,9 @@ impl<'src> Parser<'src> {
         let end_span = loop {
             let next = self.peek_tok(&[
                 TokenData::CloseBracket(BracketType::Curly),
-                TokenData::Ident("typename"),
+                TokenData::Ident("type name"),
+                TokenData::Number("integer pattern"),
+                TokenData::Ident("_"),
             ])?;
             match next.data {
                 TokenData::CloseBracket(BracketType::Curly) => {
@@ -816,10 +1520,22 @@ impl<'src> Parser<'src> {
                     break tok.span.to;
                 }
                 _ => {
-                    let ty = self.parse_typename()?;
+                    self.trace.push("match arm pattern".into());
+                    let pattern = self.parse_match_pattern()?;
+                    self.trace.pop();
+                    let guard = match self.toks.peek().map(|tok| &tok.data) {
+                        Some(TokenData::Ident("if")) => {
+                            self.toks.next();
+                            self.trace.push("match arm guard".into());
+                            let guard = self.parse_expr()?;
+                            self.trace.pop();
+                            Some(guard)
+                        }
+                        _ => None,
+                    };
                     self.expect_next(&[TokenData::Arrow])?;
                     let stmt = self.parse_stmt()?;
-                    cases.push((ty, stmt));
+                    cases.push((pattern, guard, stmt));
                 }
             }
         };
@@ -833,6 +1549,162 @@ impl<'src> Parser<'src> {
         })
     }
 
+    /// Parse a single pattern of a match arm
+    fn parse_match_pattern(&mut self) -> ParseResult<'src, MatchPattern<UnresolvedType>> {
+        let peeked = self.peek_tok(&[TokenData::Ident("type name")])?.clone();
+        match peeked.data {
+            TokenData::Ident("_") => {
+                self.toks.next();
+                Ok(MatchPattern::Default)
+            }
+            TokenData::Number(_) | TokenData::Op(Op::Sub) => {
+                let start = self.parse_int_pattern()?;
+                match (
+                    self.toks.peek().map(|tok| &tok.data),
+                    self.toks.peek2().map(|tok| &tok.data),
+                ) {
+                    (Some(TokenData::Period), Some(TokenData::Period)) => {
+                        self.toks.next();
+                        self.toks.next();
+                        self.expect_next(&[TokenData::Assign])?;
+                        let end = self.parse_int_pattern()?;
+                        Ok(MatchPattern::Range(start, end))
+                    }
+                    _ => Ok(MatchPattern::Integer(start)),
+                }
+            }
+            _ => {
+                let ty = self.parse_typename()?;
+                let destructured = match self.toks.peek().map(|tok| &tok.data) {
+                    Some(TokenData::OpenBracket(BracketType::Curly)) => {
+                        self.toks.next();
+                        Some(DestructurePattern::Struct(self.parse_struct_pattern()?.0))
+                    }
+                    Some(TokenData::OpenBracket(BracketType::Smooth)) => {
+                        self.toks.next();
+                        Some(DestructurePattern::Tuple(self.parse_tuple_pattern()?))
+                    }
+                    _ => None,
+                };
+                Ok(MatchPattern::Type(ty, destructured))
+            }
+        }
+    }
+
+    /// Parse the names of structure fields to bind in a destructuring pattern after the opening
+    /// curly brace, returning the names and the end location of the closing curly brace
+    fn parse_struct_pattern(&mut self) -> ParseResult<'src, (Vec<Symbol>, usize)> {
+        const EXPECTING_IN_PATTERN: &[TokenData<'static>] = &[
+            TokenData::Ident("structure field name"),
+            TokenData::Comma,
+            TokenData::CloseBracket(BracketType::Curly),
+        ];
+
+        let mut fields = vec![];
+        loop {
+            let tok = self.next_tok(EXPECTING_IN_PATTERN)?;
+            match tok.data {
+                TokenData::CloseBracket(BracketType::Curly) => break Ok((fields, tok.span.to)),
+                TokenData::Comma => continue,
+                TokenData::Ident(field) => fields.push(self.symbol(field)),
+                _ => {
+                    return Err(ParseError {
+                        highlighted_span: Some(tok.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: tok,
+                            expecting: ExpectingOneOf(EXPECTING_IN_PATTERN),
+                        },
+                    })
+                }
+            }
+        }
+    }
+
+    /// Parse the names of tuple elements to bind in a destructuring pattern after the opening
+    /// parenthesis, with `None` for elements ignored with `_`
+    fn parse_tuple_pattern(&mut self) -> ParseResult<'src, Vec<Option<Symbol>>> {
+        const EXPECTING_IN_PATTERN: &[TokenData<'static>] = &[
+            TokenData::Ident("tuple element name"),
+            TokenData::Comma,
+            TokenData::CloseBracket(BracketType::Smooth),
+        ];
+
+        let mut names = vec![];
+        loop {
+            let tok = self.next_tok(EXPECTING_IN_PATTERN)?;
+            match tok.data {
+                TokenData::CloseBracket(BracketType::Smooth) => break Ok(names),
+                TokenData::Comma => continue,
+                TokenData::Ident("_") => names.push(None),
+                TokenData::Ident(name) => names.push(Some(self.symbol(name))),
+                _ => {
+                    return Err(ParseError {
+                        highlighted_span: Some(tok.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: tok,
+                            expecting: ExpectingOneOf(EXPECTING_IN_PATTERN),
+                        },
+                    })
+                }
+            }
+        }
+    }
+
+    /// Parse an integer constant used in a match arm pattern, which may be negated
+    fn parse_int_pattern(&mut self) -> ParseResult<'src, BigInt> {
+        const EXPECTING_INT_PATTERN: &[TokenData<'static>] =
+            &[TokenData::Number("integer pattern")];
+
+        let negated = matches!(
+            self.toks.peek().map(|tok| &tok.data),
+            Some(TokenData::Op(Op::Sub))
+        );
+        if negated {
+            self.toks.next();
+            self.negated_literal = true;
+        }
+
+        let peeked = self.peek_tok(EXPECTING_INT_PATTERN)?.clone();
+        match self.parse_numliteral()? {
+            NumberLiteral::Integer(num, _) => Ok(BigInt {
+                sign: negated && num.val != 0,
+                ..num
+            }),
+            NumberLiteral::Float(..) => Err(ParseError {
+                highlighted_span: Some(peeked.span),
+                backtrace: self.trace.clone(),
+                error: ParseErrorKind::UnexpectedToken {
+                    found: peeked,
+                    expecting: ExpectingOneOf(EXPECTING_INT_PATTERN),
+                },
+            }),
+        }
+    }
+
+    /// Parse a while loop with an optional label, where `start` is the span of the first token
+    /// of the loop
+    fn parse_while(&mut self, label: Option<Symbol>, start: Span) -> ParseResult<'src, Ast> {
+        self.expect_next(&[TokenData::Ident("while")])?;
+        self.trace.push("while condition".into());
+        let cond = self.parse_expr()?;
+        self.trace.pop();
+
+        self.trace.push("while body".into());
+        let (body, body_span) = self.parse_body()?;
+        self.trace.pop();
+
+        Ok(Ast {
+            span: (start.from, body_span.to).into(),
+            node: AstNode::While {
+                label,
+                cond: Box::new(cond),
+                body,
+            },
+        })
+    }
+
     /// Parse an if statement
     fn parse_if(&mut self) -> ParseResult<'src, IfExpr<UnresolvedType>> {
         self.expect_next(&[TokenData::Ident("if")])?;
@@ -882,10 +1754,26 @@ impl<'src> Parser<'src> {
             TokenData::Ident("variable or function name"),
             TokenData::OpenBracket(BracketType::Smooth),
             TokenData::OpenBracket(BracketType::Curly),
+            TokenData::Label("block label"),
         ];
 
         let next = self.peek_tok(EXPECTING_NEXT)?.clone();
         let member_of = match next.data {
+            TokenData::Label(label) => {
+                self.toks.next();
+                self.expect_next(&[TokenData::Colon])?;
+                if let Some(TokenData::Ident("while")) = self.toks.peek().map(|tok| &tok.data) {
+                    let label = self.symbol(label);
+                    return self.parse_while(Some(label), next.span);
+                }
+                self.trace.push(format!("block expression '{}", label).into());
+                let block = self.parse_body()?;
+
+                Ast {
+                    span: (next.span.from, block.1.to).into(),
+                    node: AstNode::Block(Some(self.symbol(label)), block.0),
+                }
+            }
             TokenData::Ident(_) => {
                 self.trace.push("variable or function name".into());
                 let name = self.expect_next_path(EXPECTING_NEXT)?;
@@ -900,7 +1788,7 @@ impl<'src> Parser<'src> {
 
                 Ast {
                     span: block.1,
-                    node: AstNode::Block(block.0),
+                    node: AstNode::Block(None, block.0),
                 }
             }
             TokenData::OpenBracket(BracketType::Smooth) => {
@@ -917,9 +1805,47 @@ impl<'src> Parser<'src> {
                 }
 
                 let expr = self.parse_expr()?;
-                self.expect_next(&[TokenData::CloseBracket(BracketType::Smooth)])?;
+                if let Some(TokenData::Comma) = self.toks.peek().map(|tok| &tok.data) {
+                    self.trace.push("tuple literal".into());
+                    let mut elements = vec![expr];
+                    let end_loc = loop {
+                        const EXPECTING_AFTER_ELEMENT: &[TokenData<'static>] = &[
+                            TokenData::Comma,
+                            TokenData::CloseBracket(BracketType::Smooth),
+                        ];
+
+                        let after = self.next_tok(EXPECTING_AFTER_ELEMENT)?;
+                        match after.data {
+                            TokenData::CloseBracket(BracketType::Smooth) => break after.span.to,
+                            TokenData::Comma => elements.push(self.parse_expr()?),
+                            _ => {
+                                return Err(ParseError {
+                                    highlighted_span: Some(after.span),
+                                    backtrace: self.trace.clone(),
+                                    error: ParseErrorKind::UnexpectedToken {
+                                        found: after,
+                                        expecting: ExpectingOneOf(EXPECTING_AFTER_ELEMENT),
+                                    },
+                                })
+                            }
+                        }
+                    };
+                    self.trace.pop();
 
-                expr
+                    Ast {
+                        span: (next.span.from, end_loc).into(),
+                        node: AstNode::Literal(Literal::Tuple(elements)),
+                    }
+                } else {
+                    let close = self
+                        .peek_tok(&[TokenData::CloseBracket(BracketType::Smooth)])?
+                        .span;
+                    self.expect_next(&[TokenData::CloseBracket(BracketType::Smooth)])?;
+                    Ast {
+                        span: (next.span.from, close.to).into(),
+                        ..expr
+                    }
+                }
             }
             _ => {
                 return Err(ParseError {
@@ -961,22 +1887,38 @@ impl<'src> Parser<'src> {
                 let next = self.next_tok(EXPECTING_AFTER_PERIOD)?;
                 match next.data {
                     TokenData::OpenBracket(BracketType::Smooth) => {
+                        const EXPECTING_AFTER_CALL_ARG: &[TokenData<'static>] = &[
+                            TokenData::Comma,
+                            TokenData::CloseBracket(BracketType::Smooth),
+                        ];
+
                         let mut args = vec![];
 
                         loop {
-                            let next_in_args = self.peek_tok(Self::EXPECTED_FOR_EXPRESSION)?;
-                            match next_in_args.data {
-                                TokenData::Comma => {
-                                    self.next_tok(&[TokenData::Comma])?;
-                                }
-                                TokenData::CloseBracket(BracketType::Smooth) => {
-                                    self.next_tok(&[TokenData::CloseBracket(BracketType::Smooth)])?;
-                                    break;
-                                }
+                            if let TokenData::CloseBracket(BracketType::Smooth) =
+                                self.peek_tok(Self::EXPECTED_FOR_EXPRESSION)?.data
+                            {
+                                self.toks.next();
+                                break;
+                            }
+
+                            self.trace.push("function call argument".into());
+                            args.push(self.parse_expr()?);
+                            self.trace.pop();
+
+                            let next = self.next_tok(EXPECTING_AFTER_CALL_ARG)?;
+                            match next.data {
+                                TokenData::Comma => (),
+                                TokenData::CloseBracket(BracketType::Smooth) => break,
                                 _ => {
-                                    self.trace.push("function call argument".into());
-                                    args.push(self.parse_expr()?);
-                                    self.trace.pop();
+                                    return Err(ParseError {
+                                        highlighted_span: Some(next.span),
+                                        backtrace: self.trace.clone(),
+                                        error: ParseErrorKind::UnexpectedToken {
+                                            found: next,
+                                            expecting: ExpectingOneOf(EXPECTING_AFTER_CALL_ARG),
+                                        },
+                                    })
                                 }
                             }
                         }
@@ -1034,6 +1976,15 @@ impl<'src> Parser<'src> {
     /// Parse a full typename from the input stream
     fn parse_typename(&mut self) -> ParseResult<'src, UnresolvedType> {
         let first = self.parse_first_typename()?;
+        self.parse_enum_typename_rest(first)
+    }
+
+    /// Parse the remaining variants of an enum typename after the first variant, returning the
+    /// first typename if it is not followed by `|`
+    fn parse_enum_typename_rest(
+        &mut self,
+        first: UnresolvedType,
+    ) -> ParseResult<'src, UnresolvedType> {
         match self.toks.peek().map(|tok| &tok.data) {
             Some(TokenData::Op(Op::OR)) => {
                 let mut variants = vec![first];
@@ -1061,6 +2012,7 @@ impl<'src> Parser<'src> {
             TokenData::OpenBracket(BracketType::Smooth),
             TokenData::OpenBracket(BracketType::Square),
             TokenData::Op(Op::Star),
+            TokenData::Op(Op::Less),
         ];
 
         const EXPECTING_INTEGER: &[TokenData<'static>] = &[
@@ -1078,6 +2030,14 @@ impl<'src> Parser<'src> {
 
         match next.data {
             TokenData::Ident(name) => match &name[0..1] {
+                "u" if name == "union" => {
+                    self.trace.push("union typename".into());
+                    self.expect_next(&[TokenData::OpenBracket(BracketType::Curly)])?;
+                    let fields = self.parse_fields(None)?;
+                    self.trace.pop();
+
+                    Ok(UnresolvedType::Union { fields })
+                }
                 "i" | "u" => {
                     let signed = &name[0..1] == "i";
 
@@ -1178,9 +2138,11 @@ impl<'src> Parser<'src> {
                         &[TokenData::Ident("typename path part")],
                         name,
                     )?;
+                    let args = self.parse_type_args()?;
                      
                     let ty = UnresolvedType::UserDefined {
                         name,
+                        args,
                     };
                     self.trace.pop();
                     Ok(ty)
@@ -1188,9 +2150,14 @@ impl<'src> Parser<'src> {
             },
             TokenData::OpenBracket(BracketType::Square) => {
                 self.trace.push("array type length".into());
-                let len = match self.parse_numliteral()? {
-                    NumberLiteral::Integer(bigint, _) => bigint.val,
-                    NumberLiteral::Float(floating, _) => floating as u64,
+                let len = match self.peek_tok(&[TokenData::Number("array length")])?.data {
+                    TokenData::Ident(_) => {
+                        ArrayLen::Const(self.expect_next_path(&[TokenData::Ident("constant name")])?)
+                    }
+                    _ => ArrayLen::Literal(match self.parse_numliteral()? {
+                        NumberLiteral::Integer(bigint, _) => bigint.val,
+                        NumberLiteral::Float(floating, _) => floating as u64,
+                    }),
                 };
 
                 self.trace.pop();
@@ -1219,57 +2186,8 @@ impl<'src> Parser<'src> {
                 }
             }
             TokenData::OpenBracket(BracketType::Curly) => {
-                const EXPECTING_FOR_STRUCT: &[TokenData<'static>] = &[
-                    TokenData::Ident("field type"),
-                    TokenData::CloseBracket(BracketType::Curly),
-                    TokenData::OpenBracket(BracketType::Square),
-                    TokenData::OpenBracket(BracketType::Smooth),
-                    TokenData::Op(Op::Star),
-                ];
-
                 self.trace.push("structure typename".into());
-
-                let mut fields = vec![];
-
-                loop {
-                    const EXPECTING_AFTER_FIELD: &[TokenData<'static>] = &[
-                        TokenData::Comma,
-                        TokenData::CloseBracket(BracketType::Curly),
-                    ];
-
-                    if let TokenData::CloseBracket(BracketType::Curly) =
-                        self.peek_tok(EXPECTING_FOR_STRUCT)?.data
-                    {
-                        self.toks.next();
-                        break;
-                    }
-
-                    self.trace.push("struct type field".into());
-                    let field_typename = self.parse_typename()?;
-
-                    let field_name =
-                        self.expect_next_ident(&[TokenData::Ident("struct field name")])?;
-                    self.trace.pop();
-                    fields.push((field_typename, self.symbol(field_name)));
-
-                    let next = self.next_tok(EXPECTING_AFTER_FIELD)?;
-
-                    match next.data {
-                        TokenData::Comma => (),
-                        TokenData::CloseBracket(BracketType::Curly) => break,
-                        _ => {
-                            return Err(ParseError {
-                                highlighted_span: Some(next.span),
-                                backtrace: self.trace.clone(),
-                                error: ParseErrorKind::UnexpectedToken {
-                                    found: next,
-                                    expecting: ExpectingOneOf(EXPECTING_AFTER_FIELD),
-                                },
-                            })
-                        }
-                    }
-                }
-
+                let fields = self.parse_fields(None)?;
                 self.trace.pop();
 
                 Ok(UnresolvedType::Struct { fields })
@@ -1290,8 +2208,13 @@ impl<'src> Parser<'src> {
                     }
                     _ => {
                         let ty = self.parse_typename()?;
-                        self.expect_next(&[TokenData::CloseBracket(BracketType::Smooth)])?;
-                        ty
+                        let elements = self.parse_tuple_typename_rest(ty)?;
+                        self.trace.pop();
+                        if elements.len() == 1 {
+                            elements.into_iter().next().unwrap()
+                        } else {
+                            UnresolvedType::Tuple { elements }
+                        }
                     }
                 };
 
@@ -1304,6 +2227,34 @@ impl<'src> Parser<'src> {
 
                 Ok(UnresolvedType::Pointer(Box::new(pointed_to)))
             }
+            TokenData::Op(Op::Less) => {
+                self.trace.push("vector type".into());
+                let len = match self.parse_numliteral()? {
+                    NumberLiteral::Integer(bigint, _) => bigint.val,
+                    NumberLiteral::Float(floating, _) => floating as u64,
+                };
+
+                let x = self.next_tok(&[TokenData::Ident("x")])?;
+                if x.data != TokenData::Ident("x") {
+                    return Err(ParseError {
+                        highlighted_span: Some(x.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: x,
+                            expecting: ExpectingOneOf(&[TokenData::Ident("x")]),
+                        },
+                    });
+                }
+
+                let element = self.parse_first_typename()?;
+                self.expect_next(&[TokenData::Op(Op::Greater)])?;
+                self.trace.pop();
+
+                Ok(UnresolvedType::Vector {
+                    element: Box::new(element),
+                    len,
+                })
+            }
             _ => Err(ParseError {
                 highlighted_span: Some(next.span),
                 backtrace: self.trace.clone(),
@@ -1315,10 +2266,113 @@ impl<'src> Parser<'src> {
         }
     }
 
+    /// Parse the typed and named fields of a structure or union type after the opening curly
+    /// brace, consuming the closing curly brace. Fields may be given default values with
+    /// `= expr` after their name if `defaults` is given to collect them in
+    fn parse_fields(
+        &mut self,
+        mut defaults: Option<&mut Vec<(Symbol, Ast)>>,
+    ) -> ParseResult<'src, Vec<(UnresolvedType, Symbol)>> {
+        const EXPECTING_FOR_STRUCT: &[TokenData<'static>] = &[
+            TokenData::Ident("field type"),
+            TokenData::CloseBracket(BracketType::Curly),
+            TokenData::OpenBracket(BracketType::Square),
+            TokenData::OpenBracket(BracketType::Smooth),
+            TokenData::Op(Op::Star),
+        ];
+
+        let mut fields = vec![];
+
+        loop {
+            const EXPECTING_AFTER_FIELD: &[TokenData<'static>] = &[
+                TokenData::Comma,
+                TokenData::CloseBracket(BracketType::Curly),
+            ];
+
+            if let TokenData::CloseBracket(BracketType::Curly) =
+                self.peek_tok(EXPECTING_FOR_STRUCT)?.data
+            {
+                self.toks.next();
+                break;
+            }
+
+            self.trace.push("struct type field".into());
+            let field_typename = self.parse_typename()?;
+
+            let field_name = self.expect_next_ident(&[TokenData::Ident("struct field name")])?;
+            let field_name = self.symbol(field_name);
+            if let Some(ref mut defaults) = defaults {
+                if let Some(TokenData::Assign) = self.toks.peek().map(|tok| &tok.data) {
+                    self.toks.next();
+                    self.trace.push("struct field default value".into());
+                    defaults.push((field_name, self.parse_expr()?));
+                    self.trace.pop();
+                }
+            }
+            self.trace.pop();
+            fields.push((field_typename, field_name));
+
+            let next = self.next_tok(EXPECTING_AFTER_FIELD)?;
+
+            match next.data {
+                TokenData::Comma => (),
+                TokenData::CloseBracket(BracketType::Curly) => break,
+                _ => {
+                    return Err(ParseError {
+                        highlighted_span: Some(next.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: next,
+                            expecting: ExpectingOneOf(EXPECTING_AFTER_FIELD),
+                        },
+                    })
+                }
+            }
+        }
+
+        Ok(fields)
+    }
+
+    /// Parse the remaining comma-separated typenames of a parenthesized type list after the
+    /// first typename, consuming the closing parenthesis
+    fn parse_tuple_typename_rest(
+        &mut self,
+        first: UnresolvedType,
+    ) -> ParseResult<'src, Vec<UnresolvedType>> {
+        const EXPECTING_AFTER_ELEMENT: &[TokenData<'static>] = &[
+            TokenData::Comma,
+            TokenData::CloseBracket(BracketType::Smooth),
+        ];
+
+        let mut elements = vec![first];
+        loop {
+            let next = self.next_tok(EXPECTING_AFTER_ELEMENT)?;
+            match next.data {
+                TokenData::CloseBracket(BracketType::Smooth) => break Ok(elements),
+                TokenData::Comma => {
+                    self.trace.push("tuple element typename".into());
+                    elements.push(self.parse_typename()?);
+                    self.trace.pop();
+                }
+                _ => {
+                    return Err(ParseError {
+                        highlighted_span: Some(next.span),
+                        backtrace: self.trace.clone(),
+                        error: ParseErrorKind::UnexpectedToken {
+                            found: next,
+                            expecting: ExpectingOneOf(EXPECTING_AFTER_ELEMENT),
+                        },
+                    })
+                }
+            }
+        }
+    }
+
     /// Parse a number literal from the token stream
     fn parse_numliteral(&mut self) -> ParseResult<'src, NumberLiteral> {
         const EXPECTED_FOR_NUMLITERAL: &[TokenData<'static>] =
-            &[TokenData::Number("Number Literal")];
+            &[TokenData::Number("number literal")];
+        let negated = std::mem::take(&mut self.negated_literal);
         let next = self.next_tok(EXPECTED_FOR_NUMLITERAL)?;
         if let TokenData::Number(num_str) = next.data {
             let (base, ignore_start) = if num_str.len() > 2 {
@@ -1385,8 +2439,29 @@ impl<'src> Parser<'src> {
                     None
                 };
 
+            let overflow = |ty: &'static str| ParseError {
+                highlighted_span: Some(next.span),
+                backtrace: self.trace.clone(),
+                error: ParseErrorKind::IntegerOverflow {
+                    number: num_str,
+                    ty,
+                },
+            };
+
             Ok(match u64::from_str_radix(number, base) {
-                Ok(val) => NumberLiteral::Integer(BigInt { val, sign: false }, annotation),
+                Ok(val) => {
+                    match annotation.and_then(|a| a.max_literal(negated).map(|max| (a, max))) {
+                        Some((annotation, max)) if val > max => {
+                            return Err(overflow(annotation.name()))
+                        }
+                        _ => NumberLiteral::Integer(BigInt { val, sign: false }, annotation),
+                    }
+                }
+                //Integer literals that are too large for a u64 must not be reinterpreted as
+                //floating point numbers
+                Err(_) if number.chars().all(|c| c.is_digit(base)) => {
+                    return Err(overflow(annotation.map(|a| a.name()).unwrap_or("u64")))
+                }
                 Err(_) => match number.parse::<f64>() {
                     Ok(val) => NumberLiteral::Float(val, annotation),
                     Err(_) => {
@@ -1423,6 +2498,54 @@ pub struct ParseError<'src> {
     pub error: ParseErrorKind<'src>,
 }
 
+impl ParseError<'_> {
+    /// Convert this error to a diagnostic that labels the offending token in the given file,
+    /// with the parser's backtrace added as notes
+    pub fn to_diagnostic(&self, file: FileId) -> Diagnostic<FileId> {
+        let (message, label) = match &self.error {
+            ParseErrorKind::UnexpectedToken { found, expecting } => (
+                format!("Unexpected {}", found.data),
+                Some(format!("Expecting {}", expecting)),
+            ),
+            ParseErrorKind::UnexpectedEOF { expecting } => (
+                "Unexpected end of file".to_owned(),
+                Some(format!("Expecting {}", expecting)),
+            ),
+            ParseErrorKind::ReservedKeyword { keyword, expecting } => (
+                format!("'{}' is a reserved keyword", keyword),
+                Some(format!("Expecting {}", expecting)),
+            ),
+            ParseErrorKind::ChainedComparison { first, second } => (
+                format!("Comparison operators '{}' and '{}' cannot be chained", first, second),
+                Some(format!(
+                    "Write '(a {} b) && (b {} c)' to compare both operands",
+                    first, second
+                )),
+            ),
+            ParseErrorKind::ExternBlockDefinition => (
+                "Only function declarations can appear in an extern block".to_owned(),
+                Some("Move this definition out of the extern block".to_owned()),
+            ),
+            other => (other.to_string().trim_end().to_owned(), None),
+        };
+
+        let diag = Diagnostic::error()
+            .with_message(message)
+            .with_notes(self.backtrace.iter().map(|trace| format!("in {}", trace)).collect());
+
+        match self.highlighted_span {
+            Some(span) => {
+                let primary = Label::primary(file, span);
+                diag.with_labels(vec![match label {
+                    Some(label) => primary.with_message(label),
+                    None => primary,
+                }])
+            }
+            None => diag,
+        }
+    }
+}
+
 /// Enumeration containing all possible parser errors
 #[derive(Clone, Debug)]
 pub enum ParseErrorKind<'src> {
@@ -1435,8 +2558,21 @@ pub enum ParseErrorKind<'src> {
     },
     /// The source ended unexpectedly
     UnexpectedEOF { expecting: ExpectingOneOf },
+    /// A reserved keyword was used where an identifier was expected
+    ReservedKeyword {
+        /// The keyword that was found
+        keyword: &'src str,
+        /// What the identifier was expected to name
+        expecting: ExpectingOneOf,
+    },
     /// Failed to parse a number literal
     NumberParse { number: &'src str },
+    /// An integer literal is too large to fit in its type
+    IntegerOverflow {
+        number: &'src str,
+        /// Name of the type that the literal was parsed as
+        ty: &'static str,
+    },
     /// An unknown escape sequence was encountered in a string literal
     UnknownEscapeSeq { escaped: char, literal: &'src str },
     /// A backslash character was encountered with no escaped character
@@ -1444,6 +2580,18 @@ pub enum ParseErrorKind<'src> {
         /// The string that an escape sequence was found in
         literal: &'src str,
     },
+    /// Two comparison operators were chained without parentheses, as in `a < b < c`
+    ChainedComparison { first: Op, second: Op },
+    /// A definition other than a function declaration without a body was found in an
+    /// `extern` block
+    ExternBlockDefinition,
+    /// A byte range to reparse is out of bounds of the source string or does not fall on
+    /// character boundaries
+    InvalidRange {
+        range: Span,
+        /// Length of the source string in bytes
+        len: usize,
+    },
 }
 
 impl fmt::Display for ParseErrorKind<'_> {
@@ -1457,9 +2605,17 @@ impl fmt::Display for ParseErrorKind<'_> {
             Self::UnexpectedEOF { expecting } => {
                 writeln!(f, "Unexpected EOF, expecting {}", expecting)
             }
+            Self::ReservedKeyword { keyword, expecting } => writeln!(
+                f,
+                "Reserved keyword '{}' cannot be used as an identifier, expecting {}",
+                keyword, expecting
+            ),
             Self::NumberParse { number } => {
                 writeln!(f, "Failed to parse numeric literal {}", number)
             }
+            Self::IntegerOverflow { number, ty } => {
+                writeln!(f, "Integer literal {} is too large for type {}", number, ty)
+            }
             Self::UnknownEscapeSeq { escaped, literal } => writeln!(
                 f,
                 "Unknown escape sequence '\\{}' in string literal \"{}\"",
@@ -1468,6 +2624,20 @@ impl fmt::Display for ParseErrorKind<'_> {
             Self::ExpectingEscapeSeq { literal } => {
                 writeln!(f, "Expecting an escape sequence in \"{}\"", literal)
             }
+            Self::ChainedComparison { first, second } => writeln!(
+                f,
+                "Comparison operators '{}' and '{}' cannot be chained, write '(a {} b) && (b {} c)' instead",
+                first, second, first, second
+            ),
+            Self::ExternBlockDefinition => writeln!(
+                f,
+                "Only function declarations without a body can appear in an extern block"
+            ),
+            Self::InvalidRange { range, len } => writeln!(
+                f,
+                "Byte range {}..{} is not a valid range of the {} byte source string",
+                range.from, range.to, len
+            ),
         }
     }
 }
@@ -1476,19 +2646,446 @@ impl fmt::Display for ParseErrorKind<'_> {
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ExpectingOneOf(&'static [TokenData<'static>]);
 
+impl ExpectingOneOf {
+    /// Describe an expected token by its category instead of its placeholder text, like
+    /// `keyword 'fun'` or `identifier (function name)`
+    fn describe(expected: &TokenData<'static>) -> String {
+        const INTEGER_TYPES: &[&str] = &["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64"];
+
+        match expected {
+            TokenData::Ident(name) if name.contains(' ') => format!("identifier ({})", name),
+            TokenData::Ident(name) if INTEGER_TYPES.contains(name) => "integer type".to_owned(),
+            TokenData::Ident("f32" | "f64") => "floating point type".to_owned(),
+            TokenData::Ident(name) if KEYWORDS.contains(name) => format!("keyword '{}'", name),
+            TokenData::Ident(name) => format!("'{}'", name),
+            TokenData::Number("number literal") => "number literal".to_owned(),
+            TokenData::Number(purpose) => format!("number literal ({})", purpose),
+            TokenData::String("string literal") => "string literal".to_owned(),
+            TokenData::String(literal) => format!("string literal \"{}\"", literal),
+            TokenData::Char(purpose) => format!("character literal ({})", purpose),
+            TokenData::Label(purpose) => format!("loop label ({})", purpose),
+            other => other.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for ExpectingOneOf {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let mut first = true;
+        if self.0.is_empty() {
+            return write!(f, "end of input");
+        }
+
+        let mut described = Vec::<String>::with_capacity(self.0.len());
         for expecting in self.0.iter() {
-            if !first {
-                write!(f, ", ")?;
+            let description = Self::describe(expecting);
+            if !described.contains(&description) {
+                described.push(description);
+            }
+        }
+
+        write!(f, "{}", described.join(", "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::files::{CompiledFile, Files};
+
+    /// Parse a source file that is expected to contain errors, returning all errors reported
+    fn parse_errors(src: &str) -> Vec<ParseError<'_>> {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        match Parser::new(src).parse(Symbol::from("test"), file) {
+            Ok(_) => panic!("Source parsed without errors"),
+            Err(errors) => errors,
+        }
+    }
+
+    /// Parse a source file that is expected to be valid, returning the body of a function
+    /// defined in it
+    fn parse_fun_body(src: &str, name: &str) -> Vec<Ast> {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src)
+            .parse(Symbol::from("test"), file)
+            .unwrap_or_else(|_| panic!("Failed to parse source"));
+        match &module.defs[&Symbol::from(name)].data {
+            DefData::FunDef(_, body) => body.clone(),
+            _ => panic!("{} is not a function definition", name),
+        }
+    }
+
+    #[test]
+    fn expected_tokens_are_described_by_category() {
+        assert_eq!(
+            ExpectingOneOf::describe(&TokenData::Ident("function name")),
+            "identifier (function name)"
+        );
+        assert_eq!(ExpectingOneOf::describe(&TokenData::Ident("u16")), "integer type");
+        assert_eq!(ExpectingOneOf::describe(&TokenData::Ident("f32")), "floating point type");
+        assert_eq!(ExpectingOneOf::describe(&TokenData::Ident("fun")), "keyword 'fun'");
+        assert_eq!(ExpectingOneOf::describe(&TokenData::Ident("while")), "keyword 'while'");
+        assert_eq!(ExpectingOneOf::describe(&TokenData::Ident("impl")), "keyword 'impl'");
+        assert_eq!(
+            ExpectingOneOf::describe(&TokenData::Number("number literal")),
+            "number literal"
+        );
+        assert_eq!(
+            ExpectingOneOf::describe(&TokenData::Number("array length")),
+            "number literal (array length)"
+        );
+        assert_eq!(ExpectingOneOf::describe(&TokenData::String("C")), "string literal \"C\"");
+    }
+
+    #[test]
+    fn repeated_categories_are_listed_once() {
+        let expecting = ExpectingOneOf(&[
+            TokenData::Ident("i8"),
+            TokenData::Ident("u64"),
+            TokenData::Ident("type name"),
+        ]);
+        assert_eq!(expecting.to_string(), "integer type, identifier (type name)");
+        assert_eq!(ExpectingOneOf(&[]).to_string(), "end of input");
+    }
+
+    #[test]
+    fn misplaced_token_lists_expected_categories() {
+        let errors = parse_errors("fun 5() {}");
+        let message = errors[0].error.to_string();
+        assert!(
+            message.contains("expecting identifier (function name)"),
+            "Unexpected message: {}",
+            message
+        );
+    }
+
+    #[test]
+    fn signed_literals_above_maximum_overflow() {
+        let errors = parse_errors("fun f() -> i8 { return 128i8 }");
+        assert!(matches!(
+            errors[0].error,
+            ParseErrorKind::IntegerOverflow { ty: "i8", .. }
+        ));
+    }
+
+    #[test]
+    fn negated_literals_reach_signed_minimum() {
+        let src = "fun f() -> i8 { return -128i8 }";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        assert!(Parser::new(src).parse(Symbol::from("test"), file).is_ok());
+
+        let errors = parse_errors("fun f() -> i8 { return -129i8 }");
+        assert!(matches!(
+            errors[0].error,
+            ParseErrorKind::IntegerOverflow { ty: "i8", .. }
+        ));
+    }
+
+    #[test]
+    fn recovery_reports_independent_errors() {
+        let errors = parse_errors("fun f() {\n    return )\n    fun inner() {}\n}\nfun 5() {}\n");
+        assert_eq!(errors.len(), 2);
+
+        let errors = parse_errors("fun f() {\n    return )\n}\nmut x = )\n");
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn error_spans_cover_the_offending_token() {
+        let errors = parse_errors("fun 55() {}");
+        let span = errors[0].highlighted_span.unwrap();
+        assert_eq!((span.from, span.to), (4, 6));
+    }
+
+    #[test]
+    fn end_of_input_errors_point_to_the_end() {
+        let src = "fun f(";
+        let errors = parse_errors(src);
+        assert!(matches!(errors[0].error, ParseErrorKind::UnexpectedEOF { .. }));
+        let span = errors[0].highlighted_span.unwrap();
+        assert_eq!((span.from, span.to), (src.len(), src.len()));
+    }
+
+    #[test]
+    fn reparsed_body_reflects_the_edit() {
+        let src = "fun a() -> i32 { return 1 }\nfun b() -> i32 { return 3 }\n";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let mut module = Parser::new(src)
+            .parse(Symbol::from("test"), file)
+            .unwrap_or_else(|_| panic!("Failed to parse source"));
+        let body_of = |module: &ParsedModule, name: &str| match &module.defs[&Symbol::from(name)].data {
+            DefData::FunDef(_, body) => body.clone(),
+            _ => panic!("{} is not a function definition", name),
+        };
+        let a_before = body_of(&module, "a");
+
+        let edited = src.replace("return 3", "return 30 + 4");
+        let from = edited.rfind('{').unwrap();
+        let to = edited.rfind('}').unwrap() + 1;
+        let (body, span) = Parser::new(&edited)
+            .parse_body_in(&edited, Span::new(from, to))
+            .unwrap_or_else(|_| panic!("Failed to reparse function body"));
+        assert!(span.from >= from && span.to <= to);
+        match &mut module.defs.get_mut(&Symbol::from("b")).unwrap().data {
+            DefData::FunDef(_, old_body) => *old_body = body,
+            _ => unreachable!(),
+        }
+
+        let b_after = body_of(&module, "b");
+        match &b_after[0].node {
+            AstNode::Return(val) => assert!(matches!(val.node, AstNode::BinExpr(_, Op::Add, ..))),
+            _ => panic!("Reparsed body is not a return statement"),
+        }
+        assert_eq!(&edited[b_after[0].span.from..b_after[0].span.to], "return 30 + 4");
+        assert_eq!(body_of(&module, "a"), a_before);
+
+        //The spliced module matches a full parse of the edited source
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(edited.clone()));
+        let reparsed = Parser::new(&edited)
+            .parse(Symbol::from("test"), file)
+            .unwrap_or_else(|_| panic!("Failed to parse edited source"));
+        assert_eq!(body_of(&reparsed, "a"), body_of(&module, "a"));
+        assert_eq!(body_of(&reparsed, "b"), b_after);
+    }
+
+    #[test]
+    fn reparsing_an_invalid_range_is_an_error() {
+        let src = "fun a() -> *u8 { return \"é\" }\n";
+        let inside_char = src.find('é').unwrap() + 1;
+        for range in &[Span::new(0, src.len() + 1), Span::new(0, inside_char), Span::new(5, 2)] {
+            match Parser::new(src).parse_body_in(src, *range) {
+                Err(e) => assert!(matches!(e.error, ParseErrorKind::InvalidRange { .. })),
+                Ok(_) => panic!("Reparsed invalid range {:?}", range),
+            }
+        }
+    }
+
+    #[test]
+    fn reparsing_stops_at_the_end_of_the_range() {
+        //The range covers only the first function's body, so the second function is never
+        //lexed even though it contains an error
+        let src = "fun a() -> i32 { return 1 }\nfun 5() {}\n";
+        let from = src.find('{').unwrap();
+        let to = src.find('}').unwrap() + 1;
+        assert!(Parser::new(src)
+            .parse_body_in(src, Span::new(from, to))
+            .is_ok());
+    }
+
+    #[test]
+    fn boolean_literals_parse_as_if_conditions() {
+        let body = parse_fun_body("fun pick() -> i32 { if true { return 1 } return 0 }", "pick");
+        match &body[0].node {
+            AstNode::IfExpr(if_expr) => assert!(matches!(
+                if_expr.cond.node,
+                AstNode::Literal(Literal::Bool(true))
+            )),
+            _ => panic!("First statement is not an if expression"),
+        }
+    }
+
+    #[test]
+    fn negative_integer_patterns_are_signed() {
+        let body = parse_fun_body(
+            "fun sign(i32 n) -> i32 {\n    return match n {\n        -5..=-1 -> phi -1\n        -10 -> phi -2\n        _ -> phi 0\n    }\n}\n",
+            "sign",
+        );
+        let cases = match &body[0].node {
+            AstNode::Return(returned) => match &returned.node {
+                AstNode::Match { cases, .. } => cases,
+                _ => panic!("Returned value is not a match expression"),
+            },
+            _ => panic!("First statement is not a return statement"),
+        };
+        assert!(matches!(
+            cases[0].0,
+            MatchPattern::Range(BigInt { val: 5, sign: true }, BigInt { val: 1, sign: true })
+        ));
+        assert!(matches!(cases[1].0, MatchPattern::Integer(BigInt { val: 10, sign: true })));
+    }
+
+    #[test]
+    fn cfg_attributes_select_definitions_for_the_target() {
+        let src = "#[cfg(target = \"wasm32\")]\nfun page_size() -> u32 { return 65536u32 }\n#[cfg(target = \"x86_64\")]\nfun page_size() -> u32 { return 4096u32 }\n";
+        let page_size = |target: &str| {
+            let mut files = Files::new();
+            let file = files.add(CompiledFile::in_memory(src.to_owned()));
+            let mut parser = Parser::new(src);
+            parser.set_target(target);
+            let module = parser
+                .parse(Symbol::from("test"), file)
+                .unwrap_or_else(|_| panic!("Failed to parse source"));
+            let body = match &module.defs[&Symbol::from("page_size")].data {
+                DefData::FunDef(_, body) => body.clone(),
+                _ => panic!("page_size is not a function definition"),
+            };
+            match &body[0].node {
+                AstNode::Return(returned) => match &returned.node {
+                    AstNode::Literal(Literal::Number(NumberLiteral::Integer(int, _))) => int.val,
+                    _ => panic!("Returned value is not an integer literal"),
+                },
+                _ => panic!("First statement is not a return statement"),
+            }
+        };
+
+        assert_eq!(page_size("wasm32-unknown-unknown"), 65536);
+        assert_eq!(page_size("x86_64-unknown-linux-gnu"), 4096);
+    }
+
+    #[test]
+    fn parse_errors_render_with_a_caret_under_the_token() {
+        use codespan_reporting::term::{self, termcolor::NoColor};
+
+        let src = "fun 55() {}";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let errors = match Parser::new(src).parse(Symbol::from("test"), file) {
+            Ok(_) => panic!("Source parsed without errors"),
+            Err(errors) => errors,
+        };
+
+        let mut out = NoColor::new(Vec::new());
+        term::emit(&mut out, &term::Config::default(), &files, &errors[0].to_diagnostic(file))
+            .expect("Failed to render diagnostic");
+        let rendered = String::from_utf8(out.into_inner()).unwrap();
+
+        let lines = rendered.lines().collect::<Vec<_>>();
+        let source_line = lines
+            .iter()
+            .position(|line| line.ends_with(src))
+            .unwrap_or_else(|| panic!("Source line not rendered:\n{}", rendered));
+        let token_column = lines[source_line].len() - src.len() + 4;
+        let caret_line = lines[source_line + 1];
+        assert_eq!(
+            caret_line.find('^'),
+            Some(token_column),
+            "Caret not under token:\n{}",
+            rendered
+        );
+        assert!(caret_line[token_column..].starts_with("^^ Expecting"), "{}", rendered);
+    }
+
+    /// Parse a source file that is expected to be valid, returning the definition with the
+    /// given name
+    fn parse_def(src: &str, name: &str) -> DefData {
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let module = Parser::new(src)
+            .parse(Symbol::from("test"), file)
+            .unwrap_or_else(|_| panic!("Failed to parse source"));
+        module.defs[&Symbol::from(name)].data.clone()
+    }
+
+    #[test]
+    fn structure_bodies_accept_a_trailing_comma() {
+        match parse_def("type pair = {\n    i32 first,\n    i32 second,\n}\n", "pair") {
+            DefData::AliasDef {
+                aliased: UnresolvedType::Struct { fields },
+                ..
+            } => assert_eq!(fields.len(), 2),
+            _ => panic!("pair is not a structure type"),
+        }
+    }
+
+    #[test]
+    fn parameter_lists_accept_a_trailing_comma() {
+        let src = "fun add(\n    i32 a,\n    i32 b,\n) -> i32 {\n    return a + b\n}\n";
+        match parse_def(src, "add") {
+            DefData::FunDef(proto, _) => {
+                assert_eq!(proto.args.len(), 2);
+                assert_eq!(proto.return_ty.to_string(), "i32");
             }
-            first = false;
+            _ => panic!("add is not a function definition"),
+        }
+    }
+
+    #[test]
+    fn call_arguments_accept_a_trailing_comma() {
+        let src = "fun f() -> i32 {\n    return add.(\n        1,\n        2,\n    )\n}\n";
+        let body = parse_fun_body(src, "f");
+        match &body[0].node {
+            AstNode::Return(val) => match &val.node {
+                AstNode::FunCall(_, args) => assert_eq!(args.len(), 2),
+                _ => panic!("Returned value is not a function call"),
+            },
+            _ => panic!("First statement is not a return statement"),
+        }
+    }
 
-            expecting.fmt(f)?;
+    #[test]
+    fn keywords_cannot_name_variables() {
+        let errors = parse_errors("fun f() {\n    let i32 while = 0\n}\n");
+        assert!(matches!(
+            errors[0].error,
+            ParseErrorKind::ReservedKeyword { keyword: "while", .. }
+        ));
+    }
 
+    #[test]
+    fn chained_comparisons_are_rejected() {
+        for &(src, first, second) in &[
+            ("fun f(i32 a, i32 b, i32 c) -> bool {\n    return a < b < c\n}\n", Op::Less, Op::Less),
+            ("fun f(i32 a, i32 b, bool c) -> bool {\n    return a == b != c\n}\n", Op::Eq, Op::NotEq),
+        ] {
+            let errors = parse_errors(src);
+            assert!(
+                errors.iter().any(|error| matches!(
+                    error.error,
+                    ParseErrorKind::ChainedComparison { first: f, second: s } if f == first && s == second
+                )),
+                "{:?}",
+                errors.iter().map(|error| error.error.to_string()).collect::<Vec<_>>()
+            );
         }
 
-        Ok(())
+        parse_fun_body("fun f(i32 a, i32 b, bool c) -> bool {\n    return (a < b) == c\n}\n", "f");
+    }
+
+    #[test]
+    fn extern_blocks_only_declare_c_functions() {
+        let errors = parse_errors("extern \"Rust\" {\n    fun abs(i32 n) -> i32\n}\n");
+        assert!(
+            errors
+                .iter()
+                .any(|error| matches!(error.error, ParseErrorKind::UnexpectedToken { .. })),
+            "{:?}",
+            errors.iter().map(|error| error.error.to_string()).collect::<Vec<_>>()
+        );
+
+        let errors = parse_errors("extern {\n    fun abs(i32 n) -> i32 {\n        return n\n    }\n}\n");
+        assert!(
+            errors
+                .iter()
+                .any(|error| matches!(error.error, ParseErrorKind::ExternBlockDefinition)),
+            "{:?}",
+            errors.iter().map(|error| error.error.to_string()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn final_statement_needs_no_terminator() {
+        let body = parse_fun_body("fun f() -> i32 {\n    let a = 1\n    return a }", "f");
+        assert_eq!(body.len(), 2);
+        assert!(matches!(body[1].node, AstNode::Return(_)));
+
+        let body = parse_fun_body("fun f() { let a = 1 }", "f");
+        assert_eq!(body.len(), 1);
+    }
+
+    #[test]
+    fn unknown_cfg_predicates_keep_the_definition() {
+        let src = "#[cfg(target = \"wasm32\", os = \"linux\")]\nfun page_size() -> u32 { return 65536u32 }\n";
+        let mut files = Files::new();
+        let file = files.add(CompiledFile::in_memory(src.to_owned()));
+        let mut parser = Parser::new(src);
+        parser.set_target("x86_64-unknown-linux-gnu");
+        let module = parser
+            .parse(Symbol::from("test"), file)
+            .unwrap_or_else(|_| panic!("Failed to parse source"));
+        assert!(module.defs.contains_key(&Symbol::from("page_size")));
     }
 }
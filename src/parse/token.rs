@@ -2,6 +2,8 @@ use std::fmt;
 
 use crate::util::loc::Span;
 
+use super::KEYWORDS;
+
 /// The main type used for a token lexed from a source file containing location information
 /// and token data
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -33,6 +35,8 @@ pub enum TokenData<'src> {
     String(&'src str),
     /// A user-defined character literal not including start or end quotes
     Char(&'src str),
+    /// A loop label not including the leading quote
+    Label(&'src str),
     /// Any opening brace character
     OpenBracket(BracketType),
     /// Any closing brace character
@@ -62,6 +66,7 @@ impl fmt::Display for TokenData<'_> {
             Self::Number(num) => write!(f, "number: {}", num),
             Self::String(literal) => write!(f, "string literal: \"{}\"", literal),
             Self::Char(character) => write!(f, "character literal: '{}'", character),
+            Self::Label(label) => write!(f, "label: '{}", label),
             Self::OpenBracket(ty) => write!(
                 f,
                 "'{}'",
@@ -92,6 +97,50 @@ impl fmt::Display for TokenData<'_> {
     }
 }
 
+/// The kind of a token without its source text, used by editor tooling to highlight source
+/// files
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenType {
+    /// A reserved keyword like `fun` or `return`
+    Keyword,
+    /// Any identifier that is not a keyword
+    Ident,
+    Number,
+    String,
+    Char,
+    Label,
+    /// Any operator, `->`, `=`, or `$`
+    Operator,
+    /// Any opening or closing bracket
+    Bracket,
+    /// `,`, `.`, `:`, or `#`
+    Punctuation,
+    /// A run of whitespace characters between tokens
+    Whitespace,
+    /// Source text that could not be lexed as a token
+    Invalid,
+}
+
+impl From<&TokenData<'_>> for TokenType {
+    fn from(data: &TokenData<'_>) -> Self {
+        match data {
+            TokenData::Ident(name) if KEYWORDS.contains(name) => Self::Keyword,
+            TokenData::Ident(_) => Self::Ident,
+            TokenData::Number(_) => Self::Number,
+            TokenData::String(_) => Self::String,
+            TokenData::Char(_) => Self::Char,
+            TokenData::Label(_) => Self::Label,
+            TokenData::OpenBracket(_) | TokenData::CloseBracket(_) => Self::Bracket,
+            TokenData::Op(_) | TokenData::Arrow | TokenData::Assign | TokenData::Dollar => {
+                Self::Operator
+            }
+            TokenData::Comma | TokenData::Period | TokenData::Colon | TokenData::Pound => {
+                Self::Punctuation
+            }
+        }
+    }
+}
+
 /// A binary or unary operator
 #[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Op {
@@ -115,6 +164,7 @@ pub enum Op {
     Less,
     LessEq,
     Eq,
+    NotEq,
 
     ShLeft,
     ShRight,
@@ -143,6 +193,7 @@ impl fmt::Display for Op {
             Self::Less => write!(f, "<"),
             Self::LessEq => write!(f, "<="),
             Self::Eq => write!(f, "=="),
+            Self::NotEq => write!(f, "!="),
 
             Self::ShLeft => write!(f, "<<"),
             Self::ShRight => write!(f, ">>"),
@@ -0,0 +1,58 @@
+//! Suggesting similarly named items when a name cannot be resolved
+
+/// Get the Levenshtein edit distance between two strings, the minimum number of single
+/// character insertions, deletions, or substitutions needed to turn one into the other
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + if a_char == *b_char { 0 } else { 1 };
+            current[j + 1] = substitution.min(prev[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut current);
+    }
+
+    prev[b.len()]
+}
+
+/// Find the candidate closest to `name`, ignoring candidates that are too different to be a
+/// likely typo
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_single_character_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("point", "point"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("pont", "point"), 1);
+        assert_eq!(edit_distance("point", "pint"), 1);
+        assert_eq!(edit_distance("u23", "u32"), 2);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("é", "e"), 1);
+    }
+
+    #[test]
+    fn closest_ignores_distant_candidates() {
+        let candidates = ["point", "vector", "u32"];
+        assert_eq!(closest("pointt", candidates), Some("point"));
+        assert_eq!(closest("u23", candidates), None);
+        assert_eq!(closest("matrix", candidates), None);
+    }
+}